@@ -1,7 +1,38 @@
 extern crate cc;
 
+use std::env;
+
 fn main() {
-    cc::Build::new()
-        .file("src/impl.c")
-        .compile("impl");
+    println!("cargo:rerun-if-changed=src/impl.c");
+    println!("cargo:rerun-if-changed=src/valgrind.h");
+    println!("cargo:rerun-if-changed=src/asan.h");
+    println!("cargo:rerun-if-changed=src/uffd.c");
+    println!("cargo:rustc-check-cfg=cfg(bulletproof_fallback)");
+    println!("cargo:rustc-check-cfg=cfg(bulletproof_unsupported)");
+
+    // Faults cannot be recovered without Unix signals (e.g. on WebAssembly), so no shim is built.
+    if env::var_os("CARGO_CFG_UNIX").is_none() {
+        println!("cargo:rustc-cfg=bulletproof_unsupported");
+        return;
+    }
+
+    let mut build = cc::Build::new();
+    build.file("src/impl.c");
+    if env::var_os("CARGO_FEATURE_VALGRIND").is_some() {
+        build.define("BULLETPROOF_VALGRIND", None);
+    }
+    if env::var_os("CARGO_FEATURE_ASAN").is_some() {
+        build.define("BULLETPROOF_ASAN", None);
+    }
+    if env::var_os("CARGO_FEATURE_TRACING").is_some() {
+        build.define("BULLETPROOF_TRACING", None);
+    }
+    build.compile("impl");
+
+    let target_os = env::var("CARGO_CFG_TARGET_OS").unwrap();
+    if env::var_os("CARGO_FEATURE_USERFAULTFD").is_some() && target_os == "linux" {
+        cc::Build::new()
+            .file("src/uffd.c")
+            .compile("uffd");
+    }
 }
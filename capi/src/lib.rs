@@ -0,0 +1,8 @@
+//! The C API of bulletproof memory access, built as a shared and a static library.
+//!
+//! The functions are declared in `include/bulletproof.h`, and documented in the `capi` module of
+//! the `bulletproof` crate.
+
+extern crate bulletproof;
+
+pub use bulletproof::capi::*;
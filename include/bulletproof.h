@@ -0,0 +1,61 @@
+// The C API of bulletproof memory access.
+//
+// Built as a shared library by the `bulletproof-capi` package:
+//
+//     $ cargo build --release -p bulletproof-capi
+//     $ cc -Iinclude main.c -Ltarget/release -lbulletproof_capi
+//
+// Call `bulletproof_register()` once before accessing memory, and `bulletproof_register_thread()`
+// on every other thread that accesses memory.  The accessors return 0 on success, and the signal
+// number (`SIGSEGV` or `SIGBUS`) if the access faulted.  See the documentation of the `capi`
+// module of the `bulletproof` crate for details.
+
+#ifndef BULLETPROOF_H
+#define BULLETPROOF_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+// The version of the API, incremented on every incompatible change.  It should be equal to
+// `bulletproof_api_version()` of the linked library.
+#define BULLETPROOF_API_VERSION 1
+
+// Returns the version of the API implemented by the library.
+uint32_t bulletproof_api_version(void);
+
+// Installs the `SIGSEGV` and `SIGBUS` handlers, and registers the current thread.  Returns 0 on
+// success, and the error number (or -1 if unknown) otherwise.
+//
+// The handlers replace the existing ones, which are still called for faults outside bulletproof
+// accesses.
+int bulletproof_register(void);
+
+// Registers the current thread: unblocks the signals and installs its alternate signal stack.
+// Returns 0 on success, and the error number (or -1 if unknown) otherwise.
+int bulletproof_register_thread(void);
+
+// Loads a word from `loc` to `dst`.
+int bulletproof_load(const size_t *loc, size_t *dst);
+
+// Stores a word `val` to `loc`.
+int bulletproof_store(size_t *loc, size_t val);
+
+// Loads `size` bytes from `loc` to `dst`.  On fault, `dst` may be partially written.
+int bulletproof_load_bytes(const void *loc, void *dst, size_t size);
+
+// Stores `size` bytes from `src` to `loc`.  On fault, `loc` may be partially written.
+int bulletproof_store_bytes(void *loc, const void *src, size_t size);
+
+// Returns the signal number of the last fault returned by an accessor on the current thread, or 0
+// if none.  Stores its signal code to `code` and faulting address to `addr` if they are not null.
+int bulletproof_last_fault(int *code, void **addr);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif  // BULLETPROOF_H
@@ -0,0 +1,82 @@
+//! The `#[bulletproof]` attribute macro, re-exported by the `bulletproof` crate (`macros` feature).
+
+extern crate proc_macro;
+extern crate proc_macro2;
+extern crate quote;
+extern crate syn;
+
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+use quote::quote;
+use syn::{parse_macro_input, Error, ItemFn, ReturnType};
+
+/// Runs the body of a function in a protected section, changing its return type from `T` to
+/// `Result<T, Fault>`.
+///
+/// The function returns `Ok(r)` if the body returns `r`, and `Err(fault)` if a memory access in the
+/// body faults, as [`Bulletproof::protect()`]. It saves wrapping the body of every function, e.g.
+/// of the opcode handlers of an interpreter, in a closure by hand. `return` and `?` in the body
+/// return from the original body.
+///
+/// The function should be `unsafe`, and its callers are responsible for the safety conditions of
+/// [`Bulletproof::protect()`]. Besides, the signal handlers should be installed (e.g. by
+/// `Bulletproof::new()`) and the calling thread registered.
+///
+/// # Examples
+///
+/// ```
+/// # extern crate bulletproof;
+/// use bulletproof::{bulletproof, Bulletproof, Fault};
+/// use std::ptr;
+///
+/// #[bulletproof]
+/// unsafe fn opcode(pc: *const u8) -> u8 {
+///     ptr::read_volatile(pc)
+/// }
+///
+/// unsafe {
+///     let _bulletproof = Bulletproof::new();
+///
+///     assert_eq!(opcode(&42), Ok(42));
+///     assert!(matches!(opcode(ptr::null()), Err(Fault::Segv { addr: 0, .. })));
+/// }
+/// ```
+///
+/// [`Bulletproof::protect()`]: https://docs.rs/bulletproof/*/bulletproof/struct.Bulletproof.html#method.protect
+#[proc_macro_attribute]
+pub fn bulletproof(attr: TokenStream, item: TokenStream) -> TokenStream {
+    if !attr.is_empty() {
+        let error = Error::new(Span::call_site(), "`#[bulletproof]` takes no arguments");
+        return error.to_compile_error().into();
+    }
+
+    let ItemFn {
+        attrs,
+        vis,
+        mut sig,
+        block,
+    } = parse_macro_input!(item as ItemFn);
+    if sig.unsafety.is_none() {
+        let message = "`#[bulletproof]` functions should be `unsafe`";
+        let error = Error::new_spanned(sig.fn_token, message);
+        return error.to_compile_error().into();
+    }
+    if let Some(asyncness) = sig.asyncness {
+        let error = Error::new_spanned(asyncness, "`#[bulletproof]` functions cannot be `async`");
+        return error.to_compile_error().into();
+    }
+
+    let output = match sig.output {
+        ReturnType::Default => quote!(()),
+        ReturnType::Type(_, ref ty) => quote!(#ty),
+    };
+    sig.output = syn::parse_quote!(-> ::std::result::Result<#output, ::bulletproof::Fault>);
+
+    let expanded = quote! {
+        #(#attrs)*
+        #vis #sig {
+            ::bulletproof::__private::protect(move || -> #output #block)
+        }
+    };
+    expanded.into()
+}
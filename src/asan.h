@@ -0,0 +1,59 @@
+// AddressSanitizer queries for the `asan` feature.
+//
+// Under ASan, the intentional faults of bulletproof memory access abort the run, as ASan handles
+// `SIGSEGV` itself, and ASan also knows of invalid memory that does not fault, e.g. freed heap
+// blocks and stack redzones.  With `BULLETPROOF_ASAN` defined, the accessors ask ASan first whether
+// the location is poisoned, and fail without touching it if it is.
+//
+// The ASan runtime's interface is declared weak, so that it is not required to link.  The queries
+// are no-ops when the runtime is not linked.
+
+#ifndef BULLETPROOF_ASAN_H
+#define BULLETPROOF_ASAN_H
+
+#include <stddef.h>
+
+#if defined(BULLETPROOF_ASAN) && defined(__ELF__)
+
+// From `<sanitizer/asan_interface.h>`.
+extern void *__asan_region_is_poisoned(void *beg, size_t size) __attribute__((weak));
+extern void *__asan_get_current_fake_stack(void) __attribute__((weak));
+extern void *__asan_addr_is_in_fake_stack(void *fake_stack, void *addr, void **beg, void **end)
+    __attribute__((weak));
+
+// Checks if `[loc, loc + size)` is not poisoned according to ASan, without reporting an error.
+//
+// With `detect_stack_use_after_return`, the frames of the current thread live on a fake stack, and
+// an access should not cross from one fake frame to another.
+static inline int bulletproof_asan_addressable(const void *loc, size_t size) {
+  if (size == 0 || __asan_region_is_poisoned == NULL) {
+    return 1;
+  }
+
+  if (__asan_region_is_poisoned((void *) loc, size) != NULL) {
+    return 0;
+  }
+
+  if (__asan_get_current_fake_stack != NULL && __asan_addr_is_in_fake_stack != NULL) {
+    void *beg, *end;
+    void *fake_stack = __asan_get_current_fake_stack();
+    if (fake_stack != NULL &&
+        __asan_addr_is_in_fake_stack(fake_stack, (void *) loc, &beg, &end) != NULL &&
+        (size_t) ((const char *) end - (const char *) loc) < size) {
+      return 0;
+    }
+  }
+
+  return 1;
+}
+
+#else
+
+static inline int bulletproof_asan_addressable(const void *loc __attribute__((unused)),
+                                               size_t size __attribute__((unused))) {
+  return 1;
+}
+
+#endif
+
+#endif
@@ -0,0 +1,216 @@
+//! The regions that the kernel sets up at startup (Linux, glibc and musl).
+//!
+//! At `execve()`, the kernel maps the vDSO, and lays out the arguments, the environment, and the
+//! auxiliary vector on the initial stack. Runtime introspection tools read them, e.g. to symbolize
+//! the vDSO or to show the command line of the process, but the process may have changed them
+//! since: the environment strings may have been overwritten (e.g. by `setproctitle()`), and the
+//! stack may have been unmapped by a runtime that switched stacks for good.
+//! [`layout()`](fn.layout.html) locates the regions, and [`Layout`](struct.Layout.html) reads them
+//! with bulletproof loads.
+//!
+//! The regions are located with `getauxval()`, the memory map, and `/proc/self/stat`; the
+//! auxiliary vector itself is read from `/proc/self/auxv`, which the kernel keeps a copy of.
+//!
+//! # Examples
+//!
+//! ```
+//! use bulletproof::{auxv, Bulletproof};
+//!
+//! let layout = auxv::layout().unwrap();
+//! let entries = auxv::entries().unwrap();
+//! assert!(entries.iter().any(|entry| entry.key == libc::AT_PAGESZ));
+//!
+//! # #[cfg(not(bulletproof_fallback))]
+//! unsafe {
+//!     let bulletproof = Bulletproof::new();
+//!     let args = layout.read_args(bulletproof).unwrap();
+//!     assert_eq!(args.len(), std::env::args_os().len());
+//!     if let Some(vdso) = layout.read_vdso(bulletproof) {
+//!         assert_eq!(&vdso.unwrap()[..4], b"\x7fELF");
+//!     }
+//! }
+//! ```
+
+use std::fs;
+use std::io;
+use std::mem;
+use std::ops::Range;
+
+use libc::{self, c_ulong};
+
+use snapshot;
+use {Bulletproof, Fault};
+
+/// An entry of the auxiliary vector, e.g. `AT_PAGESZ` and the page size.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct AuxEntry {
+    /// The type of the entry, e.g. `libc::AT_PAGESZ`.
+    pub key: c_ulong,
+    /// The value of the entry, which is an address for some types (e.g. `AT_SYSINFO_EHDR`).
+    pub value: c_ulong,
+}
+
+/// Returns the auxiliary vector of the current process, without the terminating `AT_NULL`.
+///
+/// # Errors
+///
+/// Returns the error of reading `/proc/self/auxv`.
+pub fn entries() -> io::Result<Vec<AuxEntry>> {
+    const WORD: usize = mem::size_of::<c_ulong>();
+
+    let bytes = fs::read("/proc/self/auxv")?;
+    let word = |chunk: &[u8]| {
+        let mut word = [0u8; WORD];
+        word.copy_from_slice(chunk);
+        c_ulong::from_ne_bytes(word)
+    };
+    Ok(bytes
+        .chunks_exact(2 * WORD)
+        .map(|pair| AuxEntry { key: word(&pair[..WORD]), value: word(&pair[WORD..]) })
+        .take_while(|entry| entry.key != libc::AT_NULL)
+        .collect())
+}
+
+/// The regions that the kernel set up at startup, returned by [`layout()`](fn.layout.html).
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Layout {
+    /// The argument strings, each terminated by a NUL byte.
+    pub args: Range<usize>,
+    /// The environment strings, each terminated by a NUL byte, as of `execve()`.
+    pub env: Range<usize>,
+    /// The initial stack (`[stack]`), if it is still mapped.
+    pub stack: Option<Range<usize>>,
+    /// The vDSO, if the kernel mapped one and it is still mapped.
+    pub vdso: Option<Range<usize>>,
+}
+
+/// Returns the fields at `indices` of `/proc/self/stat`, counted from 1 as in `proc(5)`.
+fn stat_fields(stat: &str, indices: &[usize]) -> Option<Vec<usize>> {
+    // The command name may contain spaces and parentheses, but it is followed by the last one.
+    let fields = stat[stat.rfind(')')? + 1..].split_whitespace().collect::<Vec<_>>();
+    // The fields after the command name start with the third.
+    indices.iter().map(|&index| fields.get(index - 3)?.parse().ok()).collect()
+}
+
+/// Locates the regions that the kernel set up at startup.
+///
+/// # Errors
+///
+/// Returns the error of reading the memory map or `/proc/self/stat`, and `InvalidData` if the
+/// latter does not report the arguments and the environment (before Linux 3.5).
+pub fn layout() -> io::Result<Layout> {
+    let stat = fs::read_to_string("/proc/self/stat")?;
+    let fields = stat_fields(&stat, &[48, 49, 50, 51]).ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidData, "malformed /proc/self/stat")
+    })?;
+
+    let regions = snapshot::regions()?;
+    let find = |addr: usize| {
+        regions
+            .iter()
+            .find(|region| region.start <= addr && addr < region.end)
+            .map(|region| region.start..region.end)
+    };
+    let stack = regions.iter().find(|region| region.name == "[stack]");
+    let vdso = unsafe { libc::getauxval(libc::AT_SYSINFO_EHDR) } as usize;
+
+    Ok(Layout {
+        args: fields[0]..fields[1],
+        env: fields[2]..fields[3],
+        stack: stack.map(|region| region.start..region.end),
+        vdso: if vdso == 0 { None } else { find(vdso).map(|region| vdso..region.end) },
+    })
+}
+
+/// Reads the NUL-terminated strings in `range`.
+unsafe fn read_strings(
+    bulletproof: Bulletproof,
+    range: &Range<usize>,
+) -> Result<Vec<Vec<u8>>, Fault> {
+    let len = range.end.saturating_sub(range.start);
+    let bytes = bulletproof.load_vec(range.start as *const u8, len)?;
+    let mut strings = bytes.split(|&byte| byte == 0).map(<[u8]>::to_vec).collect::<Vec<_>>();
+    // The last string is terminated, so the split ends with an empty string.
+    if strings.last().is_some_and(|last| last.is_empty()) {
+        strings.pop();
+    }
+    Ok(strings)
+}
+
+impl Layout {
+    /// Reads the argument strings, without their NUL terminators.
+    ///
+    /// The strings are racy if the process modifies them concurrently.
+    ///
+    /// # Safety
+    ///
+    /// Reading the arguments should have no side effects, except that they can be invalid.
+    pub unsafe fn read_args(&self, bulletproof: Bulletproof) -> Result<Vec<Vec<u8>>, Fault> {
+        read_strings(bulletproof, &self.args)
+    }
+
+    /// Reads the environment strings as of `execve()`, e.g. `b"HOME=/root"`, without their NUL
+    /// terminators.
+    ///
+    /// Variables set since are not included: see `std::env::vars_os()` for those. The strings are
+    /// racy if the process modifies them concurrently.
+    ///
+    /// # Safety
+    ///
+    /// Reading the environment should have no side effects, except that it can be invalid.
+    pub unsafe fn read_env(&self, bulletproof: Bulletproof) -> Result<Vec<Vec<u8>>, Fault> {
+        read_strings(bulletproof, &self.env)
+    }
+
+    /// Reads the image of the vDSO, an ELF shared object, e.g. to symbolize addresses in it.
+    ///
+    /// Returns `None` if there is no vDSO.
+    ///
+    /// # Safety
+    ///
+    /// Reading the vDSO should have no side effects, except that it can be invalid.
+    pub unsafe fn read_vdso(&self, bulletproof: Bulletproof) -> Option<Result<Vec<u8>, Fault>> {
+        let vdso = self.vdso.as_ref()?;
+        Some(bulletproof.load_vec(vdso.start as *const u8, vdso.end - vdso.start))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::os::unix::ffi::OsStrExt;
+
+    use super::*;
+
+    #[test]
+    fn stat_fields() {
+        let stat = "1 (a) b) S 2 3 4";
+        assert_eq!(super::stat_fields(stat, &[3, 5, 6]), None);
+        assert_eq!(super::stat_fields(stat, &[4, 6]), Some(vec![2, 4]));
+    }
+
+    #[test]
+    #[cfg_attr(any(miri, bulletproof_fallback), ignore)]
+    fn layout() {
+        let entries = entries().unwrap();
+        let page_size = entries.iter().find(|entry| entry.key == libc::AT_PAGESZ).unwrap();
+        assert_eq!(page_size.value as usize, ::probe::page_size());
+
+        let layout = super::layout().unwrap();
+        let stack = layout.stack.clone().unwrap();
+        assert!(stack.start <= layout.args.start && layout.env.end <= stack.end);
+
+        unsafe {
+            let bulletproof = Bulletproof::new();
+            let args = layout.read_args(bulletproof).unwrap();
+            let expected = env::args_os().map(|arg| arg.as_bytes().to_vec()).collect::<Vec<_>>();
+            assert_eq!(args, expected);
+            let vars = layout.read_env(bulletproof).unwrap();
+            assert!(vars.iter().all(|var| var.contains(&b'=')));
+
+            // Another region, e.g. one unmapped since.
+            let unmapped = Layout { args: 0..8, ..layout };
+            assert_eq!(unmapped.read_args(bulletproof).map_err(|fault| fault.addr()), Err(0));
+        }
+    }
+}
@@ -0,0 +1,216 @@
+//! Frame-pointer stack walking tolerant of corruption.
+//!
+//! Sampling profilers interrupt arbitrary code, whose stack may be in the middle of being set up or
+//! corrupted. [`walk()`](fn.walk.html) follows the frame pointer chain with bulletproof loads, and
+//! stops cleanly at the first invalid frame instead of faulting.
+//!
+//! It assumes the usual frame layout of x86-64 and AArch64 with frame pointers enabled: the frame
+//! pointer points to the saved frame pointer of the caller, followed by the return address.
+//! [`stack_bounds()`](fn.stack_bounds.html) returns the bounds of the current thread's stack to
+//! walk within.
+//!
+//! # Examples
+//!
+//! ```
+//! use bulletproof::{backtrace, Bulletproof};
+//!
+//! // A fake stack of two frames, whose second frame pointer is corrupted.
+//! let mut stack = [0usize; 4];
+//! let base = stack.as_mut_ptr() as usize;
+//! let word = std::mem::size_of::<usize>();
+//!
+//! unsafe {
+//!     let bulletproof = Bulletproof::new();
+//!
+//!     let frame = [base + 2 * word, 0x1234, 8, 0x5678];
+//!     std::ptr::copy_nonoverlapping(frame.as_ptr(), base as *mut usize, 4);
+//!
+//!     let frames = backtrace::walk(bulletproof, base, 0..usize::MAX).collect::<Vec<_>>();
+//!     let return_addrs = frames.iter().map(|frame| frame.return_addr).collect::<Vec<_>>();
+//!     assert_eq!(return_addrs, vec![0x1234, 0x5678]);
+//! }
+//! ```
+
+use std::io;
+use std::mem;
+use std::ops::Range;
+
+use super::Bulletproof;
+
+/// A stack frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Frame {
+    /// The frame pointer.
+    pub fp: usize,
+    /// The return address into the caller.
+    pub return_addr: usize,
+}
+
+/// An iterator over the frames of a frame pointer chain, created by [`walk()`](fn.walk.html).
+#[derive(Debug, Clone)]
+pub struct Walk {
+    bulletproof: Bulletproof,
+    fp: usize,
+    stack: Range<usize>,
+}
+
+impl Iterator for Walk {
+    type Item = Frame;
+
+    fn next(&mut self) -> Option<Frame> {
+        let fp = self.fp;
+        let word = mem::size_of::<usize>();
+        let aligned = fp & (word - 1) == 0;
+        if !aligned || fp < self.stack.start || fp.saturating_add(2 * word) > self.stack.end {
+            return None;
+        }
+
+        let (next, return_addr) = unsafe {
+            let next = self.bulletproof.load_usize(fp as *const usize).ok()?;
+            let return_addr = self.bulletproof.load_usize((fp + word) as *const usize).ok()?;
+            (next, return_addr)
+        };
+        if return_addr == 0 {
+            return None;
+        }
+
+        // The stack grows downwards, so the caller's frame should be above. Otherwise, the next
+        // iteration stops.
+        self.fp = if next > fp { next } else { 0 };
+        Some(Frame { fp, return_addr })
+    }
+}
+
+/// Walks the frame pointer chain from `fp`, within `stack`.
+///
+/// The walk stops at the first frame that is misaligned, outside `stack`, unreadable, not above the
+/// previous frame, or whose return address is zero.
+///
+/// # Safety
+///
+/// Reading `stack` should have no side effects.
+#[inline]
+pub unsafe fn walk(bulletproof: Bulletproof, fp: usize, stack: Range<usize>) -> Walk {
+    Walk {
+        bulletproof,
+        fp,
+        stack,
+    }
+}
+
+/// Returns the stack of the current thread, as the range of its addresses.
+///
+/// It is queried with `pthread_getattr_np()` on Linux and NetBSD, `pthread_attr_get_np()` on
+/// FreeBSD and illumos, `pthread_stackseg_np()` on OpenBSD, and `pthread_get_stackaddr_np()` on
+/// macOS. It may include the guard page. Returns an error of kind `Unsupported` on other
+/// platforms.
+pub fn stack_bounds() -> io::Result<Range<usize>> {
+    unsafe { stack_bounds_impl() }
+}
+
+#[cfg(any(
+    target_os = "linux",
+    target_os = "netbsd",
+    target_os = "freebsd",
+    target_os = "illumos"
+))]
+unsafe fn stack_bounds_impl() -> io::Result<Range<usize>> {
+    use libc;
+
+    let check = |errno| match errno {
+        0 => Ok(()),
+        errno => Err(io::Error::from_raw_os_error(errno)),
+    };
+
+    let mut attr = mem::zeroed::<libc::pthread_attr_t>();
+    #[cfg(any(target_os = "linux", target_os = "netbsd"))]
+    check(libc::pthread_getattr_np(libc::pthread_self(), &mut attr))?;
+    #[cfg(any(target_os = "freebsd", target_os = "illumos"))]
+    {
+        check(libc::pthread_attr_init(&mut attr))?;
+        if let Err(error) = check(libc::pthread_attr_get_np(libc::pthread_self(), &mut attr)) {
+            libc::pthread_attr_destroy(&mut attr);
+            return Err(error);
+        }
+    }
+
+    let mut addr = ::std::ptr::null_mut();
+    let mut size = 0;
+    let result = check(libc::pthread_attr_getstack(&attr, &mut addr, &mut size));
+    libc::pthread_attr_destroy(&mut attr);
+    result.map(|()| addr as usize..addr as usize + size)
+}
+
+#[cfg(target_os = "openbsd")]
+unsafe fn stack_bounds_impl() -> io::Result<Range<usize>> {
+    use libc;
+
+    let mut stack = mem::zeroed::<libc::stack_t>();
+    match libc::pthread_stackseg_np(libc::pthread_self(), &mut stack) {
+        // `ss_sp` is the top of the stack.
+        0 => Ok(stack.ss_sp as usize - stack.ss_size..stack.ss_sp as usize),
+        errno => Err(io::Error::from_raw_os_error(errno)),
+    }
+}
+
+#[cfg(any(target_os = "macos", target_os = "ios"))]
+unsafe fn stack_bounds_impl() -> io::Result<Range<usize>> {
+    use libc;
+
+    // The address is the top of the stack.
+    let top = libc::pthread_get_stackaddr_np(libc::pthread_self()) as usize;
+    let size = libc::pthread_get_stacksize_np(libc::pthread_self());
+    Ok(top - size..top)
+}
+
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "netbsd",
+    target_os = "freebsd",
+    target_os = "illumos",
+    target_os = "openbsd",
+    target_os = "macos",
+    target_os = "ios"
+)))]
+unsafe fn stack_bounds_impl() -> io::Result<Range<usize>> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "stack bounds are not supported"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ptr;
+
+    use super::*;
+
+    #[test]
+    fn stops_at_invalid_frames() {
+        let mut stack = [0usize; 8];
+        let base = stack.as_mut_ptr() as usize;
+        let word = mem::size_of::<usize>();
+
+        unsafe {
+            let bulletproof = Bulletproof::new();
+
+            // The third frame loops back to the first one.
+            let frames = [base + 2 * word, 1, base + 4 * word, 2, base, 3];
+            ptr::copy_nonoverlapping(frames.as_ptr(), base as *mut usize, frames.len());
+
+            let frames = walk(bulletproof, base, base..base + 8 * word).collect::<Vec<_>>();
+            let return_addrs = frames.iter().map(|frame| frame.return_addr).collect::<Vec<_>>();
+            assert_eq!(return_addrs, vec![1, 2, 3]);
+
+            // The stack bounds are respected.
+            assert_eq!(walk(bulletproof, base, base..base + 3 * word).count(), 1);
+            assert_eq!(walk(bulletproof, base + 1, base..base + 8 * word).count(), 0);
+        }
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn current_stack() {
+        let local = 0usize;
+        let addr = &local as *const usize as usize;
+        let stack = stack_bounds().unwrap();
+        assert!(stack.start <= addr && addr < stack.end, "{:#x} not in {:#x?}", addr, stack);
+    }
+}
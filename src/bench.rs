@@ -0,0 +1,216 @@
+//! Timing of bulletproof accesses (`bench` feature).
+//!
+//! Every single access (e.g. a load, a store, or a bulk read that aborts on a fault) is timed with
+//! a monotonic clock (`clock_gettime()`), and with the time-stamp counter (`rdtsc`) on x86 and
+//! x86-64. The costs of the accesses that succeed and of those that fault are accumulated
+//! separately, so that the overhead of the success path and of the recovery can be compared across
+//! backends and hardware. Protected sections and scans are not timed, as they run user code or many
+//! accesses at once.
+//! [`Bulletproof::timing_stats()`](../struct.Bulletproof.html#method.timing_stats) returns them.
+//!
+//! # Examples
+//!
+//! ```
+//! use bulletproof::Bulletproof;
+//!
+//! let x = 42usize;
+//!
+//! unsafe {
+//!     let bulletproof = Bulletproof::new();
+//!
+//!     for _ in 0..1000 {
+//!         bulletproof.load_usize(&x).unwrap();
+//!     }
+//!     let stats = bulletproof.timing_stats();
+//!     assert!(stats.success.count >= 1000);
+//!     println!("{}: {} ns per access", stats.backend, stats.success.mean_ns());
+//! }
+//! ```
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Instant;
+
+/// The accumulated costs of the accesses that took a path.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PathStats {
+    /// The number of accesses.
+    pub count: u64,
+    /// The total time of the accesses, in nanoseconds.
+    pub total_ns: u64,
+    /// The shortest time of an access, in nanoseconds, or 0 if there are none.
+    pub min_ns: u64,
+    /// The longest time of an access, in nanoseconds.
+    pub max_ns: u64,
+    /// The total number of time-stamp counter cycles of the accesses, or 0 if not measured (on
+    /// targets other than x86 and x86-64).
+    pub total_cycles: u64,
+}
+
+impl PathStats {
+    /// Returns the mean time of an access, in nanoseconds, or 0 if there are none.
+    #[inline]
+    pub fn mean_ns(&self) -> u64 {
+        self.total_ns.checked_div(self.count).unwrap_or(0)
+    }
+
+    /// Returns the mean number of time-stamp counter cycles of an access, or 0 if there are none.
+    #[inline]
+    pub fn mean_cycles(&self) -> u64 {
+        self.total_cycles.checked_div(self.count).unwrap_or(0)
+    }
+}
+
+/// The accumulated costs of the accesses of the process, returned by
+/// [`Bulletproof::timing_stats()`](../struct.Bulletproof.html#method.timing_stats).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TimingStats {
+    /// The backend performing the accesses: `"native"`, `"signal-hook"`, `"fallback"`, or
+    /// `"unsupported"`.
+    pub backend: &'static str,
+    /// The accesses that succeeded.
+    pub success: PathStats,
+    /// The accesses that faulted, including the recovery.
+    pub fault: PathStats,
+}
+
+/// The counters of a path.
+struct Path {
+    count: AtomicU64,
+    total_ns: AtomicU64,
+    min_ns: AtomicU64,
+    max_ns: AtomicU64,
+    total_cycles: AtomicU64,
+}
+
+impl Path {
+    const fn new() -> Self {
+        Self {
+            count: AtomicU64::new(0),
+            total_ns: AtomicU64::new(0),
+            min_ns: AtomicU64::new(u64::MAX),
+            max_ns: AtomicU64::new(0),
+            total_cycles: AtomicU64::new(0),
+        }
+    }
+
+    fn record(&self, ns: u64, cycles: u64) {
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.total_ns.fetch_add(ns, Ordering::Relaxed);
+        self.min_ns.fetch_min(ns, Ordering::Relaxed);
+        self.max_ns.fetch_max(ns, Ordering::Relaxed);
+        self.total_cycles.fetch_add(cycles, Ordering::Relaxed);
+    }
+
+    fn stats(&self) -> PathStats {
+        let count = self.count.load(Ordering::Relaxed);
+        PathStats {
+            count,
+            total_ns: self.total_ns.load(Ordering::Relaxed),
+            min_ns: if count == 0 { 0 } else { self.min_ns.load(Ordering::Relaxed) },
+            max_ns: self.max_ns.load(Ordering::Relaxed),
+            total_cycles: self.total_cycles.load(Ordering::Relaxed),
+        }
+    }
+
+    fn reset(&self) {
+        self.count.store(0, Ordering::Relaxed);
+        self.total_ns.store(0, Ordering::Relaxed);
+        self.min_ns.store(u64::MAX, Ordering::Relaxed);
+        self.max_ns.store(0, Ordering::Relaxed);
+        self.total_cycles.store(0, Ordering::Relaxed);
+    }
+}
+
+static SUCCESS: Path = Path::new();
+static FAULT: Path = Path::new();
+
+/// Reads the time-stamp counter.
+#[inline(always)]
+fn cycles() -> u64 {
+    #[cfg(target_arch = "x86_64")]
+    unsafe {
+        ::std::arch::x86_64::_rdtsc()
+    }
+    #[cfg(target_arch = "x86")]
+    unsafe {
+        ::std::arch::x86::_rdtsc()
+    }
+    #[cfg(not(any(target_arch = "x86_64", target_arch = "x86")))]
+    0
+}
+
+/// Times an access, from its start to its return.
+pub(crate) struct Timer {
+    start: Instant,
+    cycles: u64,
+}
+
+impl Timer {
+    #[inline(always)]
+    pub(crate) fn start() -> Self {
+        Self {
+            start: Instant::now(),
+            cycles: cycles(),
+        }
+    }
+
+    /// Records the cost of the access, which faulted if `faulted`.
+    #[inline(always)]
+    pub(crate) fn stop(self, faulted: bool) {
+        let cycles = cycles().wrapping_sub(self.cycles);
+        let ns = self.start.elapsed().as_nanos() as u64;
+        if faulted {
+            FAULT.record(ns, cycles);
+        } else {
+            SUCCESS.record(ns, cycles);
+        }
+    }
+}
+
+/// Returns the accumulated costs of the accesses.
+pub(crate) fn stats() -> TimingStats {
+    let backend = if cfg!(any(miri, bulletproof_fallback)) {
+        "fallback"
+    } else if cfg!(bulletproof_unsupported) {
+        "unsupported"
+    } else if cfg!(feature = "signal-hook") {
+        "signal-hook"
+    } else {
+        "native"
+    };
+    TimingStats {
+        backend,
+        success: SUCCESS.stats(),
+        fault: FAULT.stats(),
+    }
+}
+
+/// Resets the accumulated costs of the accesses, e.g. between the runs of a benchmark.
+pub fn reset() {
+    SUCCESS.reset();
+    FAULT.reset();
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ptr;
+
+    use Bulletproof;
+
+    #[test]
+    fn timing_stats() {
+        let x = 42usize;
+
+        unsafe {
+            let bulletproof = Bulletproof::new();
+            let before = bulletproof.timing_stats();
+            assert_eq!(bulletproof.load_usize(&x), Ok(42));
+            assert!(bulletproof.load_usize(ptr::null()).is_err());
+
+            let after = bulletproof.timing_stats();
+            assert!(after.success.count > before.success.count);
+            assert!(after.fault.count > before.fault.count);
+            assert!(after.fault.min_ns <= after.fault.max_ns);
+        }
+    }
+}
@@ -0,0 +1,105 @@
+//! Types valid for any bit pattern, and types without padding.
+//!
+//! A value copied from possibly-invalid memory may contain any bytes, e.g. if the location was
+//! freed and reused. [`FromBytes`](trait.FromBytes.html) marks the types for which any bytes are a
+//! valid value, so that the copy can be inspected safely before it is trusted, as
+//! [`Bulletproof::load_validated()`](../struct.Bulletproof.html#method.load_validated) does.
+//!
+//! # Examples
+//!
+//! ```
+//! use bulletproof::{FromBytes, NoPadding};
+//!
+//! /// An object header of a virtual machine.
+//! #[derive(Clone, Copy)]
+//! #[repr(C)]
+//! struct Header {
+//!     class: usize,
+//!     hash: u32,
+//!     flags: u32,
+//! }
+//!
+//! // Safe, as each field is valid for any bit pattern, and there is no padding.
+//! unsafe impl FromBytes for Header {}
+//! ```
+
+/// A type for which any bit pattern of `size_of::<Self>()` bytes is a valid value.
+///
+/// It is implemented for the integer and floating-point types, raw pointers, and arrays of such
+/// types.
+///
+/// # Safety
+///
+/// Any bit pattern should be a valid `Self`, e.g. a `#[repr(C)]` struct of `FromBytes` fields
+/// without padding. Types with invalid bit patterns (e.g. `bool`, `char`, references, and most
+/// enums) should not implement it.
+pub unsafe trait FromBytes: Sized {}
+
+macro_rules! impl_from_bytes {
+    ($($ty:ty),*) => {
+        $(unsafe impl FromBytes for $ty {})*
+    };
+}
+
+impl_from_bytes!(u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize, f32, f64, ());
+
+unsafe impl<T> FromBytes for *const T {}
+unsafe impl<T> FromBytes for *mut T {}
+unsafe impl<T: FromBytes, const N: usize> FromBytes for [T; N] {}
+
+/// A type without padding bytes, so that each byte of a value is initialized.
+///
+/// It is implemented for the primitive types, raw pointers, and arrays of such types.
+///
+/// # Safety
+///
+/// `Self` should have no padding bytes, e.g. a `#[repr(C)]` struct of `NoPadding` fields whose
+/// sizes and alignments leave no gaps.
+pub unsafe trait NoPadding: Sized {}
+
+macro_rules! impl_no_padding {
+    ($($ty:ty),*) => {
+        $(unsafe impl NoPadding for $ty {})*
+    };
+}
+
+impl_no_padding!(u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize, f32, f64, ());
+impl_no_padding!(bool, char);
+
+unsafe impl<T> NoPadding for *const T {}
+unsafe impl<T> NoPadding for *mut T {}
+unsafe impl<T: NoPadding, const N: usize> NoPadding for [T; N] {}
+
+/// A type accepted by the generic loads and stores.
+///
+/// With the `strict` feature, it is the `Copy` types that are [`NoPadding`](trait.NoPadding.html).
+/// Otherwise, it is every type.
+///
+/// # Examples
+///
+/// Loading a `Box` would duplicate it, and does not compile:
+///
+/// ```compile_fail
+/// use bulletproof::Bulletproof;
+///
+/// let boxed = Box::new(42);
+///
+/// unsafe {
+///     let copy = Bulletproof::new().load(&boxed).unwrap();
+/// }
+/// ```
+#[cfg(feature = "strict")]
+pub trait Plain: Copy + NoPadding {}
+
+#[cfg(feature = "strict")]
+impl<T: Copy + NoPadding> Plain for T {}
+
+/// A type accepted by the generic loads and stores.
+///
+/// With the `strict` feature, it is the `Copy` types that are [`NoPadding`](trait.NoPadding.html).
+/// Otherwise, it is every type.
+#[cfg(not(feature = "strict"))]
+pub trait Plain {}
+
+#[cfg(not(feature = "strict"))]
+impl<T> Plain for T {}
@@ -0,0 +1,190 @@
+//! The C API (`capi` feature).
+//!
+//! The accessors and registration are exported as C functions, declared in
+//! [`include/bulletproof.h`](https://github.com/jeehoonkang/bulletproof-rs/blob/master/include/bulletproof.h),
+//! so that C and C++ runtimes can reuse the recovery machinery of this crate. The
+//! `bulletproof-capi` package in `capi/` builds them into a shared library:
+//!
+//! ```text
+//! $ cargo build --release -p bulletproof-capi
+//! $ cc -Iinclude main.c -Ltarget/release -lbulletproof_capi
+//! ```
+//!
+//! The API is versioned by [`API_VERSION`](constant.API_VERSION.html), which is incremented on
+//! every incompatible change. C code should compare `BULLETPROOF_API_VERSION` of the header with
+//! `bulletproof_api_version()` of the library it is linked with.
+
+use std::cell::Cell;
+
+use super::{load_bytes, load_word, store_bytes, store_word, thread, Backend, Bulletproof, Fault};
+use error::RegisterError;
+use sys::{c_int, c_void, size_t};
+
+/// The version of the C API, `BULLETPROOF_API_VERSION` in `bulletproof.h`.
+pub const API_VERSION: u32 = 1;
+
+thread_local! {
+    /// The last fault returned by an accessor of the C API on the current thread.
+    static LAST_FAULT: Cell<Option<Fault>> = const { Cell::new(None) };
+}
+
+/// Converts the result of an accessor into its C return value, recording the fault.
+fn signal(result: Result<(), Fault>) -> c_int {
+    match result {
+        Ok(()) => 0,
+        Err(fault) => {
+            LAST_FAULT.with(|last| last.set(Some(fault)));
+            fault.signal()
+        }
+    }
+}
+
+/// Converts a registration error into its C return value, the error number or -1 if unknown.
+fn errno(error: RegisterError) -> c_int {
+    match error.errno() {
+        0 => -1,
+        errno => errno,
+    }
+}
+
+/// Returns the version of the C API, [`API_VERSION`](constant.API_VERSION.html).
+#[no_mangle]
+pub extern "C" fn bulletproof_api_version() -> u32 {
+    API_VERSION
+}
+
+/// Installs the signal handlers and registers the current thread, as
+/// [`Bulletproof::try_new()`](../struct.Bulletproof.html#method.try_new). Returns 0 on success,
+/// and the error number (or -1 if unknown) otherwise.
+///
+/// # Safety
+///
+/// The same as [`Bulletproof::new()`](../struct.Bulletproof.html#method.new).
+#[no_mangle]
+pub unsafe extern "C" fn bulletproof_register() -> c_int {
+    match Bulletproof::try_new() {
+        Ok(_) => 0,
+        Err(error) => errno(error),
+    }
+}
+
+/// Registers the current thread, as
+/// [`thread::try_register_current_thread()`](../thread/fn.try_register_current_thread.html).
+/// Returns 0 on success, and the error number (or -1 if unknown) otherwise.
+///
+/// # Safety
+///
+/// [`bulletproof_register()`](fn.bulletproof_register.html) should have succeeded.
+#[no_mangle]
+pub unsafe extern "C" fn bulletproof_register_thread() -> c_int {
+    match thread::try_register_current_thread() {
+        Ok(()) => 0,
+        Err(error) => errno(error),
+    }
+}
+
+/// Loads a word from `loc` to `dst`. Returns the signal number on fault, and 0 otherwise.
+///
+/// # Safety
+///
+/// The current thread should be registered, and `dst` should be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn bulletproof_load(loc: *const size_t, dst: *mut size_t) -> c_int {
+    signal(load_word(Backend::Signal, loc, dst))
+}
+
+/// Stores a word `val` to `loc`. Returns the signal number on fault, and 0 otherwise.
+///
+/// # Safety
+///
+/// The current thread should be registered, and writing to `loc` should not break the invariants
+/// of other code.
+#[no_mangle]
+pub unsafe extern "C" fn bulletproof_store(loc: *mut size_t, val: size_t) -> c_int {
+    signal(store_word(Backend::Signal, loc, val))
+}
+
+/// Loads `size` bytes from `loc` to `dst`. Returns the signal number on fault, and 0 otherwise.
+///
+/// # Safety
+///
+/// The current thread should be registered, and `dst` should be valid for writes of `size` bytes.
+/// On fault, `dst` may be partially written.
+#[no_mangle]
+pub unsafe extern "C" fn bulletproof_load_bytes(
+    loc: *const c_void,
+    dst: *mut c_void,
+    size: size_t,
+) -> c_int {
+    signal(load_bytes(Backend::Signal, loc, dst, size))
+}
+
+/// Stores `size` bytes from `src` to `loc`. Returns the signal number on fault, and 0 otherwise.
+///
+/// # Safety
+///
+/// The current thread should be registered, `src` should be valid for reads of `size` bytes, and
+/// writing to `loc` should not break the invariants of other code. On fault, `loc` may be
+/// partially written.
+#[no_mangle]
+pub unsafe extern "C" fn bulletproof_store_bytes(
+    loc: *mut c_void,
+    src: *const c_void,
+    size: size_t,
+) -> c_int {
+    signal(store_bytes(Backend::Signal, loc, src, size))
+}
+
+/// Returns the signal number of the last fault returned by an accessor on the current thread, and
+/// stores its signal code to `code` and faulting address to `addr` if they are not null. Returns 0
+/// if no access has faulted.
+///
+/// # Safety
+///
+/// `code` and `addr` should be null or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn bulletproof_last_fault(code: *mut c_int, addr: *mut *mut c_void) -> c_int {
+    let fault = match LAST_FAULT.with(Cell::get) {
+        Some(fault) => fault,
+        None => return 0,
+    };
+    if !code.is_null() {
+        *code = fault.code();
+    }
+    if !addr.is_null() {
+        *addr = fault.addr() as *mut c_void;
+    }
+    fault.signal()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ptr;
+
+    use libc;
+
+    use super::*;
+
+    #[test]
+    fn load_store() {
+        unsafe {
+            assert_eq!(bulletproof_api_version(), API_VERSION);
+            assert_eq!(bulletproof_register(), 0);
+
+            let mut word = 0;
+            assert_eq!(bulletproof_store(&mut word, 42), 0);
+            let mut value = 0;
+            assert_eq!(bulletproof_load(&word, &mut value), 0);
+            assert_eq!(value, 42);
+
+            let invalid = 24 as *const c_void;
+            let mut buf = [0u8; 16];
+            let sig = bulletproof_load_bytes(invalid, buf.as_mut_ptr() as *mut c_void, buf.len());
+            assert_eq!(sig, libc::SIGSEGV);
+
+            let (mut code, mut addr) = (0, ptr::null_mut());
+            assert_eq!(bulletproof_last_fault(&mut code, &mut addr), libc::SIGSEGV);
+            assert_eq!(addr as usize, 24);
+        }
+    }
+}
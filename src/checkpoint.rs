@@ -0,0 +1,165 @@
+//! Checkpoints of regions of memory, which can be restored later.
+//!
+//! [`checkpoint()`](fn.checkpoint.html) copies the contents of a set of regions out page by page,
+//! and [`Checkpoint::restore()`](struct.Checkpoint.html#method.restore) writes them back, e.g. to
+//! undo the effects of an experiment on the heap of a virtual machine and replay it. Pages that
+//! cannot be read at the checkpoint, or written at the restore (e.g. because they were unmapped in
+//! between), are skipped and reported as faults.
+//!
+//! Only the contents are saved: mappings and permissions are not, unlike a full checkpoint of the
+//! process.
+//!
+//! # Examples
+//!
+//! ```
+//! use bulletproof::{checkpoint, Bulletproof};
+//!
+//! let mut heap = vec![1u8; 10000];
+//! let range = heap.as_ptr() as usize..heap.as_ptr() as usize + heap.len();
+//!
+//! unsafe {
+//!     let saved = checkpoint::checkpoint(Bulletproof::new(), &[range]);
+//!     assert!(saved.faults().is_empty());
+//!
+//!     heap.iter_mut().for_each(|byte| *byte = 2);
+//!     assert!(saved.restore().is_empty());
+//! }
+//! assert!(heap.iter().all(|&byte| byte == 1));
+//! ```
+
+use std::cmp;
+use std::ops::Range;
+
+use libc::c_void;
+
+use super::{load_bytes, probe, store_bytes, Bulletproof, Fault};
+
+/// The saved contents of a page, or of the part of it in a region.
+#[derive(Debug)]
+struct Page {
+    addr: usize,
+    contents: Box<[u8]>,
+}
+
+/// The contents of a set of regions, returned by [`checkpoint()`](fn.checkpoint.html).
+#[derive(Debug)]
+pub struct Checkpoint {
+    bulletproof: Bulletproof,
+    pages: Vec<Page>,
+    faults: Vec<Fault>,
+}
+
+impl Checkpoint {
+    /// Returns the faults of the pages that could not be saved, which are not restored.
+    #[inline]
+    pub fn faults(&self) -> &[Fault] {
+        &self.faults
+    }
+
+    /// Returns the number of bytes saved.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.pages.iter().map(|page| page.contents.len()).sum()
+    }
+
+    /// Returns `true` if no bytes were saved.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.pages.is_empty()
+    }
+
+    /// Writes the saved contents back, and returns the faults of the pages that could not be
+    /// written, which are skipped.
+    ///
+    /// The checkpoint is kept, so that it can be restored again.
+    ///
+    /// # Safety
+    ///
+    /// Writing the saved pages should be safe, except that they can be invalid. In particular,
+    /// nothing should hold references into them.
+    pub unsafe fn restore(&self) -> Vec<Fault> {
+        let bulletproof = self.bulletproof;
+        let mut faults = Vec::new();
+        for page in &self.pages {
+            let (dst, len) = (page.addr as *mut c_void, page.contents.len());
+            let result = bulletproof.config().retry(|| {
+                let src = page.contents.as_ptr() as *const c_void;
+                store_bytes(bulletproof.backend(), dst, src, len)
+            });
+            if let Err(fault) = result {
+                faults.push(fault);
+            }
+        }
+        faults
+    }
+}
+
+/// Saves the contents of `regions`, page by page.
+///
+/// Pages that cannot be read (e.g. because they are unmapped) are skipped, and their faults are
+/// reported by [`Checkpoint::faults()`](struct.Checkpoint.html#method.faults).
+///
+/// # Safety
+///
+/// Reading the regions should have no side effects, except that they can be invalid.
+pub unsafe fn checkpoint(bulletproof: Bulletproof, regions: &[Range<usize>]) -> Checkpoint {
+    let page_size = probe::page_size();
+    let mut pages = Vec::new();
+    let mut faults = Vec::new();
+
+    for region in regions {
+        let mut addr = region.start;
+        while addr < region.end {
+            let end = cmp::min((addr & !(page_size - 1)).saturating_add(page_size), region.end);
+            let mut contents = vec![0u8; end - addr].into_boxed_slice();
+            let result = bulletproof.config().retry(|| {
+                let dst = contents.as_mut_ptr() as *mut c_void;
+                load_bytes(bulletproof.backend(), addr as *const c_void, dst, end - addr)
+            });
+            match result {
+                Ok(()) => pages.push(Page { addr, contents }),
+                Err(fault) => faults.push(fault),
+            }
+            addr = end;
+        }
+    }
+
+    Checkpoint { bulletproof, pages, faults }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::{ptr, slice};
+
+    use super::*;
+    use mapping::TestPages;
+
+    #[test]
+    #[cfg_attr(any(miri, bulletproof_fallback), ignore)]
+    fn checkpoint() {
+        unsafe {
+            let mut pages = TestPages::new(3);
+            let page_size = pages.page_size();
+            let start = pages.page(0) as usize;
+            ptr::write_bytes(pages.page(0), 1, 3 * page_size);
+
+            // The middle page is unmapped before the checkpoint.
+            pages.unmap(1);
+            let regions = [start + 8..start + page_size, start + page_size..start + 3 * page_size];
+            let saved = super::checkpoint(Bulletproof::new(), &regions);
+            assert_eq!(saved.len(), 2 * page_size - 8);
+            assert_eq!(saved.faults().len(), 1);
+            assert_eq!(saved.faults()[0].addr(), start + page_size);
+
+            // The last page is unmapped before the restore.
+            ptr::write_bytes(pages.page(0), 2, page_size);
+            pages.unmap(2);
+            let faults = saved.restore();
+            assert_eq!(faults.len(), 1);
+            assert_eq!(faults[0].addr(), start + 2 * page_size);
+            let first = slice::from_raw_parts(pages.page(0) as *const u8, page_size);
+            assert_eq!(&first[..8], &[2; 8]);
+            assert!(first[8..].iter().all(|&byte| byte == 1));
+        }
+    }
+}
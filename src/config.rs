@@ -0,0 +1,582 @@
+//! Configuration of bulletproof memory access.
+//!
+//! # Examples
+//!
+//! ```
+//! use bulletproof::{Bulletproof, Config};
+//! use std::time::Duration;
+//!
+//! let config = Config::new()
+//!     .bus_retry(3, Duration::from_millis(10))
+//!     .save_sigmask(false);
+//! let bulletproof = unsafe { Bulletproof::with_config(config) };
+//! ```
+//!
+//! The signal handlers and their behavior are process-wide: the settings of the signals to catch,
+//! chaining, the signal mask, and fault hooks apply to the whole process from the creation of the
+//! manager, and the handlers and fault hooks are never removed. The other settings only apply to
+//! the manager.
+
+use std::fmt;
+use std::ops::Range;
+use std::{ptr, thread};
+use std::time::Duration;
+
+use fault::Fault;
+use limit;
+use oracle::PermissionOracle;
+
+/// The default size of alternate signal stacks.
+const DEFAULT_ALTSTACK_SIZE: usize = 64 * 1024;
+
+/// How [`Bulletproof::read()`](../struct.Bulletproof.html#method.read) recovers from faults.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Recovery {
+    /// Abort the whole read on the first fault, returning the fault.
+    Abort,
+    /// Zero-fill the bytes of the unreadable pages, and continue with the next page.
+    ZeroFill,
+}
+
+/// What to do when a thread exceeds a fault limit.
+///
+/// See [`Config::max_consecutive_faults()`](struct.Config.html#method.max_consecutive_faults) and
+/// [`Config::max_fault_rate()`](struct.Config.html#method.max_fault_rate).
+#[derive(Clone, Copy)]
+pub enum LimitAction {
+    /// Print the last fault to the standard error, and abort the process.
+    Abort,
+    /// Call the function with the last fault, and return the fault from the access. The counts of
+    /// the thread start over.
+    Call(fn(&Fault)),
+}
+
+impl fmt::Debug for LimitAction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LimitAction::Abort => f.write_str("Abort"),
+            LimitAction::Call(func) => f
+                .debug_tuple("Call")
+                .field(&(func as usize as *const ()))
+                .finish(),
+        }
+    }
+}
+
+impl PartialEq for LimitAction {
+    #[inline]
+    fn eq(&self, other: &Self) -> bool {
+        match (*self, *other) {
+            (LimitAction::Abort, LimitAction::Abort) => true,
+            (LimitAction::Call(f), LimitAction::Call(g)) => f as usize == g as usize,
+            _ => false,
+        }
+    }
+}
+
+impl Eq for LimitAction {}
+
+/// A fault hook: a function called by the handler on faults in an address range, before checking
+/// for a bulletproof memory access, e.g. to resolve faults on lazily mapped memory.
+///
+/// The function is called with the fault, and returns `true` if it resolved the fault, in which
+/// case the faulting instruction is retried. It runs in the signal handler, so it should be
+/// async-signal-safe: e.g. it should not allocate or take locks.
+#[derive(Clone)]
+pub struct FaultHook {
+    range: Range<usize>,
+    f: fn(&Fault) -> bool,
+}
+
+impl FaultHook {
+    /// Creates a hook calling `f` on faults at addresses in `range`.
+    #[inline]
+    pub const fn new(range: Range<usize>, f: fn(&Fault) -> bool) -> Self {
+        Self { range, f }
+    }
+
+    /// Returns the address range.
+    #[inline]
+    pub fn range(&self) -> Range<usize> {
+        self.range.clone()
+    }
+
+    /// Returns the function.
+    #[inline]
+    pub fn function(&self) -> fn(&Fault) -> bool {
+        self.f
+    }
+}
+
+impl fmt::Debug for FaultHook {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("FaultHook")
+            .field("range", &self.range)
+            .field("f", &(self.f as usize as *const ()))
+            .finish()
+    }
+}
+
+impl PartialEq for FaultHook {
+    #[inline]
+    fn eq(&self, other: &Self) -> bool {
+        self.range == other.range && self.f as usize == other.f as usize
+    }
+}
+
+impl Eq for FaultHook {}
+
+/// A permission oracle, compared by address.
+#[derive(Clone, Copy)]
+struct Oracle(&'static dyn PermissionOracle);
+
+impl fmt::Debug for Oracle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl PartialEq for Oracle {
+    #[inline]
+    fn eq(&self, other: &Self) -> bool {
+        ptr::addr_eq(self.0, other.0)
+    }
+}
+
+impl Eq for Oracle {}
+
+/// Configuration of a [`Bulletproof`](../struct.Bulletproof.html) manager.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Config {
+    bus_attempts: u32,
+    bus_backoff: Duration,
+    max_consecutive_faults: u32,
+    max_fault_rate: (u32, Duration),
+    limit_action: LimitAction,
+    altstack_size: usize,
+    recovery: Recovery,
+    catch_segv: bool,
+    catch_bus: bool,
+    chain: bool,
+    save_sigmask: bool,
+    fault_hooks: &'static [FaultHook],
+    oracle: Option<Oracle>,
+}
+
+impl Default for Config {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            bus_attempts: 0,
+            bus_backoff: Duration::from_secs(0),
+            max_consecutive_faults: 0,
+            max_fault_rate: (0, Duration::from_secs(1)),
+            limit_action: LimitAction::Abort,
+            altstack_size: DEFAULT_ALTSTACK_SIZE,
+            recovery: Recovery::Abort,
+            catch_segv: true,
+            catch_bus: true,
+            chain: false,
+            save_sigmask: true,
+            fault_hooks: &[],
+            oracle: None,
+        }
+    }
+}
+
+impl Config {
+    /// Creates the default configuration, which catches `SIGSEGV` and `SIGBUS`, does not chain,
+    /// saves the signal mask, has no fault hooks, does not retry, does not limit faults, uses
+    /// 64 KiB alternate signal stacks, aborts reads on the first fault, and has no permission
+    /// oracle.
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the size of the alternate signal stacks of registered threads.
+    ///
+    /// The handlers run on a per-thread alternate signal stack (`SA_ONSTACK`), so that faults
+    /// raised when the regular stack is exhausted or corrupted can still be recovered. Registering
+    /// a thread allocates the stack lazily, unless the thread already has one that is at least as
+    /// large (e.g. installed by the Rust runtime), and it is freed on thread exit.
+    ///
+    /// The size applies to the threads registered after the manager is created, including those
+    /// spawned later with [`thread::spawn()`](../thread/fn.spawn.html). If `size` is 0, alternate
+    /// signal stacks are not allocated.
+    #[inline]
+    pub fn altstack_size(mut self, size: usize) -> Self {
+        self.altstack_size = size;
+        self
+    }
+
+    /// Returns the size of the alternate signal stacks.
+    #[inline]
+    pub fn get_altstack_size(&self) -> usize {
+        self.altstack_size
+    }
+
+    /// Retries accesses that fail with `SIGBUS` up to `attempts` times.
+    ///
+    /// Reads from memory backed by network or user-space file systems (e.g. NFS or FUSE) can raise
+    /// transient bus errors. Before the first retry, it sleeps for `backoff`, and the sleep doubles
+    /// for each subsequent retry. If the last retry fails as well, the access returns the fault.
+    ///
+    /// Only the accessors of [`Bulletproof`](../struct.Bulletproof.html) are retried; closures run
+    /// by [`Bulletproof::protect()`](../struct.Bulletproof.html#method.protect) are not.
+    #[inline]
+    pub fn bus_retry(mut self, attempts: u32, backoff: Duration) -> Self {
+        self.bus_attempts = attempts;
+        self.bus_backoff = backoff;
+        self
+    }
+
+    /// Limits the faults caught in a row by a thread, i.e. without a successful access in between.
+    ///
+    /// A bug that probes garbage pointers in a tight loop would otherwise spin forever with every
+    /// access recovered. When a thread catches `count` faults in a row, the
+    /// [limit action](#method.on_fault_limit) is taken. If `count` is 0, there is no limit.
+    ///
+    /// Faults are counted by the accessors of [`Bulletproof`](../struct.Bulletproof.html) and by
+    /// [`Bulletproof::protect()`](../struct.Bulletproof.html#method.protect), after retries.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bulletproof::{Bulletproof, Config, LimitAction};
+    /// use std::ptr;
+    /// use std::time::Duration;
+    ///
+    /// fn on_limit(fault: &bulletproof::Fault) {
+    ///     // It runs on the faulting thread after the access returned, not in the signal handler,
+    ///     // so it may allocate and print.
+    ///     eprintln!("too many faults, the last one: {}", fault);
+    /// }
+    ///
+    /// let config = Config::new()
+    ///     .max_consecutive_faults(1000)
+    ///     .max_fault_rate(10_000, Duration::from_secs(1))
+    ///     .on_fault_limit(LimitAction::Call(on_limit));
+    /// let bulletproof = unsafe { Bulletproof::with_config(config) };
+    /// ```
+    #[inline]
+    pub fn max_consecutive_faults(mut self, count: u32) -> Self {
+        self.max_consecutive_faults = count;
+        self
+    }
+
+    /// Returns the limit on the faults caught in a row by a thread.
+    #[inline]
+    pub fn get_max_consecutive_faults(&self) -> u32 {
+        self.max_consecutive_faults
+    }
+
+    /// Limits the faults caught by a thread in each `interval`, counted as
+    /// [`max_consecutive_faults()`](#method.max_consecutive_faults).
+    ///
+    /// When a thread catches `count` faults within `interval` from the first of them, the
+    /// [limit action](#method.on_fault_limit) is taken. If `count` is 0, there is no limit.
+    #[inline]
+    pub fn max_fault_rate(mut self, count: u32, interval: Duration) -> Self {
+        self.max_fault_rate = (count, interval);
+        self
+    }
+
+    /// Returns the limit on the faults caught by a thread in an interval, and the interval.
+    #[inline]
+    pub fn get_max_fault_rate(&self) -> (u32, Duration) {
+        self.max_fault_rate
+    }
+
+    /// Sets what to do when a thread exceeds a fault limit. By default, the process is aborted.
+    ///
+    /// The action is taken on the thread that exceeded the limit, once the access that faulted
+    /// has returned: a [`LimitAction::Call`](enum.LimitAction.html#variant.Call) function does not
+    /// run in the signal handler, so it needs not be async-signal-safe.
+    #[inline]
+    pub fn on_fault_limit(mut self, action: LimitAction) -> Self {
+        self.limit_action = action;
+        self
+    }
+
+    /// Returns what to do when a thread exceeds a fault limit.
+    #[inline]
+    pub fn get_on_fault_limit(&self) -> LimitAction {
+        self.limit_action
+    }
+
+    /// Sets how [`Bulletproof::read()`](../struct.Bulletproof.html#method.read) recovers from
+    /// faults.
+    ///
+    /// Heap snapshotting tools may prefer [`Recovery::ZeroFill`](enum.Recovery.html), reading as
+    /// much as possible with zeros in place of the unreadable pages, over all-or-nothing reads.
+    #[inline]
+    pub fn recovery(mut self, recovery: Recovery) -> Self {
+        self.recovery = recovery;
+        self
+    }
+
+    /// Returns how reads recover from faults.
+    #[inline]
+    pub fn get_recovery(&self) -> Recovery {
+        self.recovery
+    }
+
+    /// Sets whether to install a handler for `SIGSEGV`.
+    ///
+    /// A program that only reads memory-mapped files, which fail with `SIGBUS`, may leave `SIGSEGV`
+    /// to another handler (e.g. of a language runtime). Faults of uncaught signals are not
+    /// recovered, and crash the process. Handlers are never uninstalled, so it only decides whether
+    /// a handler is installed by this manager.
+    #[inline]
+    pub fn catch_segv(mut self, catch: bool) -> Self {
+        self.catch_segv = catch;
+        self
+    }
+
+    /// Returns whether to install a handler for `SIGSEGV`.
+    #[inline]
+    pub fn get_catch_segv(&self) -> bool {
+        self.catch_segv
+    }
+
+    /// Sets whether to install a handler for `SIGBUS`, as [`catch_segv()`](#method.catch_segv).
+    #[inline]
+    pub fn catch_bus(mut self, catch: bool) -> Self {
+        self.catch_bus = catch;
+        self
+    }
+
+    /// Returns whether to install a handler for `SIGBUS`.
+    #[inline]
+    pub fn get_catch_bus(&self) -> bool {
+        self.catch_bus
+    }
+
+    /// Sets whether to chain to the previous handlers.
+    ///
+    /// By default, a fault outside bulletproof memory access restores the disposition before the
+    /// handlers were installed and re-raises the signal, so that the process crashes as if they
+    /// were not installed. If chaining, the previous handler is called instead (if any), and the
+    /// faulting instruction is retried when it returns, as expected by runtimes that resolve their
+    /// own faults (e.g. a JVM). Without the previous handler, the process crashes as by default.
+    ///
+    /// It is ignored with the `signal-hook` feature, as `signal-hook-registry` composes the
+    /// handlers.
+    #[inline]
+    pub fn chain(mut self, chain: bool) -> Self {
+        self.chain = chain;
+        self
+    }
+
+    /// Returns whether to chain to the previous handlers.
+    #[inline]
+    pub fn get_chain(&self) -> bool {
+        self.chain
+    }
+
+    /// Sets whether protected sections save and restore the signal mask of the thread.
+    ///
+    /// Saving it (`sigsetjmp(env, 1)`) costs a system call for each access. Not saving it makes
+    /// accesses cheaper, and is safe unless the signal mask is changed in a protected section (e.g.
+    /// in a closure run by [`Bulletproof::protect()`](../struct.Bulletproof.html#method.protect)),
+    /// as the handler unblocks the fault signal itself before recovering.
+    ///
+    /// It is ignored with the `signal-hook` feature, whose recovery relies on restoring the mask.
+    #[inline]
+    pub fn save_sigmask(mut self, save: bool) -> Self {
+        self.save_sigmask = save;
+        self
+    }
+
+    /// Returns whether protected sections save and restore the signal mask.
+    #[inline]
+    pub fn get_save_sigmask(&self) -> bool {
+        self.save_sigmask
+    }
+
+    /// Sets the fault hooks to add.
+    ///
+    /// They are added once when a manager with them is created, and are never removed. Adding them
+    /// fails with the fallback backend.
+    ///
+    /// # Safety
+    ///
+    /// The hooks run in the `SIGSEGV` and `SIGBUS` handlers, on whichever thread faults, so they
+    /// must be async-signal-safe: they must not allocate, take locks, or do I/O (e.g. with
+    /// `println!`). Creating the manager that adds them is unsafe, and this is part of its
+    /// contract.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bulletproof::config::{Config, FaultHook};
+    /// use bulletproof::{Bulletproof, Fault};
+    ///
+    /// fn on_fault(_fault: &Fault) -> bool {
+    ///     // E.g., map the faulting page and return `true`.
+    ///     false
+    /// }
+    ///
+    /// static HOOKS: [FaultHook; 1] = [FaultHook::new(0x7000_0000..0x7100_0000, on_fault)];
+    ///
+    /// # #[cfg(not(bulletproof_fallback))]
+    /// let bulletproof = unsafe { Bulletproof::with_config(Config::new().fault_hooks(&HOOKS)) };
+    /// ```
+    #[inline]
+    pub fn fault_hooks(mut self, hooks: &'static [FaultHook]) -> Self {
+        self.fault_hooks = hooks;
+        self
+    }
+
+    /// Returns the fault hooks to add.
+    #[inline]
+    pub fn get_fault_hooks(&self) -> &'static [FaultHook] {
+        self.fault_hooks
+    }
+
+    /// Sets the oracle asked about the accesses before they are performed.
+    ///
+    /// See the [`oracle`](../oracle/index.html) module for more details.
+    #[inline]
+    pub fn oracle(mut self, oracle: &'static dyn PermissionOracle) -> Self {
+        self.oracle = Some(Oracle(oracle));
+        self
+    }
+
+    /// Returns the oracle asked about the accesses, if any.
+    #[inline]
+    pub fn get_oracle(&self) -> Option<&'static dyn PermissionOracle> {
+        self.oracle.map(|oracle| oracle.0)
+    }
+
+    /// Calls `access` until it does not fail with `SIGBUS`, or the retries are exhausted, and
+    /// counts the fault against the limits.
+    pub(crate) fn retry<F>(&self, mut access: F) -> Result<(), Fault>
+    where
+        F: FnMut() -> Result<(), Fault>,
+    {
+        let mut result = access();
+        let mut backoff = self.bus_backoff;
+        for _ in 0..self.bus_attempts {
+            match result {
+                Err(Fault::Bus { .. }) => {}
+                _ => break,
+            }
+
+            thread::sleep(backoff);
+            backoff = backoff.saturating_mul(2);
+            result = access();
+        }
+        limit::record(self, result.as_ref().err());
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{self, OpenOptions};
+    use std::os::unix::io::AsRawFd;
+    use std::{env, process, ptr};
+
+    use libc;
+
+    use super::*;
+    use super::super::Bulletproof;
+
+    #[test]
+    #[cfg_attr(any(miri, bulletproof_fallback), ignore)]
+    fn bus_retry() {
+        let path = env::temp_dir().join(format!("bulletproof-config-{}", process::id()));
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)
+            .unwrap();
+
+        unsafe {
+            // Map a page past the end of the empty file, so that reading it raises `SIGBUS`.
+            let page_size = libc::sysconf(libc::_SC_PAGESIZE) as usize;
+            let map = libc::mmap(
+                ptr::null_mut(),
+                page_size,
+                libc::PROT_READ,
+                libc::MAP_SHARED,
+                file.as_raw_fd(),
+                0,
+            );
+            assert_ne!(map, libc::MAP_FAILED);
+            let location = map as *const usize;
+
+            let config = Config::new().bus_retry(2, Duration::from_millis(1));
+            let bulletproof = Bulletproof::with_config(config);
+            assert!(matches!(bulletproof.load_usize(location), Err(Fault::Bus { .. })));
+
+            // The backing store recovers while the access is being retried.
+            let config = Config::new().bus_retry(10, Duration::from_millis(10));
+            let bulletproof = Bulletproof::with_config(config);
+            let extend = thread::spawn(move || {
+                thread::sleep(Duration::from_millis(20));
+                file.set_len(page_size as u64).unwrap();
+            });
+            assert_eq!(bulletproof.load_usize(location), Ok(0));
+            extend.join().unwrap();
+
+            libc::munmap(map, page_size);
+        }
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    #[cfg_attr(any(miri, bulletproof_fallback), ignore)]
+    fn fault_hooks() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        static PAGE: AtomicUsize = AtomicUsize::new(0);
+        static PAGE_SIZE: AtomicUsize = AtomicUsize::new(0);
+
+        /// Makes `PAGE` accessible on faults in it.
+        fn map_on_fault(fault: &Fault) -> bool {
+            let (page, page_size) = (PAGE.load(Ordering::SeqCst), PAGE_SIZE.load(Ordering::SeqCst));
+            if page == 0 || fault.addr() < page || fault.addr() >= page + page_size {
+                return false;
+            }
+            let prot = libc::PROT_READ | libc::PROT_WRITE;
+            unsafe { libc::mprotect(page as *mut libc::c_void, page_size, prot) == 0 }
+        }
+
+        static HOOKS: [FaultHook; 1] = [FaultHook::new(0..usize::MAX, map_on_fault)];
+
+        // In a new process, as the hook and the configuration are process-wide and never removed.
+        if !super::super::tests::in_subprocess("config::tests::fault_hooks") {
+            return;
+        }
+
+        unsafe {
+            let config = Config::new().fault_hooks(&HOOKS).save_sigmask(false);
+            let bulletproof = Bulletproof::with_config(config);
+
+            let page_size = libc::sysconf(libc::_SC_PAGESIZE) as usize;
+            let flags = libc::MAP_PRIVATE | libc::MAP_ANONYMOUS;
+            let map = libc::mmap(ptr::null_mut(), page_size, libc::PROT_NONE, flags, -1, 0);
+            assert_ne!(map, libc::MAP_FAILED);
+            PAGE_SIZE.store(page_size, Ordering::SeqCst);
+            PAGE.store(map as usize, Ordering::SeqCst);
+
+            // Resolved by the hook.
+            assert_eq!(bulletproof.load_usize(map as *const usize), Ok(0));
+
+            // Recovered repeatedly, even without restoring the signal mask.
+            PAGE.store(0, Ordering::SeqCst);
+            libc::mprotect(map, page_size, libc::PROT_NONE);
+            for _ in 0..3 {
+                assert!(bulletproof.load_usize(map as *const usize).is_err());
+            }
+
+            libc::munmap(map, page_size);
+            Bulletproof::new();
+        }
+    }
+}
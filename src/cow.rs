@@ -0,0 +1,310 @@
+//! Copy-on-write snapshots of memory regions.
+//!
+//! [`Snapshot::capture()`](struct.Snapshot.html#method.capture) write-protects the pages of a
+//! region. The first write to each page afterwards faults, and the handler copies the page's
+//! contents at the time of the capture aside before restoring write access. Thus a virtual machine
+//! can take a consistent snapshot of its heap, e.g. for a concurrent collector or a checkpoint,
+//! while the mutators keep running: only the pages written in the meantime are copied.
+//!
+//! With the fallback backend (under Miri, or with `--cfg bulletproof_fallback`), there is no
+//! handler to resolve the faults, so [`Snapshot::capture()`](struct.Snapshot.html#method.capture)
+//! returns an error.
+//!
+//! # Examples
+//!
+//! ```
+//! use bulletproof::{cow::Snapshot, Bulletproof};
+//! use std::alloc::{self, Layout};
+//!
+//! # #[cfg(not(bulletproof_fallback))]
+//! unsafe {
+//!     let bulletproof = Bulletproof::new();
+//!
+//!     // A heap of 4 pages, which do not share pages with other data.
+//!     let layout = Layout::from_size_align(4 * 4096, 4096).unwrap();
+//!     let heap = alloc::alloc_zeroed(layout);
+//!     *heap = 1;
+//!
+//!     let snapshot = Snapshot::capture(bulletproof, heap, layout.size()).unwrap();
+//!     *heap = 2;
+//!     assert_eq!(snapshot.read(heap, 1), Ok(vec![1]));
+//!     assert_eq!(*heap, 2);
+//!
+//!     drop(snapshot);
+//!     alloc::dealloc(heap, layout);
+//! }
+//! ```
+
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::{cmp, hint, io, ptr, slice};
+
+use libc::{self, c_int, c_void, siginfo_t};
+
+use super::fault::SEGV_ACCERR;
+use super::hook::Hook;
+use super::mapping::Mapping;
+use super::{load_bytes, probe, Bulletproof, Fault};
+
+/// The page has not been written since the capture.
+const ORIGINAL: u8 = 0;
+/// The page is being copied by a handler.
+const COPYING: u8 = 1;
+/// The page has been copied, and may have been written since.
+const COPIED: u8 = 2;
+
+/// The state shared with the fault hook, which only reads it except for `states` and `copies`.
+#[derive(Debug)]
+struct Pages {
+    start: usize,
+    page_size: usize,
+    count: usize,
+    /// The state of each page.
+    states: Mapping,
+    /// The contents of the copied pages at the time of the capture.
+    copies: Mapping,
+}
+
+impl Pages {
+    fn state(&self, index: usize) -> &AtomicU8 {
+        let states = self.states.as_ptr() as *const AtomicU8;
+        unsafe { &*states.add(index) }
+    }
+
+    fn copy(&self, index: usize) -> *mut u8 {
+        unsafe { self.copies.as_ptr().add(index * self.page_size) }
+    }
+}
+
+/// A copy-on-write snapshot of a region.
+///
+/// Dropping it discards the snapshot, and makes the pages writable again.
+#[derive(Debug)]
+pub struct Snapshot {
+    bulletproof: Bulletproof,
+    // Dropped before `pages`, which the hook refers to.
+    _hook: Hook,
+    pages: Box<Pages>,
+}
+
+/// The fault hook: copies the faulting page aside, and makes it writable.
+unsafe extern "C" fn on_fault(data: *mut c_void, sig: c_int, info: *const siginfo_t) -> c_int {
+    // If the page is not mapped, the fault is not ours.
+    if sig != libc::SIGSEGV || (*info).si_code != SEGV_ACCERR {
+        return 0;
+    }
+
+    let pages = &*(data as *const Pages);
+    let index = ((*info).si_addr() as usize - pages.start) / pages.page_size;
+    let page = pages.start + index * pages.page_size;
+
+    // Only one handler copies the page, and the others wait for it, so that the page is not written
+    // before being copied.
+    let state = pages.state(index);
+    match state.compare_exchange(ORIGINAL, COPYING, Ordering::SeqCst, Ordering::SeqCst) {
+        Ok(_) => {
+            ptr::copy_nonoverlapping(page as *const u8, pages.copy(index), pages.page_size);
+            state.store(COPIED, Ordering::SeqCst);
+        }
+        Err(_) => {
+            while state.load(Ordering::SeqCst) != COPIED {
+                hint::spin_loop();
+            }
+        }
+    }
+
+    let prot = libc::PROT_READ | libc::PROT_WRITE;
+    if libc::mprotect(page as *mut c_void, pages.page_size, prot) != 0 {
+        return 0;
+    }
+    1
+}
+
+impl Snapshot {
+    /// Captures the pages overlapping `[start, start + len)`.
+    ///
+    /// The pages are write-protected until the snapshot is dropped, and the first write to each
+    /// page costs a fault and a copy.
+    ///
+    /// # Safety
+    ///
+    /// The pages should be mapped readable and writable, and their protection should not be changed
+    /// by others while captured. They are made readable and writable again when the snapshot is
+    /// dropped.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the pages cannot be write-protected or the copies cannot be allocated,
+    /// if too many regions are captured (or otherwise hooked) at once, or with the fallback
+    /// backend.
+    pub unsafe fn capture(
+        bulletproof: Bulletproof,
+        start: *mut u8,
+        len: usize,
+    ) -> io::Result<Self> {
+        let page_size = probe::page_size();
+        let first = start as usize & !(page_size - 1);
+        let end = (start as usize + len).div_ceil(page_size) * page_size;
+        let count = (end - first) / page_size;
+
+        // The copies are mapped lazily by the kernel, so that only the copied pages cost memory.
+        let pages = Box::new(Pages {
+            start: first,
+            page_size,
+            count,
+            states: Mapping::new(count)?,
+            copies: Mapping::new(count * page_size)?,
+        });
+        let data = &*pages as *const Pages as *mut c_void;
+        let hook = Hook::add(first..end, on_fault, data)?;
+
+        if libc::mprotect(first as *mut c_void, end - first, libc::PROT_READ) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self {
+            bulletproof,
+            _hook: hook,
+            pages,
+        })
+    }
+
+    /// Returns the address of the first captured page.
+    #[inline]
+    pub fn start(&self) -> usize {
+        self.pages.start
+    }
+
+    /// Returns the number of captured pages.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.pages.count
+    }
+
+    /// Returns `true` if no pages are captured.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.pages.count == 0
+    }
+
+    /// Returns the number of pages copied so far, i.e. written since the capture.
+    pub fn copied(&self) -> usize {
+        (0..self.pages.count)
+            .filter(|&index| self.pages.state(index).load(Ordering::SeqCst) == COPIED)
+            .count()
+    }
+
+    /// Reads `len` bytes from `ptr` as they were at the time of the capture.
+    ///
+    /// The pages that have not been written since the capture are read in place, with bulletproof
+    /// loads. Returns `Err(fault)` if one of them has been unmapped in the meantime.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `[ptr, ptr + len)` is not in the captured pages.
+    pub fn read(&self, ptr: *const u8, len: usize) -> Result<Vec<u8>, Fault> {
+        let pages = &*self.pages;
+        let start = ptr as usize;
+        let end = pages.start + pages.count * pages.page_size;
+        assert!(
+            start >= pages.start && start.checked_add(len).is_some_and(|e| e <= end),
+            "range out of the snapshot",
+        );
+
+        let mut buf = vec![0; len];
+        let mut offset = 0;
+        while offset < len {
+            let addr = start + offset;
+            let index = (addr - pages.start) / pages.page_size;
+            let chunk = cmp::min(pages.page_size - addr % pages.page_size, len - offset);
+            let dst = buf[offset..offset + chunk].as_mut_ptr();
+            let copy = unsafe { pages.copy(index).add(addr % pages.page_size) };
+
+            // A page is written only after it is copied. If it was copied while being read in
+            // place, the copy is read instead.
+            let state = pages.state(index);
+            if state.load(Ordering::SeqCst) != COPIED {
+                let via = self.bulletproof.backend();
+                self.bulletproof.config().retry(|| unsafe {
+                    load_bytes(via, addr as *const c_void, dst as *mut c_void, chunk)
+                })?;
+            }
+            if state.load(Ordering::SeqCst) == COPIED {
+                unsafe { ptr::copy_nonoverlapping(copy, dst, chunk) };
+            }
+            offset += chunk;
+        }
+        Ok(buf)
+    }
+
+    /// Returns the contents of the `index`-th page at the time of the capture, if it has been
+    /// copied, i.e. written since the capture.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds.
+    pub fn copied_page(&self, index: usize) -> Option<&[u8]> {
+        let pages = &*self.pages;
+        assert!(index < pages.count, "page index out of bounds");
+        if pages.state(index).load(Ordering::SeqCst) != COPIED {
+            return None;
+        }
+        Some(unsafe { slice::from_raw_parts(pages.copy(index), pages.page_size) })
+    }
+}
+
+impl Drop for Snapshot {
+    fn drop(&mut self) {
+        let pages = &*self.pages;
+        unsafe {
+            libc::mprotect(
+                pages.start as *mut c_void,
+                pages.count * pages.page_size,
+                libc::PROT_READ | libc::PROT_WRITE,
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg_attr(any(miri, bulletproof_fallback), ignore)]
+    fn capture() {
+        unsafe {
+            let bulletproof = Bulletproof::new();
+            let page_size = probe::page_size();
+
+            let map = libc::mmap(
+                ptr::null_mut(),
+                3 * page_size,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                -1,
+                0,
+            );
+            assert_ne!(map, libc::MAP_FAILED);
+            let start = map as *mut u8;
+            ptr::write_bytes(start, 1, 3 * page_size);
+
+            let snapshot = Snapshot::capture(bulletproof, start, 3 * page_size).unwrap();
+            assert_eq!(snapshot.len(), 3);
+
+            // Plain writes, and bulletproof stores, across pages.
+            ptr::write_bytes(start.add(page_size - 2), 2, 4);
+            let word = start.add(2 * page_size) as *mut usize;
+            assert_eq!(bulletproof.store_usize(word, 0), Ok(()));
+            assert_eq!(snapshot.copied(), 3);
+            assert!(snapshot.copied_page(0).unwrap().iter().all(|&b| b == 1));
+
+            let view = snapshot.read(start, 3 * page_size).unwrap();
+            assert!(view.iter().all(|&b| b == 1));
+            assert_eq!(*start.add(page_size - 1), 2);
+            assert_eq!(*word, 0);
+
+            drop(snapshot);
+            *start = 3;
+            libc::munmap(map, 3 * page_size);
+        }
+    }
+}
@@ -0,0 +1,128 @@
+//! Decoders of common value encodings of virtual machines.
+//!
+//! The words loaded from the heap of a virtual machine usually encode more than an address: low-bit
+//! tags of aligned pointers, bit fields of object headers, or NaN-boxed values. The loads
+//! [`load_tagged_ptr()`], [`load_bits()`], and [`load_nan_boxed()`] decode them along with the
+//! load, with the functions of this module.
+//!
+//! # Examples
+//!
+//! ```
+//! use bulletproof::decode::{self, NanBoxed, Tagged};
+//!
+//! // A pointer to an 8-byte aligned object, tagged with 0b101.
+//! assert_eq!(decode::untag(0x1000 | 0b101, 0b111), Tagged { tag: 0b101, payload: 0x1000 });
+//!
+//! // Bits 3 to 7 of a header.
+//! assert_eq!(decode::bits(0b1010_1000, 3..8), 0b10101);
+//!
+//! // A double, and a boxed 47-bit payload.
+//! assert_eq!(decode::unbox(1.5f64.to_bits(), 47), NanBoxed::Double(1.5));
+//! let boxed = 0xfff9_0000_0000_002a;
+//! assert_eq!(decode::unbox(boxed, 47), NanBoxed::Boxed { tag: boxed >> 47, payload: 42 });
+//! ```
+//!
+//! [`load_tagged_ptr()`]: ../struct.Bulletproof.html#method.load_tagged_ptr
+//! [`load_bits()`]: ../struct.Bulletproof.html#method.load_bits
+//! [`load_nan_boxed()`]: ../struct.Bulletproof.html#method.load_nan_boxed
+
+use std::mem;
+use std::ops::Range;
+
+/// The exponent and quiet bits, set in every quiet NaN.
+const QUIET_NAN: u64 = 0x7ff8_0000_0000_0000;
+
+/// A word split into a tag and a payload.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Tagged {
+    /// The bits in the tag mask.
+    pub tag: usize,
+    /// The other bits, e.g. the untagged pointer.
+    pub payload: usize,
+}
+
+/// A NaN-boxed value.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NanBoxed {
+    /// A double, including the canonical NaN.
+    Double(f64),
+    /// A boxed value.
+    Boxed {
+        /// The bits above the payload, including the exponent and quiet bits.
+        tag: u64,
+        /// The payload, e.g. a pointer or an integer.
+        payload: u64,
+    },
+}
+
+/// Splits `word` into the bits in `tag_mask` and the others.
+#[inline]
+pub fn untag(word: usize, tag_mask: usize) -> Tagged {
+    Tagged {
+        tag: word & tag_mask,
+        payload: word & !tag_mask,
+    }
+}
+
+/// Returns the bits of `word` in `range` (bit 0 is the least significant), shifted down.
+///
+/// # Panics
+///
+/// Panics if `range` is decreasing or goes past the bits of a word.
+#[inline]
+pub fn bits(word: usize, range: Range<u32>) -> usize {
+    let width = mem::size_of::<usize>() as u32 * 8;
+    assert!(range.start <= range.end && range.end <= width, "invalid bit range: {:?}", range);
+    let len = range.end - range.start;
+    if len == 0 {
+        return 0;
+    }
+    (word >> range.start) & (usize::MAX >> (width - len))
+}
+
+/// Decodes the bits of a NaN-boxed value with a payload of `payload_bits` bits.
+///
+/// Doubles are expected to be canonicalized, so that the only NaN double is
+/// `0x7ff8_0000_0000_0000`. The other quiet NaNs (with the exponent and quiet bits set, and any
+/// sign) are boxed values, whose tag is the bits above the payload, as in SpiderMonkey's and
+/// LuaJIT's 64-bit layouts.
+///
+/// # Panics
+///
+/// Panics if `payload_bits` is more than 51, as the payload would overlap the quiet bit.
+#[inline]
+pub fn unbox(bits: u64, payload_bits: u32) -> NanBoxed {
+    assert!(payload_bits <= 51, "payload of {} bits overlaps the quiet bit", payload_bits);
+    if bits & QUIET_NAN != QUIET_NAN || bits == QUIET_NAN {
+        return NanBoxed::Double(f64::from_bits(bits));
+    }
+    NanBoxed::Boxed {
+        tag: bits >> payload_bits,
+        payload: bits & ((1 << payload_bits) - 1),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode() {
+        assert_eq!(untag(usize::MAX, 0), Tagged { tag: 0, payload: usize::MAX });
+        assert_eq!(untag(0x1003, 0b11), Tagged { tag: 0b11, payload: 0x1000 });
+
+        assert_eq!(bits(usize::MAX, 0..0), 0);
+        assert_eq!(bits(usize::MAX, 0..usize::BITS), usize::MAX);
+        assert_eq!(bits(0xf0, 4..6), 0b11);
+
+        // The canonical NaN is a double, but compares unequal to itself.
+        match unbox(QUIET_NAN, 47) {
+            NanBoxed::Double(double) => assert!(double.is_nan()),
+            boxed => panic!("unexpected value: {:?}", boxed),
+        }
+        assert_eq!(unbox((-0.0f64).to_bits(), 47), NanBoxed::Double(-0.0));
+        assert_eq!(unbox(f64::INFINITY.to_bits(), 47), NanBoxed::Double(f64::INFINITY));
+        let boxed = NanBoxed::Boxed { tag: 0x7ff8_0000, payload: 1 };
+        assert_eq!(unbox(0x7ff8_0000_0000_0001, 32), boxed);
+    }
+}
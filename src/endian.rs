@@ -0,0 +1,105 @@
+//! Loads and stores of integers in a given byte order, for structures of another ABI (e.g. in a
+//! dump or a foreign mapping).
+
+use super::{load_bytes, store_bytes, Bulletproof, Fault};
+use sys::c_void;
+
+macro_rules! endian_accessors {
+    ($($ty:ident: $load:ident, $store:ident, $from:ident, $to:ident, $order:expr;)*) => {
+        impl Bulletproof {
+            $(
+                #[doc = concat!(
+                    "Loads a ", stringify!($ty), " in ", $order, " byte order from the location."
+                )]
+                ///
+                /// The location need not be aligned.
+                ///
+                /// Returns `Ok(v)` if `location` contains `v`, and `Err(fault)` if the location is
+                /// invalid.
+                ///
+                /// # Safety
+                ///
+                /// The location should satisfy the safety guarantee of
+                /// [`std::ptr::read_unaligned()`](https://doc.rust-lang.org/stable/std/ptr/fn.read_unaligned.html),
+                /// except that it can be an invalid pointer.
+                #[inline]
+                pub unsafe fn $load(self, location: *const u8) -> Result<$ty, Fault> {
+                    let mut bytes = [0; ::std::mem::size_of::<$ty>()];
+                    self.config().retry(|| {
+                        load_bytes(
+                            self.backend(),
+                            location as *const c_void,
+                            bytes.as_mut_ptr() as *mut c_void,
+                            bytes.len(),
+                        )
+                    })?;
+                    Ok($ty::$from(bytes))
+                }
+
+                #[doc = concat!(
+                    "Stores a ", stringify!($ty), " in ", $order, " byte order to the location."
+                )]
+                ///
+                /// The location need not be aligned.
+                ///
+                /// Returns `Ok(())` if `location` is valid, and `Err(fault)` if the location is
+                /// invalid.
+                ///
+                /// # Safety
+                ///
+                /// The location should satisfy the safety guarantee of
+                /// [`std::ptr::write_unaligned()`](https://doc.rust-lang.org/stable/std/ptr/fn.write_unaligned.html),
+                /// except that it can be an invalid pointer.
+                #[inline]
+                pub unsafe fn $store(self, location: *mut u8, val: $ty) -> Result<(), Fault> {
+                    let bytes = val.$to();
+                    self.config().retry(|| {
+                        store_bytes(
+                            self.backend(),
+                            location as *mut c_void,
+                            bytes.as_ptr() as *const c_void,
+                            bytes.len(),
+                        )
+                    })
+                }
+            )*
+        }
+    };
+}
+
+endian_accessors! {
+    u16: load_u16_le, store_u16_le, from_le_bytes, to_le_bytes, "little-endian";
+    u16: load_u16_be, store_u16_be, from_be_bytes, to_be_bytes, "big-endian";
+    u32: load_u32_le, store_u32_le, from_le_bytes, to_le_bytes, "little-endian";
+    u32: load_u32_be, store_u32_be, from_be_bytes, to_be_bytes, "big-endian";
+    u64: load_u64_le, store_u64_le, from_le_bytes, to_le_bytes, "little-endian";
+    u64: load_u64_be, store_u64_be, from_be_bytes, to_be_bytes, "big-endian";
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ptr;
+
+    use super::*;
+
+    #[test]
+    fn endian() {
+        unsafe {
+            let bulletproof = Bulletproof::new();
+
+            let mut buf = [0u8, 1, 2, 3, 4, 5, 6, 7, 8];
+            let p = buf.as_mut_ptr();
+            assert_eq!(bulletproof.load_u16_le(p.add(1)), Ok(0x0201));
+            assert_eq!(bulletproof.load_u16_be(p.add(1)), Ok(0x0102));
+            assert_eq!(bulletproof.load_u32_be(p.add(1)), Ok(0x0102_0304));
+            assert_eq!(bulletproof.load_u64_le(p.add(1)), Ok(0x0807_0605_0403_0201));
+            assert!(bulletproof.load_u32_le(ptr::null()).is_err());
+
+            assert_eq!(bulletproof.store_u32_be(p.add(1), 0xdead_beef), Ok(()));
+            assert_eq!(buf[1..5], [0xde, 0xad, 0xbe, 0xef]);
+            assert_eq!(bulletproof.store_u64_le(p, 1), Ok(()));
+            assert_eq!(buf[..], [1, 0, 0, 0, 0, 0, 0, 0, 8]);
+            assert!(bulletproof.store_u16_be(ptr::null_mut(), 1).is_err());
+        }
+    }
+}
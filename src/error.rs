@@ -0,0 +1,440 @@
+//! Registration, handler, self-test, copy, load, and store errors.
+
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::time::{Duration, Instant};
+
+use fault::Fault;
+use runtime::Backend;
+use sys::c_int;
+
+/// A step of registration for bulletproof memory access.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RegisterStep {
+    /// Installing the handler for the signal with `sigaction()`.
+    Sigaction(c_int),
+    /// Unblocking the signals of the current thread with `pthread_sigmask()`.
+    SignalMask,
+    /// Allocating and installing the alternate signal stack of the current thread with
+    /// `sigaltstack()`.
+    Sigaltstack,
+    /// Registering the handler through `signal-hook-registry` (`signal-hook` feature).
+    Registry,
+    /// Adding a fault hook of
+    /// [`Config::fault_hooks()`](../config/struct.Config.html#method.fault_hooks), e.g. because
+    /// there are too many hooks, or with the fallback backend.
+    FaultHook,
+    /// Recovering from faults at all, which is not supported on the target (e.g. WebAssembly).
+    Unsupported,
+    /// Selecting the backend, which is not available in this process.
+    Backend(Backend),
+}
+
+impl fmt::Display for RegisterStep {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RegisterStep::Sigaction(sig) => write!(f, "sigaction() for signal {}", sig),
+            RegisterStep::SignalMask => f.write_str("pthread_sigmask()"),
+            RegisterStep::Sigaltstack => f.write_str("sigaltstack()"),
+            RegisterStep::Registry => f.write_str("signal-hook-registry registration"),
+            RegisterStep::FaultHook => f.write_str("adding a fault hook"),
+            RegisterStep::Unsupported => f.write_str("fault recovery on this platform"),
+            RegisterStep::Backend(backend) => write!(f, "selecting the {} backend", backend),
+        }
+    }
+}
+
+/// An error of registration for bulletproof memory access: the failed step and the error number.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RegisterError {
+    step: RegisterStep,
+    errno: c_int,
+}
+
+impl RegisterError {
+    /// Creates an error of `step` failed with the error number `errno`.
+    #[inline]
+    pub fn new(step: RegisterStep, errno: c_int) -> Self {
+        Self { step, errno }
+    }
+
+    /// Returns the failed step.
+    #[inline]
+    pub fn step(&self) -> RegisterStep {
+        self.step
+    }
+
+    /// Returns the error number (`errno`), or 0 if unknown.
+    #[inline]
+    pub fn errno(&self) -> c_int {
+        self.errno
+    }
+
+    /// Returns the error number as an `io::Error`.
+    #[inline]
+    pub fn os_error(&self) -> io::Error {
+        io::Error::from_raw_os_error(self.errno)
+    }
+}
+
+impl fmt::Display for RegisterError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.errno == 0 {
+            return write!(f, "{} failed", self.step);
+        }
+        write!(f, "{} failed: {}", self.step, self.os_error())
+    }
+}
+
+impl Error for RegisterError {}
+
+/// An error of
+/// [`Bulletproof::verify_installed()`](../struct.Bulletproof.html#method.verify_installed): the
+/// handler of a signal was replaced since it was installed, e.g. by a profiler or a crash reporter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct HandlerConflict {
+    sig: c_int,
+    handler: usize,
+    last_seen: Instant,
+    detected: Instant,
+}
+
+impl HandlerConflict {
+    /// Creates an error of the handler of `sig` replaced by `handler`, seen installed at
+    /// `last_seen` but not at `detected`.
+    #[inline]
+    pub fn new(sig: c_int, handler: usize, last_seen: Instant, detected: Instant) -> Self {
+        Self { sig, handler, last_seen, detected }
+    }
+
+    /// Returns the signal whose handler was replaced.
+    #[inline]
+    pub fn sig(&self) -> c_int {
+        self.sig
+    }
+
+    /// Returns the current handler (the `sa_sigaction` field), e.g. `SIG_DFL`.
+    #[inline]
+    pub fn handler(&self) -> usize {
+        self.handler
+    }
+
+    /// Returns when the handler was last seen installed: at installation, or at the last successful
+    /// check. It was replaced between then and [`detected()`](#method.detected).
+    #[inline]
+    pub fn last_seen(&self) -> Instant {
+        self.last_seen
+    }
+
+    /// Returns when the replacement was detected.
+    #[inline]
+    pub fn detected(&self) -> Instant {
+        self.detected
+    }
+
+    /// Returns the length of the window in which the handler was replaced.
+    #[inline]
+    pub fn window(&self) -> Duration {
+        self.detected.saturating_duration_since(self.last_seen)
+    }
+}
+
+impl fmt::Display for HandlerConflict {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "handler of signal {} replaced by ", self.sig)?;
+        // `SIG_DFL` and `SIG_IGN` on every Unix.
+        match self.handler {
+            0 => f.write_str("SIG_DFL")?,
+            1 => f.write_str("SIG_IGN")?,
+            handler => write!(f, "{:#x}", handler)?,
+        }
+        write!(f, " within {:?} before the check", self.window())
+    }
+}
+
+impl Error for HandlerConflict {}
+
+/// An error of [`Bulletproof::self_test()`](../struct.Bulletproof.html#method.self_test): the check
+/// that failed, and the fault it got.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SelfTestError {
+    check: &'static str,
+    fault: Option<Fault>,
+}
+
+impl SelfTestError {
+    /// Creates an error of `check` getting `fault` (`None` if no fault was recovered).
+    #[inline]
+    pub fn new(check: &'static str, fault: Option<Fault>) -> Self {
+        Self { check, fault }
+    }
+
+    /// Returns the description of the failed check.
+    #[inline]
+    pub fn check(&self) -> &'static str {
+        self.check
+    }
+
+    /// Returns the fault the check got, or `None` if no fault was recovered.
+    #[inline]
+    pub fn fault(&self) -> Option<Fault> {
+        self.fault
+    }
+}
+
+impl fmt::Display for SelfTestError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.fault {
+            Some(fault) => write!(f, "self test failed: {}: got {}", self.check, fault),
+            None => write!(f, "self test failed: {}: got no fault", self.check),
+        }
+    }
+}
+
+impl Error for SelfTestError {}
+
+/// An error of [`Bulletproof::copy_chunked()`](../struct.Bulletproof.html#method.copy_chunked).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CopyError {
+    /// A chunk faulted, with the recovery
+    /// [`Recovery::Abort`](../config/enum.Recovery.html#variant.Abort).
+    Fault {
+        /// The offset of the chunk in the source.
+        offset: usize,
+        /// The fault of the chunk.
+        fault: Fault,
+    },
+    /// The copy was cancelled.
+    Cancelled {
+        /// The number of bytes copied before the cancellation.
+        copied: usize,
+    },
+}
+
+impl fmt::Display for CopyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CopyError::Fault { offset, fault } => {
+                write!(f, "copy failed at offset {:#x}: {}", offset, fault)
+            }
+            CopyError::Cancelled { copied } => write!(f, "copy cancelled after {} bytes", copied),
+        }
+    }
+}
+
+impl Error for CopyError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            CopyError::Fault { ref fault, .. } => Some(fault),
+            CopyError::Cancelled { .. } => None,
+        }
+    }
+}
+
+/// An error of [`Bulletproof::load_validated()`](../struct.Bulletproof.html#method.load_validated).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LoadError {
+    /// The location is invalid.
+    Fault(Fault),
+    /// The value was loaded, but the validation rejected it.
+    Invalid,
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LoadError::Fault(fault) => write!(f, "load failed: {}", fault),
+            LoadError::Invalid => f.write_str("loaded value rejected by the validation"),
+        }
+    }
+}
+
+impl Error for LoadError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            LoadError::Fault(ref fault) => Some(fault),
+            LoadError::Invalid => None,
+        }
+    }
+}
+
+impl From<Fault> for LoadError {
+    #[inline]
+    fn from(fault: Fault) -> Self {
+        LoadError::Fault(fault)
+    }
+}
+
+/// An error of [`Watchdog::bound()`](../watchdog/struct.Watchdog.html#method.bound).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TimeoutError {
+    /// The access faulted.
+    Fault(Fault),
+    /// The access did not complete in time.
+    TimedOut,
+}
+
+impl fmt::Display for TimeoutError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TimeoutError::Fault(fault) => write!(f, "access failed: {}", fault),
+            TimeoutError::TimedOut => f.write_str("access timed out"),
+        }
+    }
+}
+
+impl Error for TimeoutError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            TimeoutError::Fault(ref fault) => Some(fault),
+            TimeoutError::TimedOut => None,
+        }
+    }
+}
+
+impl From<Fault> for TimeoutError {
+    #[inline]
+    fn from(fault: Fault) -> Self {
+        TimeoutError::Fault(fault)
+    }
+}
+
+/// An error of [`Bulletproof::store()`](../struct.Bulletproof.html#method.store): the fault, and
+/// the permissions of the page if it is mapped but could not be written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct StoreError {
+    fault: Fault,
+    perms: Option<[u8; 4]>,
+}
+
+impl StoreError {
+    /// Creates an error of a store that raised `fault` on a page with the permissions `perms`
+    /// (e.g. `r--p`, as in `/proc/self/maps`).
+    #[inline]
+    pub fn new(fault: Fault, perms: Option<[u8; 4]>) -> Self {
+        Self { fault, perms }
+    }
+
+    /// Returns the fault of the store.
+    #[inline]
+    pub fn fault(&self) -> Fault {
+        self.fault
+    }
+
+    /// Returns the permissions of the page, e.g. `r--p`.
+    ///
+    /// It is `Some` only for `SEGV_ACCERR` faults on the platforms with
+    /// [`snapshot::regions()`](../snapshot/fn.regions.html), if the page is still mapped. The
+    /// caller may then decide whether to `mprotect()` the page and retry, e.g. not if it is a
+    /// guard page (`---p`) or shared read-only (`r--s`).
+    #[inline]
+    pub fn perms(&self) -> Option<[u8; 4]> {
+        self.perms
+    }
+}
+
+impl fmt::Display for StoreError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "store failed: {}", self.fault)?;
+        if let Some(perms) = self.perms {
+            write!(f, " (page {})", String::from_utf8_lossy(&perms))?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for StoreError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.fault)
+    }
+}
+
+impl From<Fault> for StoreError {
+    #[inline]
+    fn from(fault: Fault) -> Self {
+        Self::new(fault, None)
+    }
+}
+
+/// An error of [`Bulletproof::store_fields()`](../struct.Bulletproof.html#method.store_fields): the
+/// index of the faulting field, and the fault.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct StoreFieldsFault {
+    index: usize,
+    fault: Fault,
+}
+
+impl StoreFieldsFault {
+    /// Creates an error of a scatter write whose field at `index` raised `fault`.
+    #[inline]
+    pub fn new(index: usize, fault: Fault) -> Self {
+        Self { index, fault }
+    }
+
+    /// Returns the index of the faulting field. The fields before it were written.
+    #[inline]
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Returns the fault.
+    #[inline]
+    pub fn fault(&self) -> Fault {
+        self.fault
+    }
+}
+
+impl fmt::Display for StoreFieldsFault {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "store of field {} failed: {}", self.index, self.fault)
+    }
+}
+
+impl Error for StoreFieldsFault {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.fault)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use libc;
+
+    use super::*;
+
+    #[test]
+    fn display() {
+        let error = RegisterError::new(RegisterStep::Sigaction(libc::SIGSEGV), libc::EINVAL);
+        let message = error.to_string();
+        assert!(message.starts_with(&format!("sigaction() for signal {} failed: ", libc::SIGSEGV)));
+        assert_eq!(error.os_error().raw_os_error(), Some(libc::EINVAL));
+
+        let error = RegisterError::new(RegisterStep::Registry, 0);
+        assert_eq!(error.to_string(), "signal-hook-registry registration failed");
+
+        let error = RegisterError::new(RegisterStep::Unsupported, 0);
+        assert_eq!(error.to_string(), "fault recovery on this platform failed");
+
+        let error = RegisterError::new(RegisterStep::Backend(Backend::Mincore), 0);
+        assert_eq!(error.to_string(), "selecting the mincore backend failed");
+
+        let now = Instant::now();
+        let error = HandlerConflict::new(libc::SIGSEGV, 0, now, now + Duration::from_secs(1));
+        let message = format!("handler of signal {} replaced by SIG_DFL within 1s", libc::SIGSEGV);
+        assert_eq!(error.to_string(), message + " before the check");
+
+        let error = SelfTestError::new("load from the null page", None);
+        assert_eq!(error.to_string(), "self test failed: load from the null page: got no fault");
+
+        let error = CopyError::Cancelled { copied: 4096 };
+        assert_eq!(error.to_string(), "copy cancelled after 4096 bytes");
+
+        let error = LoadError::Invalid;
+        assert_eq!(error.to_string(), "loaded value rejected by the validation");
+
+        let fault = Fault::Segv { addr: 0x1000, code: ::fault::SEGV_ACCERR };
+        let error = StoreError::new(fault, Some(*b"r--p"));
+        assert_eq!(error.to_string(), format!("store failed: {} (page r--p)", fault));
+    }
+}
@@ -0,0 +1,351 @@
+//! The fallback backend, for tools under which the signal trick does not work.
+//!
+//! Miri does not support signals or foreign functions, and sanitizers (ASan, TSan) report the
+//! intentional faults as errors. This backend installs no handlers, and performs plain accesses.
+//! It only rejects accesses to the first page (including null pointers), with the `asan` feature,
+//! to the memory poisoned by AddressSanitizer, and, with the `testing` feature, to the poisoned
+//! ranges. Other invalid accesses are undefined behavior, and are expected to be reported by the
+//! tool itself.
+//!
+//! It is selected automatically under Miri, and can be selected with `--cfg bulletproof_fallback`
+//! in `RUSTFLAGS`, e.g. for a sanitizer build:
+//!
+//! ```text
+//! RUSTFLAGS="-Zsanitizer=address --cfg bulletproof_fallback" cargo +nightly test
+//! ```
+
+use std::cell::Cell;
+use std::io;
+use std::ops::Range;
+use std::sync::atomic::{AtomicU8, Ordering};
+#[cfg(feature = "tracing")]
+use std::time::Duration;
+use std::{cmp, mem, ptr};
+
+use config::Config;
+use error::{HandlerConflict, RegisterError};
+use fault::{Fault, Registers, SEGV_MAPERR};
+use hook::HookFn;
+use sys::{self, c_int, c_void, siginfo_t, size_t};
+
+/// The lowest valid address. No platform maps the first page by default.
+const MIN_ADDRESS: usize = 4096;
+
+thread_local! {
+    /// The last rejected access of the current thread.
+    static LAST_FAULT: Cell<Fault> = const {
+        Cell::new(Fault::Segv { addr: 0, code: SEGV_MAPERR })
+    };
+}
+
+/// Checks if `[start, start + size)` may be accessed, recording the fault if not.
+#[inline]
+fn valid(start: usize, size: usize) -> bool {
+    let in_range = start >= MIN_ADDRESS && start.checked_add(size).is_some();
+    if size == 0 || (in_range && asan_addressable(start, size)) {
+        return true;
+    }
+
+    let fault = Fault::Segv { addr: start, code: SEGV_MAPERR };
+    LAST_FAULT.with(|last| last.set(fault));
+    false
+}
+
+/// Checks if `[start, start + size)` is not poisoned according to ASan (`asan` feature).
+#[cfg(all(feature = "asan", not(any(miri, bulletproof_unsupported))))]
+#[inline]
+fn asan_addressable(start: usize, size: usize) -> bool {
+    extern "C" {
+        fn bulletproof_impl_asan_addressable(loc: *const c_void, size: size_t) -> c_int;
+    }
+
+    unsafe { bulletproof_impl_asan_addressable(start as *const c_void, size) != 0 }
+}
+
+#[cfg(not(all(feature = "asan", not(any(miri, bulletproof_unsupported)))))]
+#[inline(always)]
+fn asan_addressable(_start: usize, _size: usize) -> bool {
+    true
+}
+
+/// Does nothing, as no handlers are needed.
+#[inline]
+pub unsafe fn register(_config: &Config) -> Result<(), RegisterError> {
+    Ok(())
+}
+
+/// Does nothing, as no handlers are installed.
+#[inline]
+pub unsafe fn unregister() {}
+
+/// Does nothing, as no handlers are installed.
+#[inline]
+pub unsafe fn record_handlers(_config: &Config) {}
+
+/// Does nothing, as no handlers are installed.
+#[inline]
+pub fn forget_handlers() {}
+
+/// Returns `Ok(())`, as no handlers are installed.
+#[inline]
+pub fn verify_handlers() -> Result<(), HandlerConflict> {
+    Ok(())
+}
+
+/// Does nothing, as no handlers are needed.
+#[inline]
+pub unsafe fn register_thread() -> Result<(), RegisterError> {
+    Ok(())
+}
+
+/// Does nothing, as no handlers are needed.
+#[inline]
+pub fn set_altstack_size(_size: usize) {}
+
+/// Does nothing, as no handlers are needed. Returns 0.
+#[inline]
+pub unsafe fn reinit_after_fork() -> size_t {
+    0
+}
+
+/// Returns 0, as no fault is raised by bulletproof memory access.
+#[inline]
+pub unsafe fn handle_signal(_sig: c_int, _info: *mut siginfo_t, _ctx: *mut c_void) -> c_int {
+    0
+}
+
+/// Loads a word from `loc` to `dst`. Returns `SIGSEGV` if rejected, and 0 otherwise.
+#[inline]
+pub unsafe fn load_word(loc: *const usize, dst: *mut usize) -> c_int {
+    load_bytes(loc as *const c_void, dst as *mut c_void, mem::size_of::<usize>())
+}
+
+/// Stores a word `val` to `loc`. Returns `SIGSEGV` if rejected, and 0 otherwise.
+#[inline]
+pub unsafe fn store_word(loc: *mut usize, val: usize) -> c_int {
+    store_bytes(
+        loc as *mut c_void,
+        &val as *const usize as *const c_void,
+        mem::size_of::<usize>(),
+    )
+}
+
+/// Loads a `u64` from `loc` to `dst`. Returns `SIGSEGV` if rejected, and 0 otherwise.
+#[inline]
+pub unsafe fn load_u64(loc: *const u64, dst: *mut u64) -> c_int {
+    if !valid(loc as usize, mem::size_of::<u64>()) {
+        return sys::SIGSEGV;
+    }
+
+    *dst = ptr::read_volatile(loc);
+    0
+}
+
+/// Stores a `u64` `val` to `loc`. Returns `SIGSEGV` if rejected, and 0 otherwise.
+#[inline]
+pub unsafe fn store_u64(loc: *mut u64, val: u64) -> c_int {
+    if !valid(loc as usize, mem::size_of::<u64>()) {
+        return sys::SIGSEGV;
+    }
+
+    ptr::write_volatile(loc, val);
+    0
+}
+
+/// Loads `size` bytes from `loc` to `dst`. Returns `SIGSEGV` if rejected, and 0 otherwise.
+#[inline]
+pub unsafe fn load_bytes(loc: *const c_void, dst: *mut c_void, size: usize) -> c_int {
+    if !valid(loc as usize, size) {
+        return sys::SIGSEGV;
+    }
+
+    // Reading past the end of an object (e.g. of a NUL-terminated string up to the end of the page)
+    // may overlap `dst`.
+    ptr::copy(loc as *const u8, dst as *mut u8, size);
+    0
+}
+
+/// Loads `size` bytes from `loc` to `dst`, zero-filling the bytes of rejected pages. Returns the
+/// number of zero-filled bytes.
+pub unsafe fn load_bytes_zero_fill(
+    loc: *const u8,
+    dst: *mut u8,
+    size: usize,
+    page_size: usize,
+) -> usize {
+    let mut zero_filled = 0;
+    let mut offset = 0;
+    while offset < size {
+        let chunk = cmp::min(page_size - (loc as usize + offset) % page_size, size - offset);
+        if valid(loc as usize + offset, chunk) {
+            ptr::copy_nonoverlapping(loc.wrapping_add(offset), dst.add(offset), chunk);
+        } else {
+            ptr::write_bytes(dst.add(offset), 0, chunk);
+            zero_filled += chunk;
+        }
+        offset += chunk;
+    }
+    zero_filled
+}
+
+/// Stores `size` bytes from `src` to `loc`. Returns `SIGSEGV` if rejected, and 0 otherwise.
+#[inline]
+pub unsafe fn store_bytes(loc: *mut c_void, src: *const c_void, size: usize) -> c_int {
+    if !valid(loc as usize, size) {
+        return sys::SIGSEGV;
+    }
+
+    ptr::copy_nonoverlapping(src as *const u8, loc as *mut u8, size);
+    0
+}
+
+/// Stores the leading bytes of `size` bytes from `src` to `loc`, up to the first rejected page.
+/// Returns the number of bytes written.
+pub unsafe fn store_bytes_prefix(
+    loc: *mut u8,
+    src: *const u8,
+    size: usize,
+    page_size: usize,
+) -> usize {
+    let mut offset = 0;
+    while offset < size {
+        let chunk = cmp::min(page_size - (loc as usize + offset) % page_size, size - offset);
+        if !valid(loc as usize + offset, chunk) {
+            break;
+        }
+        ptr::copy_nonoverlapping(src.add(offset), loc.add(offset), chunk);
+        offset += chunk;
+    }
+    offset
+}
+
+/// Loads `size` bytes from `loc` to `old`, and then stores `size` bytes from `src` to `loc`. Returns
+/// `SIGSEGV` if rejected, and 0 otherwise.
+#[inline]
+pub unsafe fn exchange_bytes(
+    loc: *mut c_void,
+    src: *const c_void,
+    old: *mut c_void,
+    size: usize,
+) -> c_int {
+    if !valid(loc as usize, size) {
+        return sys::SIGSEGV;
+    }
+
+    ptr::copy_nonoverlapping(loc as *const u8, old as *mut u8, size);
+    ptr::copy_nonoverlapping(src as *const u8, loc as *mut u8, size);
+    0
+}
+
+/// Copies `size` bytes from `src` to `dst`, which may overlap. Returns `SIGSEGV` if rejected, and 0
+/// otherwise.
+#[inline]
+pub unsafe fn move_bytes(src: *const c_void, dst: *mut c_void, size: usize) -> c_int {
+    if !valid(src as usize, size) || !valid(dst as usize, size) {
+        return sys::SIGSEGV;
+    }
+
+    ptr::copy(src as *const u8, dst as *mut u8, size);
+    0
+}
+
+/// Stores the indexes of the `words` words from `loc` that are in `heap` to `out`, and their number
+/// to `found`. Returns `SIGSEGV` if rejected, and 0 otherwise.
+pub unsafe fn scan(
+    loc: *const usize,
+    words: usize,
+    heap: &Range<usize>,
+    out: *mut usize,
+    found: &mut usize,
+) -> c_int {
+    if !valid(loc as usize, words * mem::size_of::<usize>()) {
+        return sys::SIGSEGV;
+    }
+
+    let mut n = 0;
+    for i in 0..words {
+        if heap.contains(&*loc.add(i)) {
+            *out.add(n) = i;
+            n += 1;
+        }
+    }
+    *found = n;
+    0
+}
+
+/// Calls `f(data)`. Returns 0, as faults cannot be recovered.
+#[inline]
+pub unsafe fn protect(f: unsafe extern "C" fn(*mut c_void), data: *mut c_void) -> c_int {
+    f(data);
+    0
+}
+
+/// Sets the bits of the readable (and writable, if not null) pages of `pages` pages from `start`.
+/// The pages at addresses that are not rejected are assumed to be readable and writable.
+pub unsafe fn probe_range(
+    start: *const u8,
+    page_size: usize,
+    pages: usize,
+    readable: *mut u8,
+    writable: *mut u8,
+) {
+    for i in 0..pages {
+        let page = start as usize + i * page_size;
+        if page < MIN_ADDRESS {
+            continue;
+        }
+
+        ptr::read_volatile(page as *const u8);
+        *readable.add(i / 8) |= 1 << (i % 8);
+
+        if !writable.is_null() {
+            (*(page as *const AtomicU8)).fetch_add(0, Ordering::Relaxed);
+            *writable.add(i / 8) |= 1 << (i % 8);
+        }
+    }
+}
+
+/// Returns an error, as faults cannot be resolved without a handler.
+#[inline]
+pub unsafe fn add_hook(_range: &Range<usize>, _f: HookFn, _data: *mut c_void) -> io::Result<c_int> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "fault hooks are not supported by the fallback backend",
+    ))
+}
+
+/// Does nothing, as no hooks are added.
+#[inline]
+pub unsafe fn remove_hook(_slot: c_int) {}
+
+/// Does nothing, as Miri does not run machine code. Sanitizer builds on targets whose instruction
+/// cache is not coherent with the data cache (e.g. AArch64) should flush it themselves.
+#[cfg(unix)]
+#[inline]
+pub unsafe fn flush_icache(_start: *mut c_void, _len: usize) {}
+
+/// Returns the last access rejected on the current thread.
+#[inline]
+pub fn last_fault() -> Fault {
+    LAST_FAULT.with(|last| last.get())
+}
+
+/// Returns `None`, as no fault signal is handled.
+#[inline]
+pub fn last_registers() -> Option<Registers> {
+    None
+}
+
+/// Returns `None`, as the fallback backend does not decode signal contexts.
+#[cfg(unix)]
+#[inline]
+pub unsafe fn context_registers(_ctx: *mut c_void) -> Option<Registers> {
+    None
+}
+
+/// Returns zero, as rejected accesses are not recovered from a fault.
+#[cfg(feature = "tracing")]
+#[inline]
+pub fn last_fault_elapsed() -> Duration {
+    Duration::from_secs(0)
+}
@@ -0,0 +1,155 @@
+//! Faults.
+
+use std::error::Error;
+use std::fmt;
+
+use super::backend;
+use sys::{self, c_int};
+
+/// `si_code` of `SIGSEGV` for an address not mapped to an object.
+pub const SEGV_MAPERR: c_int = 1;
+/// `si_code` of `SIGSEGV` for invalid permissions for a mapped object.
+pub const SEGV_ACCERR: c_int = 2;
+/// `si_code` of `SIGBUS` for a nonexistent physical address, e.g. past the end of a file.
+pub const BUS_ADRERR: c_int = 2;
+
+/// A fault recovered by bulletproof memory access.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Fault {
+    /// Segmentation fault (`SIGSEGV`): the address is not mapped, or the access is not permitted.
+    Segv {
+        /// The faulting address.
+        addr: usize,
+        /// The signal code, e.g. `SEGV_MAPERR` or `SEGV_ACCERR`.
+        code: c_int,
+    },
+    /// Bus error (`SIGBUS`): e.g. the address is past the end of a truncated file mapping.
+    Bus {
+        /// The faulting address.
+        addr: usize,
+        /// The signal code, e.g. `BUS_ADRERR`.
+        code: c_int,
+    },
+    /// Segmentation fault (`SIGSEGV`) raised by fetching an instruction: the program counter itself
+    /// is not mapped or not executable, e.g. after a call through a garbage function pointer.
+    ///
+    /// It is told apart from [`Segv`](#variant.Segv) from the signal context on Linux (x86, x86-64,
+    /// ARM, AArch64, and RISC-V), macOS (x86-64 and AArch64), FreeBSD, OpenBSD, and NetBSD (x86,
+    /// x86-64, and AArch64), and illumos (x86 and x86-64), except with the `signal-hook` feature,
+    /// which does not pass the context.
+    ExecViolation {
+        /// The address of the instruction, i.e. the target of the jump or call.
+        addr: usize,
+        /// The signal code, e.g. `SEGV_MAPERR` or `SEGV_ACCERR`.
+        code: c_int,
+    },
+}
+
+impl Fault {
+    /// Creates a fault from a signal number, a signal code, and a faulting address.
+    #[inline]
+    pub fn from_signal(signal: c_int, code: c_int, addr: usize) -> Self {
+        if signal == sys::SIGBUS {
+            Fault::Bus { addr, code }
+        } else {
+            Fault::Segv { addr, code }
+        }
+    }
+
+    /// Returns the signal number.
+    #[inline]
+    pub fn signal(&self) -> c_int {
+        match *self {
+            Fault::Segv { .. } | Fault::ExecViolation { .. } => sys::SIGSEGV,
+            Fault::Bus { .. } => sys::SIGBUS,
+        }
+    }
+
+    /// Returns the signal code.
+    #[inline]
+    pub fn code(&self) -> c_int {
+        match *self {
+            Fault::Segv { code, .. } | Fault::Bus { code, .. } => code,
+            Fault::ExecViolation { code, .. } => code,
+        }
+    }
+
+    /// Returns the faulting address.
+    #[inline]
+    pub fn addr(&self) -> usize {
+        match *self {
+            Fault::Segv { addr, .. } | Fault::Bus { addr, .. } => addr,
+            Fault::ExecViolation { addr, .. } => addr,
+        }
+    }
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Fault::Segv { addr, .. } => write!(f, "segmentation fault at {:#x}", addr),
+            Fault::Bus { addr, .. } => write!(f, "bus error at {:#x}", addr),
+            Fault::ExecViolation { addr, .. } => {
+                write!(f, "segmentation fault executing {:#x}", addr)
+            }
+        }
+    }
+}
+
+impl Error for Fault {}
+
+/// Registers at a fault, e.g. for crash analysis or the deoptimization of JIT-compiled code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Registers {
+    /// The program counter, i.e. the address of the faulting instruction.
+    pub pc: usize,
+    /// The stack pointer.
+    pub sp: usize,
+    /// The frame pointer (e.g. `rbp` on x86-64, and `x29` on AArch64).
+    pub fp: usize,
+}
+
+/// Returns the registers at the last fault signal handled on the current thread.
+///
+/// It may be called after an access returned a fault, or in a fault hook (see
+/// [`Config::fault_hooks()`](../config/struct.Config.html#method.fault_hooks)), for the fault
+/// being handled. The register holding the faulting address is not derived, as it would require
+/// decoding the instruction; the address itself is in the fault.
+///
+/// Returns `None` if they are unknown: with the fallback backend, with the `signal-hook` feature,
+/// which does not pass the signal context, and on platforms other than those listed for
+/// [`Fault::ExecViolation`](enum.Fault.html#variant.ExecViolation). Faults injected by the
+/// `testing` module are not signals, and do not update them.
+#[inline]
+pub fn last_registers() -> Option<Registers> {
+    backend::last_registers()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ptr;
+
+    use Bulletproof;
+
+    #[test]
+    #[cfg_attr(any(miri, bulletproof_fallback), ignore)]
+    fn last_registers() {
+        unsafe {
+            let bulletproof = Bulletproof::new();
+            assert!(bulletproof.load_usize(ptr::null()).is_err());
+        }
+
+        let registers = super::last_registers();
+        if cfg!(feature = "signal-hook") {
+            assert_eq!(registers, None);
+            return;
+        }
+
+        // The fault is deeper in the current stack.
+        let local = 0usize;
+        let here = &local as *const usize as usize;
+        let registers = registers.unwrap();
+        assert_ne!(registers.pc, 0);
+        assert!(registers.sp < here && here - registers.sp < 1 << 20);
+    }
+}
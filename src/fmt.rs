@@ -0,0 +1,173 @@
+//! Formatting of possibly-invalid memory.
+//!
+//! # Examples
+//!
+//! ```
+//! use bulletproof::{fmt, Bulletproof};
+//! use std::ptr;
+//!
+//! let hello = b"Hello, world!";
+//!
+//! unsafe {
+//!     let bulletproof = Bulletproof::new();
+//!
+//!     let dump = fmt::hexdump(bulletproof, hello.as_ptr(), hello.len());
+//!     assert!(dump.ends_with(
+//!         "48 65 6c 6c 6f 2c 20 77  6f 72 6c 64 21           |Hello, world!|\n",
+//!     ));
+//!
+//!     let dump = fmt::hexdump(bulletproof, ptr::null(), 4);
+//!     assert!(dump.ends_with("?? ?? ?? ??                                       |????|\n"));
+//! }
+//! ```
+
+use std::fmt;
+use std::mem;
+
+use super::Bulletproof;
+
+/// The number of bytes per line.
+const LINE: usize = 16;
+
+/// A [`Display`](https://doc.rust-lang.org/stable/std/fmt/trait.Display.html) adapter rendering a
+/// classic hex and ASCII dump of possibly-invalid memory.
+///
+/// Each line shows the address, up to 16 bytes in hex, and the bytes in ASCII (`.` for
+/// non-printable bytes). Unreadable bytes are shown as `??` in hex, and `?` in ASCII.
+#[derive(Debug, Clone, Copy)]
+pub struct HexDump {
+    bulletproof: Bulletproof,
+    start: *const u8,
+    len: usize,
+}
+
+impl HexDump {
+    /// Creates a dump of `[start, start + len)`.
+    ///
+    /// # Safety
+    ///
+    /// Reading the range should have no side effects, e.g. the range should not contain device
+    /// memory. The range is read whenever the dump is formatted.
+    #[inline]
+    pub unsafe fn new(bulletproof: Bulletproof, start: *const u8, len: usize) -> Self {
+        Self {
+            bulletproof,
+            start,
+            len,
+        }
+    }
+
+    /// Reads `len` bytes at `addr` into `buf`, and returns which bytes are readable.
+    fn read(&self, addr: usize, buf: &mut [u8; LINE], len: usize) -> [bool; LINE] {
+        unsafe {
+            // Try the whole line at once, and then byte by byte.
+            if len == LINE {
+                if let Ok(line) = self.bulletproof.load::<[u8; LINE]>(addr as *const _) {
+                    *buf = line;
+                    return [true; LINE];
+                }
+            }
+
+            let mut readable = [false; LINE];
+            for i in 0..len {
+                if let Ok(byte) = self.bulletproof.load((addr + i) as *const u8) {
+                    buf[i] = byte;
+                    readable[i] = true;
+                }
+            }
+            readable
+        }
+    }
+}
+
+impl fmt::Display for HexDump {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let width = 2 * mem::size_of::<usize>();
+        let start = self.start as usize;
+
+        for offset in (0..self.len).step_by(LINE) {
+            let addr = start.wrapping_add(offset);
+            let len = LINE.min(self.len - offset);
+            let mut buf = [0u8; LINE];
+            let readable = self.read(addr, &mut buf, len);
+
+            write!(f, "{:0width$x}  ", addr, width = width)?;
+            for i in 0..LINE {
+                if i == LINE / 2 {
+                    f.write_str(" ")?;
+                }
+                if i >= len {
+                    f.write_str("   ")?;
+                } else if readable[i] {
+                    write!(f, "{:02x} ", buf[i])?;
+                } else {
+                    f.write_str("?? ")?;
+                }
+            }
+
+            f.write_str(" |")?;
+            for i in 0..len {
+                let c = match buf[i] {
+                    _ if !readable[i] => '?',
+                    b @ 0x20..=0x7e => b as char,
+                    _ => '.',
+                };
+                write!(f, "{}", c)?;
+            }
+            f.write_str("|\n")?;
+        }
+        Ok(())
+    }
+}
+
+/// Renders a hex and ASCII dump of `[start, start + len)`. See [`HexDump`](struct.HexDump.html).
+///
+/// # Safety
+///
+/// The same as [`HexDump::new()`](struct.HexDump.html#method.new).
+#[inline]
+pub unsafe fn hexdump(bulletproof: Bulletproof, start: *const u8, len: usize) -> String {
+    HexDump::new(bulletproof, start, len).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ptr;
+
+    use libc;
+
+    use super::*;
+
+    #[test]
+    #[cfg_attr(any(miri, bulletproof_fallback), ignore)]
+    fn partially_readable() {
+        unsafe {
+            let bulletproof = Bulletproof::new();
+            let page_size = libc::sysconf(libc::_SC_PAGESIZE) as usize;
+            let map = libc::mmap(
+                ptr::null_mut(),
+                2 * page_size,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                -1,
+                0,
+            );
+            assert_ne!(map, libc::MAP_FAILED);
+            let boundary = map as usize + page_size;
+            libc::mprotect(boundary as *mut _, page_size, libc::PROT_NONE);
+            ptr::write_bytes((boundary - 4) as *mut u8, b'A', 4);
+
+            let dump = hexdump(bulletproof, (boundary - 4) as *const u8, 20);
+            let lines = dump.lines().collect::<Vec<_>>();
+            assert_eq!(lines.len(), 2);
+            assert!(lines[0].ends_with(
+                "41 41 41 41 ?? ?? ?? ??  ?? ?? ?? ?? ?? ?? ?? ??  |AAAA????????????|",
+            ));
+            assert!(lines[1].ends_with(
+                "?? ?? ?? ??                                       |????|",
+            ));
+
+            libc::munmap(map, 2 * page_size);
+        }
+    }
+}
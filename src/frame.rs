@@ -0,0 +1,232 @@
+//! Length-prefixed, checksummed frames in possibly-invalid memory.
+//!
+//! Mailbox protocols between a host and a plugin (or another process) typically exchange frames of
+//! a header, which holds the length of the payload and its checksum, followed by the payload. If
+//! the peer crashed in the middle of writing a frame, the header may hold garbage, and the payload
+//! may run into unmapped memory.
+//! [`Bulletproof::load_frame()`](../struct.Bulletproof.html#method.load_frame) reads the header,
+//! checks the length against a maximum before copying the payload, and then checks its CRC-32.
+//!
+//! # Examples
+//!
+//! ```
+//! use bulletproof::frame::{self, FrameError, FrameLayout};
+//! use bulletproof::Bulletproof;
+//!
+//! // The default layout: the length and the CRC-32 of the payload as little-endian `u32`s.
+//! let payload = b"hello";
+//! let mut mailbox = Vec::new();
+//! mailbox.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+//! mailbox.extend_from_slice(&frame::crc32(payload).to_le_bytes());
+//! mailbox.extend_from_slice(payload);
+//!
+//! unsafe {
+//!     let bulletproof = Bulletproof::new();
+//!     let layout = FrameLayout::default();
+//!
+//!     assert_eq!(bulletproof.load_frame(mailbox.as_ptr(), &layout, 64), Ok(payload.to_vec()));
+//!     let error = bulletproof.load_frame(mailbox.as_ptr(), &layout, 4).unwrap_err();
+//!     assert_eq!(error, FrameError::TooLong(5));
+//!
+//!     mailbox[8] = b'j';
+//!     let error = bulletproof.load_frame(mailbox.as_ptr(), &layout, 64).unwrap_err();
+//!     assert!(matches!(error, FrameError::Checksum { .. }));
+//! }
+//! ```
+
+use std::error::Error;
+use std::fmt;
+
+use super::{Bulletproof, Fault};
+
+/// The table of the CRC-32 of each byte.
+static CRC32_TABLE: [u32; 256] = crc32_table();
+
+const fn crc32_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xedb8_8320 } else { crc >> 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+/// Returns the CRC-32 (IEEE 802.3, as of zlib) of `bytes`.
+pub fn crc32(bytes: &[u8]) -> u32 {
+    !bytes.iter().fold(!0u32, |crc, &byte| {
+        CRC32_TABLE[((crc ^ byte as u32) & 0xff) as usize] ^ (crc >> 8)
+    })
+}
+
+/// The layout of the header of a frame.
+///
+/// The length and the checksum are `u32`s at the given offsets in the header, and the payload
+/// follows the header. The default is a header of 8 bytes: the length and then the checksum, in
+/// little-endian byte order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FrameLayout {
+    /// The size of the header in bytes.
+    pub header: usize,
+    /// The offset of the length of the payload in the header.
+    pub length: usize,
+    /// The offset of the CRC-32 of the payload in the header.
+    pub checksum: usize,
+    /// Whether the length and the checksum are big-endian.
+    pub big_endian: bool,
+}
+
+impl Default for FrameLayout {
+    fn default() -> Self {
+        Self {
+            header: 8,
+            length: 0,
+            checksum: 4,
+            big_endian: false,
+        }
+    }
+}
+
+impl FrameLayout {
+    /// Returns the `u32` at `offset` of `header`.
+    fn field(&self, header: &[u8], offset: usize) -> u32 {
+        let mut bytes = [0u8; 4];
+        bytes.copy_from_slice(&header[offset..offset + 4]);
+        if self.big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        }
+    }
+}
+
+/// An error of [`Bulletproof::load_frame()`](../struct.Bulletproof.html#method.load_frame).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FrameError {
+    /// The header or the payload is invalid.
+    Fault(Fault),
+    /// The length in the header exceeds the maximum.
+    TooLong(usize),
+    /// The checksum of the payload does not match the header.
+    Checksum {
+        /// The checksum in the header.
+        expected: u32,
+        /// The checksum of the payload.
+        actual: u32,
+    },
+}
+
+impl fmt::Display for FrameError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            FrameError::Fault(fault) => write!(f, "frame load failed: {}", fault),
+            FrameError::TooLong(len) => write!(f, "frame of {} bytes exceeds the maximum", len),
+            FrameError::Checksum { expected, actual } => write!(
+                f,
+                "frame checksum mismatch: expected {:#010x}, got {:#010x}",
+                expected, actual
+            ),
+        }
+    }
+}
+
+impl Error for FrameError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            FrameError::Fault(ref fault) => Some(fault),
+            _ => None,
+        }
+    }
+}
+
+impl From<Fault> for FrameError {
+    #[inline]
+    fn from(fault: Fault) -> Self {
+        FrameError::Fault(fault)
+    }
+}
+
+impl Bulletproof {
+    /// Loads the payload of the frame at the location, whose header has `layout` (see the
+    /// [`frame`](frame/index.html) module).
+    ///
+    /// The length in the header is checked against `max_len` before the payload is copied, and the
+    /// CRC-32 of the copy against the header afterwards.
+    ///
+    /// Returns `Ok(payload)` if the frame is valid, and `Err(error)` otherwise.
+    ///
+    /// # Safety
+    ///
+    /// Reading the frame should have no side effects, except that it can be invalid.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the length or the checksum is not inside the header.
+    pub unsafe fn load_frame(
+        self,
+        location: *const u8,
+        layout: &FrameLayout,
+        max_len: usize,
+    ) -> Result<Vec<u8>, FrameError> {
+        for &offset in &[layout.length, layout.checksum] {
+            let end = offset.checked_add(4);
+            assert!(end.is_some_and(|end| end <= layout.header), "field out of the header");
+        }
+
+        let header = self.load_vec::<u8>(location, layout.header)?;
+        let len = layout.field(&header, layout.length) as usize;
+        if len > max_len {
+            return Err(FrameError::TooLong(len));
+        }
+
+        let payload = self.load_vec::<u8>(location.wrapping_add(layout.header), len)?;
+        let (expected, actual) = (layout.field(&header, layout.checksum), crc32(&payload));
+        if expected != actual {
+            return Err(FrameError::Checksum { expected, actual });
+        }
+        Ok(payload)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ptr;
+
+    use super::*;
+
+    #[test]
+    fn crc32() {
+        assert_eq!(super::crc32(b""), 0);
+        assert_eq!(super::crc32(b"123456789"), 0xcbf4_3926);
+    }
+
+    #[test]
+    #[cfg_attr(any(miri, bulletproof_fallback), ignore)]
+    fn load_frame() {
+        // A big-endian header of the checksum, padding, and then the length.
+        let layout = FrameLayout { header: 12, length: 8, checksum: 0, big_endian: true };
+        let mut frame = vec![0u8; 12];
+        frame[..4].copy_from_slice(&super::crc32(b"abc").to_be_bytes());
+        frame[8..12].copy_from_slice(&3u32.to_be_bytes());
+        frame.extend_from_slice(b"abc");
+
+        unsafe {
+            let bulletproof = Bulletproof::new();
+
+            assert_eq!(bulletproof.load_frame(frame.as_ptr(), &layout, 3), Ok(b"abc".to_vec()));
+            let error = bulletproof.load_frame(ptr::null(), &layout, 3).unwrap_err();
+            assert!(matches!(error, FrameError::Fault(_)));
+
+            // A garbage length is not followed.
+            frame[8..12].copy_from_slice(&u32::MAX.to_be_bytes());
+            let error = bulletproof.load_frame(frame.as_ptr(), &layout, 1 << 20).unwrap_err();
+            assert_eq!(error, FrameError::TooLong(u32::MAX as usize));
+        }
+    }
+}
@@ -0,0 +1,295 @@
+//! Allocations surrounded by guard pages.
+//!
+//! Like Electric Fence, [`GuardedBox`](struct.GuardedBox.html) and
+//! [`GuardedVec`](struct.GuardedVec.html) map each allocation on its own pages between two
+//! inaccessible guard pages, and place it at the end of its pages. An access running past the end
+//! (or, beyond the slack at the beginning of the pages, before the start) trips the guard page
+//! instead of silently corrupting a neighbor. In a protected section, it is reported as a
+//! [`Fault`](../fault/enum.Fault.html), which [`overrun()`](struct.GuardedBox.html#method.overrun)
+//! attributes to the allocation.
+//!
+//! Each allocation costs at least three pages of address space and a system call, so they are
+//! meant for debugging, or for buffers shared with untrusted code.
+//!
+//! # Examples
+//!
+//! ```
+//! use bulletproof::guarded::{GuardedVec, Overrun};
+//! use bulletproof::Bulletproof;
+//!
+//! let buf = GuardedVec::from_elem(0u8, 10).unwrap();
+//!
+//! # #[cfg(not(bulletproof_fallback))]
+//! unsafe {
+//!     let bulletproof = Bulletproof::new();
+//!
+//!     let fault = bulletproof.load(buf.as_ptr().add(10)).unwrap_err();
+//!     assert_eq!(buf.overrun(&fault), Some(Overrun::Overflow));
+//! }
+//! ```
+
+use std::marker::PhantomData;
+use std::ops::{Deref, DerefMut};
+use std::{io, mem, ptr, slice};
+
+use libc::{self, c_void};
+
+use super::{probe, Fault};
+
+/// Which guard page an access hit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Overrun {
+    /// The guard page before the allocation.
+    Underflow,
+    /// The guard page after the allocation.
+    Overflow,
+}
+
+/// The mapping of an allocation, with a guard page on each side.
+#[derive(Debug)]
+struct Region {
+    map: *mut u8,
+    len: usize,
+    page_size: usize,
+}
+
+impl Region {
+    /// Maps pages for `size` bytes aligned to `align`, between two guard pages. Returns the region,
+    /// and the address of the allocation at the end of its pages.
+    fn new(size: usize, align: usize) -> io::Result<(Self, *mut u8)> {
+        let page_size = probe::page_size();
+        assert!(align <= page_size, "alignment greater than the page size");
+
+        let data_len = size.div_ceil(page_size).max(1) * page_size;
+        let len = data_len + 2 * page_size;
+        let map = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                len,
+                libc::PROT_NONE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                -1,
+                0,
+            )
+        };
+        if map == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+
+        let region = Self {
+            map: map as *mut u8,
+            len,
+            page_size,
+        };
+        let data = unsafe { region.map.add(page_size) };
+        let prot = libc::PROT_READ | libc::PROT_WRITE;
+        if unsafe { libc::mprotect(data as *mut c_void, data_len, prot) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        // Rounded down for alignment, which leaves a gap of less than `align` bytes.
+        let end = data as usize + data_len;
+        let start = (end - size) & !(align - 1);
+        Ok((region, start as *mut u8))
+    }
+
+    fn overrun(&self, addr: usize) -> Option<Overrun> {
+        let start = self.map as usize;
+        let end = start + self.len;
+        if start <= addr && addr < start + self.page_size {
+            Some(Overrun::Underflow)
+        } else if end - self.page_size <= addr && addr < end {
+            Some(Overrun::Overflow)
+        } else {
+            None
+        }
+    }
+}
+
+impl Drop for Region {
+    fn drop(&mut self) {
+        unsafe {
+            libc::munmap(self.map as *mut c_void, self.len);
+        }
+    }
+}
+
+/// A box whose value is surrounded by guard pages.
+#[derive(Debug)]
+pub struct GuardedBox<T> {
+    region: Region,
+    value: *mut T,
+    _marker: PhantomData<T>,
+}
+
+unsafe impl<T: Send> Send for GuardedBox<T> {}
+unsafe impl<T: Sync> Sync for GuardedBox<T> {}
+
+impl<T> GuardedBox<T> {
+    /// Allocates `value` between guard pages.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the pages cannot be mapped.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the alignment of `T` is greater than the page size.
+    pub fn new(value: T) -> io::Result<Self> {
+        let (region, ptr) = Region::new(mem::size_of::<T>(), mem::align_of::<T>())?;
+        let value_ptr = ptr as *mut T;
+        unsafe { ptr::write(value_ptr, value) };
+        Ok(Self {
+            region,
+            value: value_ptr,
+            _marker: PhantomData,
+        })
+    }
+
+    /// Returns which guard page of the allocation `fault` hit, if any.
+    #[inline]
+    pub fn overrun(&self, fault: &Fault) -> Option<Overrun> {
+        self.region.overrun(fault.addr())
+    }
+}
+
+impl<T> Deref for GuardedBox<T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &T {
+        unsafe { &*self.value }
+    }
+}
+
+impl<T> DerefMut for GuardedBox<T> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.value }
+    }
+}
+
+impl<T> Drop for GuardedBox<T> {
+    fn drop(&mut self) {
+        unsafe { ptr::drop_in_place(self.value) }
+    }
+}
+
+/// A fixed-length vector whose elements are surrounded by guard pages.
+///
+/// The last element ends at the trailing guard page, so that reading or writing one element past
+/// the end faults.
+#[derive(Debug)]
+pub struct GuardedVec<T> {
+    region: Region,
+    ptr: *mut T,
+    len: usize,
+    _marker: PhantomData<T>,
+}
+
+unsafe impl<T: Send> Send for GuardedVec<T> {}
+unsafe impl<T: Sync> Sync for GuardedVec<T> {}
+
+impl<T> GuardedVec<T> {
+    /// Moves the elements of `vec` between guard pages.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the pages cannot be mapped.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the alignment of `T` is greater than the page size.
+    pub fn from_vec(mut vec: Vec<T>) -> io::Result<Self> {
+        let len = vec.len();
+        let (region, ptr) = Region::new(len * mem::size_of::<T>(), mem::align_of::<T>())?;
+        let ptr = ptr as *mut T;
+        unsafe {
+            ptr::copy_nonoverlapping(vec.as_ptr(), ptr, len);
+            vec.set_len(0);
+        }
+        Ok(Self {
+            region,
+            ptr,
+            len,
+            _marker: PhantomData,
+        })
+    }
+
+    /// Creates a vector of `len` clones of `elem` between guard pages.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the pages cannot be mapped.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the alignment of `T` is greater than the page size.
+    pub fn from_elem(elem: T, len: usize) -> io::Result<Self>
+    where
+        T: Clone,
+    {
+        Self::from_vec(vec![elem; len])
+    }
+
+    /// Returns which guard page of the allocation `fault` hit, if any.
+    #[inline]
+    pub fn overrun(&self, fault: &Fault) -> Option<Overrun> {
+        self.region.overrun(fault.addr())
+    }
+}
+
+impl<T> Deref for GuardedVec<T> {
+    type Target = [T];
+
+    #[inline]
+    fn deref(&self) -> &[T] {
+        unsafe { slice::from_raw_parts(self.ptr, self.len) }
+    }
+}
+
+impl<T> DerefMut for GuardedVec<T> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut [T] {
+        unsafe { slice::from_raw_parts_mut(self.ptr, self.len) }
+    }
+}
+
+impl<T> Drop for GuardedVec<T> {
+    fn drop(&mut self) {
+        unsafe { ptr::drop_in_place(ptr::slice_from_raw_parts_mut(self.ptr, self.len)) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::Bulletproof;
+
+    #[test]
+    #[cfg_attr(any(miri, bulletproof_fallback), ignore)]
+    fn overrun() {
+        unsafe {
+            let bulletproof = Bulletproof::new();
+
+            let mut value = GuardedBox::new([1u64; 3]).unwrap();
+            value[2] = 2;
+            assert_eq!(*value, [1, 1, 2]);
+            let end = (&*value as *const [u64; 3]).add(1) as *mut u64;
+            let fault = bulletproof.store(end, &0).unwrap_err().fault();
+            assert_eq!(value.overrun(&fault), Some(Overrun::Overflow));
+
+            // Beyond the slack before the allocation.
+            let page_size = probe::page_size();
+            let before = (value.region.map as usize + page_size - 1) as *const u8;
+            let fault = bulletproof.load(before).unwrap_err();
+            assert_eq!(value.overrun(&fault), Some(Overrun::Underflow));
+
+            let strings = GuardedVec::from_vec(vec![String::from("a"), String::from("b")]).unwrap();
+            assert_eq!(strings.concat(), "ab");
+            let fault = bulletproof.load(strings.as_ptr().add(2) as *const u8).unwrap_err();
+            assert_eq!(strings.overrun(&fault), Some(Overrun::Overflow));
+            assert_eq!(value.overrun(&fault), None);
+        }
+    }
+}
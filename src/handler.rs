@@ -0,0 +1,61 @@
+//! The raw signal handler, for hosts that own `sigaction` themselves.
+//!
+//! By default, [`Bulletproof::new()`](../struct.Bulletproof.html#method.new) installs its own
+//! `SIGSEGV` and `SIGBUS` handlers. Some hosts (e.g. language runtimes and crash reporters) insist
+//! on installing their own handlers. They can create the manager with
+//! [`Bulletproof::with_external_handler()`](../struct.Bulletproof.html#method.with_external_handler)
+//! instead, and forward faults to [`segv_handler()`](fn.segv_handler.html) from their handlers.
+//!
+//! # Examples
+//!
+//! ```
+//! # extern crate bulletproof;
+//! # extern crate libc;
+//! use bulletproof::{handler, Bulletproof};
+//! use libc::{c_int, c_void, siginfo_t};
+//! use std::{mem, ptr};
+//!
+//! extern "C" fn host_handler(sig: c_int, info: *mut siginfo_t, ctx: *mut c_void) {
+//!     unsafe {
+//!         if handler::segv_handler(sig, info, ctx) {
+//!             return;
+//!         }
+//!         // Not a bulletproof access: the host's own crash handling goes here.
+//!         libc::signal(sig, libc::SIG_DFL);
+//!     }
+//! }
+//!
+//! # fn main() {
+//! unsafe {
+//!     let mut action: libc::sigaction = mem::zeroed();
+//!     action.sa_sigaction = host_handler as usize;
+//!     action.sa_flags = libc::SA_SIGINFO;
+//!     libc::sigaction(libc::SIGSEGV, &action, ptr::null_mut());
+//!
+//!     let bulletproof = Bulletproof::with_external_handler();
+//!     assert!(bulletproof.load_usize(ptr::null()).is_err());
+//! }
+//! # }
+//! ```
+
+use super::backend;
+use sys::{c_int, c_void, siginfo_t};
+
+/// Handles a fault signal on behalf of the crate.
+///
+/// If the fault was raised by a bulletproof memory access of the current thread, this function
+/// does not return: it transfers control back to the access, which returns `Err`. Otherwise, it
+/// returns whether the fault was handled, in which case the handler should return to retry the
+/// faulting instruction. If it returns `false`, the fault is not the crate's business, and the host
+/// should handle it as usual.
+///
+/// It is async-signal-safe.
+///
+/// # Safety
+///
+/// It should be called only from a `SIGSEGV` or `SIGBUS` handler installed with `SA_SIGINFO`,
+/// passing the handler's arguments as they are.
+#[inline]
+pub unsafe fn segv_handler(sig: c_int, info: *mut siginfo_t, ctx: *mut c_void) -> bool {
+    backend::handle_signal(sig, info, ctx) != 0
+}
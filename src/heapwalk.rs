@@ -0,0 +1,154 @@
+//! Object-graph walking tolerant of bad pointers.
+//!
+//! Heap verification passes traverse the object graph from a root set, and should report broken
+//! edges instead of crashing on them. [`walk()`](fn.walk.html) performs the traversal, while a user
+//! callback reads the outgoing pointer fields of each object with bulletproof loads.
+//! [`Bulletproof::classify_words()`](../struct.Bulletproof.html#method.classify_words) counts the
+//! words of a region by class (e.g. null, heap pointer, or other), reporting the unreadable pages.
+//!
+//! # Examples
+//!
+//! ```
+//! use bulletproof::{heapwalk, Bulletproof};
+//!
+//! // A list of two nodes, whose last node points to an invalid address.
+//! let last = Box::new(8usize);
+//! let first = Box::new(&*last as *const usize as usize);
+//! let first = &*first as *const usize as usize;
+//!
+//! unsafe {
+//!     let bulletproof = Bulletproof::new();
+//!
+//!     let report = heapwalk::walk(bulletproof, vec![first], |bulletproof, object| {
+//!         bulletproof.load_usize(object as *const usize).map(|next| vec![next])
+//!     });
+//!     assert_eq!(report.reachable.len(), 2);
+//!     assert_eq!(report.broken.len(), 1);
+//!     assert_eq!(report.broken[0].to, 8);
+//! }
+//! ```
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use super::{Bulletproof, Fault};
+
+/// An edge whose target object could not be read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BrokenEdge {
+    /// The source object, or `None` for a root.
+    pub from: Option<usize>,
+    /// The target object.
+    pub to: usize,
+    /// The fault raised by reading the target object.
+    pub fault: Fault,
+}
+
+/// The result of walking an object graph.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Report {
+    /// The reachable objects whose fields were read successfully.
+    pub reachable: BTreeSet<usize>,
+    /// The edges whose target objects could not be read.
+    pub broken: Vec<BrokenEdge>,
+}
+
+impl Report {
+    /// Returns the objects of `objects` that are not reachable, e.g. the leaked objects of a heap.
+    pub fn unreachable<I>(&self, objects: I) -> Vec<usize>
+    where
+        I: IntoIterator<Item = usize>,
+    {
+        objects
+            .into_iter()
+            .filter(|object| !self.reachable.contains(object))
+            .collect()
+    }
+}
+
+/// The result of
+/// [`Bulletproof::classify_words()`](../struct.Bulletproof.html#method.classify_words).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Classification<C> {
+    /// The number of words of each class.
+    pub counts: BTreeMap<C, usize>,
+    /// The faults of the pages that could not be read, in ascending order of address.
+    pub faults: Vec<Fault>,
+}
+
+/// Walks the object graph from `roots`.
+///
+/// For each object, `fields(bulletproof, object)` should read the object with bulletproof loads,
+/// and return its outgoing pointers, or the fault raised by reading it. Null pointers are ignored.
+/// Each object is visited at most once, and the edges to objects that fail to be read are reported
+/// as broken.
+pub fn walk<I, F>(bulletproof: Bulletproof, roots: I, mut fields: F) -> Report
+where
+    I: IntoIterator<Item = usize>,
+    F: FnMut(Bulletproof, usize) -> Result<Vec<usize>, Fault>,
+{
+    let mut report = Report::default();
+    let mut faults = BTreeMap::new();
+    let mut stack = roots
+        .into_iter()
+        .map(|root| (None, root))
+        .collect::<Vec<_>>();
+
+    while let Some((from, object)) = stack.pop() {
+        if object == 0 || report.reachable.contains(&object) {
+            continue;
+        }
+
+        // Every edge to a broken object is reported, but the object is read only once.
+        let fault = match faults.get(&object) {
+            Some(&fault) => fault,
+            None => match fields(bulletproof, object) {
+                Ok(targets) => {
+                    report.reachable.insert(object);
+                    stack.extend(targets.into_iter().map(|target| (Some(object), target)));
+                    continue;
+                }
+                Err(fault) => {
+                    faults.insert(object, fault);
+                    fault
+                }
+            },
+        };
+        report.broken.push(BrokenEdge {
+            from,
+            to: object,
+            fault,
+        });
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{mem, ptr};
+
+    use super::*;
+
+    #[test]
+    fn cycles_and_broken_edges() {
+        // Two nodes pointing to each other, and to invalid addresses.
+        let mut nodes = vec![[0usize; 2]; 2];
+        let a = nodes.as_mut_ptr() as usize;
+        let b = a + mem::size_of::<[usize; 2]>();
+
+        unsafe {
+            let bulletproof = Bulletproof::new();
+            ptr::write(a as *mut [usize; 2], [b, 16]);
+            ptr::write(b as *mut [usize; 2], [a, 16]);
+
+            let report = walk(bulletproof, vec![a, 0], |bulletproof, object| {
+                bulletproof.load::<[usize; 2]>(object as *const _).map(|fields| fields.to_vec())
+            });
+            assert_eq!(report.reachable, vec![a, b].into_iter().collect());
+            let mut broken = report.broken.iter().map(|edge| edge.from).collect::<Vec<_>>();
+            broken.sort();
+            assert_eq!(broken, vec![Some(a), Some(b)]);
+            assert!(report.broken.iter().all(|edge| edge.to == 16));
+            assert_eq!(report.unreachable(vec![a, b, 42]), vec![42]);
+        }
+    }
+}
@@ -0,0 +1,97 @@
+//! Fault hooks, called by the handler on faults in registered address ranges.
+//!
+//! Subsystems that resolve faults rather than recover from them (e.g. the write barrier of
+//! [`tracking`](../tracking/index.html)) add a hook for their address range. The handler calls the
+//! hooks before checking for a protected section, and retries the faulting instruction if one of
+//! them handled the fault.
+//!
+//! The hooks of [`Config::fault_hooks()`](../config/struct.Config.html#method.fault_hooks) are
+//! added through the same table, by [`install()`](fn.install.html).
+
+use std::io;
+use std::mem;
+use std::ops::Range;
+use std::sync::Mutex;
+
+use super::backend;
+use config::FaultHook;
+use error::{RegisterError, RegisterStep};
+use fault::Fault;
+use sys::{c_int, c_void, siginfo_t};
+#[cfg(feature = "tracing")]
+use trace;
+
+/// A fault hook `f(data, sig, info)`. Returns nonzero if it handled the fault.
+pub(crate) type HookFn = unsafe extern "C" fn(*mut c_void, c_int, *const siginfo_t) -> c_int;
+
+/// A fault hook, removed on drop.
+#[derive(Debug)]
+pub(crate) struct Hook {
+    slot: c_int,
+}
+
+impl Hook {
+    /// Adds a hook `f(data, sig, info)` for faults at addresses in `range`.
+    ///
+    /// # Safety
+    ///
+    /// `f` should be async-signal-safe, and `data` should be valid until the hook is dropped.
+    pub(crate) unsafe fn add(
+        range: Range<usize>,
+        f: HookFn,
+        data: *mut c_void,
+    ) -> io::Result<Self> {
+        let slot = backend::add_hook(&range, f, data)?;
+        #[cfg(feature = "tracing")]
+        trace::hook_added(&range, slot);
+        Ok(Self { slot })
+    }
+}
+
+impl Drop for Hook {
+    fn drop(&mut self) {
+        // Waits for the handlers calling the hook, so that `data` can be freed afterwards.
+        unsafe { backend::remove_hook(self.slot) }
+        #[cfg(feature = "tracing")]
+        trace::hook_removed(self.slot);
+    }
+}
+
+/// The configured hooks added so far, which are never removed.
+static INSTALLED: Mutex<Vec<FaultHook>> = Mutex::new(Vec::new());
+
+/// Calls the configured hook function in `data`.
+unsafe extern "C" fn call_fault_hook(
+    data: *mut c_void,
+    sig: c_int,
+    info: *const siginfo_t,
+) -> c_int {
+    let f: fn(&Fault) -> bool = mem::transmute(data);
+    let fault = Fault::from_signal(sig, (*info).si_code, (*info).si_addr() as usize);
+    f(&fault) as c_int
+}
+
+/// Adds the configured `hooks` that have not been added yet.
+pub(crate) unsafe fn install(hooks: &[FaultHook]) -> Result<(), RegisterError> {
+    if hooks.is_empty() {
+        return Ok(());
+    }
+
+    let mut installed = INSTALLED.lock().unwrap();
+    for hook in hooks {
+        if installed.contains(hook) {
+            continue;
+        }
+
+        let data = hook.function() as *mut c_void;
+        match Hook::add(hook.range(), call_fault_hook, data) {
+            Ok(added) => mem::forget(added),
+            Err(error) => {
+                let errno = error.raw_os_error().unwrap_or(0);
+                return Err(RegisterError::new(RegisterStep::FaultHook, errno));
+            }
+        }
+        installed.push(hook.clone());
+    }
+    Ok(())
+}
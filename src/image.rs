@@ -0,0 +1,458 @@
+//! Identifying executable images in memory.
+//!
+//! Profilers resolve code addresses against the images loaded in memory, and should not crash on
+//! an address that is not the start of an image (or one that was unloaded in the meantime).
+//! [`identify_module()`](fn.identify_module.html) sniffs the ELF, Mach-O, or PE header at an
+//! address with bulletproof loads, and reports the entry point and segments of the image.
+//!
+//! # Examples
+//!
+//! ```
+//! use bulletproof::image::{self, Format};
+//! use bulletproof::Bulletproof;
+//!
+//! // A 64-bit little-endian ELF header with a single executable `PT_LOAD` segment.
+//! let mut elf = [0u8; 120];
+//! elf[..6].copy_from_slice(b"\x7fELF\x02\x01");
+//! elf[24..32].copy_from_slice(&0x1040u64.to_le_bytes()); // e_entry
+//! elf[32..40].copy_from_slice(&64u64.to_le_bytes()); // e_phoff
+//! elf[54..56].copy_from_slice(&56u16.to_le_bytes()); // e_phentsize
+//! elf[56..58].copy_from_slice(&1u16.to_le_bytes()); // e_phnum
+//! elf[64..68].copy_from_slice(&1u32.to_le_bytes()); // p_type
+//! elf[68..72].copy_from_slice(&5u32.to_le_bytes()); // p_flags
+//! elf[80..88].copy_from_slice(&0x1000u64.to_le_bytes()); // p_vaddr
+//! elf[104..112].copy_from_slice(&0x100u64.to_le_bytes()); // p_memsz
+//!
+//! unsafe {
+//!     let bulletproof = Bulletproof::new();
+//!
+//!     let base = elf.as_ptr() as usize;
+//!     let module = image::identify_module(bulletproof, elf.as_ptr()).unwrap();
+//!     assert_eq!(module.format, Format::Elf);
+//!     assert_eq!(module.entry, Some(base + 0x40));
+//!     assert_eq!(module.segments[0].range, base..base + 0x100);
+//!     assert!(module.segments[0].executable);
+//! }
+//! ```
+
+use std::error::Error;
+use std::fmt;
+use std::ops::Range;
+
+use super::{Bulletproof, Fault};
+
+/// The format of an image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// Executable and Linkable Format, of Linux and the BSDs.
+    Elf,
+    /// Mach-O, of macOS and iOS.
+    MachO,
+    /// Portable Executable, of Windows.
+    Pe,
+}
+
+/// A segment (or a PE section) of an image.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Segment {
+    /// The address range of the segment, relocated by the load bias of the image.
+    pub range: Range<usize>,
+    /// Whether the segment is mapped readable.
+    pub readable: bool,
+    /// Whether the segment is mapped writable.
+    pub writable: bool,
+    /// Whether the segment is mapped executable.
+    pub executable: bool,
+}
+
+/// The basic header information of an image in memory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Module {
+    /// The format of the image.
+    pub format: Format,
+    /// Whether the image is for a 64-bit target.
+    pub is_64bit: bool,
+    /// The difference between the addresses in memory and those in the headers, i.e. where
+    /// position-independent images were loaded.
+    pub bias: usize,
+    /// The address of the entry point, relocated by the load bias, if the image has one.
+    pub entry: Option<usize>,
+    /// The segments of the image, in the order of the headers.
+    pub segments: Vec<Segment>,
+}
+
+/// An error of identifying an image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageError {
+    /// The headers are not readable.
+    Fault(Fault),
+    /// There is no ELF, Mach-O, or PE magic at the address.
+    Unknown,
+    /// The headers are inconsistent.
+    Malformed(&'static str),
+}
+
+impl fmt::Display for ImageError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ImageError::Fault(ref fault) => write!(f, "cannot read image headers: {}", fault),
+            ImageError::Unknown => f.write_str("unknown image format"),
+            ImageError::Malformed(what) => write!(f, "malformed image headers: {}", what),
+        }
+    }
+}
+
+impl Error for ImageError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            ImageError::Fault(ref fault) => Some(fault),
+            _ => None,
+        }
+    }
+}
+
+impl From<Fault> for ImageError {
+    #[inline]
+    fn from(fault: Fault) -> Self {
+        ImageError::Fault(fault)
+    }
+}
+
+/// Loads the fields of headers at offsets from the start of an image.
+#[derive(Clone, Copy)]
+struct Header {
+    bulletproof: Bulletproof,
+    base: *const u8,
+    big_endian: bool,
+}
+
+impl Header {
+    unsafe fn u16(self, offset: usize) -> Result<u16, Fault> {
+        let location = self.base.wrapping_add(offset);
+        if self.big_endian {
+            self.bulletproof.load_u16_be(location)
+        } else {
+            self.bulletproof.load_u16_le(location)
+        }
+    }
+
+    unsafe fn u32(self, offset: usize) -> Result<u32, Fault> {
+        let location = self.base.wrapping_add(offset);
+        if self.big_endian {
+            self.bulletproof.load_u32_be(location)
+        } else {
+            self.bulletproof.load_u32_le(location)
+        }
+    }
+
+    unsafe fn u64(self, offset: usize) -> Result<u64, Fault> {
+        let location = self.base.wrapping_add(offset);
+        if self.big_endian {
+            self.bulletproof.load_u64_be(location)
+        } else {
+            self.bulletproof.load_u64_le(location)
+        }
+    }
+
+    /// Loads a 32- or 64-bit address as a `usize`.
+    unsafe fn addr(self, offset: usize, is_64bit: bool) -> Result<usize, Fault> {
+        if is_64bit {
+            Ok(self.u64(offset)? as usize)
+        } else {
+            Ok(self.u32(offset)? as usize)
+        }
+    }
+}
+
+/// Identifies the image whose headers start at `ptr`, and returns its basic header information.
+///
+/// The image should be loaded, i.e. its headers are at the start of its first segment as mapped by
+/// the loader. The load bias is computed from the segment containing the headers, so that the
+/// addresses of position-independent images are relocated.
+///
+/// For Mach-O, `__PAGEZERO` and other segments with no access are omitted, and the entry point is
+/// that of `LC_MAIN`.
+///
+/// # Safety
+///
+/// Reading the headers should have no side effects, except that they can be invalid.
+///
+/// # Errors
+///
+/// Returns `Err(ImageError::Fault(fault))` if the headers are not readable,
+/// `Err(ImageError::Unknown)` if there is no known magic at `ptr`, and `Err(ImageError::Malformed)`
+/// if the headers are inconsistent.
+pub unsafe fn identify_module(
+    bulletproof: Bulletproof,
+    ptr: *const u8,
+) -> Result<Module, ImageError> {
+    let header = Header {
+        bulletproof,
+        base: ptr,
+        big_endian: false,
+    };
+    let magic = header.u32(0)?;
+    match magic {
+        0x464c_457f => elf(header),
+        0xfeed_face | 0xfeed_facf => mach_o(header, magic == 0xfeed_facf),
+        0xcefa_edfe | 0xcffa_edfe => mach_o(
+            Header {
+                big_endian: true,
+                ..header
+            },
+            magic == 0xcffa_edfe,
+        ),
+        _ if magic & 0xffff == 0x5a4d => pe(header),
+        _ => Err(ImageError::Unknown),
+    }
+}
+
+/// Parses the headers of an ELF image.
+unsafe fn elf(header: Header) -> Result<Module, ImageError> {
+    const PT_LOAD: u32 = 1;
+    const PF_X: u32 = 1;
+    const PF_W: u32 = 2;
+    const PF_R: u32 = 4;
+
+    let ident = header.u16(4)?.to_le_bytes();
+    let is_64bit = match ident[0] {
+        1 => false,
+        2 => true,
+        _ => return Err(ImageError::Malformed("unknown ELF class")),
+    };
+    let header = match ident[1] {
+        1 => header,
+        2 => Header {
+            big_endian: true,
+            ..header
+        },
+        _ => return Err(ImageError::Malformed("unknown ELF data encoding")),
+    };
+
+    let (entry, phoff, phentsize, phnum) = if is_64bit {
+        (header.addr(24, true)?, header.addr(32, true)?, header.u16(54)?, header.u16(56)?)
+    } else {
+        (header.addr(24, false)?, header.addr(28, false)?, header.u16(42)?, header.u16(44)?)
+    };
+    if usize::from(phentsize) < if is_64bit { 56 } else { 32 } {
+        return Err(ImageError::Malformed("program headers too small"));
+    }
+    if phoff.checked_add(usize::from(phnum) * usize::from(phentsize)).is_none() {
+        return Err(ImageError::Malformed("program headers out of range"));
+    }
+
+    let mut bias = None;
+    let mut segments = Vec::new();
+    for i in 0..usize::from(phnum) {
+        let phdr = phoff + i * usize::from(phentsize);
+        if header.u32(phdr)? != PT_LOAD {
+            continue;
+        }
+        let (flags, offset, vaddr, memsz) = if is_64bit {
+            (
+                header.u32(phdr + 4)?,
+                header.addr(phdr + 8, true)?,
+                header.addr(phdr + 16, true)?,
+                header.addr(phdr + 40, true)?,
+            )
+        } else {
+            (
+                header.u32(phdr + 24)?,
+                header.addr(phdr + 4, false)?,
+                header.addr(phdr + 8, false)?,
+                header.addr(phdr + 20, false)?,
+            )
+        };
+
+        // The headers are at offset 0 of the first loadable segment.
+        let bias = *bias.get_or_insert_with(|| {
+            (header.base as usize).wrapping_sub(vaddr.wrapping_sub(offset))
+        });
+        let start = vaddr.wrapping_add(bias);
+        segments.push(Segment {
+            range: start..start.wrapping_add(memsz),
+            readable: flags & PF_R != 0,
+            writable: flags & PF_W != 0,
+            executable: flags & PF_X != 0,
+        });
+    }
+
+    let bias = bias.ok_or(ImageError::Malformed("no loadable segments"))?;
+    Ok(Module {
+        format: Format::Elf,
+        is_64bit,
+        bias,
+        entry: if entry == 0 { None } else { Some(entry.wrapping_add(bias)) },
+        segments,
+    })
+}
+
+/// Parses the headers of a Mach-O image.
+unsafe fn mach_o(header: Header, is_64bit: bool) -> Result<Module, ImageError> {
+    const LC_SEGMENT: u32 = 0x1;
+    const LC_SEGMENT_64: u32 = 0x19;
+    const LC_MAIN: u32 = 0x8000_0028;
+    const VM_PROT_READ: u32 = 1;
+    const VM_PROT_WRITE: u32 = 2;
+    const VM_PROT_EXECUTE: u32 = 4;
+
+    let ncmds = header.u32(16)? as usize;
+    let sizeofcmds = header.u32(20)? as usize;
+    let mut cmd = if is_64bit { 32 } else { 28 };
+    let end = sizeofcmds.saturating_add(cmd);
+
+    let mut bias = None;
+    let mut entryoff = None;
+    // The segments with their addresses in the headers, and the initial protection.
+    let mut segments = Vec::new();
+    for _ in 0..ncmds {
+        if cmd + 8 > end {
+            return Err(ImageError::Malformed("load commands overrun sizeofcmds"));
+        }
+        let kind = header.u32(cmd)?;
+        let cmdsize = header.u32(cmd + 4)? as usize;
+        if cmdsize < 8 || cmdsize > end - cmd {
+            return Err(ImageError::Malformed("bad load command size"));
+        }
+
+        if kind == LC_SEGMENT || kind == LC_SEGMENT_64 {
+            let is_64bit = kind == LC_SEGMENT_64;
+            let (vmaddr, vmsize, fileoff, filesize, initprot) = if is_64bit {
+                (
+                    header.addr(cmd + 24, true)?,
+                    header.addr(cmd + 32, true)?,
+                    header.addr(cmd + 40, true)?,
+                    header.addr(cmd + 48, true)?,
+                    header.u32(cmd + 60)?,
+                )
+            } else {
+                (
+                    header.addr(cmd + 24, false)?,
+                    header.addr(cmd + 28, false)?,
+                    header.addr(cmd + 32, false)?,
+                    header.addr(cmd + 36, false)?,
+                    header.u32(cmd + 44)?,
+                )
+            };
+            // The headers are at offset 0 of `__TEXT`.
+            if fileoff == 0 && filesize != 0 && bias.is_none() {
+                bias = Some((header.base as usize).wrapping_sub(vmaddr));
+            }
+            if initprot != 0 {
+                segments.push((vmaddr, vmsize, initprot));
+            }
+        } else if kind == LC_MAIN {
+            entryoff = Some(header.u64(cmd + 8)? as usize);
+        }
+        cmd += cmdsize;
+    }
+
+    let bias = bias.ok_or(ImageError::Malformed("no segment maps the headers"))?;
+    Ok(Module {
+        format: Format::MachO,
+        is_64bit,
+        bias,
+        entry: entryoff.map(|entryoff| (header.base as usize).wrapping_add(entryoff)),
+        segments: segments
+            .into_iter()
+            .map(|(vmaddr, vmsize, prot)| {
+                let start = vmaddr.wrapping_add(bias);
+                Segment {
+                    range: start..start.wrapping_add(vmsize),
+                    readable: prot & VM_PROT_READ != 0,
+                    writable: prot & VM_PROT_WRITE != 0,
+                    executable: prot & VM_PROT_EXECUTE != 0,
+                }
+            })
+            .collect(),
+    })
+}
+
+/// Parses the headers of a PE image.
+unsafe fn pe(header: Header) -> Result<Module, ImageError> {
+    const IMAGE_SCN_MEM_EXECUTE: u32 = 0x2000_0000;
+    const IMAGE_SCN_MEM_READ: u32 = 0x4000_0000;
+    const IMAGE_SCN_MEM_WRITE: u32 = 0x8000_0000;
+
+    // `e_lfanew` of the MS-DOS header points to the signature.
+    let signature = header.u32(0x3c)? as usize;
+    if signature > 0x1000_0000 {
+        return Err(ImageError::Malformed("e_lfanew out of range"));
+    }
+    if header.u32(signature)? != 0x0000_4550 {
+        return Err(ImageError::Unknown);
+    }
+    let coff = signature + 4;
+    let nsections = usize::from(header.u16(coff + 2)?);
+    let optional = coff + 20;
+    let optional_size = usize::from(header.u16(coff + 16)?);
+
+    let (is_64bit, image_base) = match header.u16(optional)? {
+        0x10b if optional_size >= 96 => (false, header.addr(optional + 28, false)?),
+        0x20b if optional_size >= 112 => (true, header.addr(optional + 24, true)?),
+        _ => return Err(ImageError::Malformed("unknown optional header")),
+    };
+    let entry = header.u32(optional + 16)? as usize;
+    let base = header.base as usize;
+
+    let mut segments = Vec::with_capacity(nsections);
+    for i in 0..nsections {
+        let section = optional + optional_size + i * 40;
+        let size = header.u32(section + 8)? as usize;
+        let start = base.wrapping_add(header.u32(section + 12)? as usize);
+        let flags = header.u32(section + 36)?;
+        segments.push(Segment {
+            range: start..start.wrapping_add(size),
+            readable: flags & IMAGE_SCN_MEM_READ != 0,
+            writable: flags & IMAGE_SCN_MEM_WRITE != 0,
+            executable: flags & IMAGE_SCN_MEM_EXECUTE != 0,
+        });
+    }
+
+    Ok(Module {
+        format: Format::Pe,
+        is_64bit,
+        bias: base.wrapping_sub(image_base),
+        entry: if entry == 0 { None } else { Some(base.wrapping_add(entry)) },
+        segments,
+    })
+}
+
+#[cfg(all(test, any(target_os = "linux", target_os = "macos")))]
+mod tests {
+    use std::{mem, ptr};
+
+    use super::*;
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn current_image() {
+        unsafe {
+            let bulletproof = Bulletproof::new();
+
+            // The image of this crate, which is linked into the test executable.
+            let function = identify_module as *const () as usize;
+            let mut info = mem::zeroed::<libc::Dl_info>();
+            assert_ne!(libc::dladdr(function as *const libc::c_void, &mut info), 0);
+
+            let base = info.dli_fbase as *const u8;
+            let module = identify_module(bulletproof, base).unwrap();
+            let format = if cfg!(target_os = "linux") { Format::Elf } else { Format::MachO };
+            assert_eq!(module.format, format);
+            assert_eq!(module.is_64bit, mem::size_of::<usize>() == 8);
+            assert!(module
+                .segments
+                .iter()
+                .any(|segment| segment.executable && segment.range.contains(&function)));
+
+            let garbage = [0x42u8; 64];
+            assert_eq!(identify_module(bulletproof, garbage.as_ptr()), Err(ImageError::Unknown));
+            if !cfg!(bulletproof_fallback) {
+                assert!(matches!(
+                    identify_module(bulletproof, ptr::null()),
+                    Err(ImageError::Fault(_))
+                ));
+            }
+        }
+    }
+}
@@ -1,44 +1,813 @@
+// For the register indices of `ucontext_t` (e.g. `REG_RIP`) on Linux.
+#ifndef _GNU_SOURCE
+#define _GNU_SOURCE
+#endif
+// For the register indices of `ucontext_t` (e.g. `REG_PC`) on illumos.
+#if defined(__sun) && !defined(__EXTENSIONS__)
+#define __EXTENSIONS__
+#endif
+
+#include <errno.h>
 #include <signal.h>
+#include <pthread.h>
+#include <sched.h>
 #include <setjmp.h>
 #include <stddef.h>
+#include <stdint.h>
 #include <memory.h>
+#include <sys/mman.h>
+#include <time.h>
+// OpenBSD has no `<ucontext.h>`; its `ucontext_t` is `struct sigcontext` from `<signal.h>`.
+#ifndef __OpenBSD__
+#include <ucontext.h>
+#endif
+#include <unistd.h>
+
+#include "asan.h"
+#include "valgrind.h"
+
+// Calling environment for recovering from the fault of the innermost protected section, or `NULL`
+// if the current thread is not in a protected section.  `__thread` for thread-safe handling of
+// sigsegv.
+static __thread sigjmp_buf *volatile current = NULL;
+
+// The last fault recovered by the current thread.
+static __thread struct {
+  int sig;
+  int code;
+  void *addr;
+  int exec;
+#ifdef BULLETPROOF_TRACING
+  // When the fault was raised (`CLOCK_MONOTONIC`), for the recovery time reported by `tracing`.
+  struct timespec time;
+#endif
+} last_fault;
+
+// Records the fault to be recovered.  `exec` is nonzero if it was raised by an instruction fetch.
+static void bulletproof_record_fault(int sig, int code, void *addr, int exec) {
+  last_fault.sig = sig;
+  last_fault.code = code;
+  last_fault.addr = addr;
+  last_fault.exec = exec;
+#ifdef BULLETPROOF_TRACING
+  clock_gettime(CLOCK_MONOTONIC, &last_fault.time);
+#endif
+}
+
+// Registers at a fault.
+struct bulletproof_regs {
+  void *pc;
+  void *sp;
+  void *fp;
+};
+
+// The registers at the last fault signal handled by the current thread, if `valid` is nonzero.
+static __thread struct {
+  int valid;
+  struct bulletproof_regs regs;
+} last_regs;
+
+// Stores the registers at the fault from the handler's context to `regs`.  Returns 0 if they are
+// unknown on this platform, and 1 otherwise.
+//
+// It is the only architecture-specific part of the shim: recovery only relies on `siglongjmp()`, and
+// does not rewrite the program counter or consult fixup tables.
+static int bulletproof_fault_regs(void *ctx, struct bulletproof_regs *regs) {
+  ucontext_t *uc = ctx;
+  if (uc == NULL) {
+    return 0;
+  }
+#if defined(__linux__) && defined(__x86_64__)
+  regs->pc = (void *) uc->uc_mcontext.gregs[REG_RIP];
+  regs->sp = (void *) uc->uc_mcontext.gregs[REG_RSP];
+  regs->fp = (void *) uc->uc_mcontext.gregs[REG_RBP];
+#elif defined(__linux__) && defined(__i386__)
+  regs->pc = (void *) uc->uc_mcontext.gregs[REG_EIP];
+  regs->sp = (void *) uc->uc_mcontext.gregs[REG_ESP];
+  regs->fp = (void *) uc->uc_mcontext.gregs[REG_EBP];
+#elif defined(__linux__) && defined(__aarch64__)
+  regs->pc = (void *) uc->uc_mcontext.pc;
+  regs->sp = (void *) uc->uc_mcontext.sp;
+  regs->fp = (void *) uc->uc_mcontext.regs[29];
+#elif defined(__linux__) && defined(__arm__)
+  regs->pc = (void *) uc->uc_mcontext.arm_pc;
+  regs->sp = (void *) uc->uc_mcontext.arm_sp;
+  regs->fp = (void *) uc->uc_mcontext.arm_fp;
+#elif defined(__linux__) && defined(__riscv)
+  regs->pc = (void *) uc->uc_mcontext.__gregs[REG_PC];
+  regs->sp = (void *) uc->uc_mcontext.__gregs[REG_SP];
+  regs->fp = (void *) uc->uc_mcontext.__gregs[REG_S0];
+#elif defined(__APPLE__) && defined(__x86_64__)
+  regs->pc = (void *) uc->uc_mcontext->__ss.__rip;
+  regs->sp = (void *) uc->uc_mcontext->__ss.__rsp;
+  regs->fp = (void *) uc->uc_mcontext->__ss.__rbp;
+#elif defined(__APPLE__) && (defined(__aarch64__) || defined(__arm64__))
+  regs->pc = (void *) uc->uc_mcontext->__ss.__pc;
+  regs->sp = (void *) uc->uc_mcontext->__ss.__sp;
+  regs->fp = (void *) uc->uc_mcontext->__ss.__fp;
+#elif defined(__FreeBSD__) && defined(__x86_64__)
+  regs->pc = (void *) uc->uc_mcontext.mc_rip;
+  regs->sp = (void *) uc->uc_mcontext.mc_rsp;
+  regs->fp = (void *) uc->uc_mcontext.mc_rbp;
+#elif defined(__FreeBSD__) && defined(__i386__)
+  regs->pc = (void *) uc->uc_mcontext.mc_eip;
+  regs->sp = (void *) uc->uc_mcontext.mc_esp;
+  regs->fp = (void *) uc->uc_mcontext.mc_ebp;
+#elif defined(__FreeBSD__) && defined(__aarch64__)
+  regs->pc = (void *) uc->uc_mcontext.mc_gpregs.gp_elr;
+  regs->sp = (void *) uc->uc_mcontext.mc_gpregs.gp_sp;
+  regs->fp = (void *) uc->uc_mcontext.mc_gpregs.gp_x[29];
+#elif defined(__NetBSD__) && defined(_UC_MACHINE_FP)
+  regs->pc = (void *) _UC_MACHINE_PC(uc);
+  regs->sp = (void *) _UC_MACHINE_SP(uc);
+  regs->fp = (void *) _UC_MACHINE_FP(uc);
+#elif defined(__OpenBSD__) && defined(__x86_64__)
+  regs->pc = (void *) uc->sc_rip;
+  regs->sp = (void *) uc->sc_rsp;
+  regs->fp = (void *) uc->sc_rbp;
+#elif defined(__OpenBSD__) && defined(__i386__)
+  regs->pc = (void *) uc->sc_eip;
+  regs->sp = (void *) uc->sc_esp;
+  regs->fp = (void *) uc->sc_ebp;
+#elif defined(__OpenBSD__) && defined(__aarch64__)
+  regs->pc = (void *) uc->sc_elr;
+  regs->sp = (void *) uc->sc_sp;
+  regs->fp = (void *) uc->sc_x[29];
+#elif defined(__sun) && (defined(__x86_64__) || defined(__i386__))
+  regs->pc = (void *) uc->uc_mcontext.gregs[REG_PC];
+  regs->sp = (void *) uc->uc_mcontext.gregs[REG_SP];
+  regs->fp = (void *) uc->uc_mcontext.gregs[REG_FP];
+#else
+  (void) regs;
+  return 0;
+#endif
+  return 1;
+}
+
+// Records the registers at the fault from the handler's context, or that they are unknown if `ctx`
+// is `NULL`.
+static void bulletproof_record_regs(void *ctx) {
+  last_regs.valid = bulletproof_fault_regs(ctx, &last_regs.regs);
+}
+
+// Returns 1 if the fault was raised by fetching the instruction at the faulting address, i.e. the
+// program counter itself is invalid (e.g. after a call through a garbage function pointer), and 0
+// otherwise or if it is unknown.  The registers should have been recorded.
+static int bulletproof_exec_fault(const siginfo_t *si) {
+  return last_regs.valid && last_regs.regs.pc == si->si_addr;
+}
+
+// Begins a protected section.  If a fault occurs inside it, the enclosing function returns the
+// signal number.
+//
+// Local variables modified inside the section should be `volatile` if they are read after a fault.
+#define PROTECT_BEGIN                               \
+  sigjmp_buf env;                                   \
+  sigjmp_buf *prev = current;                       \
+  int sig = sigsetjmp(env, bulletproof_savemask()); \
+  if (sig != 0) {                                   \
+    current = prev;                                 \
+    return sig;                                     \
+  }                                                 \
+  current = &env;                                   \
+  __atomic_signal_fence(__ATOMIC_SEQ_CST)
+
+// Ends the protected section begun by `PROTECT_BEGIN`.
+#define PROTECT_END                                 \
+  __atomic_signal_fence(__ATOMIC_SEQ_CST);          \
+  current = prev
+
+// Checks if `[loc, loc + size)` is addressable according to Valgrind (see `valgrind.h`) and ASan
+// (see `asan.h`).
+static inline int bulletproof_checked_addressable(const void *loc, size_t size) {
+  return bulletproof_addressable(loc, size) && bulletproof_asan_addressable(loc, size);
+}
+
+// Returns `SIGSEGV` from the enclosing function if `[loc, loc + size)` is not addressable according
+// to `bulletproof_checked_addressable()`.
+#define CHECK_ADDRESSABLE(loc, size)                                      \
+  do {                                                                    \
+    if (!bulletproof_checked_addressable((loc), (size))) {                \
+      bulletproof_record_fault(SIGSEGV, SEGV_MAPERR, (void *) (loc), 0);  \
+      return SIGSEGV;                                                     \
+    }                                                                     \
+  } while (0)
+
+// The fault signal to escape with from the escape signal's handler, or 0.  See
+// `bulletproof_impl_defer_signal()`.
+static __thread volatile sig_atomic_t pending = 0;
+
+// Whether the escape signal's handler is installed.
+static volatile sig_atomic_t escape_installed = 0;
+
+// The signal used to escape from handlers that cannot be longjmped out of.
+static int bulletproof_escape_signal() {
+#ifdef SIGRTMAX
+  return SIGRTMAX - 1;
+#else
+  return SIGUSR2;
+#endif
+}
+
+// Whether `bulletproof_impl_register()` has succeeded.  Consulted in forked children.
+static volatile sig_atomic_t registered = 0;
+
+// The signals whose handlers are installed (`CATCH_SEGV` and `CATCH_BUS`).  It only grows until
+// the handlers are uninstalled by `bulletproof_impl_unregister()`.
+#define CATCH_SEGV 1
+#define CATCH_BUS 2
+static volatile sig_atomic_t caught = 0;
+
+// Whether faults outside bulletproof memory access are passed to the previous handler, instead of
+// restoring the previous disposition and re-raising them.
+static volatile sig_atomic_t chain_prev = 0;
+
+// Whether protected sections save and restore the signal mask.  Saving it costs a system call per
+// section.  Not saving it is safe as long as the sections do not change the mask, as the handler
+// unblocks the fault signal before longjmping.
+static volatile sig_atomic_t save_sigmask = 1;
+
+// The `savemask` argument of `sigsetjmp()` for protected sections.  Escaping through the escape
+// signal relies on restoring the mask, so that the escape signal is unblocked again.
+static int bulletproof_savemask() {
+  return save_sigmask || escape_installed;
+}
+
+// Guards installation of the `pthread_atfork()` hooks.
+static pthread_once_t atfork_once = PTHREAD_ONCE_INIT;
+
+// The size of the alternate signal stacks of registered threads, or 0 not to manage them.
+static volatile size_t altstack_size = 64 * 1024;
+
+// The mapping of the alternate signal stack allocated for the current thread (including the guard
+// page), or `NULL`.
+static __thread char *altstack = NULL;
+static __thread size_t altstack_len = 0;
+
+// The key whose destructor frees the alternate signal stack on thread exit.
+static pthread_key_t altstack_key;
+static pthread_once_t altstack_key_once = PTHREAD_ONCE_INIT;
+
+// The steps of thread registration, reported on failure.
+#define STEP_SIGNAL_MASK 1
+#define STEP_SIGALTSTACK 2
+
+size_t bulletproof_impl_reinit_after_fork();
+
+// Re-registers in the child after `fork()`.
+static void bulletproof_atfork_child() {
+  bulletproof_impl_reinit_after_fork();
+}
+
+static void bulletproof_install_atfork() {
+  pthread_atfork(NULL, NULL, bulletproof_atfork_child);
+}
+
+// Whether the signal was sent by a process (e.g. with `kill()` or `raise()`) rather than by a
+// fault.  Returning from the handler does not raise it again.
+static int bulletproof_sent_by_process(const siginfo_t *si) {
+#if defined(__linux__) || defined(__sun)
+  return si->si_code <= 0;
+#elif defined(SI_LWP)
+  return si->si_code == SI_USER || si->si_code == SI_QUEUE || si->si_code == SI_LWP;
+#else
+  return si->si_code == SI_USER || si->si_code == SI_QUEUE;
+#endif
+}
+
+// The maximum number of fault hooks.
+#define MAX_HOOKS 64
+
+// The states of a fault hook's slot.
+#define HOOK_FREE 0
+#define HOOK_RESERVED 1
+#define HOOK_LIVE 2
+#define HOOK_REMOVING 3
+
+// A fault hook, called on faults at addresses in `[start, end)`.  It returns nonzero if it handled
+// the fault, in which case the faulting instruction is retried.  It should be async-signal-safe.
+typedef int (*bulletproof_hook_fn)(void *data, int sig, const siginfo_t *si);
+
+static struct {
+  int state;
+  // The number of handlers calling the hook.
+  int active;
+  size_t start;
+  size_t end;
+  bulletproof_hook_fn f;
+  void *data;
+} hooks[MAX_HOOKS];
+
+// Adds a fault hook `f(data, sig, si)` for faults at addresses in `[start, end)`.
+//
+// # Returns
+//
+// Return the hook's slot, or -1 if there are already `MAX_HOOKS` hooks.
+int bulletproof_impl_add_hook(size_t start, size_t end, bulletproof_hook_fn f, void *data) {
+  for (int i = 0; i < MAX_HOOKS; i++) {
+    int expected = HOOK_FREE;
+    if (!__atomic_compare_exchange_n(&hooks[i].state, &expected, HOOK_RESERVED, 0,
+                                     __ATOMIC_ACQUIRE, __ATOMIC_RELAXED)) {
+      continue;
+    }
+
+    hooks[i].start = start;
+    hooks[i].end = end;
+    hooks[i].f = f;
+    hooks[i].data = data;
+    __atomic_store_n(&hooks[i].state, HOOK_LIVE, __ATOMIC_RELEASE);
+    return i;
+  }
+  return -1;
+}
+
+// Removes the fault hook in `slot`, waiting for the handlers calling it to return.
+void bulletproof_impl_remove_hook(int slot) {
+  __atomic_store_n(&hooks[slot].state, HOOK_REMOVING, __ATOMIC_SEQ_CST);
+  while (__atomic_load_n(&hooks[slot].active, __ATOMIC_SEQ_CST) != 0) {
+    sched_yield();
+  }
+  __atomic_store_n(&hooks[slot].state, HOOK_FREE, __ATOMIC_RELEASE);
+}
+
+// Calls the fault hooks whose ranges contain the faulting address.  Returns nonzero if one of them
+// handled the fault.
+static int bulletproof_call_hooks(int sig, const siginfo_t *si) {
+  if (bulletproof_sent_by_process(si)) {
+    return 0;
+  }
+
+  size_t addr = (size_t) si->si_addr;
+  for (int i = 0; i < MAX_HOOKS; i++) {
+    if (__atomic_load_n(&hooks[i].state, __ATOMIC_ACQUIRE) != HOOK_LIVE) {
+      continue;
+    }
+
+    // Checked again after announcing the call, so that the hook is not removed while being called.
+    int handled = 0;
+    __atomic_fetch_add(&hooks[i].active, 1, __ATOMIC_SEQ_CST);
+    if (__atomic_load_n(&hooks[i].state, __ATOMIC_SEQ_CST) == HOOK_LIVE &&
+        hooks[i].start <= addr && addr < hooks[i].end) {
+      handled = hooks[i].f(hooks[i].data, sig, si);
+    }
+    __atomic_fetch_sub(&hooks[i].active, 1, __ATOMIC_SEQ_CST);
+
+    if (handled) {
+      return 1;
+    }
+  }
+  return 0;
+}
+
+// Handles a fault signal.
+//
+// If a fault hook handles the fault, returns 1.  Otherwise, if the current thread is in a protected
+// section, longjmps to its environment, passing the signal number.  Otherwise, returns 0.
+//
+// # Safety
+//
+// It is async-signal-safe.  It should be called only from a `SIGSEGV` or `SIGBUS` handler.
+int bulletproof_impl_handle_signal(int sig, siginfo_t *si, void *ctx) {
+  bulletproof_record_regs(ctx);
+  if (bulletproof_call_hooks(sig, si)) {
+    return 1;
+  }
+
+  sigjmp_buf *env = current;
+  if (env == NULL) {
+    return 0;
+  }
+
+  bulletproof_record_fault(sig, si->si_code, si->si_addr, bulletproof_exec_fault(si));
+
+  // The signal is blocked while its handler runs.  Unblocked here, as the protected section may not
+  // restore the signal mask (see `save_sigmask`).
+  sigset_t set;
+  sigemptyset(&set);
+  sigaddset(&set, sig);
+  pthread_sigmask(SIG_UNBLOCK, &set, NULL);
+
+  siglongjmp(*env, sig);
+}
+
+// The dispositions of `SIGSEGV` and `SIGBUS` before the crate's handlers were installed, restored on
+// foreign faults.  The default disposition (`SIG_DFL`) until saved.
+static struct sigaction prev_segv;
+static struct sigaction prev_bus;
+
+static struct sigaction *bulletproof_prev_action(int sig) {
+  return sig == SIGBUS ? &prev_bus : &prev_segv;
+}
+
+// Saves the disposition of `sig` to be restored on foreign faults.
+void bulletproof_impl_save_prev_action(int sig, const struct sigaction *act) {
+  *bulletproof_prev_action(sig) = *act;
+}
+
+// Handles a fault that did not originate from a bulletproof memory access: restores the previous
+// disposition, and re-raises the signal.  A fault is raised again by retrying the faulting
+// instruction once the handler returns, so only a signal sent by a process is raised explicitly.
+// Thus the crash is reported to the previous handler (e.g. a crash reporter) or the default action
+// (e.g. a core dump) as if the crate's handler were not installed.
+static void bulletproof_reraise(int sig, const siginfo_t *si) {
+  sigaction(sig, bulletproof_prev_action(sig), NULL);
+  if (bulletproof_sent_by_process(si)) {
+    raise(sig);
+  }
+}
 
-// Calling environment for recovering from segmentation fault.  `__thread` for thread-safe handling
-// of sigsegv.
-__thread sigjmp_buf jbuf;
+// Passes a fault that did not originate from a bulletproof memory access to the previous handler,
+// if chaining is enabled and there is one.  The faulting instruction is retried once it returns.
+// Otherwise, restores the previous disposition and re-raises the signal as `bulletproof_reraise()`.
+static void bulletproof_forward(int sig, siginfo_t *si, void *ctx) {
+  struct sigaction *prev = bulletproof_prev_action(sig);
+  if (chain_prev) {
+    if (prev->sa_flags & SA_SIGINFO) {
+      prev->sa_sigaction(sig, si, ctx);
+      return;
+    }
+    if (prev->sa_handler != SIG_DFL && prev->sa_handler != SIG_IGN) {
+      prev->sa_handler(sig);
+      return;
+    }
+  }
+  bulletproof_reraise(sig, si);
+}
 
-// Longjmps to the stored environment.
-static void bulletproof_handler(int sig __attribute__((unused)),
-                                siginfo_t *si __attribute__((unused)),
-                                void *unused __attribute__((unused))) {
-  siglongjmp(jbuf, 1);
+// The installed handler.  Faults outside protected sections are not ours: see
+// `bulletproof_forward()`.
+static void bulletproof_handler(int sig, siginfo_t *si, void *ctx) {
+  if (!bulletproof_impl_handle_signal(sig, si, ctx)) {
+    bulletproof_forward(sig, si, ctx);
+  }
 }
 
-// Installs the SIGSEGV handler.
-size_t bulletproof_register() {
+// Installs the handlers of the signals in `catch` (`CATCH_SEGV` and `CATCH_BUS`).
+//
+// # Returns
+//
+// If it fails, set `*failed_sig` to the signal whose handler could not be installed, and return
+// `errno`.  Otherwise, return 0.
+static int bulletproof_install_handler(int catch, int *failed_sig) {
+  static const int sigs[] = {SIGSEGV, SIGBUS};
+  static const int catches[] = {CATCH_SEGV, CATCH_BUS};
+
   struct sigaction new_handler;
-  new_handler.sa_flags = SA_SIGINFO;
+  new_handler.sa_flags = SA_SIGINFO | SA_ONSTACK;
   sigemptyset(&new_handler.sa_mask);
   new_handler.sa_sigaction = bulletproof_handler;
 
-  return sigaction(SIGSEGV, &new_handler, NULL);
+  for (size_t i = 0; i < sizeof(sigs) / sizeof(sigs[0]); i++) {
+    if (!(catch & catches[i])) {
+      continue;
+    }
+
+    struct sigaction old;
+    if (sigaction(sigs[i], &new_handler, &old) != 0) {
+      *failed_sig = sigs[i];
+      return errno;
+    }
+
+    // Do not save the handler itself when reinstalled.
+    if (old.sa_sigaction != bulletproof_handler) {
+      bulletproof_impl_save_prev_action(sigs[i], &old);
+    }
+    caught |= catches[i];
+  }
+  return 0;
+}
+
+// Installs the handlers of the signals in `catch` (`CATCH_SEGV` and `CATCH_BUS`), and the `fork()`
+// hooks that reinstall them in children.
+//
+// # Returns
+//
+// If it fails, set `*failed_sig` to the signal whose handler could not be installed, and return
+// `errno`.  Otherwise, return 0.
+int bulletproof_impl_register(int catch, int *failed_sig) {
+  int err = bulletproof_install_handler(catch, failed_sig);
+  if (err != 0) {
+    return err;
+  }
+
+  registered = 1;
+  pthread_once(&atfork_once, bulletproof_install_atfork);
+  return 0;
 }
 
-// Loads `size_t` from `loc`, and store it to `dst`.
+// Uninstalls the handlers, restoring the dispositions saved when they were installed.  A signal
+// whose handler was replaced since (e.g. by a crash reporter) is left alone.  The `fork()` hooks
+// stay installed, and do nothing until the handlers are installed again.
+void bulletproof_impl_unregister() {
+  static const int sigs[] = {SIGSEGV, SIGBUS};
+  static const int catches[] = {CATCH_SEGV, CATCH_BUS};
+
+  for (size_t i = 0; i < sizeof(sigs) / sizeof(sigs[0]); i++) {
+    if (!(caught & catches[i])) {
+      continue;
+    }
+
+    struct sigaction now;
+    if (sigaction(sigs[i], NULL, &now) == 0 && now.sa_sigaction == bulletproof_handler) {
+      sigaction(sigs[i], bulletproof_prev_action(sigs[i]), NULL);
+    }
+  }
+  caught = 0;
+  registered = 0;
+}
+
+// Sets whether faults outside bulletproof memory access are passed to the previous handler, and
+// whether protected sections save and restore the signal mask.
+void bulletproof_impl_configure(int chain, int save_mask) {
+  chain_prev = chain;
+  save_sigmask = save_mask;
+}
+
+// Sets the size of the alternate signal stacks allocated for threads registered afterwards.
+void bulletproof_impl_set_altstack_size(size_t size) {
+  altstack_size = size;
+}
+
+// Frees the alternate signal stack of the exiting thread.
+static void bulletproof_free_altstack(void *unused __attribute__((unused))) {
+  if (altstack == NULL) {
+    return;
+  }
+
+  // Disable it only if it is still in use: other code (e.g. the Rust runtime) may have replaced it.
+  stack_t old;
+  size_t page = (size_t) sysconf(_SC_PAGESIZE);
+  if (sigaltstack(NULL, &old) == 0 && old.ss_sp == altstack + page) {
+    stack_t disable;
+    memset(&disable, 0, sizeof(disable));
+    disable.ss_flags = SS_DISABLE;
+    sigaltstack(&disable, NULL);
+  }
+
+  munmap(altstack, altstack_len);
+  altstack = NULL;
+}
+
+static void bulletproof_create_altstack_key() {
+  pthread_key_create(&altstack_key, bulletproof_free_altstack);
+}
+
+// Installs an alternate signal stack for the current thread, unless it already has one that is
+// large enough.  The handlers run on it (`SA_ONSTACK`), so that faults raised when the regular stack
+// is exhausted can be recovered.  It is freed on thread exit.
+//
+// # Returns
+//
+// If it fails, return `errno`.  Otherwise, return 0.
+static int bulletproof_install_altstack() {
+  size_t size = altstack_size;
+  if (size == 0 || altstack != NULL) {
+    return 0;
+  }
+
+  stack_t old;
+  if (sigaltstack(NULL, &old) != 0) {
+    return errno;
+  }
+  if (!(old.ss_flags & SS_DISABLE) && old.ss_size >= size) {
+    return 0;
+  }
+
+  size_t page = (size_t) sysconf(_SC_PAGESIZE);
+  if (size < (size_t) MINSIGSTKSZ) {
+    size = MINSIGSTKSZ;
+  }
+  size = (size + page - 1) & ~(page - 1);
+
+  // The first page is a guard page, so that an overflow of the alternate stack faults.
+  char *map = mmap(NULL, page + size, PROT_READ | PROT_WRITE, MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+  if (map == MAP_FAILED) {
+    return errno;
+  }
+  mprotect(map, page, PROT_NONE);
+
+  stack_t new_stack;
+  memset(&new_stack, 0, sizeof(new_stack));
+  new_stack.ss_sp = map + page;
+  new_stack.ss_size = size;
+  if (sigaltstack(&new_stack, NULL) != 0) {
+    int err = errno;
+    munmap(map, page + size);
+    return err;
+  }
+
+  altstack = map;
+  altstack_len = page + size;
+  pthread_once(&altstack_key_once, bulletproof_create_altstack_key);
+  pthread_setspecific(altstack_key, map);
+  return 0;
+}
+
+// Unblocks the signals handled by the crate for the current thread.
+//
+// The signal mask is per-thread and inherited from the creating thread, so a thread may have
+// `SIGSEGV` or `SIGBUS` blocked.  A blocked fault signal is never delivered to the handler: the
+// kernel kills the process instead.
+//
+// # Returns
+//
+// If it fails, return the error number of `pthread_sigmask()`.  Otherwise, return 0.
+static int bulletproof_unblock_signals() {
+  sigset_t set;
+  sigemptyset(&set);
+  sigaddset(&set, SIGSEGV);
+  sigaddset(&set, SIGBUS);
+  if (escape_installed) {
+    sigaddset(&set, bulletproof_escape_signal());
+  }
+
+  return pthread_sigmask(SIG_UNBLOCK, &set, NULL);
+}
+
+// Prepares the current thread for bulletproof memory access: unblocks the signals, and installs an
+// alternate signal stack.
+//
+// # Returns
+//
+// If it fails, set `*failed_step` to the failed step (`STEP_SIGNAL_MASK` or `STEP_SIGALTSTACK`),
+// and return the error number.  Otherwise, return 0.
+int bulletproof_impl_register_thread(int *failed_step) {
+  int err = bulletproof_unblock_signals();
+  if (err != 0) {
+    *failed_step = STEP_SIGNAL_MASK;
+    return err;
+  }
+
+  err = bulletproof_install_altstack();
+  if (err != 0) {
+    *failed_step = STEP_SIGALTSTACK;
+    return err;
+  }
+  return 0;
+}
+
+// Escapes from the protected section on behalf of `bulletproof_impl_defer_signal()`.
+static void bulletproof_escape_handler(int sig __attribute__((unused)),
+                                       siginfo_t *si __attribute__((unused)),
+                                       void *ctx __attribute__((unused))) {
+  int fault = pending;
+  sigjmp_buf *env = current;
+  if (fault == 0 || env == NULL) {
+    return;
+  }
+
+  pending = 0;
+  siglongjmp(*env, fault);
+}
+
+// Installs the escape signal's handler.  Threads should be registered afterwards, so that the
+// escape signal is unblocked as well.
+//
+// # Returns
+//
+// If it fails, set `*failed_sig` to the escape signal, and return `errno`.  Otherwise, return 0.
+int bulletproof_impl_register_escape(int *failed_sig) {
+  struct sigaction new_handler;
+  new_handler.sa_flags = SA_SIGINFO | SA_ONSTACK;
+  sigemptyset(&new_handler.sa_mask);
+  new_handler.sa_sigaction = bulletproof_escape_handler;
+
+  if (sigaction(bulletproof_escape_signal(), &new_handler, NULL) != 0) {
+    *failed_sig = bulletproof_escape_signal();
+    return errno;
+  }
+
+  escape_installed = 1;
+  return 0;
+}
+
+// Handles a fault signal without longjmping out of the current handler.
+//
+// For handlers that cannot be escaped by `siglongjmp()`, e.g. those holding locks.  If a fault hook
+// handles the fault, it returns 1.  Otherwise, if the current thread is in a protected section, it
+// raises the escape signal blocked, and returns 1.  When the current handler returns, the mask is
+// restored and the escape signal is delivered before the faulting instruction is retried: its
+// handler longjmps to the protected section's environment, passing `sig`.  Otherwise, restores the
+// previous disposition and re-raises the signal as `bulletproof_reraise()`, and returns 0.  `si` is
+// the fault signal's information.
 //
 // # Safety
 //
-// You should call it after calling `bulletproof_register()`.
+// It is async-signal-safe.  It should be called only from a `SIGSEGV` or `SIGBUS` handler, after
+// `bulletproof_impl_register_escape()` succeeded.
+int bulletproof_impl_defer_signal(int sig, const siginfo_t *si) {
+  bulletproof_record_regs(NULL);
+  if (bulletproof_call_hooks(sig, si)) {
+    return 1;
+  }
+
+  if (current == NULL) {
+    bulletproof_reraise(sig, si);
+    return 0;
+  }
+
+  // The context is not passed through `signal-hook`, so exec faults cannot be told apart.
+  bulletproof_record_fault(sig, si->si_code, si->si_addr, 0);
+
+  sigset_t set;
+  sigemptyset(&set);
+  sigaddset(&set, bulletproof_escape_signal());
+  pthread_sigmask(SIG_BLOCK, &set, NULL);
+
+  pending = sig;
+  raise(bulletproof_escape_signal());
+  return 1;
+}
+
+// The timer of the current thread's bounded accesses: whether it is armed, and whether it
+// expired.  See `bulletproof_impl_arm_timer()`.
+static __thread volatile sig_atomic_t timer_armed = 0;
+static __thread volatile sig_atomic_t timer_expired = 0;
+
+// Arms the timer of the current thread.  Until it is disarmed, the timeout signal escapes from the
+// protected section of the current thread once the watchdog sets `*expired` to nonzero, where
+// `expired` is the returned pointer.
+//
+// # Returns
+//
+// If the timer is already armed, return `NULL`.  Otherwise, return the pointer to set when the
+// timer expires, which is valid until the thread exits.
+volatile sig_atomic_t *bulletproof_impl_arm_timer() {
+  if (timer_armed) {
+    return NULL;
+  }
+  timer_expired = 0;
+  timer_armed = 1;
+  return &timer_expired;
+}
+
+// Disarms the timer of the current thread, so that later timeout signals are ignored.
+void bulletproof_impl_disarm_timer() {
+  timer_armed = 0;
+}
+
+// Handles the timeout signal, sent by the watchdog: escapes from the protected section if the
+// timer of the current thread is armed and expired.  Otherwise (e.g. if the signal arrives after
+// the bounded access), does nothing, except interrupting a system call without `SA_RESTART`.
+void bulletproof_impl_timeout_handler(int sig,
+                                      siginfo_t *si __attribute__((unused)),
+                                      void *ctx __attribute__((unused))) {
+  sigjmp_buf *env = current;
+  if (!timer_armed || !timer_expired || env == NULL) {
+    return;
+  }
+
+  bulletproof_record_fault(sig, 0, NULL, 0);
+
+  // Unblocked as in `bulletproof_impl_handle_signal()`.
+  sigset_t set;
+  sigemptyset(&set);
+  sigaddset(&set, sig);
+  pthread_sigmask(SIG_UNBLOCK, &set, NULL);
+
+  siglongjmp(*env, sig);
+}
+
+// Restores the handler and the calling thread's registration in a forked child.
+//
+// # Safety
+//
+// It only calls async-signal-safe functions, so it may be called between `fork()` and `exec()` of
+// a multithreaded process.
 //
 // # Returns
 //
-// If `loc` is invalid, return 1. Otherwise, return 0.
-size_t bulletproof_load(const size_t *loc, size_t *dst) {
-  if (sigsetjmp(jbuf, -1) != 0) {
+// If re-registration fails, return 1. Otherwise (including when `bulletproof_impl_register()` has
+// never been called), return 0.
+size_t bulletproof_impl_reinit_after_fork() {
+  if (!registered) {
+    return 0;
+  }
+
+  int failed_sig;
+  if (bulletproof_install_handler(caught, &failed_sig) != 0) {
     return 1;
   }
 
+  // The alternate signal stack is inherited.
+  return bulletproof_unblock_signals() != 0;
+}
+
+// Loads `size_t` from `loc`, and store it to `dst`.
+//
+// # Safety
+//
+// You should call it after calling `bulletproof_impl_register()`.
+//
+// # Returns
+//
+// If `loc` is invalid, return the signal number (`SIGSEGV` or `SIGBUS`). Otherwise, return 0.
+size_t bulletproof_impl_load(const size_t *loc, size_t *dst) {
+  CHECK_ADDRESSABLE(loc, sizeof(*loc));
+  PROTECT_BEGIN;
   *dst = *loc;
+  PROTECT_END;
+
   return 0;
 }
 
@@ -46,17 +815,66 @@ size_t bulletproof_load(const size_t *loc, size_t *dst) {
 //
 // # Safety
 //
-// You should call it after calling `bulletproof_register()`.
+// You should call it after calling `bulletproof_impl_register()`.
 //
 // # Returns
 //
-// If `loc` is invalid, return 1. Otherwise, return 0.
-size_t bulletproof_store(size_t *loc, size_t val) {
-  if (sigsetjmp(jbuf, -1) != 0) {
-    return 1;
-  }
-
+// If `loc` is invalid, return the signal number (`SIGSEGV` or `SIGBUS`). Otherwise, return 0.
+size_t bulletproof_impl_store(size_t *loc, size_t val) {
+  CHECK_ADDRESSABLE(loc, sizeof(*loc));
+  PROTECT_BEGIN;
   *loc = val;
+  PROTECT_END;
+
+  return 0;
+}
+
+// Loads `uint64_t` from `loc`, and store it to `dst`.
+//
+// Where 64-bit atomics are lock-free, it is a single access if `loc` is aligned, even on 32-bit
+// targets (e.g. with an x87 or SSE load on i686, or `ldrexd` on ARMv7).  Otherwise, it is two word
+// accesses, which may tear.
+//
+// # Safety
+//
+// You should call it after calling `bulletproof_impl_register()`.
+//
+// # Returns
+//
+// If `loc` is invalid, return the signal number (`SIGSEGV` or `SIGBUS`). Otherwise, return 0.
+size_t bulletproof_impl_load_u64(const uint64_t *loc, uint64_t *dst) {
+  CHECK_ADDRESSABLE(loc, sizeof(*loc));
+  PROTECT_BEGIN;
+#if __GCC_ATOMIC_LLONG_LOCK_FREE == 2
+  *dst = __atomic_load_n(loc, __ATOMIC_RELAXED);
+#else
+  *dst = *(const volatile uint64_t *) loc;
+#endif
+  PROTECT_END;
+
+  return 0;
+}
+
+// Stores `val` of type `uint64_t` into `loc`, in a single access if possible as
+// `bulletproof_impl_load_u64()`.
+//
+// # Safety
+//
+// You should call it after calling `bulletproof_impl_register()`.
+//
+// # Returns
+//
+// If `loc` is invalid, return the signal number (`SIGSEGV` or `SIGBUS`). Otherwise, return 0.
+size_t bulletproof_impl_store_u64(uint64_t *loc, uint64_t val) {
+  CHECK_ADDRESSABLE(loc, sizeof(*loc));
+  PROTECT_BEGIN;
+#if __GCC_ATOMIC_LLONG_LOCK_FREE == 2
+  __atomic_store_n(loc, val, __ATOMIC_RELAXED);
+#else
+  *(volatile uint64_t *) loc = val;
+#endif
+  PROTECT_END;
+
   return 0;
 }
 
@@ -64,38 +882,323 @@ size_t bulletproof_store(size_t *loc, size_t val) {
 //
 // # Safety
 //
-// You should call it after calling `bulletproof_register()`.
+// You should call it after calling `bulletproof_impl_register()`.
 //
 // `dst` should be a valid buffer with size at least `size`.
 //
 // # Returns
 //
-// If `loc` is invalid, return 1. Otherwise, return 0.
-size_t bulletproof_load_bytes(const char *loc, char *dst, size_t size) {
-  if (sigsetjmp(jbuf, -1) != 0) {
-    return 1;
-  }
-
+// If `loc` is invalid, return the signal number (`SIGSEGV` or `SIGBUS`). Otherwise, return 0.
+size_t bulletproof_impl_load_bytes(const char *loc, char *dst, size_t size) {
+  CHECK_ADDRESSABLE(loc, size);
+  PROTECT_BEGIN;
   memcpy((void *) dst, (void *) loc, size);
+  PROTECT_END;
+
   return 0;
 }
 
-// Stores `val` into `loc`.
+// Returns the length of the chunk from `loc` up to the next page boundary, at most `size`.
+static size_t bulletproof_chunk_len(const char *loc, size_t size, size_t page_size) {
+  size_t len = page_size - (size_t) loc % page_size;
+  return len < size ? len : size;
+}
+
+// Loads `size` bytes from `loc`, and store it to `dst`, zero-filling the bytes of unreadable pages
+// instead of stopping at the first fault.
 //
 // # Safety
 //
-// You should call it after calling `bulletproof_register()`.
+// You should call it after calling `bulletproof_impl_register()`.
 //
 // `dst` should be a valid buffer with size at least `size`.
 //
 // # Returns
 //
-// If `loc` is invalid, return 1. Otherwise, return 0.
-size_t bulletproof_store_bytes(char *loc, const char *src, size_t size) {
-  if (sigsetjmp(jbuf, -1) != 0) {
-    return 1;
+// Return the number of zero-filled bytes.
+size_t bulletproof_impl_load_bytes_zero_fill(const char *loc, char *dst, size_t size,
+                                             size_t page_size) {
+  sigjmp_buf env;
+  sigjmp_buf *prev = current;
+  volatile size_t offset = 0;
+  volatile size_t zero_filled = 0;
+
+  // A fault zero-fills the faulting page's chunk, which may have been partially copied, and skips
+  // it.
+  if (sigsetjmp(env, bulletproof_savemask()) != 0) {
+    size_t len = bulletproof_chunk_len(loc + offset, size - offset, page_size);
+    memset(dst + offset, 0, len);
+    zero_filled += len;
+    offset += len;
   }
+  current = &env;
+  __atomic_signal_fence(__ATOMIC_SEQ_CST);
 
+  while (offset < size) {
+    size_t len = bulletproof_chunk_len(loc + offset, size - offset, page_size);
+    if (bulletproof_checked_addressable(loc + offset, len)) {
+      memcpy(dst + offset, loc + offset, len);
+    } else {
+      memset(dst + offset, 0, len);
+      zero_filled += len;
+    }
+    offset += len;
+  }
+
+  __atomic_signal_fence(__ATOMIC_SEQ_CST);
+  current = prev;
+  return zero_filled;
+}
+
+// Stores `val` into `loc`.
+//
+// # Safety
+//
+// You should call it after calling `bulletproof_impl_register()`.
+//
+// `dst` should be a valid buffer with size at least `size`.
+//
+// # Returns
+//
+// If `loc` is invalid, return the signal number (`SIGSEGV` or `SIGBUS`). Otherwise, return 0.
+size_t bulletproof_impl_store_bytes(char *loc, const char *src, size_t size) {
+  CHECK_ADDRESSABLE(loc, size);
+  PROTECT_BEGIN;
   memcpy((void *) loc, (void *) src, size);
+  PROTECT_END;
+
   return 0;
 }
+
+// Loads `size` bytes from `loc` to `old`, and then stores `size` bytes from `src` to `loc`, in a
+// single protected section.  It is not atomic.
+//
+// # Safety
+//
+// You should call it after calling `bulletproof_impl_register()`.
+//
+// `src` and `old` should be valid buffers with size at least `size`, not overlapping `loc`.
+//
+// # Returns
+//
+// If `loc` is invalid, return the signal number (`SIGSEGV` or `SIGBUS`). Otherwise, return 0.  If
+// only the store faults (e.g. on a read-only page), `old` is written but `loc` is not.
+size_t bulletproof_impl_exchange_bytes(char *loc, const char *src, char *old, size_t size) {
+  CHECK_ADDRESSABLE(loc, size);
+  PROTECT_BEGIN;
+  memcpy((void *) old, (void *) loc, size);
+  memcpy((void *) loc, (void *) src, size);
+  PROTECT_END;
+
+  return 0;
+}
+
+// Copies `size` bytes from `src` to `dst`, which may overlap, as `memmove()`.
+//
+// # Safety
+//
+// You should call it after calling `bulletproof_impl_register()`.
+//
+// # Returns
+//
+// If `src` or `dst` is invalid, return the signal number (`SIGSEGV` or `SIGBUS`).  Otherwise,
+// return 0.  On a fault, a part of the bytes may have been copied.
+size_t bulletproof_impl_move_bytes(const char *src, char *dst, size_t size) {
+  CHECK_ADDRESSABLE(src, size);
+  CHECK_ADDRESSABLE(dst, size);
+  PROTECT_BEGIN;
+  memmove((void *) dst, (const void *) src, size);
+  PROTECT_END;
+
+  return 0;
+}
+
+// Stores the leading bytes of `size` bytes from `src` to `loc`, stopping at the first page that
+// cannot be written.
+//
+// # Safety
+//
+// You should call it after calling `bulletproof_impl_register()`.
+//
+// `src` should be a valid buffer with size at least `size`.
+//
+// # Returns
+//
+// Return the number of bytes written, i.e. up to the first page that cannot be written.
+size_t bulletproof_impl_store_bytes_prefix(char *loc, const char *src, size_t size,
+                                           size_t page_size) {
+  sigjmp_buf env;
+  sigjmp_buf *prev = current;
+  volatile size_t offset = 0;
+
+  // Pages are protected as a whole, so a fault writes nothing of the faulting page's chunk.
+  if (sigsetjmp(env, bulletproof_savemask()) != 0) {
+    current = prev;
+    return offset;
+  }
+  current = &env;
+  __atomic_signal_fence(__ATOMIC_SEQ_CST);
+
+  while (offset < size) {
+    size_t len = bulletproof_chunk_len(loc + offset, size - offset, page_size);
+    if (!bulletproof_checked_addressable(loc + offset, len)) {
+      break;
+    }
+    memcpy(loc + offset, src + offset, len);
+    offset += len;
+  }
+
+  __atomic_signal_fence(__ATOMIC_SEQ_CST);
+  current = prev;
+  return offset;
+}
+
+// Scans `words` words from `loc`, and stores the indexes of the words in `[heap_start, heap_end)`
+// to `out`, and their number to `found`.
+//
+// # Safety
+//
+// You should call it after calling `bulletproof_impl_register()`.
+//
+// `out` should be a valid buffer of at least `words` words.
+//
+// # Returns
+//
+// If `loc` is invalid, return the signal number (`SIGSEGV` or `SIGBUS`). Otherwise, return 0.
+size_t bulletproof_impl_scan(const size_t *loc, size_t words, size_t heap_start, size_t heap_end,
+                        size_t *out, size_t *found) {
+  CHECK_ADDRESSABLE(loc, words * sizeof(size_t));
+  PROTECT_BEGIN;
+  size_t n = 0;
+  for (size_t i = 0; i < words; i++) {
+    size_t word = loc[i];
+    if (heap_start <= word && word < heap_end) {
+      out[n++] = i;
+    }
+  }
+  *found = n;
+  PROTECT_END;
+
+  return 0;
+}
+
+// Calls `f(data)` in a protected section.
+//
+// # Safety
+//
+// You should call it after calling `bulletproof_impl_register()`.
+//
+// If a fault occurs, `f` does not return, and its frames are abandoned.
+//
+// # Returns
+//
+// If a fault occurs in `f`, return the signal number (`SIGSEGV` or `SIGBUS`). Otherwise, return 0.
+size_t bulletproof_impl_protect(void (*f)(void *), void *data) {
+  PROTECT_BEGIN;
+  f(data);
+  PROTECT_END;
+
+  return 0;
+}
+
+// Probes `pages` pages of `page_size` bytes from `start` inside one protected loop.
+//
+// Sets bit `i` of `readable` if page `i` is readable.  If `writable` is not `NULL`, also sets bit
+// `i` of `writable` if page `i` is writable.  Writability is probed by atomically adding zero to
+// the first byte of the page, which preserves its contents but may populate (or copy-on-write) the
+// page.
+//
+// # Safety
+//
+// You should call it after calling `bulletproof_impl_register()`.
+//
+// The bitmaps should be zero-initialized buffers of at least `(pages + 7) / 8` bytes.
+void bulletproof_impl_probe_range(const char *start, size_t page_size, size_t pages,
+                             unsigned char *readable, unsigned char *writable) {
+  sigjmp_buf env;
+  sigjmp_buf *prev = current;
+  volatile size_t i = 0;
+
+  // A fault skips the rest of the faulting page.
+  if (sigsetjmp(env, bulletproof_savemask()) != 0) {
+    i++;
+  }
+  current = &env;
+  __atomic_signal_fence(__ATOMIC_SEQ_CST);
+
+  for (; i < pages; i++) {
+    volatile char *page = (volatile char *) (start + i * page_size);
+    if (!bulletproof_checked_addressable((const void *) page, 1)) {
+      continue;
+    }
+
+    (void) *page;
+    readable[i / 8] |= 1 << (i % 8);
+
+    if (writable != NULL) {
+      __atomic_fetch_add(page, 0, __ATOMIC_RELAXED);
+      writable[i / 8] |= 1 << (i % 8);
+    }
+  }
+
+  __atomic_signal_fence(__ATOMIC_SEQ_CST);
+  current = prev;
+}
+
+// Reads the last fault recovered by the current thread.
+void bulletproof_impl_last_fault(int *sig, int *code, void **addr, int *exec) {
+  *sig = last_fault.sig;
+  *code = last_fault.code;
+  *addr = last_fault.addr;
+  *exec = last_fault.exec;
+}
+
+#ifdef BULLETPROOF_TRACING
+// Stores when the last fault recovered by the current thread was raised (`CLOCK_MONOTONIC`) to
+// `time`.
+void bulletproof_impl_last_fault_time(struct timespec *time) {
+  *time = last_fault.time;
+}
+#endif
+
+// Checks if `[loc, loc + size)` is not poisoned according to ASan, for the fallback backend.
+// Returns 1 without the `asan` feature or the ASan runtime.
+int bulletproof_impl_asan_addressable(const void *loc, size_t size) {
+  return bulletproof_asan_addressable(loc, size);
+}
+
+// Stores the registers at the last fault signal handled by the current thread to `*pc`, `*sp`, and
+// `*fp`.  Returns 0 if they are unknown, and 1 otherwise.
+//
+// It is async-signal-safe, so that fault hooks may call it.
+int bulletproof_impl_last_registers(void **pc, void **sp, void **fp) {
+  if (!last_regs.valid) {
+    return 0;
+  }
+  *pc = last_regs.regs.pc;
+  *sp = last_regs.regs.sp;
+  *fp = last_regs.regs.fp;
+  return 1;
+}
+
+// Stores the registers of the interrupted code from the context `ctx` of a signal handler to `*pc`,
+// `*sp`, and `*fp`.  Returns 0 if they are unknown on this platform, and 1 otherwise.
+//
+// It is async-signal-safe, so that the handlers of sampling signals may call it.
+int bulletproof_impl_context_registers(void *ctx, void **pc, void **sp, void **fp) {
+  struct bulletproof_regs regs;
+  if (!bulletproof_fault_regs(ctx, &regs)) {
+    return 0;
+  }
+  *pc = regs.pc;
+  *sp = regs.sp;
+  *fp = regs.fp;
+  return 1;
+}
+
+// Flushes the instruction cache for `[start, start + len)`, after the code in it was written.  It
+// does nothing on targets whose instruction cache is coherent with the data cache (e.g. x86), and
+// calls e.g. `sys_icache_invalidate()` on macOS.
+void bulletproof_impl_flush_icache(void *start, size_t len) {
+  __builtin___clear_cache((char *) start, (char *) start + len);
+}
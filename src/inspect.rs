@@ -0,0 +1,138 @@
+//! Safe inspection of the process memory (Linux, FreeBSD, OpenBSD, NetBSD, and illumos).
+//!
+//! [`Inspector::read()`](struct.Inspector.html#method.read) copies a range of the process memory
+//! given by its address, from safe code. It checks the range against the memory map of the process
+//! ([`snapshot::regions()`](../snapshot/fn.regions.html)) first, so that it only reads readable
+//! memory that is not device memory, and then copies it with bulletproof memory access, so that a
+//! range unmapped in between is not read either. Diagnostic tools may call it with any address, e.g. one found in a core of a crashed
+//! thread, without re-deriving the safety argument each time.
+//!
+//! It is not available with the fallback backend (under Miri, or with
+//! `--cfg bulletproof_fallback`), whose accesses to memory unmapped in between are not recovered.
+//!
+//! # Examples
+//!
+//! ```
+//! use bulletproof::{inspect::Inspector, Bulletproof};
+//!
+//! let inspector = Inspector::new(unsafe { Bulletproof::new() });
+//!
+//! let data = Box::new(*b"inspect me");
+//! let addr = data.as_ptr() as usize;
+//! assert_eq!(inspector.read(addr, 7).as_deref(), Some(&b"inspect"[..]));
+//! assert_eq!(inspector.read(0, 8), None);
+//! ```
+
+use libc::c_void;
+
+use super::snapshot::{self, Region};
+use super::{load_bytes, Bulletproof};
+
+/// A safe reader of the process memory.
+#[derive(Debug, Clone, Copy)]
+pub struct Inspector {
+    bulletproof: Bulletproof,
+}
+
+impl Inspector {
+    /// Creates an inspector reading with `bulletproof`.
+    #[inline]
+    pub fn new(bulletproof: Bulletproof) -> Self {
+        Self { bulletproof }
+    }
+
+    /// Returns `true` if reading `region` has no side effects other than populating pages.
+    fn may_read(region: &Region) -> bool {
+        let device = (region.name.starts_with("/dev/")
+            && region.name != "/dev/zero"
+            && !region.name.starts_with("/dev/shm/"))
+            || region.name == "[device]";
+        region.is_readable() && !device
+    }
+
+    /// Returns `true` if `[start, end)` is covered by readable regions of the memory map.
+    fn covered(start: usize, end: usize) -> bool {
+        let regions = match snapshot::regions() {
+            Ok(regions) => regions,
+            Err(_) => return false,
+        };
+
+        // The regions are sorted by address.
+        let mut next = start;
+        for region in &regions {
+            if region.end <= next {
+                continue;
+            }
+            if region.start > next || !Self::may_read(region) {
+                return false;
+            }
+            next = region.end;
+            if next >= end {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Reads `len` bytes at `addr`.
+    ///
+    /// Returns `None` if any of them is not in a readable region of the memory map, is in device
+    /// memory, or cannot be read (e.g. because the region was unmapped concurrently, or a mapped
+    /// file was truncated).
+    pub fn read(&self, addr: usize, len: usize) -> Option<Vec<u8>> {
+        let end = addr.checked_add(len)?;
+        if len == 0 {
+            return Some(Vec::new());
+        }
+        if !Self::covered(addr, end) {
+            return None;
+        }
+
+        let mut buf = vec![0u8; len];
+        // Safe, as the range is in readable regions other than device memory, whose reads have no
+        // side effects, and faults (e.g. if they are unmapped in between) are recovered.
+        let via = self.bulletproof.backend();
+        let result = self.bulletproof.config().retry(|| unsafe {
+            load_bytes(via, addr as *const c_void, buf.as_mut_ptr() as *mut c_void, len)
+        });
+        result.ok().map(|()| buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ptr;
+
+    use libc;
+
+    use super::*;
+    use probe;
+
+    #[test]
+    fn read() {
+        let inspector = Inspector::new(unsafe { Bulletproof::new() });
+
+        let data = Box::new([7u8; 64]);
+        let addr = data.as_ptr() as usize;
+        assert_eq!(inspector.read(addr, 64), Some(vec![7; 64]));
+        assert_eq!(inspector.read(addr, 0), Some(Vec::new()));
+        assert_eq!(inspector.read(usize::MAX, 2), None);
+
+        unsafe {
+            // A readable page followed by an inaccessible one.
+            let page_size = probe::page_size();
+            let flags = libc::MAP_PRIVATE | libc::MAP_ANONYMOUS;
+            let map = libc::mmap(ptr::null_mut(), 2 * page_size, libc::PROT_READ, flags, -1, 0);
+            assert_ne!(map, libc::MAP_FAILED);
+            let second = map as usize + page_size;
+            libc::mprotect(second as *mut c_void, page_size, libc::PROT_NONE);
+
+            assert_eq!(inspector.read(second - 8, 8), Some(vec![0; 8]));
+            assert_eq!(inspector.read(second - 8, 16), None);
+            assert_eq!(inspector.read(second, 8), None);
+
+            libc::munmap(map, 2 * page_size);
+            assert_eq!(inspector.read(map as usize, 8), None);
+        }
+    }
+}
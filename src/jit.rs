@@ -0,0 +1,179 @@
+//! Patching machine code, e.g. for JIT compilers.
+//!
+//! Patching code takes more than a store: the code pages are usually not writable, and on targets
+//! whose instruction cache is not coherent with the data cache (e.g. ARM and AArch64), the old
+//! instructions may still be executed unless the cache is flushed.
+//! [`patch_code()`](fn.patch_code.html) takes care of both.
+//!
+//! # Examples
+//!
+//! ```
+//! # extern crate bulletproof;
+//! # extern crate libc;
+//! use bulletproof::{jit, Bulletproof};
+//! use std::ptr;
+//!
+//! # #[cfg(not(bulletproof_fallback))]
+//! unsafe {
+//!     let bulletproof = Bulletproof::new();
+//!
+//!     // A code page, readable and executable.
+//!     let prot = libc::PROT_READ | libc::PROT_EXEC;
+//!     let flags = libc::MAP_PRIVATE | libc::MAP_ANONYMOUS;
+//!     let page = libc::mmap(ptr::null_mut(), 4096, prot, flags, -1, 0) as *mut u8;
+//!
+//!     jit::patch_code(bulletproof, page.add(16), &[0x90, 0x90]).unwrap();
+//!     assert_eq!(*page.add(16), 0x90);
+//!     assert!(jit::patch_code(bulletproof, ptr::null_mut(), &[0x90]).is_err());
+//!
+//!     libc::munmap(page as *mut _, 4096);
+//! }
+//! ```
+
+use std::error::Error;
+use std::{fmt, io};
+
+use libc::{self, c_void};
+
+use super::fault::SEGV_ACCERR;
+use super::{backend, probe, store_bytes, Bulletproof, Fault};
+
+/// An error of patching code.
+#[derive(Debug)]
+pub enum PatchError {
+    /// The location is not mapped (or otherwise cannot be written even if unprotected).
+    Fault(Fault),
+    /// The protection of the code pages cannot be changed, e.g. because the system forbids
+    /// writable and executable pages.
+    Protect(io::Error),
+}
+
+impl fmt::Display for PatchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PatchError::Fault(ref fault) => write!(f, "cannot patch code: {}", fault),
+            PatchError::Protect(ref error) => write!(f, "cannot unprotect code: {}", error),
+        }
+    }
+}
+
+impl Error for PatchError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            PatchError::Fault(ref fault) => Some(fault),
+            PatchError::Protect(ref error) => Some(error),
+        }
+    }
+}
+
+impl From<Fault> for PatchError {
+    #[inline]
+    fn from(fault: Fault) -> Self {
+        PatchError::Fault(fault)
+    }
+}
+
+/// Changes the protection of the pages overlapping `[start, start + len)`.
+unsafe fn mprotect(start: usize, len: usize, prot: libc::c_int) -> Result<(), PatchError> {
+    let page_size = probe::page_size();
+    let first = start & !(page_size - 1);
+    let end = (start + len).div_ceil(page_size) * page_size;
+    if libc::mprotect(first as *mut c_void, end - first, prot) != 0 {
+        return Err(PatchError::Protect(io::Error::last_os_error()));
+    }
+    Ok(())
+}
+
+/// Writes `bytes` to the code at `loc`, and flushes the instruction cache for them.
+///
+/// The write is a bulletproof store. If the code pages are write-protected, they are made writable
+/// (and executable) for the write, and then readable and executable.
+///
+/// # Safety
+///
+/// No thread should be executing the instructions being patched, unless the patch is atomic on the
+/// target (e.g. an aligned word). The pages overlapping `[loc, loc + bytes.len())` should not be
+/// made read-only or non-executable by the patch, i.e. if one of them is write-protected, all of
+/// them should be code pages.
+///
+/// # Errors
+///
+/// Returns `Err(PatchError::Fault(fault))` if the location is invalid, and
+/// `Err(PatchError::Protect(error))` if the protection of the code pages cannot be changed.
+pub unsafe fn patch_code(
+    bulletproof: Bulletproof,
+    loc: *mut u8,
+    bytes: &[u8],
+) -> Result<(), PatchError> {
+    let src = bytes.as_ptr() as *const c_void;
+    let store = || store_bytes(bulletproof.backend(), loc as *mut c_void, src, bytes.len());
+    match bulletproof.config().retry(store) {
+        Ok(()) => {}
+        Err(Fault::Segv { code: SEGV_ACCERR, .. }) => {
+            let (start, len) = (loc as usize, bytes.len());
+            mprotect(start, len, libc::PROT_READ | libc::PROT_WRITE | libc::PROT_EXEC)?;
+            let result = bulletproof.config().retry(store);
+            mprotect(start, len, libc::PROT_READ | libc::PROT_EXEC)?;
+            result?;
+        }
+        Err(fault) => return Err(PatchError::Fault(fault)),
+    }
+
+    backend::flush_icache(loc as *mut c_void, bytes.len());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{mem, ptr};
+
+    use super::*;
+
+    #[test]
+    #[cfg_attr(any(miri, bulletproof_fallback), ignore)]
+    fn patch_code() {
+        unsafe {
+            let bulletproof = Bulletproof::new();
+            let page_size = probe::page_size();
+
+            let prot = libc::PROT_READ | libc::PROT_WRITE;
+            let flags = libc::MAP_PRIVATE | libc::MAP_ANONYMOUS;
+            let map = libc::mmap(ptr::null_mut(), page_size, prot, flags, -1, 0);
+            assert_ne!(map, libc::MAP_FAILED);
+            let code = map as *mut u8;
+
+            // `return 1;`, patched to `return 2;`.
+            #[cfg(target_arch = "x86_64")]
+            let (ret1, ret2) = ([0xb8, 1, 0, 0, 0, 0xc3], [0xb8, 2, 0, 0, 0, 0xc3]);
+            #[cfg(target_arch = "aarch64")]
+            let (ret1, ret2) = (
+                [0x20, 0x00, 0x80, 0x52, 0xc0, 0x03, 0x5f, 0xd6],
+                [0x40, 0x00, 0x80, 0x52, 0xc0, 0x03, 0x5f, 0xd6],
+            );
+            #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+            let (ret1, ret2) = ([1], [2]);
+
+            // Into writable pages, and into write-protected ones.
+            super::patch_code(bulletproof, code, &ret1).unwrap();
+            assert_eq!(libc::mprotect(map, page_size, libc::PROT_READ | libc::PROT_EXEC), 0);
+            let f: extern "C" fn() -> u32 = mem::transmute(code);
+            if cfg!(any(target_arch = "x86_64", target_arch = "aarch64")) {
+                assert_eq!(f(), 1);
+            }
+            super::patch_code(bulletproof, code, &ret2).unwrap();
+            if cfg!(any(target_arch = "x86_64", target_arch = "aarch64")) {
+                assert_eq!(f(), 2);
+            }
+
+            // Write-protected again.
+            let fault = bulletproof.store(code, &0).unwrap_err().fault();
+            assert_eq!(fault.code(), SEGV_ACCERR);
+
+            libc::munmap(map, page_size);
+            match super::patch_code(bulletproof, code, &ret1) {
+                Err(PatchError::Fault(fault)) => assert_eq!(fault.addr(), code as usize),
+                result => panic!("unexpected result: {:?}", result),
+            }
+        }
+    }
+}
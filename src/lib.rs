@@ -6,7 +6,7 @@
 //! # Examples
 //!
 //! ```
-//! use bulletproof::Bulletproof;
+//! use bulletproof::{Bulletproof, Fault};
 //! use std::ptr;
 //!
 //! let mut x = 42usize;
@@ -16,30 +16,39 @@
 //!     let bulletproof = Bulletproof::new();
 //!
 //!     assert_eq!(bulletproof.load_usize(&x), Ok(42));
-//!     assert_eq!(bulletproof.load_usize(ptr::null()), Err(()));
+//!     assert!(bulletproof.load_usize(ptr::null()).is_err());
 //!
 //!     assert_eq!(bulletproof.store_usize(&mut x, 37), Ok(()));
-//!     assert_eq!(bulletproof.store_usize(ptr::null_mut(), 37), Err(()));
+//!     assert!(bulletproof.store_usize(ptr::null_mut(), 37).is_err());
 //!     assert_eq!(bulletproof.load_usize(&x), Ok(37));
 //!     assert_eq!(ptr::read(&x), 37);
 //!
 //!     assert_eq!(bulletproof.load(&y), Ok(42));
-//!     assert_eq!(bulletproof.load::<[usize; 32]>(ptr::null()), Err(()));
+//!     match bulletproof.load::<[usize; 32]>(ptr::null()) {
+//!         Err(Fault::Segv { addr, .. }) => assert_eq!(addr, 0),
+//!         _ => unreachable!(),
+//!     }
 //! }
 //! ```
 //!
 //! # How?
 //!
-//! Internally, `Bulletproof::new()` installs a signal handler for segmentation fault (`SIGSEGV`),
-//! which recovers from the fault using `siglongjmp()`.
+//! Internally, `Bulletproof::new()` installs a signal handler for segmentation fault (`SIGSEGV`)
+//! and bus error (`SIGBUS`), which recovers from the fault using `siglongjmp()`.
+//!
+//! On targets without Unix signals (e.g. WebAssembly), the crate still builds, but faults cannot be
+//! recovered: [`Bulletproof::try_new()`](struct.Bulletproof.html#method.try_new) returns an error
+//! of step [`RegisterStep::Unsupported`](error/enum.RegisterStep.html#variant.Unsupported), and
+//! the modules managing page protection are not available. With `--cfg bulletproof_fallback`,
+//! accesses are performed as plain accesses instead, rejecting only the first page.
 //!
 //! # Safe?
 //!
 //! Even if a location is deallocated, it may still be accessible because it is not returned to the
 //! OS yet.
 //!
-//! Since `Bulletproof::new()` registers a `SIGSEGV` signal handler, it may disrupt the existing or
-//! future signal handlers. Most notably, [Rust installs a `SIGSEGV` signal
+//! Since `Bulletproof::new()` registers `SIGSEGV` and `SIGBUS` signal handlers, it may disrupt the
+//! existing or future signal handlers. Most notably, [Rust installs a `SIGSEGV` signal
 //! handler](https://github.com/rust-lang/rust/blob/e7e982ac03b496dd4d4b5c182fdcd5fb4f2b5470/src/libstd/sys/unix/stack_overflow.rs#L76)
 //! for protecting stack from overflow at initialization. By creating a `Bulletproof`, stack is no
 //! longer protected.
@@ -55,44 +64,762 @@
 
 #![warn(missing_docs, missing_debug_implementations)]
 
+#[cfg(feature = "macros")]
+extern crate bulletproof_macros;
 extern crate libc;
+#[cfg(feature = "memmap2")]
+extern crate memmap2;
+#[cfg(feature = "signal-hook")]
+extern crate signal_hook_registry;
+#[cfg(feature = "tracing")]
+extern crate tracing;
+
+use std::any::Any;
+use std::cmp;
+use std::collections::BTreeMap;
+use std::mem::{self, MaybeUninit};
+use std::ops::Range;
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::slice;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+use std::thread::JoinHandle;
+
+use decode::{NanBoxed, Tagged};
+use heapwalk::Classification;
+use sys::{c_int, c_void};
+
+#[cfg(not(any(miri, bulletproof_fallback, bulletproof_unsupported)))]
+use native as backend;
+#[cfg(any(miri, bulletproof_fallback))]
+use fallback as backend;
+#[cfg(all(bulletproof_unsupported, not(any(miri, bulletproof_fallback))))]
+use unsupported as backend;
+
+#[macro_use]
+mod macros;
+
+#[cfg(any(miri, bulletproof_fallback))]
+mod fallback;
+#[cfg(not(any(miri, bulletproof_fallback, bulletproof_unsupported)))]
+mod native;
+#[cfg(all(bulletproof_unsupported, not(any(miri, bulletproof_fallback))))]
+mod unsupported;
+
+#[cfg(all(target_os = "linux", any(target_env = "gnu", target_env = "musl")))]
+pub mod auxv;
+pub mod backtrace;
+#[cfg(feature = "bench")]
+pub mod bench;
+pub mod bytes;
+#[cfg(feature = "capi")]
+pub mod capi;
+pub mod checkpoint;
+pub mod config;
+#[cfg(unix)]
+pub mod cow;
+pub mod decode;
+mod endian;
+pub mod error;
+pub mod fault;
+pub mod fmt;
+pub mod frame;
+#[cfg(unix)]
+pub mod guarded;
+pub mod handler;
+pub mod heapwalk;
+mod hook;
+pub mod image;
+#[cfg(all(
+    any(
+        target_os = "linux",
+        target_os = "freebsd",
+        target_os = "openbsd",
+        target_os = "netbsd",
+        target_os = "illumos"
+    ),
+    not(any(miri, bulletproof_fallback))
+))]
+pub mod inspect;
+#[cfg(unix)]
+pub mod jit;
+mod limit;
+#[cfg(unix)]
+mod mapping;
+#[cfg(any(
+    target_os = "linux",
+    target_os = "freebsd",
+    target_os = "openbsd",
+    target_os = "netbsd",
+    target_os = "illumos"
+))]
+mod maps;
+pub mod memio;
+#[cfg(feature = "memmap2")]
+pub mod mmap;
+#[cfg(feature = "async")]
+pub mod offload;
+pub mod oracle;
+#[cfg(unix)]
+pub mod pin;
+pub mod probe;
+pub mod prober;
+pub mod registration;
+#[cfg(any(
+    target_os = "linux",
+    target_os = "freebsd",
+    target_os = "openbsd",
+    target_os = "netbsd",
+    target_os = "illumos"
+))]
+pub mod report;
+#[cfg(all(
+    feature = "signal-hook",
+    not(any(miri, bulletproof_fallback, bulletproof_unsupported))
+))]
+mod registry;
+pub mod ring;
+mod runtime;
+#[cfg(unix)]
+pub mod safepoint;
+#[cfg(unix)]
+pub mod sampling;
+mod selftest;
+pub mod shared;
+#[cfg(any(
+    target_os = "linux",
+    target_os = "freebsd",
+    target_os = "openbsd",
+    target_os = "netbsd",
+    target_os = "illumos"
+))]
+pub mod snapshot;
+mod sys;
+#[cfg(feature = "testing")]
+pub mod testing;
+pub mod thread;
+#[cfg(all(target_os = "linux", any(target_env = "gnu", target_env = "musl")))]
+pub mod tls;
+#[cfg(feature = "tracing")]
+mod trace;
+#[cfg(unix)]
+pub mod tracking;
+#[cfg(all(feature = "userfaultfd", target_os = "linux"))]
+pub mod uffd;
+#[cfg(any(
+    target_os = "linux",
+    target_os = "freebsd",
+    target_os = "openbsd",
+    target_os = "netbsd",
+    target_os = "illumos"
+))]
+pub mod watch;
+#[cfg(not(any(miri, bulletproof_fallback, bulletproof_unsupported)))]
+pub mod watchdog;
+
+#[cfg(feature = "macros")]
+pub use bulletproof_macros::bulletproof;
+pub use bytes::{FromBytes, NoPadding, Plain};
+pub use config::{Config, FaultHook, LimitAction, Recovery};
+pub use error::{
+    CopyError, HandlerConflict, LoadError, RegisterError, RegisterStep, SelfTestError, StoreError,
+    StoreFieldsFault, TimeoutError,
+};
+pub use fault::Fault;
+pub use probe::{PageBitmap, Pages};
+pub use registration::Registration;
+pub use runtime::Backend;
+
+/// Returns the fault for a poisoned range overlapping `[start, start + size)`, if any.
+#[cfg(feature = "testing")]
+#[inline]
+fn poisoned(start: usize, size: usize) -> Option<Fault> {
+    testing::poisoned(start, size)
+}
+
+#[cfg(not(feature = "testing"))]
+#[inline(always)]
+fn poisoned(_start: usize, _size: usize) -> Option<Fault> {
+    None
+}
+
+/// Returns the error of a store that raised `fault`, with the permissions of the page if it is
+/// mapped but not writable.
+#[cold]
+fn store_error(fault: Fault) -> StoreError {
+    #[cfg(any(
+        target_os = "linux",
+        target_os = "freebsd",
+        target_os = "openbsd",
+        target_os = "netbsd",
+        target_os = "illumos"
+    ))]
+    {
+        if let Fault::Segv { addr, code: fault::SEGV_ACCERR } = fault {
+            let perms = snapshot::regions().ok().and_then(|regions| {
+                regions
+                    .into_iter()
+                    .find(|region| region.start <= addr && addr < region.end)
+                    .map(|region| region.perms)
+            });
+            return StoreError::new(fault, perms);
+        }
+    }
+    StoreError::from(fault)
+}
+
+/// Converts the backend's result into a `Result`.
+#[inline]
+fn check(sig: c_int) -> Result<(), Fault> {
+    if sig != 0 {
+        let fault = backend::last_fault();
+        #[cfg(feature = "tracing")]
+        trace::fault_caught(&fault);
+        return Err(fault);
+    }
+
+    Ok(())
+}
+
+/// Performs an access. The access is timed with the `bench` feature.
+#[inline(always)]
+fn access<F: FnOnce() -> Result<(), Fault>>(f: F) -> Result<(), Fault> {
+    #[cfg(feature = "bench")]
+    let timer = bench::Timer::start();
+    let result = f();
+    #[cfg(feature = "bench")]
+    timer.stop(result.is_err());
+    result
+}
+
+/// Loads a word from `loc` to `dst`.
+#[inline]
+unsafe fn load_word(via: Backend, loc: *const usize, dst: *mut usize) -> Result<(), Fault> {
+    if let Some(fault) = poisoned(loc as usize, mem::size_of::<usize>()) {
+        return Err(fault);
+    }
+    access(|| match via {
+        Backend::Signal => check(backend::load_word(loc, dst)),
+        _ => runtime::load(via, loc as usize, dst as *mut u8, mem::size_of::<usize>()),
+    })
+}
+
+/// Stores a word `val` to `loc`.
+#[inline]
+unsafe fn store_word(via: Backend, loc: *mut usize, val: usize) -> Result<(), Fault> {
+    if let Some(fault) = poisoned(loc as usize, mem::size_of::<usize>()) {
+        return Err(fault);
+    }
+    access(|| match via {
+        Backend::Signal => check(backend::store_word(loc, val)),
+        _ => {
+            let src = &val as *const usize as *const u8;
+            runtime::store(via, loc as usize, src, mem::size_of::<usize>())
+        }
+    })
+}
+
+/// Loads a `u64` from `loc` to `dst`.
+#[inline]
+unsafe fn load_u64(via: Backend, loc: *const u64, dst: *mut u64) -> Result<(), Fault> {
+    if let Some(fault) = poisoned(loc as usize, mem::size_of::<u64>()) {
+        return Err(fault);
+    }
+    access(|| match via {
+        Backend::Signal => check(backend::load_u64(loc, dst)),
+        _ => runtime::load(via, loc as usize, dst as *mut u8, mem::size_of::<u64>()),
+    })
+}
+
+/// Stores a `u64` `val` to `loc`.
+#[inline]
+unsafe fn store_u64(via: Backend, loc: *mut u64, val: u64) -> Result<(), Fault> {
+    if let Some(fault) = poisoned(loc as usize, mem::size_of::<u64>()) {
+        return Err(fault);
+    }
+    access(|| match via {
+        Backend::Signal => check(backend::store_u64(loc, val)),
+        _ => {
+            let src = &val as *const u64 as *const u8;
+            runtime::store(via, loc as usize, src, mem::size_of::<u64>())
+        }
+    })
+}
+
+/// Loads `size` bytes from `loc` to `dst`.
+#[inline]
+unsafe fn load_bytes(
+    via: Backend,
+    loc: *const c_void,
+    dst: *mut c_void,
+    size: usize,
+) -> Result<(), Fault> {
+    if let Some(fault) = poisoned(loc as usize, size) {
+        return Err(fault);
+    }
+    access(|| match via {
+        Backend::Signal => check(backend::load_bytes(loc, dst, size)),
+        _ => runtime::load(via, loc as usize, dst as *mut u8, size),
+    })
+}
+
+/// Loads `dst.len()` bytes from `loc` to `dst`, zero-filling the bytes of unreadable pages. Returns
+/// the number of zero-filled bytes.
+unsafe fn load_bytes_zero_fill(via: Backend, loc: *const u8, dst: &mut [u8]) -> usize {
+    let page_size = probe::page_size();
+    let (start, len) = (loc as usize, dst.len());
+    let mut zero_filled = 0;
+
+    // Other backends load page by page.
+    if via != Backend::Signal {
+        let mut offset = 0;
+        while offset < len {
+            let chunk = cmp::min(page_size - (start + offset) % page_size, len - offset);
+            let dst = dst[offset..].as_mut_ptr();
+            if poisoned(start + offset, chunk).is_some()
+                || runtime::load(via, start + offset, dst, chunk).is_err()
+            {
+                ptr::write_bytes(dst, 0, chunk);
+                zero_filled += chunk;
+            }
+            offset += chunk;
+        }
+        return zero_filled;
+    }
+
+    // Poisoned pages are zero-filled here, and the runs of pages between them by the backend.
+    let mut run = 0;
+    let mut offset = 0;
+    while offset < len {
+        let chunk = cmp::min(page_size - (start + offset) % page_size, len - offset);
+        if poisoned(start + offset, chunk).is_some() {
+            zero_filled += backend::load_bytes_zero_fill(
+                loc.wrapping_add(run),
+                dst[run..].as_mut_ptr(),
+                offset - run,
+                page_size,
+            );
+            ptr::write_bytes(dst[offset..].as_mut_ptr(), 0, chunk);
+            zero_filled += chunk;
+            run = offset + chunk;
+        }
+        offset += chunk;
+    }
+    zero_filled
+        + backend::load_bytes_zero_fill(
+            loc.wrapping_add(run),
+            dst[run..].as_mut_ptr(),
+            len - run,
+            page_size,
+        )
+}
+
+/// Stores `size` bytes from `src` to `loc`.
+#[inline]
+unsafe fn store_bytes(
+    via: Backend,
+    loc: *mut c_void,
+    src: *const c_void,
+    size: usize,
+) -> Result<(), Fault> {
+    if let Some(fault) = poisoned(loc as usize, size) {
+        return Err(fault);
+    }
+    access(|| match via {
+        Backend::Signal => check(backend::store_bytes(loc, src, size)),
+        _ => runtime::store(via, loc as usize, src as *const u8, size),
+    })
+}
+
+/// Stores the leading bytes of `src` to `loc`, up to the first page that cannot be written. Returns
+/// the number of bytes written.
+unsafe fn store_bytes_prefix(via: Backend, loc: *mut u8, src: &[u8]) -> usize {
+    let page_size = probe::page_size();
+    let start = loc as usize;
+
+    // Stops at the first poisoned page, and the backend at the first unwritable one. Other
+    // backends store page by page.
+    let mut len = 0;
+    while len < src.len() {
+        let chunk = cmp::min(page_size - (start + len) % page_size, src.len() - len);
+        if poisoned(start + len, chunk).is_some() {
+            break;
+        }
+        let from = src[len..].as_ptr();
+        if via != Backend::Signal && runtime::store(via, start + len, from, chunk).is_err() {
+            return len;
+        }
+        len += chunk;
+    }
+    if via != Backend::Signal {
+        return len;
+    }
+    backend::store_bytes_prefix(loc, src.as_ptr(), len, page_size)
+}
+
+/// Loads `size` bytes from `loc` to `old`, and then stores `size` bytes from `src` to `loc`.
+#[inline]
+unsafe fn exchange_bytes(
+    via: Backend,
+    loc: *mut c_void,
+    src: *const c_void,
+    old: *mut c_void,
+    size: usize,
+) -> Result<(), Fault> {
+    if let Some(fault) = poisoned(loc as usize, size) {
+        return Err(fault);
+    }
+    access(|| match via {
+        Backend::Signal => check(backend::exchange_bytes(loc, src, old, size)),
+        _ => {
+            runtime::load(via, loc as usize, old as *mut u8, size)?;
+            runtime::store(via, loc as usize, src as *const u8, size)
+        }
+    })
+}
+
+/// Copies `size` bytes from `src` to `dst`, which may overlap.
+#[inline]
+unsafe fn move_bytes(
+    via: Backend,
+    src: *const c_void,
+    dst: *mut c_void,
+    size: usize,
+) -> Result<(), Fault> {
+    if let Some(fault) = poisoned(src as usize, size).or_else(|| poisoned(dst as usize, size)) {
+        return Err(fault);
+    }
+    access(|| match via {
+        Backend::Signal => check(backend::move_bytes(src, dst, size)),
+        _ => {
+            // Through a buffer, as the ranges may overlap.
+            let mut buffer = vec![0u8; size];
+            runtime::load(via, src as usize, buffer.as_mut_ptr(), size)?;
+            runtime::store(via, dst as usize, buffer.as_ptr(), size)
+        }
+    })
+}
+
+/// The closure and its result, passed through `bulletproof_impl_protect()`.
+struct Protected<F, R> {
+    f: Option<F>,
+    result: Option<Result<R, Box<dyn Any + Send>>>,
+}
+
+/// Calls the closure in `data`, a `Protected<F, R>`, catching panics so that they do not unwind
+/// through C frames.
+unsafe extern "C" fn call_protected<F: FnOnce() -> R, R>(data: *mut c_void) {
+    let protected = &mut *(data as *mut Protected<F, R>);
+    let f = protected.f.take().unwrap();
+    protected.result = Some(panic::catch_unwind(AssertUnwindSafe(f)));
+}
+
+/// Calls `f` in a protected section.
+unsafe fn protect<F, R>(f: F) -> Result<R, Fault>
+where
+    F: FnOnce() -> R,
+{
+    let mut protected = Protected { f: Some(f), result: None };
+    check(backend::protect(
+        call_protected::<F, R>,
+        &mut protected as *mut Protected<F, R> as *mut c_void,
+    ))?;
+
+    match protected.result.unwrap() {
+        Ok(result) => Ok(result),
+        Err(payload) => panic::resume_unwind(payload),
+    }
+}
+
+/// Support for the expansion of [`#[bulletproof]`](attr.bulletproof.html) and
+/// [`load_field!`](macro.load_field.html). Not public API.
+#[doc(hidden)]
+pub mod __private {
+    use super::{Bulletproof, Fault, Plain};
 
-use std::mem;
+    /// Calls `f` in a protected section.
+    #[cfg(feature = "macros")]
+    #[inline]
+    pub unsafe fn protect<F, R>(f: F) -> Result<R, Fault>
+    where
+        F: FnOnce() -> R,
+    {
+        super::protect(f)
+    }
 
-use libc::{size_t, c_void};
+    /// Loads the field at `offset` in the `S` at `base`. `_field` projects to the field, only to
+    /// infer its type `F`.
+    #[inline]
+    pub unsafe fn load_field<S, F: Plain>(
+        bulletproof: Bulletproof,
+        base: *const S,
+        offset: usize,
+        _field: fn(&S) -> &F,
+    ) -> Result<F, Fault> {
+        bulletproof.load_field(base, offset)
+    }
+}
 
-extern {
-    fn bulletproof_register() -> size_t;
-    fn bulletproof_load(loc: *const size_t, dst: *mut size_t) -> size_t;
-    fn bulletproof_store(loc: *const size_t, val: size_t) -> size_t;
-    fn bulletproof_load_bytes(loc: *const c_void, dst: *mut c_void, size: size_t) -> size_t;
-    fn bulletproof_store_bytes(loc: *mut c_void, src: *const c_void, size: size_t) -> size_t;
+/// Restores bulletproof memory access in a child process after `fork()`.
+///
+/// After `fork()`, only the forking thread exists in the child, and the signal disposition may
+/// have been changed in between by other code. This function reinstalls the handler and
+/// re-registers the calling thread. It does nothing if [`Bulletproof::new()`] has never been
+/// called.
+///
+/// [`Bulletproof::new()`] already installs a `pthread_atfork()` hook that calls it in every child,
+/// so it is only needed if the child process is created by other means (e.g. a raw `clone()`
+/// system call), or if the child replaces signal handlers itself.
+///
+/// # Safety
+///
+/// The same as [`Bulletproof::new()`]. It is async-signal-safe, so it may be called between
+/// `fork()` and `exec()` of a multithreaded process.
+///
+/// # Panics
+///
+/// Panics if re-registration fails.
+///
+/// [`Bulletproof::new()`]: struct.Bulletproof.html#method.new
+#[inline]
+pub unsafe fn reinit_after_fork() {
+    assert_eq!(
+        backend::reinit_after_fork(),
+        0,
+        "bulletproof_reinit_after_fork() failed",
+    );
+    #[cfg(feature = "tracing")]
+    trace::handlers_reinstalled();
 }
 
 /// Bulletproof loader.
 #[derive(Debug, Clone, Copy)]
-pub struct Bulletproof {}
+pub struct Bulletproof {
+    config: Config,
+    backend: Backend,
+}
 
 impl Bulletproof {
     /// Creates a new bulletproof memory access manager.
     ///
     /// # Safety
     ///
-    /// It registers a new signal handler for `SIGSEGV`. See [`README.md`](/README.md) for more
-    /// details on its impact.
+    /// It registers new signal handlers for `SIGSEGV` and `SIGBUS`. See [`README.md`](/README.md)
+    /// for more details on its impact.
+    ///
+    /// The current thread is registered as well. Other threads should be spawned with
+    /// [`thread::spawn()`](thread/fn.spawn.html) or call
+    /// [`thread::register_current_thread()`](thread/fn.register_current_thread.html) before
+    /// performing bulletproof memory access.
+    ///
+    /// # Panics
+    ///
+    /// Panics if registration fails. See [`try_new()`](#method.try_new) for a non-panicking
+    /// variant.
     #[inline]
     pub unsafe fn new() -> Self {
-        assert_eq!(
-            bulletproof_register(),
-            0,
-            "bulletproof_register() failed",
-        );
-        Self {}
+        Self::with_config(Config::new())
+    }
+
+    /// Creates a new bulletproof memory access manager, returning the error if registration fails.
+    ///
+    /// The error tells which step failed, and the error number, e.g. in a container that restricts
+    /// signal handling.
+    ///
+    /// # Safety
+    ///
+    /// The same as [`new()`](#method.new).
+    #[inline]
+    pub unsafe fn try_new() -> Result<Self, RegisterError> {
+        Self::try_with_config(Config::new())
+    }
+
+    /// Creates a new bulletproof memory access manager with the configuration.
+    ///
+    /// # Safety
+    ///
+    /// The same as [`new()`](#method.new). In addition, the fault hooks of `config` should be
+    /// async-signal-safe: see
+    /// [`Config::fault_hooks()`](config/struct.Config.html#method.fault_hooks).
+    ///
+    /// # Panics
+    ///
+    /// Panics if registration fails.
+    #[inline]
+    pub unsafe fn with_config(config: Config) -> Self {
+        match Self::try_with_config(config) {
+            Ok(bulletproof) => bulletproof,
+            Err(error) => panic!("bulletproof_register() failed: {}", error),
+        }
+    }
+
+    /// Creates a new bulletproof memory access manager with the configuration, returning the error
+    /// if registration fails.
+    ///
+    /// # Safety
+    ///
+    /// The same as [`new()`](#method.new). In addition, the fault hooks of `config` should be
+    /// async-signal-safe: see
+    /// [`Config::fault_hooks()`](config/struct.Config.html#method.fault_hooks).
+    #[inline]
+    pub unsafe fn try_with_config(config: Config) -> Result<Self, RegisterError> {
+        registration::register_permanently(config)
+    }
+
+    /// Installs the signal handlers and fault hooks of `config`, and registers the current thread.
+    unsafe fn install(config: Config) -> Result<Self, RegisterError> {
+        backend::set_altstack_size(config.get_altstack_size());
+        let result = backend::register(&config);
+        #[cfg(feature = "tracing")]
+        trace::handlers_installed(&config, result.as_ref().map(|_| ()));
+        result?;
+        backend::record_handlers(&config);
+        hook::install(config.get_fault_hooks())?;
+        thread::try_register_current_thread()?;
+        Ok(Self { config, backend: Backend::Signal })
+    }
+
+    /// Asks the oracle of the configuration about `len` bytes at `addr`. Returns `Ok(true)` if they
+    /// can be accessed directly, `Ok(false)` if there is no oracle or it does not know, and
+    /// `Err(fault)` if it declares them invalid.
+    #[inline]
+    fn ask(&self, addr: usize, len: usize, write: bool) -> Result<bool, Fault> {
+        let oracle = match self.config.get_oracle() {
+            Some(oracle) => oracle,
+            None => return Ok(false),
+        };
+        if let Some(fault) = poisoned(addr, len) {
+            return Err(fault);
+        }
+        match oracle.check(addr, len, write) {
+            oracle::Permission::Valid => Ok(true),
+            oracle::Permission::Invalid => Err(Fault::Segv { addr, code: fault::SEGV_MAPERR }),
+            oracle::Permission::Unknown => Ok(false),
+        }
+    }
+
+    /// Returns the process-wide bulletproof memory access manager with the default configuration.
+    ///
+    /// The signal handlers are registered by the first call only, and later calls hand out the same
+    /// manager, or the same error if registration failed. Libraries deep in a dependency tree can
+    /// call it instead of [`try_new()`](#method.try_new), so that they do not each install the
+    /// handlers again. The current thread is registered on every call.
+    ///
+    /// # Safety
+    ///
+    /// The same as [`new()`](#method.new).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bulletproof::Bulletproof;
+    ///
+    /// unsafe {
+    ///     let bulletproof = Bulletproof::global().unwrap();
+    ///     assert!(bulletproof.load_usize(std::ptr::null()).is_err());
+    /// }
+    /// ```
+    pub unsafe fn global() -> Result<Self, RegisterError> {
+        static GLOBAL: OnceLock<Result<Bulletproof, RegisterError>> = OnceLock::new();
+
+        let mut registered = false;
+        let result = *GLOBAL.get_or_init(|| {
+            registered = true;
+            Self::try_new()
+        });
+        if !registered {
+            thread::try_register_current_thread()?;
+        }
+        result
+    }
+
+    /// Creates a new bulletproof memory access manager without installing signal handlers.
+    ///
+    /// The current thread is registered as in [`new()`](#method.new).
+    ///
+    /// # Safety
+    ///
+    /// The caller should have installed `SIGSEGV` and `SIGBUS` handlers that call
+    /// [`handler::segv_handler()`](handler/fn.segv_handler.html). Otherwise, invalid memory
+    /// accesses crash the process.
+    #[inline]
+    pub unsafe fn with_external_handler() -> Self {
+        thread::register_current_thread();
+        Self { config: Config::new(), backend: Backend::Signal }
+    }
+
+    /// Creates a new bulletproof memory access manager performing the accesses with `backend`,
+    /// returning the error if it is not available.
+    ///
+    /// With [`Backend::Signal`](enum.Backend.html#variant.Signal), it is the same as
+    /// [`try_new()`](#method.try_new). The other backends install no signal handler and need no
+    /// thread registration, but cannot run protected sections. Pass
+    /// [`Backend::detect()`](enum.Backend.html#method.detect) to pick the best one at runtime.
+    ///
+    /// # Safety
+    ///
+    /// The same as [`new()`](#method.new) for `Backend::Signal`. For the other backends, see the
+    /// caveats of each [`Backend`](enum.Backend.html).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of step [`RegisterStep::Backend`](error/enum.RegisterStep.html) if the
+    /// backend is not available in this process, and the registration error for
+    /// `Backend::Signal`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bulletproof::{Backend, Bulletproof};
+    ///
+    /// let x = 42usize;
+    ///
+    /// unsafe {
+    ///     let bulletproof = match Bulletproof::with_backend(Backend::detect()) {
+    ///         Ok(bulletproof) => bulletproof,
+    ///         Err(_) => return,
+    ///     };
+    ///     assert_eq!(bulletproof.load_usize(&x), Ok(42));
+    /// #   #[cfg(not(bulletproof_fallback))]
+    ///     assert!(bulletproof.load_usize(std::ptr::null()).is_err());
+    /// }
+    /// ```
+    pub unsafe fn with_backend(backend: Backend) -> Result<Self, RegisterError> {
+        if backend == Backend::Signal {
+            return Self::try_new();
+        }
+        if !backend.is_available() {
+            return Err(RegisterError::new(RegisterStep::Backend(backend), 0));
+        }
+        Ok(Self { config: Config::new(), backend })
+    }
+
+    /// Returns the configuration.
+    #[inline]
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Returns the backend performing the accesses.
+    #[inline]
+    pub fn backend(&self) -> Backend {
+        self.backend
+    }
+
+    /// Returns the accumulated costs of the accesses of the process, separately for the accesses
+    /// that succeeded and for those that faulted (`bench` feature).
+    ///
+    /// See the [`bench`](bench/index.html) module for more details.
+    #[cfg(feature = "bench")]
+    #[inline]
+    pub fn timing_stats(&self) -> bench::TimingStats {
+        bench::stats()
     }
 
     /// Loads a usize from the location.
     ///
-    /// Returns `Ok(v)` if `location` contains `v`, and `Err(())` if the location is invalid.
+    /// Returns `Ok(v)` if `location` contains `v`, and `Err(fault)` if the location is invalid.
     ///
     /// # Safety
     ///
@@ -100,18 +827,23 @@ impl Bulletproof {
     /// [`std::ptr::read()`](https://doc.rust-lang.org/stable/std/ptr/fn.read.html), except that it
     /// can be an invalid pointer.
     #[inline]
-    pub unsafe fn load_usize(self, location: *const usize) -> Result<usize, ()> {
-        let mut result: usize = mem::uninitialized();
-        if bulletproof_load(location, &mut result) != 0 {
-            return Err(());
+    pub unsafe fn load_usize(self, location: *const usize) -> Result<usize, Fault> {
+        if self.ask(location as usize, mem::size_of::<usize>(), false)? {
+            return Ok(ptr::read(location));
         }
-
-        Ok(result)
+        let mut result = MaybeUninit::<usize>::uninit();
+        self.config.retry(|| load_word(self.backend, location, result.as_mut_ptr()))?;
+        Ok(result.assume_init())
     }
 
-    /// Loads a value of type `T` from the location.
+    /// Loads a u64 from the location.
+    ///
+    /// Unlike [`load()`](#method.load), it is a single access if `location` is aligned, even on
+    /// 32-bit targets where `usize` is 32 bits (e.g. i686 and ARMv7), so that it does not tear
+    /// against concurrent stores of the field. On the rare targets without lock-free 64-bit
+    /// atomics, it is two word accesses.
     ///
-    /// Returns `Ok(v)` if `location` contains `v`, and `Err(())` if the location is invalid.
+    /// Returns `Ok(v)` if `location` contains `v`, and `Err(fault)` if the location is invalid.
     ///
     /// # Safety
     ///
@@ -119,84 +851,2106 @@ impl Bulletproof {
     /// [`std::ptr::read()`](https://doc.rust-lang.org/stable/std/ptr/fn.read.html), except that it
     /// can be an invalid pointer.
     #[inline]
-    pub unsafe fn load<T>(self, location: *const T) -> Result<T, ()> {
-        let mut result: T = mem::uninitialized();
-        let buffer = &mut result as *mut T as *mut c_void;
-        if bulletproof_load_bytes(
-            location as *const c_void,
-            buffer,
-            mem::size_of::<T>(),
-        ) != 0 {
-            return Err(());
+    pub unsafe fn load_u64(self, location: *const u64) -> Result<u64, Fault> {
+        // A direct load could tear on 32-bit targets, so only the faults of the oracle are taken.
+        if self.ask(location as usize, mem::size_of::<u64>(), false)?
+            && cfg!(target_pointer_width = "64")
+        {
+            return Ok(ptr::read(location));
         }
-
-        Ok(result)
+        let mut result = MaybeUninit::<u64>::uninit();
+        self.config.retry(|| load_u64(self.backend, location, result.as_mut_ptr()))?;
+        Ok(result.assume_init())
     }
 
-    /// Stores a usize to the location.
+    /// Loads a value of type `T` from the location.
     ///
-    /// Returns `Ok(v)` if `location` contains `v`, and `Err(())` if the location is invalid.
+    /// Returns `Ok(v)` if `location` contains `v`, and `Err(fault)` if the location is invalid.
     ///
     /// # Safety
     ///
     /// The location should satisfy the safety guarantee of
-    /// [`std::ptr::write()`](https://doc.rust-lang.org/stable/std/ptr/fn.write.html), except that
-    /// it can be an invalid pointer.
+    /// [`std::ptr::read()`](https://doc.rust-lang.org/stable/std/ptr/fn.read.html), except that it
+    /// can be an invalid pointer.
+    ///
+    /// With the `strict` feature, `T` should be `Copy` and without padding (see
+    /// [`Plain`](trait.Plain.html)).
     #[inline]
-    pub unsafe fn store_usize(self, location: *mut usize, val: usize) -> Result<(), ()> {
-        if bulletproof_store(location, val) != 0 {
-            return Err(());
-        }
+    pub unsafe fn load<T: Plain>(self, location: *const T) -> Result<T, Fault> {
+        self.load_unchecked(location)
+    }
 
-        Ok(())
+    /// Loads a value of type `T` from the location, as [`load()`](#method.load) without the
+    /// `strict` bound.
+    #[inline]
+    pub(crate) unsafe fn load_unchecked<T>(self, location: *const T) -> Result<T, Fault> {
+        if self.ask(location as usize, mem::size_of::<T>(), false)? {
+            return Ok(ptr::read(location));
+        }
+        let mut result = MaybeUninit::<T>::uninit();
+        self.config.retry(|| {
+            load_bytes(
+                self.backend,
+                location as *const c_void,
+                result.as_mut_ptr() as *mut c_void,
+                mem::size_of::<T>(),
+            )
+        })?;
+        Ok(result.assume_init())
     }
 
-    /// Stores a value of type `T` to the location.
+    /// Loads an array of `N` values of type `T` from the location.
     ///
-    /// Returns `Ok(())` if `location` is valid, and `Err(())` if the location is invalid.
+    /// Returns `Ok(a)` if `location` contains `a`, and `Err(fault)` if the location is invalid.
     ///
     /// # Safety
     ///
     /// The location should satisfy the safety guarantee of
-    /// [`std::ptr::write()`](https://doc.rust-lang.org/stable/std/ptr/fn.write.html), except that
-    /// it can be an invalid pointer.
+    /// [`std::ptr::read()`](https://doc.rust-lang.org/stable/std/ptr/fn.read.html) for `[T; N]`,
+    /// except that it can be an invalid pointer.
     #[inline]
-    pub unsafe fn store<T>(self, location: *mut T, src: &T) -> Result<(), ()> {
-        if bulletproof_store_bytes(
-            location as *mut c_void,
-            src as *const T as *const c_void,
-            mem::size_of::<T>(),
-        ) != 0 {
-            return Err(());
-        }
+    pub unsafe fn load_array<T: Plain, const N: usize>(
+        self,
+        location: *const T,
+    ) -> Result<[T; N], Fault> {
+        self.load(location as *const [T; N])
+    }
 
-        Ok(())
+    /// Loads the value of type `F` at `offset` bytes in the `S` at `base`, e.g. a field of a large
+    /// struct, without copying the whole struct.
+    ///
+    /// The offset is usually computed with
+    /// [`std::mem::offset_of!`](https://doc.rust-lang.org/stable/std/mem/macro.offset_of.html), as
+    /// [`load_field!`](macro.load_field.html) does, which also infers `F` from the field.
+    ///
+    /// Returns `Ok(v)` if the location contains `v`, and `Err(fault)` if it is invalid.
+    ///
+    /// # Safety
+    ///
+    /// The location `base + offset` should satisfy the safety guarantee of
+    /// [`std::ptr::read()`](https://doc.rust-lang.org/stable/std/ptr/fn.read.html) for `F`, except
+    /// that it can be an invalid pointer.
+    #[inline]
+    pub unsafe fn load_field<S, F: Plain>(self, base: *const S, offset: usize) -> Result<F, Fault> {
+        self.load((base as *const u8).wrapping_add(offset) as *const F)
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use std::ptr;
-    use super::*;
+    /// Loads a usize from the location, or returns `Ok(None)` if it is null, as
+    /// [`load_opt()`](#method.load_opt).
+    ///
+    /// # Safety
+    ///
+    /// The same as [`load_usize()`](#method.load_usize), unless the location is null.
+    #[inline]
+    pub unsafe fn load_usize_opt(self, location: *const usize) -> Result<Option<usize>, Fault> {
+        if location.is_null() {
+            return Ok(None);
+        }
+        self.load_usize(location).map(Some)
+    }
 
-    #[test]
-    fn bulletproof() {
-        let mut x = 42usize;
-        let y = 42u8;
+    /// Loads a value of type `T` from the location, or returns `Ok(None)` if it is null, e.g. a
+    /// nullable pointer field of a heap object.
+    ///
+    /// Only the null pointer itself is `Ok(None)`: other addresses in the null page fault as
+    /// usual. A null location costs neither a protected access nor a fault.
+    ///
+    /// Returns `Ok(Some(v))` if `location` contains `v`, and `Err(fault)` if the location is
+    /// invalid.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bulletproof::Bulletproof;
+    /// use std::{mem, ptr};
+    ///
+    /// struct Node {
+    ///     value: u32,
+    ///     next: *const Node,
+    /// }
+    ///
+    /// let tail = Node { value: 2, next: ptr::null() };
+    /// let head = Node { value: 1, next: &tail };
+    ///
+    /// unsafe {
+    ///     let bulletproof = Bulletproof::new();
+    ///
+    ///     let mut values = Vec::new();
+    ///     let mut node: *const Node = &head;
+    ///     let offset = mem::offset_of!(Node, value);
+    ///     while let Some(value) = bulletproof.load_field_opt::<Node, u32>(node, offset).unwrap() {
+    ///         values.push(value);
+    ///         node = bulletproof.load(&(*node).next).unwrap();
+    ///     }
+    ///     assert_eq!(values, vec![1, 2]);
+    ///     assert_eq!(bulletproof.load_opt(ptr::null::<u64>()), Ok(None));
+    /// }
+    /// ```
+    ///
+    /// # Safety
+    ///
+    /// The same as [`load()`](#method.load), unless the location is null.
+    #[inline]
+    pub unsafe fn load_opt<T: Plain>(self, location: *const T) -> Result<Option<T>, Fault> {
+        if location.is_null() {
+            return Ok(None);
+        }
+        self.load(location).map(Some)
+    }
 
-        unsafe {
-            let bulletproof = Bulletproof::new();
+    /// Loads the value of type `F` at `offset` bytes in the `S` at `base`, or returns `Ok(None)` if
+    /// `base` is null, as [`load_opt()`](#method.load_opt).
+    ///
+    /// # Safety
+    ///
+    /// The same as [`load_field()`](#method.load_field), unless `base` is null.
+    #[inline]
+    pub unsafe fn load_field_opt<S, F: Plain>(
+        self,
+        base: *const S,
+        offset: usize,
+    ) -> Result<Option<F>, Fault> {
+        if base.is_null() {
+            return Ok(None);
+        }
+        self.load_field(base, offset).map(Some)
+    }
 
-            assert_eq!(bulletproof.load_usize(&x), Ok(42));
-            assert_eq!(bulletproof.load_usize(ptr::null()), Err(()));
+    /// Loads a tagged word from the location, and splits it into the bits in `tag_mask` and the
+    /// others, e.g. the low-bit tag and the address of an aligned pointer (see
+    /// [`decode::untag()`](decode/fn.untag.html)).
+    ///
+    /// Returns `Ok(tagged)` if `location` is valid, and `Err(fault)` otherwise. The payload is not
+    /// loaded from.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bulletproof::decode::Tagged;
+    /// use bulletproof::Bulletproof;
+    ///
+    /// let slot = 0x1000usize | 0b01;
+    ///
+    /// unsafe {
+    ///     let bulletproof = Bulletproof::new();
+    ///
+    ///     let tagged = bulletproof.load_tagged_ptr(&slot, 0b111);
+    ///     assert_eq!(tagged, Ok(Tagged { tag: 0b01, payload: 0x1000 }));
+    /// }
+    /// ```
+    ///
+    /// # Safety
+    ///
+    /// The same as [`load_usize()`](#method.load_usize).
+    #[inline]
+    pub unsafe fn load_tagged_ptr(
+        self,
+        location: *const usize,
+        tag_mask: usize,
+    ) -> Result<Tagged, Fault> {
+        self.load_usize(location).map(|word| decode::untag(word, tag_mask))
+    }
 
-            assert_eq!(bulletproof.store_usize(&mut x, 37), Ok(()));
-            assert_eq!(bulletproof.store_usize(ptr::null_mut(), 37), Err(()));
-            assert_eq!(bulletproof.load_usize(&x), Ok(37));
-            assert_eq!(ptr::read(&x), 37);
+    /// Loads a word from the location, and returns its bits in `range` (bit 0 is the least
+    /// significant), shifted down, e.g. a bit field of an object header (see
+    /// [`decode::bits()`](decode/fn.bits.html)).
+    ///
+    /// Returns `Ok(bits)` if `location` is valid, and `Err(fault)` otherwise.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bulletproof::Bulletproof;
+    ///
+    /// // The age of an object in bits 3 to 6 of its header.
+    /// let header = 0b0010_1000usize;
+    ///
+    /// unsafe {
+    ///     let bulletproof = Bulletproof::new();
+    ///
+    ///     assert_eq!(bulletproof.load_bits(&header, 3..7), Ok(0b0101));
+    /// }
+    /// ```
+    ///
+    /// # Safety
+    ///
+    /// The same as [`load_usize()`](#method.load_usize).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `range` is decreasing or goes past the bits of a word.
+    #[inline]
+    pub unsafe fn load_bits(
+        self,
+        location: *const usize,
+        range: Range<u32>,
+    ) -> Result<usize, Fault> {
+        self.load_usize(location).map(|word| decode::bits(word, range))
+    }
 
-            assert_eq!(bulletproof.load(&y), Ok(42));
-            assert_eq!(bulletproof.load::<[usize; 32]>(ptr::null()), Err(()));
+    /// Loads a NaN-boxed value with a payload of `payload_bits` bits from the location, and
+    /// decodes it into a double, or a tag and a payload (see
+    /// [`decode::unbox()`](decode/fn.unbox.html)).
+    ///
+    /// Returns `Ok(value)` if `location` is valid, and `Err(fault)` otherwise.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bulletproof::decode::NanBoxed;
+    /// use bulletproof::Bulletproof;
+    ///
+    /// let values = [2.5f64.to_bits(), 0xfff9_0000_0000_1000];
+    ///
+    /// unsafe {
+    ///     let bulletproof = Bulletproof::new();
+    ///
+    ///     assert_eq!(bulletproof.load_nan_boxed(&values[0], 47), Ok(NanBoxed::Double(2.5)));
+    ///     let boxed = NanBoxed::Boxed { tag: 0x1fff2, payload: 0x1000 };
+    ///     assert_eq!(bulletproof.load_nan_boxed(&values[1], 47), Ok(boxed));
+    /// }
+    /// ```
+    ///
+    /// # Safety
+    ///
+    /// The same as [`load_u64()`](#method.load_u64).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `payload_bits` is more than 51.
+    #[inline]
+    pub unsafe fn load_nan_boxed(
+        self,
+        location: *const u64,
+        payload_bits: u32,
+    ) -> Result<NanBoxed, Fault> {
+        self.load_u64(location).map(|bits| decode::unbox(bits, payload_bits))
+    }
+
+    /// Loads a value of type `T` from the location, and checks it with `validate`, e.g. whether
+    /// the bytes at a candidate pointer look like an object header.
+    ///
+    /// The validation runs on the loaded copy, which is returned without being copied again.
+    ///
+    /// Returns `Ok(v)` if `location` contains `v` and `validate(&v)` holds,
+    /// `Err(LoadError::Fault(fault))` if the location is invalid, and `Err(LoadError::Invalid)` if
+    /// the validation fails.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bulletproof::{Bulletproof, FromBytes, LoadError};
+    ///
+    /// const MAGIC: u32 = 0xcafe_babe;
+    ///
+    /// #[derive(Clone, Copy)]
+    /// #[repr(C)]
+    /// struct Header {
+    ///     magic: u32,
+    ///     len: u32,
+    /// }
+    ///
+    /// unsafe impl FromBytes for Header {}
+    ///
+    /// let object = Header { magic: MAGIC, len: 16 };
+    /// let garbage = [0u32; 2];
+    ///
+    /// unsafe {
+    ///     let bulletproof = Bulletproof::new();
+    ///     let is_header = |header: &Header| header.magic == MAGIC;
+    ///
+    ///     assert_eq!(bulletproof.load_validated(&object, is_header).map(|h| h.len), Ok(16));
+    ///     let garbage = garbage.as_ptr() as *const Header;
+    ///     let result = bulletproof.load_validated(garbage, is_header);
+    ///     assert_eq!(result.err(), Some(LoadError::Invalid));
+    /// #   #[cfg(not(bulletproof_fallback))]
+    ///     assert!(matches!(
+    ///         bulletproof.load_validated(std::ptr::null(), is_header),
+    ///         Err(LoadError::Fault(_)),
+    ///     ));
+    /// }
+    /// ```
+    ///
+    /// # Safety
+    ///
+    /// Reading the location should have no side effects, except that it can be invalid. As `T` is
+    /// [`FromBytes`](trait.FromBytes.html), the location need not contain a valid `T`.
+    #[inline]
+    pub unsafe fn load_validated<T, F>(
+        self,
+        location: *const T,
+        validate: F,
+    ) -> Result<T, LoadError>
+    where
+        T: FromBytes,
+        F: FnOnce(&T) -> bool,
+    {
+        let value = self.load_unchecked(location)?;
+        if validate(&value) {
+            Ok(value)
+        } else {
+            Err(LoadError::Invalid)
+        }
+    }
+
+    /// Loads `count` values of type `T` from the location into a vector.
+    ///
+    /// Returns `Ok(v)` if `location` contains the elements of `v`, and `Err(fault)` if the location
+    /// is invalid.
+    ///
+    /// # Safety
+    ///
+    /// The location should satisfy the safety guarantee of
+    /// [`std::ptr::read()`](https://doc.rust-lang.org/stable/std/ptr/fn.read.html) for each of the
+    /// `count` values, except that it can be an invalid pointer.
+    ///
+    /// With the `strict` feature, `T` should be `Copy` and without padding (see
+    /// [`Plain`](trait.Plain.html)).
+    ///
+    /// # Panics
+    ///
+    /// Panics if the vector cannot be allocated, e.g. if `count` is too large.
+    pub unsafe fn load_vec<T: Plain>(
+        self,
+        location: *const T,
+        count: usize,
+    ) -> Result<Vec<T>, Fault> {
+        let size = count.checked_mul(mem::size_of::<T>()).expect("capacity overflow");
+        let mut result = Vec::<T>::with_capacity(count);
+        if self.ask(location as usize, size, false)? {
+            ptr::copy_nonoverlapping(location, result.as_mut_ptr(), count);
+            result.set_len(count);
+            return Ok(result);
+        }
+        self.config.retry(|| {
+            load_bytes(
+                self.backend,
+                location as *const c_void,
+                result.as_mut_ptr() as *mut c_void,
+                size,
+            )
+        })?;
+        result.set_len(count);
+        Ok(result)
+    }
+
+    /// Loads a NUL-terminated UTF-16 string of at most `max_code_units` code units (in native byte
+    /// order) from the location, converting unpaired surrogates to `U+FFFD REPLACEMENT CHARACTER`.
+    ///
+    /// It reads strings of JVMs, the CLR, or Windows-origin data structures. See
+    /// [`load_wstr_units()`](#method.load_wstr_units) for a strict conversion.
+    ///
+    /// Returns `Ok(s)` with the string before the NUL, or with the first `max_code_units` code
+    /// units if there is no NUL among them, and `Err(fault)` if the location is invalid before
+    /// then.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bulletproof::Bulletproof;
+    ///
+    /// let wide: Vec<u16> = "héllo\0".encode_utf16().collect();
+    ///
+    /// unsafe {
+    ///     let bulletproof = Bulletproof::new();
+    ///
+    ///     assert_eq!(bulletproof.load_wstr(wide.as_ptr(), 64), Ok("héllo".to_string()));
+    ///     assert_eq!(bulletproof.load_wstr(wide.as_ptr(), 2), Ok("hé".to_string()));
+    ///     let unpaired = [0xd800, 0];
+    ///     assert_eq!(bulletproof.load_wstr(unpaired.as_ptr(), 64), Ok("\u{fffd}".to_string()));
+    /// }
+    /// ```
+    ///
+    /// # Safety
+    ///
+    /// The location should be aligned to `u16`, and reading the string should have no side
+    /// effects, except that it can be invalid.
+    pub unsafe fn load_wstr(
+        self,
+        location: *const u16,
+        max_code_units: usize,
+    ) -> Result<String, Fault> {
+        let units = self.load_wstr_units(location, max_code_units)?;
+        Ok(String::from_utf16_lossy(&units))
+    }
+
+    /// Loads the code units of a NUL-terminated UTF-16 string, as
+    /// [`load_wstr()`](#method.load_wstr) without the conversion.
+    ///
+    /// The string is read up to the end of a page at a time, so that the pages after the NUL are
+    /// not accessed. Strict conversion is
+    /// [`String::from_utf16()`](https://doc.rust-lang.org/stable/std/string/struct.String.html#method.from_utf16).
+    ///
+    /// # Safety
+    ///
+    /// The same as [`load_wstr()`](#method.load_wstr).
+    pub unsafe fn load_wstr_units(
+        self,
+        location: *const u16,
+        max_code_units: usize,
+    ) -> Result<Vec<u16>, Fault> {
+        let page_size = probe::page_size();
+        let unit = mem::size_of::<u16>();
+
+        let mut units = Vec::new();
+        while units.len() < max_code_units {
+            let start = units.len();
+            let addr = (location as usize).wrapping_add(start * unit);
+            // A unit of an unaligned string may cross the end of the page.
+            let count = cmp::max((page_size - addr % page_size) / unit, 1);
+            let count = cmp::min(count, max_code_units - start);
+
+            units.resize(start + count, 0);
+            let (src, dst) = (addr as *const c_void, units[start..].as_mut_ptr() as *mut c_void);
+            self.config.retry(|| load_bytes(self.backend, src, dst, count * unit))?;
+            if let Some(nul) = units[start..].iter().position(|&unit| unit == 0) {
+                units.truncate(start + nul);
+                break;
+            }
+        }
+        Ok(units)
+    }
+
+    /// Reads `dst.len()` bytes from the location into `dst`.
+    ///
+    /// How faults are handled depends on
+    /// [`Config::recovery()`](config/struct.Config.html#method.recovery):
+    ///
+    /// - With [`Recovery::Abort`](config/enum.Recovery.html#variant.Abort) (default), returns
+    ///   `Err(fault)` on the first fault, and `dst` may be partially written.
+    /// - With [`Recovery::ZeroFill`](config/enum.Recovery.html#variant.ZeroFill), the bytes of the
+    ///   unreadable pages are zero-filled, and the read continues with the next page. It returns
+    ///   `Ok(n)`, where `n` is the number of zero-filled bytes. Bus errors are not retried.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bulletproof::{Bulletproof, Config, Recovery};
+    ///
+    /// let src = [1u8; 16];
+    /// let mut dst = [0u8; 16];
+    ///
+    /// unsafe {
+    ///     let bulletproof = Bulletproof::with_config(Config::new().recovery(Recovery::ZeroFill));
+    ///
+    ///     assert_eq!(bulletproof.read(src.as_ptr(), &mut dst), Ok(0));
+    ///     assert_eq!(dst, src);
+    ///     assert_eq!(bulletproof.read(std::ptr::null(), &mut dst), Ok(16));
+    ///     assert_eq!(dst, [0; 16]);
+    /// }
+    /// ```
+    ///
+    /// # Safety
+    ///
+    /// Reading the location should have no side effects, except that it can be invalid.
+    pub unsafe fn read(self, location: *const u8, dst: &mut [u8]) -> Result<usize, Fault> {
+        match self.config.get_recovery() {
+            Recovery::Abort => {
+                if self.ask(location as usize, dst.len(), false)? {
+                    ptr::copy_nonoverlapping(location, dst.as_mut_ptr(), dst.len());
+                    return Ok(0);
+                }
+                self.config.retry(|| {
+                    load_bytes(
+                        self.backend,
+                        location as *const c_void,
+                        dst.as_mut_ptr() as *mut c_void,
+                        dst.len(),
+                    )
+                })?;
+                Ok(0)
+            }
+            Recovery::ZeroFill => Ok(load_bytes_zero_fill(self.backend, location, dst)),
+        }
+    }
+
+    /// Copies `dst.len()` bytes from the location to `dst` in chunks of `chunk_size` bytes, e.g.
+    /// for multi-gigabyte dumps.
+    ///
+    /// Each chunk is read as [`read()`](#method.read), so that faults are handled per chunk as
+    /// configured by [`Config::recovery()`](config/struct.Config.html#method.recovery). Between
+    /// chunks, `on_progress` is called with the number of bytes copied so far, and the copy stops
+    /// if `cancel` is set. Returns the number of zero-filled bytes.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bulletproof::{Bulletproof, CopyError};
+    /// use std::sync::atomic::{AtomicBool, Ordering};
+    ///
+    /// let src = vec![1u8; 4096];
+    /// let mut dst = vec![0u8; 4096];
+    /// let cancel = AtomicBool::new(false);
+    ///
+    /// unsafe {
+    ///     let bulletproof = Bulletproof::new();
+    ///
+    ///     let mut progress = Vec::new();
+    ///     let copied = bulletproof.copy_chunked(src.as_ptr(), &mut dst, 1024, &cancel, |copied| {
+    ///         progress.push(copied);
+    ///     });
+    ///     assert_eq!(copied, Ok(0));
+    ///     assert_eq!(progress, vec![1024, 2048, 3072, 4096]);
+    ///     assert_eq!(dst, src);
+    ///
+    ///     cancel.store(true, Ordering::SeqCst);
+    ///     let copied = bulletproof.copy_chunked(src.as_ptr(), &mut dst, 1024, &cancel, |_| {});
+    ///     assert_eq!(copied, Err(CopyError::Cancelled { copied: 0 }));
+    /// }
+    /// ```
+    ///
+    /// # Safety
+    ///
+    /// The same as [`read()`](#method.read).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `chunk_size` is zero.
+    pub unsafe fn copy_chunked<F>(
+        self,
+        location: *const u8,
+        dst: &mut [u8],
+        chunk_size: usize,
+        cancel: &AtomicBool,
+        mut on_progress: F,
+    ) -> Result<usize, CopyError>
+    where
+        F: FnMut(usize),
+    {
+        assert!(chunk_size > 0, "chunk size is zero");
+
+        let mut zero_filled = 0;
+        for (index, chunk) in dst.chunks_mut(chunk_size).enumerate() {
+            let offset = index * chunk_size;
+            if cancel.load(Ordering::Relaxed) {
+                return Err(CopyError::Cancelled { copied: offset });
+            }
+            zero_filled += self
+                .read(location.wrapping_add(offset), chunk)
+                .map_err(|fault| CopyError::Fault { offset, fault })?;
+            on_progress(offset + chunk.len());
+        }
+        Ok(zero_filled)
+    }
+
+    /// Compares `baseline.len()` bytes at the location with `baseline` (e.g. a snapshot saved with
+    /// [`read()`](#method.read)), and returns the ranges of offsets of the bytes that changed, in
+    /// ascending order and merged when adjacent.
+    ///
+    /// The region is read a page at a time into a buffer of one page, so that it is not copied as
+    /// a whole. Unmapped pages (`SEGV_MAPERR`) are skipped, as there is nothing to compare.
+    ///
+    /// Returns `Err(fault)` of the first page that is mapped but cannot be read, e.g. because it
+    /// is protected.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bulletproof::Bulletproof;
+    ///
+    /// let mut region = [0u8; 16];
+    ///
+    /// unsafe {
+    ///     let bulletproof = Bulletproof::new();
+    ///
+    ///     let baseline = region;
+    ///     region[2] = 1;
+    ///     region[3] = 1;
+    ///     region[9] = 1;
+    ///     assert_eq!(bulletproof.diff(region.as_ptr(), &baseline), Ok(vec![2..4, 9..10]));
+    /// }
+    /// ```
+    ///
+    /// # Safety
+    ///
+    /// The same as [`read()`](#method.read).
+    pub unsafe fn diff(
+        self,
+        location: *const u8,
+        baseline: &[u8],
+    ) -> Result<Vec<Range<usize>>, Fault> {
+        let page_size = probe::page_size();
+        let (start, len) = (location as usize, baseline.len());
+
+        let mut ranges: Vec<Range<usize>> = Vec::new();
+        let mut page = vec![0u8; cmp::min(page_size, len)];
+        let mut offset = 0;
+        while offset < len {
+            let chunk = cmp::min(page_size - start.wrapping_add(offset) % page_size, len - offset);
+            let (src, dst) = (location.wrapping_add(offset) as *const c_void, page.as_mut_ptr());
+            match self.config.retry(|| load_bytes(self.backend, src, dst as *mut c_void, chunk)) {
+                Ok(()) => {}
+                Err(Fault::Segv { code: fault::SEGV_MAPERR, .. }) => {
+                    offset += chunk;
+                    continue;
+                }
+                Err(fault) => return Err(fault),
+            }
+
+            for (index, (new, old)) in page[..chunk].iter().zip(&baseline[offset..]).enumerate() {
+                if new == old {
+                    continue;
+                }
+                let at = offset + index;
+                match ranges.last_mut() {
+                    Some(range) if range.end == at => range.end = at + 1,
+                    _ => ranges.push(at..at + 1),
+                }
+            }
+            offset += chunk;
+        }
+        Ok(ranges)
+    }
+
+    /// Stores a usize to the location.
+    ///
+    /// Returns `Ok(v)` if `location` contains `v`, and `Err(fault)` if the location is invalid.
+    ///
+    /// # Safety
+    ///
+    /// The location should satisfy the safety guarantee of
+    /// [`std::ptr::write()`](https://doc.rust-lang.org/stable/std/ptr/fn.write.html), except that
+    /// it can be an invalid pointer.
+    #[inline]
+    pub unsafe fn store_usize(self, location: *mut usize, val: usize) -> Result<(), Fault> {
+        if self.ask(location as usize, mem::size_of::<usize>(), true)? {
+            ptr::write(location, val);
+            return Ok(());
+        }
+        self.config.retry(|| store_word(self.backend, location, val))
+    }
+
+    /// Stores a u64 to the location, in a single access if possible as
+    /// [`load_u64()`](#method.load_u64).
+    ///
+    /// Returns `Ok(())` if `location` is valid, and `Err(fault)` if the location is invalid.
+    ///
+    /// # Safety
+    ///
+    /// The location should satisfy the safety guarantee of
+    /// [`std::ptr::write()`](https://doc.rust-lang.org/stable/std/ptr/fn.write.html), except that
+    /// it can be an invalid pointer.
+    #[inline]
+    pub unsafe fn store_u64(self, location: *mut u64, val: u64) -> Result<(), Fault> {
+        // As in `load_u64()`.
+        if self.ask(location as usize, mem::size_of::<u64>(), true)?
+            && cfg!(target_pointer_width = "64")
+        {
+            ptr::write(location, val);
+            return Ok(());
+        }
+        self.config.retry(|| store_u64(self.backend, location, val))
+    }
+
+    /// Stores a value of type `T` to the location.
+    ///
+    /// Returns `Ok(())` if `location` is valid, and `Err(error)` if the location is invalid. If the
+    /// page is mapped but not writable (`SEGV_ACCERR`), the error carries its current permissions
+    /// (see [`StoreError::perms()`](error/struct.StoreError.html#method.perms)), so that the caller
+    /// can tell whether to `mprotect()` it and retry.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bulletproof::Bulletproof;
+    ///
+    /// let mut x = 0usize;
+    ///
+    /// unsafe {
+    ///     let bulletproof = Bulletproof::new();
+    ///
+    ///     assert_eq!(bulletproof.store(&mut x, &42), Ok(()));
+    ///     assert_eq!(x, 42);
+    ///     let error = bulletproof.store(std::ptr::null_mut(), &42usize).unwrap_err();
+    ///     assert_eq!(error.fault().addr(), 0);
+    ///     assert_eq!(error.perms(), None);
+    /// }
+    /// ```
+    ///
+    /// # Safety
+    ///
+    /// The location should satisfy the safety guarantee of
+    /// [`std::ptr::write()`](https://doc.rust-lang.org/stable/std/ptr/fn.write.html), except that
+    /// it can be an invalid pointer.
+    ///
+    /// With the `strict` feature, `T` should be `Copy` and without padding (see
+    /// [`Plain`](trait.Plain.html)).
+    #[inline]
+    pub unsafe fn store<T: Plain>(self, location: *mut T, src: &T) -> Result<(), StoreError> {
+        if self.ask(location as usize, mem::size_of::<T>(), true)? {
+            ptr::copy_nonoverlapping(src, location, 1);
+            return Ok(());
+        }
+        self.config
+            .retry(|| {
+                store_bytes(
+                    self.backend,
+                    location as *mut c_void,
+                    src as *const T as *const c_void,
+                    mem::size_of::<T>(),
+                )
+            })
+            .map_err(store_error)
+    }
+
+    /// Stores as many leading bytes of `src` to the location as are backed by writable memory, and
+    /// returns their number.
+    ///
+    /// It is the counterpart of [`read()`](#method.read) with
+    /// [`Recovery::ZeroFill`](config/enum.Recovery.html#variant.ZeroFill) for writes, e.g. into a
+    /// buffer whose tail may have been unmapped. It stops at the first page that cannot be written,
+    /// even if later ones can. Bus errors are not retried.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bulletproof::Bulletproof;
+    ///
+    /// let mut dst = [0u8; 4];
+    ///
+    /// unsafe {
+    ///     let bulletproof = Bulletproof::new();
+    ///
+    ///     assert_eq!(bulletproof.store_prefix(dst.as_mut_ptr(), &[1, 2, 3]), 3);
+    ///     assert_eq!(dst, [1, 2, 3, 0]);
+    ///     assert_eq!(bulletproof.store_prefix(std::ptr::null_mut(), &[1, 2, 3]), 0);
+    /// }
+    /// ```
+    ///
+    /// # Safety
+    ///
+    /// Writing the location should have no side effects other than modifying it, except that it
+    /// can be invalid.
+    #[inline]
+    pub unsafe fn store_prefix(self, location: *mut u8, src: &[u8]) -> usize {
+        store_bytes_prefix(self.backend, location, src)
+    }
+
+    /// Exchanges the value of type `T` at the location with `new`, returning the previous value.
+    ///
+    /// The load and the store are in a single protected section, so that the pair costs one setup
+    /// (e.g. for forwarding objects in a moving garbage collector). It is not atomic: other threads
+    /// may access the location in between. If only the store faults (e.g. on a read-only page),
+    /// the location is not modified.
+    ///
+    /// Returns `Ok(v)` if `location` contained `v`, and `Err(fault)` if the location is invalid.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bulletproof::Bulletproof;
+    ///
+    /// let mut forward = [0usize; 2];
+    ///
+    /// unsafe {
+    ///     let bulletproof = Bulletproof::new();
+    ///
+    ///     assert_eq!(bulletproof.exchange(&mut forward, &[1, 0x1000]), Ok([0, 0]));
+    ///     assert_eq!(forward, [1, 0x1000]);
+    ///     assert!(bulletproof.exchange(std::ptr::null_mut(), &[0, 0]).is_err());
+    /// }
+    /// ```
+    ///
+    /// # Safety
+    ///
+    /// The location should satisfy the safety guarantees of
+    /// [`std::ptr::read()`](https://doc.rust-lang.org/stable/std/ptr/fn.read.html) and
+    /// [`std::ptr::write()`](https://doc.rust-lang.org/stable/std/ptr/fn.write.html), except that
+    /// it can be an invalid pointer.
+    ///
+    /// With the `strict` feature, `T` should be `Copy` and without padding (see
+    /// [`Plain`](trait.Plain.html)).
+    #[inline]
+    pub unsafe fn exchange<T: Plain>(self, location: *mut T, new: &T) -> Result<T, Fault> {
+        if self.ask(location as usize, mem::size_of::<T>(), true)? {
+            let old = ptr::read(location);
+            ptr::copy_nonoverlapping(new, location, 1);
+            return Ok(old);
+        }
+        let mut old = MaybeUninit::<T>::uninit();
+        self.config.retry(|| {
+            exchange_bytes(
+                self.backend,
+                location as *mut c_void,
+                new as *const T as *const c_void,
+                old.as_mut_ptr() as *mut c_void,
+                mem::size_of::<T>(),
+            )
+        })?;
+        Ok(old.assume_init())
+    }
+
+    /// Writes each of `fields`, a pair of an offset and its bytes, into the object at `base`.
+    ///
+    /// With the [`Backend::Signal`](enum.Backend.html#variant.Signal) backend, the fields are
+    /// written in a single protected section, so that they cost one setup (e.g. for initializing
+    /// the fields of a freshly allocated object in a moving garbage collector). The fields are
+    /// written in order, and those before a faulting field stay written.
+    ///
+    /// Returns `Ok(())` if every field was written, and `Err(error)` with the index of the first
+    /// faulting field otherwise.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bulletproof::Bulletproof;
+    ///
+    /// let mut object = [0u8; 16];
+    ///
+    /// unsafe {
+    ///     let bulletproof = Bulletproof::new();
+    ///     let base = object.as_mut_ptr();
+    ///
+    ///     let header = 0x2au64.to_ne_bytes();
+    ///     assert_eq!(bulletproof.store_fields(base, &[(0, &header), (12, &[7; 4])]), Ok(()));
+    ///     assert_eq!(&object[12..], &[7; 4]);
+    ///     let fields: [(usize, &[u8]); 2] = [(0, &[]), (8, &header)];
+    ///     let error = bulletproof.store_fields(std::ptr::null_mut(), &fields).unwrap_err();
+    ///     assert_eq!(error.index(), 1);
+    /// }
+    /// ```
+    ///
+    /// # Safety
+    ///
+    /// Writing the fields should have no side effects other than modifying them, except that they
+    /// can be invalid.
+    pub unsafe fn store_fields(
+        self,
+        base: *mut u8,
+        fields: &[(usize, &[u8])],
+    ) -> Result<(), StoreFieldsFault> {
+        let mut index = 0;
+        let result = self.config.retry(|| {
+            if self.backend != Backend::Signal || cfg!(any(miri, bulletproof_fallback)) {
+                for (i, &(offset, bytes)) in fields.iter().enumerate() {
+                    index = i;
+                    let dst = base.wrapping_add(offset) as *mut c_void;
+                    store_bytes(self.backend, dst, bytes.as_ptr() as *const c_void, bytes.len())?;
+                }
+                return Ok(());
+            }
+
+            for (i, &(offset, bytes)) in fields.iter().enumerate() {
+                if let Some(fault) = poisoned((base as usize).wrapping_add(offset), bytes.len()) {
+                    index = i;
+                    return Err(fault);
+                }
+            }
+            // Written through a volatile store, so that it is up to date after a fault.
+            let index = &mut index as *mut usize;
+            access(|| {
+                protect(|| {
+                    for (i, &(offset, bytes)) in fields.iter().enumerate() {
+                        ptr::write_volatile(index, i);
+                        ptr::copy_nonoverlapping(
+                            bytes.as_ptr(),
+                            base.wrapping_add(offset),
+                            bytes.len(),
+                        );
+                    }
+                })
+            })
+        });
+        result.map_err(|fault| StoreFieldsFault::new(index, fault))
+    }
+
+    /// Reads `len` bytes at `location` on a dedicated worker thread, and returns a future of the
+    /// copy (`async` feature).
+    ///
+    /// The copy is as [`read()`](#method.read) on the worker thread, with the configuration of
+    /// this manager, so that the calling thread (e.g. a worker thread of an async executor) is
+    /// neither blocked nor required to be registered. See the [`offload`](offload/index.html)
+    /// module for more details.
+    ///
+    /// # Safety
+    ///
+    /// The same as [`read()`](#method.read), for as long as the copy runs.
+    #[cfg(feature = "async")]
+    #[inline]
+    pub unsafe fn read_bytes_async(self, location: usize, len: usize) -> offload::ReadBytes {
+        offload::read_bytes(self, location, len)
+    }
+
+    /// Copies `len` bytes from `src` to `dst`, which may overlap, as
+    /// [`std::ptr::copy()`](https://doc.rust-lang.org/stable/std/ptr/fn.copy.html) (`memmove()`).
+    ///
+    /// Both ranges may be invalid, e.g. for a compacting collector sliding objects within the
+    /// same pages. The copy is in a single protected section. Returns `Err(fault)` if either range
+    /// is invalid, whose address tells which one; a part of the bytes may have been copied then.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bulletproof::Bulletproof;
+    ///
+    /// let mut objects = [1u8, 2, 3, 4, 5, 6];
+    ///
+    /// unsafe {
+    ///     let bulletproof = Bulletproof::new();
+    ///
+    ///     // Slides the last four bytes to the start.
+    ///     let start = objects.as_mut_ptr();
+    ///     assert_eq!(bulletproof.copy_overlapping(start.add(2), start, 4), Ok(()));
+    ///     assert_eq!(objects, [3, 4, 5, 6, 5, 6]);
+    ///
+    ///     let fault = bulletproof.copy_overlapping(start, std::ptr::null_mut(), 4).unwrap_err();
+    ///     assert_eq!(fault.addr(), 0);
+    /// }
+    /// ```
+    ///
+    /// # Safety
+    ///
+    /// Reading `src` and writing `dst` should have no side effects other than modifying `dst`,
+    /// except that they can be invalid.
+    #[inline]
+    pub unsafe fn copy_overlapping(self, src: *const u8, dst: *mut u8, len: usize)
+        -> Result<(), Fault>
+    {
+        self.config.retry(|| {
+            move_bytes(self.backend, src as *const c_void, dst as *mut c_void, len)
+        })
+    }
+
+    /// Scans `[location, location + len)` word by word, and returns the offsets (in bytes from
+    /// `location`) of the words that look like pointers into `heap`.
+    ///
+    /// It is the core of conservative stack and register scanning. Trailing bytes that do not form
+    /// a whole word are ignored. Returns `Err(fault)` if the range is invalid.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bulletproof::Bulletproof;
+    /// use std::mem;
+    ///
+    /// let heap = vec![0u8; 64];
+    /// let heap = heap.as_ptr() as usize..heap.as_ptr() as usize + heap.len();
+    /// let roots = [0, heap.start + 8, 42, heap.end];
+    ///
+    /// unsafe {
+    ///     let bulletproof = Bulletproof::new();
+    ///
+    ///     let offsets = bulletproof
+    ///         .scan_range(roots.as_ptr(), mem::size_of_val(&roots), heap)
+    ///         .unwrap();
+    ///     assert_eq!(offsets, vec![mem::size_of::<usize>()]);
+    /// }
+    /// ```
+    ///
+    /// # Safety
+    ///
+    /// The location should be aligned to `usize`, and reading the range should have no side
+    /// effects.
+    pub unsafe fn scan_range(
+        self,
+        location: *const usize,
+        len: usize,
+        heap: Range<usize>,
+    ) -> Result<Vec<usize>, Fault> {
+        /// The number of words scanned in a protected section.
+        const CHUNK: usize = 4096;
+
+        let word = mem::size_of::<usize>();
+        let words = len / word;
+        if let Some(fault) = poisoned(location as usize, words * word) {
+            return Err(fault);
+        }
+
+        let mut offsets = Vec::new();
+        let mut indexes = vec![0usize; CHUNK.min(words)];
+        // Other backends copy each chunk, and scan the copy.
+        let mut copy = match self.backend {
+            Backend::Signal => Vec::new(),
+            _ => vec![0usize; CHUNK.min(words)],
+        };
+        for chunk in (0..words).step_by(CHUNK) {
+            let mut found = 0;
+            let len = CHUNK.min(words - chunk);
+            self.config.retry(|| match self.backend {
+                Backend::Signal => check(backend::scan(
+                    location.wrapping_add(chunk),
+                    len,
+                    &heap,
+                    indexes.as_mut_ptr(),
+                    &mut found,
+                )),
+                via => {
+                    let dst = copy.as_mut_ptr() as *mut u8;
+                    runtime::load(via, location.wrapping_add(chunk) as usize, dst, len * word)?;
+                    found = 0;
+                    for (index, value) in copy[..len].iter().enumerate() {
+                        if heap.contains(value) {
+                            indexes[found] = index;
+                            found += 1;
+                        }
+                    }
+                    Ok(())
+                }
+            })?;
+            offsets.extend(indexes[..found].iter().map(|&index| (chunk + index) * word));
+        }
+        Ok(offsets)
+    }
+
+    /// Feeds `[location, location + len)` word by word to `classifier`, and returns the number of
+    /// words of each class, and the faults of the pages that could not be read (see
+    /// [`Classification`](heapwalk/struct.Classification.html)).
+    ///
+    /// It is the hot loop of heap verification, e.g. counting the words that are null, point into
+    /// the heap, or point elsewhere. The region is copied 4096 words at a time, in one protected
+    /// section each, and then classified. A chunk that faults is read again a page at a time, and
+    /// the pages that fault are reported and skipped. Trailing bytes that do not form a whole word
+    /// are ignored.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bulletproof::Bulletproof;
+    /// use std::mem;
+    ///
+    /// let heap = vec![0u8; 64];
+    /// let heap = heap.as_ptr() as usize..heap.as_ptr() as usize + heap.len();
+    /// let words = [0, heap.start + 8, 42, heap.start, 0];
+    ///
+    /// unsafe {
+    ///     let bulletproof = Bulletproof::new();
+    ///
+    ///     let class = |word| match word {
+    ///         0 => "null",
+    ///         _ if heap.contains(&word) => "heap",
+    ///         _ => "other",
+    ///     };
+    ///     let classification =
+    ///         bulletproof.classify_words(words.as_ptr(), mem::size_of_val(&words), class);
+    ///     assert_eq!(classification.counts["null"], 2);
+    ///     assert_eq!(classification.counts["heap"], 2);
+    ///     assert_eq!(classification.counts["other"], 1);
+    ///     assert!(classification.faults.is_empty());
+    /// }
+    /// ```
+    ///
+    /// # Safety
+    ///
+    /// The location should be aligned to `usize`, and reading the range should have no side
+    /// effects.
+    pub unsafe fn classify_words<C, F>(
+        self,
+        location: *const usize,
+        len: usize,
+        classifier: F,
+    ) -> Classification<C>
+    where
+        C: Ord,
+        F: Fn(usize) -> C,
+    {
+        /// The number of words copied in a protected section.
+        const CHUNK: usize = 4096;
+
+        let word = mem::size_of::<usize>();
+        let page_size = probe::page_size();
+        let words = len / word;
+
+        let mut classification = Classification { counts: BTreeMap::new(), faults: Vec::new() };
+        let mut buffer = vec![0usize; CHUNK.min(words)];
+        let load = |src: *const usize, dst: &mut [usize]| {
+            let (src, len) = (src as *const c_void, mem::size_of_val(dst));
+            let dst = dst.as_mut_ptr() as *mut c_void;
+            self.config.retry(|| load_bytes(self.backend, src, dst, len))
+        };
+        let mut classify = |values: &[usize]| {
+            for &value in values {
+                *classification.counts.entry(classifier(value)).or_insert(0) += 1;
+            }
+        };
+
+        let mut faults = Vec::new();
+        for chunk in (0..words).step_by(CHUNK) {
+            let count = CHUNK.min(words - chunk);
+            let start = location.wrapping_add(chunk);
+            if load(start, &mut buffer[..count]).is_ok() {
+                classify(&buffer[..count]);
+                continue;
+            }
+
+            let mut index = 0;
+            while index < count {
+                let src = start.wrapping_add(index);
+                let n = cmp::min((page_size - src as usize % page_size) / word, count - index);
+                match load(src, &mut buffer[..n]) {
+                    Ok(()) => classify(&buffer[..n]),
+                    Err(fault) => faults.push(fault),
+                }
+                index += n;
+            }
+        }
+        classification.faults = faults;
+        classification
+    }
+
+    /// Probes which pages overlapping `[location, location + len)` are readable.
+    ///
+    /// See the [`probe`](probe/index.html) module for more details.
+    ///
+    /// # Safety
+    ///
+    /// Reading a page should have no side effects other than populating it, e.g. the range should
+    /// not contain device memory.
+    #[inline]
+    pub unsafe fn probe_range(self, location: *const u8, len: usize) -> PageBitmap {
+        probe::probe(self.backend, location as usize, len, false)
+    }
+
+    /// Probes which pages overlapping `[location, location + len)` are readable and writable.
+    ///
+    /// Writability is probed by atomically adding zero to the first byte of each readable page,
+    /// which preserves the contents but may populate private pages. It is not probed with backends
+    /// other than [`Backend::Signal`](enum.Backend.html#variant.Signal).
+    ///
+    /// # Safety
+    ///
+    /// The same as [`probe_range()`](#method.probe_range).
+    #[inline]
+    pub unsafe fn probe_range_mut(self, location: *mut u8, len: usize) -> PageBitmap {
+        probe::probe(self.backend, location as usize, len, true)
+    }
+
+    /// Prefetches the pages overlapping `[location, location + len)`, so that latency-sensitive
+    /// code scanning them afterwards does not wait for them to be faulted in.
+    ///
+    /// It advises the kernel that the pages will be needed (`madvise(MADV_WILLNEED)`), and then
+    /// reads each of them in one protected loop, as [`probe_range()`](#method.probe_range). Returns
+    /// which pages were faulted in, i.e. are readable.
+    ///
+    /// # Safety
+    ///
+    /// The same as [`probe_range()`](#method.probe_range).
+    #[inline]
+    pub unsafe fn prefetch(self, location: *const u8, len: usize) -> PageBitmap {
+        probe::prefetch(self.backend, location as usize, len)
+    }
+
+    /// Prefetches the pages overlapping `[location, location + len)` in a new thread, as
+    /// [`prefetch()`](#method.prefetch), returning a
+    /// [`JoinHandle`](https://doc.rust-lang.org/stable/std/thread/struct.JoinHandle.html) for the
+    /// pages that were faulted in.
+    ///
+    /// The range may be unmapped while the thread runs; its pages are then reported as not
+    /// readable.
+    ///
+    /// # Safety
+    ///
+    /// The same as [`probe_range()`](#method.probe_range), until the thread finishes.
+    #[inline]
+    pub unsafe fn prefetch_in_background(
+        self,
+        location: *const u8,
+        len: usize,
+    ) -> JoinHandle<PageBitmap> {
+        let (via, start) = (self.backend, location as usize);
+        thread::spawn(move || unsafe { probe::prefetch(via, start, len) })
+    }
+
+    /// Borrows `[location, location + len)` as a slice, after probing that all of its pages are
+    /// readable.
+    ///
+    /// It lets huge read-only regions (e.g. mapped heaps) be scanned without copying them. The
+    /// probe only tells that the pages were readable: the slice is only as valid as the mapping.
+    ///
+    /// Returns `Err(fault)` of reading the first unreadable byte if a page is not readable.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bulletproof::Bulletproof;
+    ///
+    /// let heap = vec![7u8; 4096];
+    ///
+    /// unsafe {
+    ///     let bulletproof = Bulletproof::new();
+    ///
+    ///     let slice = bulletproof.try_slice(heap.as_ptr(), heap.len()).unwrap();
+    ///     assert!(slice.iter().all(|&byte| byte == 7));
+    ///     assert!(bulletproof.try_slice(std::ptr::null(), 16).is_err());
+    /// }
+    /// ```
+    ///
+    /// # Safety
+    ///
+    /// The same as [`probe_range()`](#method.probe_range). In addition, the range should not be
+    /// unmapped, protected, or modified (except through `UnsafeCell`s) during `'a`, as for
+    /// [`std::slice::from_raw_parts()`](https://doc.rust-lang.org/stable/std/slice/fn.from_raw_parts.html).
+    pub unsafe fn try_slice<'a>(self, location: *const u8, len: usize) -> Result<&'a [u8], Fault> {
+        if len == 0 {
+            return Ok(&[]);
+        }
+
+        let pages = probe::probe(self.backend, location as usize, len, false);
+        // Each page that was not readable may have become so since the probe, e.g. after a retried
+        // bus error, so each is loaded from again.
+        for index in (0..pages.len()).filter(|&index| !pages.is_readable(index)) {
+            let addr = cmp::max(pages.page_addr(index), location as usize);
+            let mut byte = 0u8;
+            let dst = &mut byte as *mut u8 as *mut c_void;
+            self.config.retry(|| load_bytes(self.backend, addr as *const c_void, dst, 1))?;
+        }
+        Ok(slice::from_raw_parts(location, len))
+    }
+
+    /// Returns an iterator that borrows `[location, location + len)` page by page, each page (or
+    /// the part of it in the range) as [`try_slice()`](#method.try_slice) just before it is
+    /// yielded.
+    ///
+    /// It lets huge regions be streamed (e.g. into a hasher) without copying them, while a page
+    /// that is not readable is reported as `Err(fault)` and skipped instead of ending the stream.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bulletproof::Bulletproof;
+    ///
+    /// let heap = vec![7u8; 3 * 4096];
+    ///
+    /// unsafe {
+    ///     let bulletproof = Bulletproof::new();
+    ///
+    ///     let mut sum = 0usize;
+    ///     for page in bulletproof.pages(heap.as_ptr(), heap.len()) {
+    ///         sum += page.unwrap().iter().map(|&byte| byte as usize).sum::<usize>();
+    ///     }
+    ///     assert_eq!(sum, 7 * heap.len());
+    /// }
+    /// ```
+    ///
+    /// # Safety
+    ///
+    /// The same as [`try_slice()`](#method.try_slice), for each page yielded during `'a`.
+    #[inline]
+    pub unsafe fn pages<'a>(self, location: *const u8, len: usize) -> Pages<'a> {
+        Pages::new(self, location as usize, len)
+    }
+
+    /// Calls `f` in a protected section.
+    ///
+    /// Returns `Ok(r)` if `f` returns `r`, and `Err(fault)` if a memory access in `f` faults. A
+    /// panic in `f` is propagated.
+    ///
+    /// With the fallback backend (under Miri, or with `--cfg bulletproof_fallback`), faults in `f`
+    /// are not recovered.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the backend is not [`Backend::Signal`](enum.Backend.html#variant.Signal), as faults
+    /// in `f` could not be recovered.
+    ///
+    /// # Safety
+    ///
+    /// If a fault occurs, `f` is abandoned in the middle: the destructors of its local variables
+    /// are not run (they are leaked), and it should not leave shared data in an inconsistent state,
+    /// e.g. by holding a lock. The functions `f` calls should not perform bulletproof memory access
+    /// assuming that faults are recovered by themselves, as nested protected sections are
+    /// supported.
+    #[inline]
+    pub unsafe fn protect<F, R>(self, f: F) -> Result<R, Fault>
+    where
+        F: FnOnce() -> R,
+    {
+        assert!(self.backend == Backend::Signal, "protected sections need the signal backend");
+        let result = protect(f);
+        limit::record(&self.config, result.as_ref().err());
+        result
+    }
+
+    /// Calls `f` in a protected section, converting faults into panics.
+    ///
+    /// Returns `r` if `f` returns `r`. If a memory access in `f` faults, it panics with the
+    /// [`Fault`](fault/enum.Fault.html) as the payload, so that faults can be handled by
+    /// [`std::panic::catch_unwind()`](https://doc.rust-lang.org/stable/std/panic/fn.catch_unwind.html)
+    /// as ordinary panics.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bulletproof::{Bulletproof, Fault};
+    /// use std::{panic, ptr};
+    ///
+    /// # #[cfg(not(bulletproof_fallback))]
+    /// unsafe {
+    ///     let bulletproof = Bulletproof::new();
+    ///
+    ///     let payload = panic::catch_unwind(|| {
+    ///         bulletproof.catch_fault(|| ptr::read_volatile(ptr::null::<usize>()))
+    ///     }).unwrap_err();
+    ///     assert_eq!(payload.downcast_ref::<Fault>().map(Fault::addr), Some(0));
+    /// }
+    /// ```
+    ///
+    /// # Safety
+    ///
+    /// The same as [`protect()`](#method.protect).
+    pub unsafe fn catch_fault<F, R>(self, f: F) -> R
+    where
+        F: FnOnce() -> R,
+    {
+        match self.protect(f) {
+            Ok(result) => result,
+            Err(fault) => panic::panic_any(fault),
+        }
+    }
+
+    /// Checks that faults are recovered and reported correctly on the running platform, by raising
+    /// real ones: loads and stores to the null page, to a read-only page, and to an inaccessible
+    /// page, and a fault in a protected section. It also checks the platform queries the crate relies
+    /// on: the stack bounds of the current thread and the memory map of the process, where
+    /// supported.
+    ///
+    /// The recovery only relies on `sigsetjmp()` and `siglongjmp()`, and is expected to work on any
+    /// Unix-like platform, including x86-64, AArch64 (Linux, macOS, and the BSDs), and RISC-V. Call it at
+    /// startup to find out early if it does not, e.g. on an unusual architecture or under an
+    /// emulator, instead of crashing at the first invalid access. With the fallback backend, and
+    /// with backends other than [`Backend::Signal`](enum.Backend.html#variant.Signal), only the
+    /// null page is checked.
+    ///
+    /// # Safety
+    ///
+    /// The current thread should be registered, as for any bulletproof memory access.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first failed check, and the fault it got. If faults are not recovered at all,
+    /// the process crashes instead.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bulletproof::Bulletproof;
+    ///
+    /// unsafe {
+    ///     let bulletproof = Bulletproof::new();
+    ///     bulletproof.self_test().expect("bulletproof memory access is broken");
+    /// }
+    /// ```
+    pub unsafe fn self_test(self) -> Result<(), SelfTestError> {
+        selftest::run(self)
+    }
+
+    /// Checks that the signal handlers installed by this crate are still installed.
+    ///
+    /// Another library (e.g. a profiler or a crash reporter) may replace the `SIGSEGV` or `SIGBUS`
+    /// handler without chaining to it, after which every invalid bulletproof access crashes the
+    /// process. Call it periodically, or after loading such a library, to find out before. The
+    /// handlers are reinstalled by [`Bulletproof::new()`](#method.new) and its variants.
+    ///
+    /// The handlers are process-wide, so it checks the handlers installed by any manager. It
+    /// returns `Ok(())` if none are installed, e.g. with
+    /// [`with_external_handler()`](#method.with_external_handler), the fallback backend, or
+    /// backends other than [`Backend::Signal`](enum.Backend.html#variant.Signal).
+    ///
+    /// # Errors
+    ///
+    /// Returns the first replaced handler, with the window in which it was replaced: since it was
+    /// installed, or since the last successful check.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bulletproof::Bulletproof;
+    ///
+    /// unsafe {
+    ///     let bulletproof = Bulletproof::new();
+    ///     bulletproof.verify_installed().expect("the handler was replaced");
+    /// }
+    /// ```
+    #[inline]
+    pub fn verify_installed(self) -> Result<(), HandlerConflict> {
+        backend::verify_handlers()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process::Command;
+    use std::{env, hint, ptr};
+    use super::*;
+    use mapping::TestPages;
+
+    /// Runs the test `name` alone in a new process of the test executable, for tests that need a
+    /// process without other tests running, e.g. to fork or to change the signal dispositions.
+    ///
+    /// Returns `true` in the new process, where the test should go on. Otherwise, asserts that the
+    /// test passed in the new process, and returns `false`.
+    pub(crate) fn in_subprocess(name: &str) -> bool {
+        const VAR: &str = "BULLETPROOF_TEST_SUBPROCESS";
+        if env::var_os(VAR).is_some() {
+            return true;
+        }
+
+        let output = Command::new(env::current_exe().unwrap())
+            .args([name, "--exact", "--test-threads=1", "--nocapture"])
+            .env(VAR, "1")
+            .output()
+            .unwrap();
+        assert!(
+            output.status.success(),
+            "{} failed in a subprocess:\n{}{}",
+            name,
+            String::from_utf8_lossy(&output.stdout),
+            String::from_utf8_lossy(&output.stderr),
+        );
+        false
+    }
+
+    #[test]
+    fn bulletproof() {
+        let mut x = 42usize;
+        let y = 42u8;
+
+        unsafe {
+            let bulletproof = Bulletproof::new();
+
+            assert_eq!(bulletproof.load_usize(&x), Ok(42));
+            assert_eq!(bulletproof.load_usize(ptr::null()).map_err(|f| f.addr()), Err(0));
+
+            assert_eq!(bulletproof.store_usize(&mut x, 37), Ok(()));
+            assert_eq!(bulletproof.store_usize(ptr::null_mut(), 37).map_err(|f| f.addr()), Err(0));
+            assert_eq!(bulletproof.load_usize(&x), Ok(37));
+            assert_eq!(ptr::read(&x), 37);
+
+            assert_eq!(bulletproof.load(&y), Ok(42));
+            assert!(bulletproof.load::<[usize; 32]>(ptr::null()).is_err());
+
+            let mut z = 0x0123_4567_89ab_cdefu64;
+            assert_eq!(bulletproof.load_u64(&z), Ok(0x0123_4567_89ab_cdef));
+            assert_eq!(bulletproof.store_u64(&mut z, u64::MAX), Ok(()));
+            assert_eq!(z, u64::MAX);
+            assert_eq!(bulletproof.load_u64(ptr::null()).map_err(|f| f.addr()), Err(0));
+            assert_eq!(bulletproof.store_u64(ptr::null_mut(), 0).map_err(|f| f.addr()), Err(0));
+        }
+    }
+
+    #[test]
+    fn global() {
+        unsafe {
+            let bulletproof = Bulletproof::global().unwrap();
+            assert!(bulletproof.load_usize(ptr::null()).is_err());
+
+            // A thread not spawned by `thread::spawn()` is registered as well.
+            let handle = std::thread::spawn(|| {
+                let bulletproof = Bulletproof::global().unwrap();
+                bulletproof.load_usize(ptr::null()).is_err()
+            });
+            assert!(handle.join().unwrap());
+        }
+    }
+
+    #[test]
+    fn scan_range() {
+        // Spans multiple chunks.
+        let mut roots = vec![0usize; 10000];
+        let heap = 0x10000..0x20000;
+        roots[1] = 0x10000;
+        roots[5000] = 0x1ffff;
+        roots[9999] = 0x20000;
+
+        unsafe {
+            let bulletproof = Bulletproof::new();
+            let word = mem::size_of::<usize>();
+
+            let offsets = bulletproof.scan_range(roots.as_ptr(), 10000 * word, heap.clone());
+            assert_eq!(offsets, Ok(vec![word, 5000 * word]));
+            assert!(bulletproof.scan_range(ptr::null(), 8 * word, heap).is_err());
+        }
+    }
+
+    #[test]
+    #[cfg_attr(any(miri, bulletproof_fallback), ignore)]
+    fn read_zero_fill() {
+        unsafe {
+            // Map 3 pages, whose middle page is inaccessible.
+            let pages = TestPages::new(3);
+            let page_size = pages.page_size();
+            let start = pages.page(0);
+            ptr::write_bytes(start, 1, 3 * page_size);
+            pages.protect(1, libc::PROT_NONE);
+
+            // Starts and ends in the middle of pages.
+            let mut dst = vec![2u8; 2 * page_size];
+            let location = start.add(page_size / 2);
+
+            let bulletproof = Bulletproof::new();
+            assert!(bulletproof.read(location, &mut dst).is_err());
+
+            let bulletproof = Bulletproof::with_config(Config::new().recovery(Recovery::ZeroFill));
+            assert_eq!(bulletproof.read(location, &mut dst), Ok(page_size));
+            assert!(dst[..page_size / 2].iter().all(|&b| b == 1));
+            assert!(dst[page_size / 2..3 * page_size / 2].iter().all(|&b| b == 0));
+            assert!(dst[3 * page_size / 2..].iter().all(|&b| b == 1));
+        }
+    }
+
+    #[test]
+    fn load_field() {
+        #[allow(dead_code)]
+        struct Object {
+            header: u8,
+            fields: [usize; 64],
+            tail: [u32; 2],
+        }
+        let object = Object { header: 1, fields: [2; 64], tail: [3, 4] };
+
+        unsafe {
+            let bulletproof = Bulletproof::new();
+
+            let offset = mem::offset_of!(Object, tail);
+            assert_eq!(bulletproof.load_field(&object, offset), Ok([3u32, 4]));
+            assert_eq!(load_field!(bulletproof, &object, Object, header), Ok(1));
+            assert_eq!(load_field!(bulletproof, &object, Object, fields), Ok([2; 64]));
+            assert!(load_field!(bulletproof, ptr::null(), Object, header).is_err());
+        }
+    }
+
+    #[test]
+    #[cfg_attr(any(miri, bulletproof_fallback), ignore)]
+    fn store_prefix() {
+        unsafe {
+            let bulletproof = Bulletproof::new();
+
+            // Two writable pages followed by a read-only one.
+            let pages = TestPages::new(3);
+            let (page_size, map) = (pages.page_size(), pages.page(0));
+            let third = pages.protect(2, libc::PROT_READ);
+
+            let src = vec![7u8; 2 * page_size];
+            let loc = map.add(page_size + 16);
+            assert_eq!(bulletproof.store_prefix(loc, &src), page_size - 16);
+            assert_eq!(*third.sub(1), 7);
+            assert_eq!(*third, 0);
+            assert_eq!(bulletproof.store_prefix(third, &src), 0);
+            assert_eq!(bulletproof.store_prefix(map, &src[..8]), 8);
+        }
+    }
+
+    #[test]
+    #[cfg_attr(any(miri, bulletproof_fallback), ignore)]
+    fn copy_chunked() {
+        unsafe {
+            // A readable page followed by an inaccessible one.
+            let pages = TestPages::new(2);
+            let (page_size, map) = (pages.page_size(), pages.page(0));
+            ptr::write_bytes(map, 7, page_size);
+            pages.protect(1, libc::PROT_NONE);
+
+            let cancel = AtomicBool::new(false);
+            let mut dst = vec![1u8; 2 * page_size];
+            let bulletproof = Bulletproof::new();
+            let result = bulletproof.copy_chunked(map, &mut dst, page_size / 2, &cancel, |_| {});
+            match result {
+                Err(CopyError::Fault { offset, fault }) => {
+                    assert_eq!(offset, page_size);
+                    assert_eq!(fault.addr(), map as usize + page_size);
+                }
+                result => panic!("unexpected result: {:?}", result),
+            }
+            assert!(dst[..page_size].iter().all(|&byte| byte == 7));
+
+            let bulletproof = Bulletproof::with_config(Config::new().recovery(Recovery::ZeroFill));
+            let mut progress = 0;
+            let result = bulletproof.copy_chunked(map, &mut dst, page_size, &cancel, |copied| {
+                progress = copied;
+            });
+            assert_eq!(result, Ok(page_size));
+            assert_eq!(progress, 2 * page_size);
+            assert!(dst[page_size..].iter().all(|&byte| byte == 0));
+        }
+    }
+
+    #[test]
+    #[cfg_attr(any(miri, bulletproof_fallback), ignore)]
+    fn diff() {
+        unsafe {
+            let bulletproof = Bulletproof::new();
+
+            // Three read-write pages, the second of which is unmapped.
+            let mut pages = TestPages::new(3);
+            let (page_size, map) = (pages.page_size(), pages.page(0));
+            pages.unmap(1);
+
+            let baseline = vec![0u8; 3 * page_size];
+            *map.add(page_size - 1) = 1;
+            *map.add(2 * page_size) = 1;
+            *map.add(2 * page_size + 1) = 1;
+            let ranges = vec![page_size - 1..page_size, 2 * page_size..2 * page_size + 2];
+            assert_eq!(bulletproof.diff(map, &baseline), Ok(ranges));
+
+            let third = pages.protect(2, libc::PROT_NONE);
+            let fault = bulletproof.diff(map, &baseline).unwrap_err();
+            assert_eq!(fault, Fault::Segv { addr: third as usize, code: fault::SEGV_ACCERR });
+        }
+    }
+
+    #[test]
+    #[cfg_attr(any(miri, bulletproof_fallback), ignore)]
+    fn classify_words() {
+        unsafe {
+            let bulletproof = Bulletproof::new();
+
+            // Three read-write pages of words, the second of which is inaccessible.
+            let pages = TestPages::new(3);
+            let (page_size, map) = (pages.page_size(), pages.page(0) as *mut usize);
+            let words = page_size / mem::size_of::<usize>();
+            *map.add(1) = 42;
+            *map.add(2 * words + 3) = 37;
+            let second = pages.protect(1, libc::PROT_NONE);
+
+            let classification = bulletproof.classify_words(map, 3 * page_size, |word| word != 0);
+            assert_eq!(classification.counts[&true], 2);
+            assert_eq!(classification.counts[&false], 2 * words - 2);
+            let fault = Fault::Segv { addr: second as usize, code: fault::SEGV_ACCERR };
+            assert_eq!(classification.faults, vec![fault]);
+        }
+    }
+
+    #[test]
+    #[cfg_attr(any(miri, bulletproof_fallback), ignore)]
+    fn store_perms() {
+        unsafe {
+            let bulletproof = Bulletproof::new();
+
+            let mut pages = TestPages::new(1);
+            let map = pages.protect(0, libc::PROT_READ);
+
+            let error = bulletproof.store(map as *mut usize, &42).unwrap_err();
+            assert_eq!(error.fault(), Fault::Segv { addr: map as usize, code: fault::SEGV_ACCERR });
+            if cfg!(any(target_os = "linux", target_os = "freebsd", target_os = "netbsd")) {
+                assert_eq!(error.perms(), Some(*b"r--p"));
+            }
+
+            pages.unmap(0);
+            let error = bulletproof.store(map as *mut usize, &42).unwrap_err();
+            assert_eq!(error.perms(), None);
+        }
+    }
+
+    #[test]
+    #[cfg_attr(any(miri, bulletproof_fallback), ignore)]
+    fn verify_installed() {
+        if !in_subprocess("tests::verify_installed") {
+            return;
+        }
+
+        unsafe {
+            let bulletproof = Bulletproof::new();
+            assert_eq!(bulletproof.verify_installed(), Ok(()));
+
+            let mut default: libc::sigaction = mem::zeroed();
+            default.sa_sigaction = libc::SIG_DFL;
+            libc::sigaction(libc::SIGBUS, &default, ptr::null_mut());
+            let conflict = bulletproof.verify_installed().unwrap_err();
+            assert_eq!(conflict.sig(), libc::SIGBUS);
+            assert_eq!(conflict.handler(), libc::SIG_DFL);
+            assert!(conflict.last_seen() <= conflict.detected());
+
+            // Reinstalled.
+            let bulletproof = Bulletproof::new();
+            assert_eq!(bulletproof.verify_installed(), Ok(()));
+        }
+    }
+
+    #[test]
+    #[cfg_attr(any(miri, bulletproof_fallback), ignore)]
+    fn copy_overlapping() {
+        unsafe {
+            let bulletproof = Bulletproof::new();
+
+            // A read-write page followed by a read-only one.
+            let mut pages = TestPages::new(2);
+            let (page_size, map) = (pages.page_size(), pages.page(0));
+            let second = pages.protect(1, libc::PROT_READ);
+
+            for i in 0..page_size {
+                *map.add(i) = i as u8;
+            }
+            assert_eq!(bulletproof.copy_overlapping(map, map.add(1), page_size - 1), Ok(()));
+            assert_eq!((*map, *map.add(1), *map.add(page_size - 1)), (0, 0, (page_size - 2) as u8));
+
+            // Faults on either side.
+            let fault = bulletproof.copy_overlapping(map, second, 16).unwrap_err();
+            assert_eq!(fault, Fault::Segv { addr: second as usize, code: fault::SEGV_ACCERR });
+            pages.unmap(1);
+            let fault = bulletproof.copy_overlapping(second, map, 16).unwrap_err();
+            assert_eq!(fault, Fault::Segv { addr: second as usize, code: fault::SEGV_MAPERR });
+        }
+    }
+
+    #[test]
+    #[cfg_attr(any(miri, bulletproof_fallback), ignore)]
+    fn load_wstr() {
+        unsafe {
+            let bulletproof = Bulletproof::new();
+
+            // A readable page followed by an inaccessible one.
+            let pages = TestPages::new(2);
+            let (units, map) = (pages.page_size() / 2, pages.page(0) as *mut u16);
+            let second = pages.protect(1, libc::PROT_NONE);
+
+            // A string ending at the end of the page.
+            let tail = map.add(units - 3);
+            ptr::copy_nonoverlapping([0x61, 0x62, 0].as_ptr(), tail, 3);
+            assert_eq!(bulletproof.load_wstr(tail, usize::MAX), Ok("ab".to_string()));
+            // A string running into the inaccessible page.
+            *tail.add(2) = 0x63;
+            assert_eq!(bulletproof.load_wstr(tail, 3), Ok("abc".to_string()));
+            let fault = bulletproof.load_wstr_units(tail, 4).unwrap_err();
+            assert_eq!(fault.addr(), second as usize);
+        }
+    }
+
+    #[test]
+    fn load_opt() {
+        #[derive(Debug, Clone, Copy, PartialEq)]
+        #[repr(C)]
+        struct Pair(u16, u16);
+        unsafe impl NoPadding for Pair {}
+
+        let x = 42usize;
+        let pair = Pair(1, 2);
+
+        unsafe {
+            let bulletproof = Bulletproof::new();
+
+            assert_eq!(bulletproof.load_usize_opt(&x), Ok(Some(42)));
+            assert_eq!(bulletproof.load_usize_opt(ptr::null()), Ok(None));
+            assert_eq!(bulletproof.load_opt(&pair), Ok(Some(pair)));
+            assert_eq!(bulletproof.load_opt(ptr::null::<Pair>()), Ok(None));
+            let offset = mem::offset_of!(Pair, 1);
+            assert_eq!(bulletproof.load_field_opt::<_, u16>(&pair, offset), Ok(Some(2)));
+            assert_eq!(bulletproof.load_field_opt::<(u8, u16), u16>(ptr::null(), offset), Ok(None));
+            // Only the null pointer itself is `None`.
+            assert!(bulletproof.load_usize_opt(16 as *const usize).is_err());
+        }
+    }
+
+    #[test]
+    fn load_validated() {
+        let words = [0x1234usize, 0, 0x5678];
+
+        unsafe {
+            let bulletproof = Bulletproof::new();
+            let non_zero = |word: &usize| *word != 0;
+
+            assert_eq!(bulletproof.load_validated(&words[0], non_zero), Ok(0x1234));
+            assert_eq!(bulletproof.load_validated(&words[1], non_zero), Err(LoadError::Invalid));
+            // The validation sees the loaded pair, not the location.
+            let pair = words.as_ptr().add(1) as *const [usize; 2];
+            let valid = bulletproof.load_validated(pair, |pair| pair[1] == 0x5678);
+            assert_eq!(valid, Ok([0, 0x5678]));
+            match bulletproof.load_validated(ptr::null::<usize>(), |_| unreachable!()) {
+                Err(LoadError::Fault(fault)) => assert_eq!(fault.addr(), 0),
+                result => panic!("unexpected result: {:?}", result),
+            }
+        }
+    }
+
+    #[test]
+    #[cfg_attr(any(miri, bulletproof_fallback), ignore)]
+    fn try_slice() {
+        unsafe {
+            let bulletproof = Bulletproof::new();
+
+            // A readable page followed by an inaccessible one.
+            let pages = TestPages::new(2);
+            let (page_size, map) = (pages.page_size(), pages.page(0));
+            let second = pages.protect(1, libc::PROT_NONE);
+
+            let slice = bulletproof.try_slice(map.add(16), page_size - 16).unwrap();
+            assert_eq!(slice.as_ptr(), map.add(16) as *const u8);
+            assert_eq!(slice.len(), page_size - 16);
+            let fault = bulletproof.try_slice(map.add(16), page_size).unwrap_err();
+            assert_eq!(fault.addr(), second as usize);
+            assert_eq!(bulletproof.try_slice(second, 0), Ok(&[][..]));
+        }
+    }
+
+    #[test]
+    #[cfg_attr(any(miri, bulletproof_fallback), ignore)]
+    fn try_slice_resolved() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        use config::FaultHook;
+
+        static PAGE: AtomicUsize = AtomicUsize::new(0);
+        static FAULTS: AtomicUsize = AtomicUsize::new(0);
+
+        /// Makes `PAGE` readable on the second fault in it, i.e. after the probe.
+        fn map_on_retry(fault: &Fault) -> bool {
+            let (page, page_size) = (PAGE.load(Ordering::SeqCst), probe::page_size());
+            if page == 0 || fault.addr() < page || fault.addr() >= page + page_size {
+                return false;
+            }
+            if FAULTS.fetch_add(1, Ordering::SeqCst) == 0 {
+                return false;
+            }
+            unsafe { libc::mprotect(page as *mut c_void, page_size, libc::PROT_READ) == 0 }
+        }
+
+        static HOOKS: [FaultHook; 1] = [FaultHook::new(0..usize::MAX, map_on_retry)];
+
+        // In a new process, as the hook is never removed.
+        if !in_subprocess("tests::try_slice_resolved") {
+            return;
+        }
+
+        unsafe {
+            let bulletproof = Bulletproof::with_config(Config::new().fault_hooks(&HOOKS));
+
+            // Two inaccessible pages, of which the first becomes readable after the probe.
+            let pages = TestPages::new(3);
+            let (page_size, map) = (pages.page_size(), pages.page(0));
+            PAGE.store(pages.protect(1, libc::PROT_NONE) as usize, Ordering::SeqCst);
+            let third = pages.protect(2, libc::PROT_NONE);
+
+            let fault = bulletproof.try_slice(map, 3 * page_size).unwrap_err();
+            assert_eq!(fault.addr(), third as usize);
+            assert_eq!(FAULTS.load(Ordering::SeqCst), 2);
+        }
+    }
+
+    #[test]
+    #[cfg_attr(any(miri, bulletproof_fallback), ignore)]
+    fn pages() {
+        unsafe {
+            let bulletproof = Bulletproof::new();
+
+            // A readable page followed by an inaccessible one.
+            let pages = TestPages::new(2);
+            let (page_size, map) = (pages.page_size(), pages.page(0));
+            let second = pages.protect(1, libc::PROT_NONE);
+
+            let mut iter = bulletproof.pages(map.add(16), 2 * page_size - 32);
+            assert_eq!(iter.len(), 2);
+            let first = iter.next().unwrap().unwrap();
+            assert_eq!((first.as_ptr(), first.len()), (map.add(16) as *const u8, page_size - 16));
+            assert_eq!(iter.next().unwrap().map_err(|fault| fault.addr()), Err(second as usize));
+            assert!(iter.next().is_none());
+            assert_eq!(bulletproof.pages(map, 0).count(), 0);
+        }
+    }
+
+    #[test]
+    fn exchange() {
+        let mut pair = [1u64, 2];
+
+        unsafe {
+            let bulletproof = Bulletproof::new();
+
+            assert_eq!(bulletproof.exchange(&mut pair, &[3, 4]), Ok([1, 2]));
+            assert_eq!(pair, [3, 4]);
+            assert!(bulletproof.exchange(ptr::null_mut(), &[5u64, 6]).is_err());
+
+            // The store faults, after the load.
+            #[cfg(not(any(miri, bulletproof_fallback)))]
+            {
+                let pages = TestPages::new(1);
+                let map = pages.protect(0, libc::PROT_READ);
+                let fault = bulletproof.exchange(map as *mut usize, &42).unwrap_err();
+                assert_eq!(fault.code(), fault::SEGV_ACCERR);
+                assert_eq!(bulletproof.load_usize(map as *const usize), Ok(0));
+            }
+        }
+    }
+
+    #[test]
+    #[cfg_attr(any(miri, bulletproof_fallback), ignore)]
+    fn store_fields() {
+        unsafe {
+            let bulletproof = Bulletproof::new();
+            let pages = TestPages::new(2);
+            let (page_size, base) = (pages.page_size(), pages.page(0));
+            let second = pages.protect(1, libc::PROT_READ);
+
+            // The field on the read-only page faults, after the first one is written.
+            let fields: [(usize, &[u8]); 3] = [(8, &[1; 8]), (page_size, &[2; 8]), (16, &[3; 8])];
+            let error = bulletproof.store_fields(base, &fields).unwrap_err();
+            assert_eq!(error.index(), 1);
+            assert_eq!(error.fault().addr(), second as usize);
+            assert_eq!(bulletproof.load::<[u8; 8]>(base.add(8) as *const _), Ok([1; 8]));
+            assert_eq!(bulletproof.load::<[u8; 8]>(base.add(16) as *const _), Ok([0; 8]));
+
+            assert_eq!(bulletproof.store_fields(base, &[fields[2], fields[0]]), Ok(()));
+            assert_eq!(bulletproof.load::<[u8; 8]>(base.add(16) as *const _), Ok([3; 8]));
+        }
+    }
+
+    #[test]
+    fn load_array() {
+        let table = [1u32, 2, 3, 4];
+
+        unsafe {
+            let bulletproof = Bulletproof::new();
+
+            assert_eq!(bulletproof.load_array::<_, 3>(table[1..].as_ptr()), Ok([2, 3, 4]));
+            assert_eq!(bulletproof.load_vec(table.as_ptr(), 2), Ok(vec![1, 2]));
+            assert_eq!(bulletproof.load_vec(table.as_ptr(), 0), Ok(vec![]));
+            assert!(bulletproof.load_array::<u32, 4>(ptr::null()).is_err());
+            assert!(bulletproof.load_vec::<u32>(ptr::null(), 4).is_err());
+        }
+    }
+
+    #[test]
+    #[cfg_attr(any(miri, bulletproof_fallback), ignore)]
+    fn protect() {
+        unsafe {
+            let bulletproof = Bulletproof::new();
+
+            assert_eq!(bulletproof.protect(|| 42), Ok(42));
+            let fault = bulletproof
+                .protect(|| ptr::read_volatile(24 as *const usize))
+                .unwrap_err();
+            assert_eq!(fault.addr(), 24);
+
+            let panicked = panic::catch_unwind(|| bulletproof.protect(|| panic!("inner")));
+            assert!(panicked.is_err());
+            assert_eq!(bulletproof.protect(|| 37), Ok(37));
+        }
+    }
+
+    #[test]
+    #[cfg_attr(any(miri, bulletproof_fallback, feature = "signal-hook"), ignore)]
+    fn exec_violation() {
+        unsafe {
+            let bulletproof = Bulletproof::new();
+
+            // A call to a readable but not executable page, and to an unmapped address.
+            let data = [0u8; 16];
+            let f: extern "C" fn() = mem::transmute(data.as_ptr());
+            let fault = bulletproof.protect(|| f()).unwrap_err();
+            assert_eq!(fault, Fault::ExecViolation { addr: data.as_ptr() as usize, code: 2 });
+
+            let f: extern "C" fn() = mem::transmute(24usize);
+            let fault = bulletproof.protect(|| f()).unwrap_err();
+            assert_eq!(fault, Fault::ExecViolation { addr: 24, code: 1 });
+
+            // Data faults are not affected.
+            let fault = bulletproof.load_usize(24 as *const usize).unwrap_err();
+            assert_eq!(fault, Fault::Segv { addr: 24, code: 1 });
+        }
+    }
+
+    #[test]
+    #[cfg_attr(any(miri, bulletproof_fallback), ignore)]
+    fn stack_overflow() {
+        fn recurse(depth: usize) -> usize {
+            let frame = hint::black_box([depth; 64]);
+            if frame[0] == usize::MAX {
+                return 0;
+            }
+            recurse(depth + 1) + frame[1]
+        }
+
+        let bulletproof = unsafe { Bulletproof::new() };
+        let handle = ::std::thread::Builder::new()
+            .stack_size(256 * 1024)
+            .spawn(move || unsafe {
+                thread::register_current_thread();
+                bulletproof.protect(|| recurse(0)).map_err(|fault| fault.signal())
+            })
+            .unwrap();
+
+        // The handler runs on the alternate signal stack, as the regular one is exhausted.
+        assert_eq!(handle.join().unwrap(), Err(libc::SIGSEGV));
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn foreign_fault() {
+        // In a new process, as the fault would go through the hooks installed by other tests.
+        if !in_subprocess("tests::foreign_fault") {
+            return;
+        }
+
+        unsafe {
+            let _bulletproof = Bulletproof::new();
+
+            let pid = libc::fork();
+            assert!(pid >= 0);
+            if pid == 0 {
+                // Do not leave a core dump.
+                let limit = libc::rlimit { rlim_cur: 0, rlim_max: 0 };
+                libc::setrlimit(libc::RLIMIT_CORE, &limit);
+                ptr::read_volatile(ptr::null::<usize>());
+                libc::_exit(0);
+            }
+
+            let mut status = 0;
+            assert_eq!(libc::waitpid(pid, &mut status, 0), pid);
+            assert!(libc::WIFSIGNALED(status));
+            assert_eq!(libc::WTERMSIG(status), libc::SIGSEGV);
+        }
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn fork() {
+        // In a new process, as the child of a fork could inherit the locks held by other tests.
+        if !in_subprocess("tests::fork") {
+            return;
+        }
+
+        unsafe {
+            let bulletproof = Bulletproof::new();
+
+            let pid = libc::fork();
+            assert!(pid >= 0);
+            if pid == 0 {
+                let status = match bulletproof.load_usize(ptr::null()) {
+                    Err(_) => 0,
+                    Ok(_) => 1,
+                };
+                libc::_exit(status);
+            }
+
+            let mut status = 0;
+            assert_eq!(libc::waitpid(pid, &mut status, 0), pid);
+            assert!(libc::WIFEXITED(status));
+            assert_eq!(libc::WEXITSTATUS(status), 0);
+        }
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn with_backend() {
+        assert_eq!(
+            unsafe { Bulletproof::with_backend(Backend::Signal) }.map(|b| b.backend()),
+            Ok(Backend::Signal),
+        );
+        if !Backend::ProcessVmReadv.is_available() {
+            return;
+        }
+
+        unsafe {
+            let bulletproof = Bulletproof::with_backend(Backend::ProcessVmReadv).unwrap();
+            assert_eq!(bulletproof.backend(), Backend::ProcessVmReadv);
+            bulletproof.self_test().unwrap();
+
+            // A read-write page followed by an inaccessible one.
+            let pages = TestPages::new(2);
+            let (page_size, map) = (pages.page_size(), pages.page(0));
+            let second = pages.protect(1, libc::PROT_NONE);
+
+            let word = map as *mut usize;
+            assert_eq!(bulletproof.store_usize(word, 42), Ok(()));
+            assert_eq!(bulletproof.exchange(word, &37), Ok(42));
+            assert_eq!(bulletproof.load_usize(word), Ok(37));
+            let accerr = Fault::Segv { addr: second as usize, code: fault::SEGV_ACCERR };
+            assert_eq!(bulletproof.load_usize(second as *const usize), Err(accerr));
+            assert_eq!(bulletproof.store_prefix(map.add(page_size - 2), &[1; 4]), 2);
+
+            let bitmap = bulletproof.probe_range(map, 2 * page_size);
+            assert!(bitmap.is_readable(0) && !bitmap.is_readable(1));
+            assert_eq!(bitmap.is_writable(0), None);
+            let roots = [0, map as usize + 8];
+            let heap = map as usize..second as usize;
+            let offsets = bulletproof.scan_range(roots.as_ptr(), mem::size_of_val(&roots), heap);
+            assert_eq!(offsets, Ok(vec![mem::size_of::<usize>()]));
         }
     }
 }
@@ -0,0 +1,139 @@
+//! Per-thread fault counts, for the limits of [`Config`](../config/struct.Config.html).
+
+use std::cell::Cell;
+use std::process;
+use std::time::Instant;
+
+use config::{Config, LimitAction};
+use fault::Fault;
+#[cfg(feature = "tracing")]
+use trace;
+
+/// The faults caught by a thread.
+#[derive(Clone, Copy)]
+struct Counts {
+    /// The faults caught in a row.
+    consecutive: u32,
+    /// The time of the first fault of the current interval.
+    window: Option<Instant>,
+    /// The faults caught in the current interval.
+    in_window: u32,
+}
+
+thread_local! {
+    static COUNTS: Cell<Counts> = const {
+        Cell::new(Counts { consecutive: 0, window: None, in_window: 0 })
+    };
+}
+
+/// Counts the result of an access, given as its fault if any, and takes the limit action if a
+/// limit is exceeded.
+pub(crate) fn record(config: &Config, fault: Option<&Fault>) {
+    let max_consecutive = config.get_max_consecutive_faults();
+    let (max_rate, interval) = config.get_max_fault_rate();
+    if max_consecutive == 0 && max_rate == 0 {
+        return;
+    }
+
+    let fault = match fault {
+        Some(fault) => fault,
+        None => {
+            let _ = COUNTS.try_with(|counts| {
+                counts.set(Counts { consecutive: 0, ..counts.get() });
+            });
+            return;
+        }
+    };
+
+    let exceeded = COUNTS
+        .try_with(|counts| {
+            let mut c = counts.get();
+            c.consecutive += 1;
+            let now = Instant::now();
+            match c.window {
+                Some(start) if now.duration_since(start) < interval => c.in_window += 1,
+                _ => {
+                    c.window = Some(now);
+                    c.in_window = 1;
+                }
+            }
+
+            let exceeded = (max_consecutive != 0 && c.consecutive >= max_consecutive)
+                || (max_rate != 0 && c.in_window >= max_rate);
+            if exceeded {
+                c = Counts { consecutive: 0, window: None, in_window: 0 };
+            }
+            counts.set(c);
+            exceeded
+        })
+        .unwrap_or(false);
+    if !exceeded {
+        return;
+    }
+
+    #[cfg(feature = "tracing")]
+    trace::limit_exceeded(fault);
+    match config.get_on_fault_limit() {
+        LimitAction::Abort => {
+            eprintln!("bulletproof: fault limit exceeded, the last fault: {}", fault);
+            process::abort();
+        }
+        LimitAction::Call(f) => f(fault),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ptr;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    use super::*;
+    use thread;
+    use Bulletproof;
+
+    #[test]
+    fn limits() {
+        static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+        fn on_limit(fault: &Fault) {
+            assert_eq!(fault.addr(), 0);
+            CALLS.fetch_add(1, Ordering::SeqCst);
+        }
+
+        // In a new thread, to count its faults only.
+        thread::spawn(|| unsafe {
+            let word = 42usize;
+
+            let config = Config::new()
+                .max_consecutive_faults(3)
+                .on_fault_limit(LimitAction::Call(on_limit));
+            let bulletproof = Bulletproof::with_config(config);
+            for _ in 0..2 {
+                assert!(bulletproof.load_usize(ptr::null()).is_err());
+            }
+            assert_eq!(bulletproof.load_usize(&word), Ok(42));
+            for _ in 0..2 {
+                assert!(bulletproof.load_usize(ptr::null()).is_err());
+            }
+            assert_eq!(CALLS.load(Ordering::SeqCst), 0);
+            assert!(bulletproof.load_usize(ptr::null()).is_err());
+            assert_eq!(CALLS.load(Ordering::SeqCst), 1);
+
+            // Successful accesses in between do not reset the rate.
+            let config = Config::new()
+                .max_fault_rate(4, Duration::from_secs(3600))
+                .on_fault_limit(LimitAction::Call(on_limit));
+            let bulletproof = Bulletproof::with_config(config);
+            for _ in 0..3 {
+                assert!(bulletproof.load_usize(ptr::null()).is_err());
+                assert_eq!(bulletproof.load_usize(&word), Ok(42));
+            }
+            assert_eq!(CALLS.load(Ordering::SeqCst), 1);
+            assert!(bulletproof.store_usize(ptr::null_mut(), 0).is_err());
+            assert_eq!(CALLS.load(Ordering::SeqCst), 2);
+        })
+        .join()
+        .unwrap();
+    }
+}
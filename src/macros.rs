@@ -0,0 +1,48 @@
+//! Macros.
+
+/// Loads a field of a struct from a pointer to the struct, as
+/// [`Bulletproof::load_field()`](struct.Bulletproof.html#method.load_field), computing the offset
+/// with [`std::mem::offset_of!`](https://doc.rust-lang.org/stable/std/mem/macro.offset_of.html).
+///
+/// `load_field!(bulletproof, base, S, field)` loads `field` of the `S` at `base` (a `*const S`),
+/// returning `Result<F, Fault>` where `F` is the type of the field. Only the field is copied, not
+/// the whole struct. It should be called in an `unsafe` block, with the safety conditions of
+/// [`Bulletproof::load_field()`](struct.Bulletproof.html#method.load_field).
+///
+/// # Examples
+///
+/// ```
+/// # #[macro_use] extern crate bulletproof;
+/// use bulletproof::Bulletproof;
+/// use std::ptr;
+///
+/// #[repr(C)]
+/// struct Header {
+///     magic: u32,
+///     len: usize,
+///     _body: [u8; 4096],
+/// }
+///
+/// # fn main() {
+/// let header = Header { magic: 0xfeed, len: 42, _body: [0; 4096] };
+///
+/// unsafe {
+///     let bulletproof = Bulletproof::new();
+///
+///     assert_eq!(load_field!(bulletproof, &header, Header, len), Ok(42));
+///     assert_eq!(load_field!(bulletproof, &header, Header, magic), Ok(0xfeed));
+///     assert!(load_field!(bulletproof, ptr::null(), Header, len).is_err());
+/// }
+/// # }
+/// ```
+#[macro_export]
+macro_rules! load_field {
+    ($bulletproof:expr, $base:expr, $ty:ty, $field:tt) => {
+        $crate::__private::load_field::<$ty, _>(
+            $bulletproof,
+            $base,
+            ::std::mem::offset_of!($ty, $field),
+            |s: &$ty| &s.$field,
+        )
+    };
+}
@@ -0,0 +1,124 @@
+//! Anonymous mappings, for buffers that should not share pages with other data.
+//!
+//! Fault hooks write their state from the handler, so it should not be in a page they write-protect
+//! (e.g. one shared with a tracked region on the heap).
+
+use std::io;
+#[cfg(test)]
+use std::mem;
+use std::ptr;
+
+#[cfg(test)]
+use libc::c_int;
+use libc::{self, c_void};
+
+#[cfg(test)]
+use probe;
+
+/// A private anonymous read-write mapping, unmapped on drop.
+#[derive(Debug)]
+pub(crate) struct Mapping {
+    ptr: *mut u8,
+    len: usize,
+}
+
+unsafe impl Send for Mapping {}
+unsafe impl Sync for Mapping {}
+
+impl Mapping {
+    /// Maps `len` bytes, which are zero-initialized.
+    pub(crate) fn new(len: usize) -> io::Result<Self> {
+        let len = len.max(1);
+        let ptr = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                -1,
+                0,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self { ptr: ptr as *mut u8, len })
+    }
+
+    /// Returns the address of the mapping.
+    #[inline]
+    pub(crate) fn as_ptr(&self) -> *mut u8 {
+        self.ptr
+    }
+}
+
+impl Drop for Mapping {
+    fn drop(&mut self) {
+        unsafe {
+            libc::munmap(self.ptr as *mut c_void, self.len);
+        }
+    }
+}
+
+/// Pages for tests of accesses that fault part way, e.g. a readable page followed by an
+/// inaccessible one.
+///
+/// The pages are read-write and zero-initialized until protected, and those still mapped are
+/// unmapped on drop.
+#[cfg(test)]
+#[derive(Debug)]
+pub(crate) struct TestPages {
+    ptr: *mut u8,
+    page_size: usize,
+    mapped: Vec<bool>,
+}
+
+#[cfg(test)]
+impl TestPages {
+    /// Maps `count` pages.
+    pub(crate) fn new(count: usize) -> Self {
+        let page_size = probe::page_size();
+        let mapping = Mapping::new(count * page_size).unwrap();
+        let ptr = mapping.as_ptr();
+        mem::forget(mapping);
+        Self { ptr, page_size, mapped: vec![true; count] }
+    }
+
+    /// Returns the page size.
+    #[inline]
+    pub(crate) fn page_size(&self) -> usize {
+        self.page_size
+    }
+
+    /// Returns the address of the `index`-th page.
+    #[inline]
+    pub(crate) fn page(&self, index: usize) -> *mut u8 {
+        self.ptr.wrapping_add(index * self.page_size)
+    }
+
+    /// Sets the protection of the `index`-th page, and returns its address.
+    pub(crate) fn protect(&self, index: usize, prot: c_int) -> *mut u8 {
+        let page = self.page(index);
+        assert_eq!(unsafe { libc::mprotect(page as *mut c_void, self.page_size, prot) }, 0);
+        page
+    }
+
+    /// Unmaps the `index`-th page, and returns its address.
+    pub(crate) fn unmap(&mut self, index: usize) -> *mut u8 {
+        let page = self.page(index);
+        assert_eq!(unsafe { libc::munmap(page as *mut c_void, self.page_size) }, 0);
+        self.mapped[index] = false;
+        page
+    }
+}
+
+#[cfg(test)]
+impl Drop for TestPages {
+    fn drop(&mut self) {
+        for index in 0..self.mapped.len() {
+            if self.mapped[index] {
+                unsafe { libc::munmap(self.page(index) as *mut c_void, self.page_size) };
+            }
+        }
+    }
+}
@@ -0,0 +1,256 @@
+//! The memory map of the current process, for [`snapshot::regions()`](../snapshot/fn.regions.html).
+//!
+//! - Linux: `/proc/self/maps`.
+//! - FreeBSD and NetBSD: `kinfo_getvmmap()` of libutil.
+//! - OpenBSD: the `KERN_PROC_VMMAP` sysctl, which does not report path names.
+//! - illumos: `/proc/self/map`, whose names are those of the mapped objects in `/proc/self/object`.
+
+use std::io;
+
+#[cfg(not(target_os = "linux"))]
+use libc;
+
+use snapshot::Region;
+
+/// Returns the permission field of a region as in `/proc/self/maps`.
+#[cfg(not(target_os = "linux"))]
+fn perms(read: bool, write: bool, exec: bool, shared: bool) -> [u8; 4] {
+    [
+        if read { b'r' } else { b'-' },
+        if write { b'w' } else { b'-' },
+        if exec { b'x' } else { b'-' },
+        if shared { b's' } else { b'p' },
+    ]
+}
+
+/// Returns the path name of a `kinfo_vmentry`, or `fallback` if it is empty.
+#[cfg(any(target_os = "freebsd", target_os = "netbsd"))]
+fn path(path: &[libc::c_char], fallback: &str) -> String {
+    let bytes = path.iter().take_while(|&&c| c != 0).map(|&c| c as u8).collect::<Vec<_>>();
+    if bytes.is_empty() {
+        return fallback.to_string();
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+/// Parses a line of `/proc/self/maps`.
+#[cfg(target_os = "linux")]
+fn parse(line: &str) -> Option<Region> {
+    let mut fields = line.splitn(6, ' ');
+    let mut range = fields.next()?.splitn(2, '-');
+    let start = usize::from_str_radix(range.next()?, 16).ok()?;
+    let end = usize::from_str_radix(range.next()?, 16).ok()?;
+
+    let perms_field = fields.next()?.as_bytes();
+    if perms_field.len() != 4 {
+        return None;
+    }
+    let mut perms = [0; 4];
+    perms.copy_from_slice(perms_field);
+
+    // Skip the offset, the device, and the inode.
+    let name = fields.nth(3).unwrap_or("").trim_start().to_string();
+    Some(Region {
+        start,
+        end,
+        perms,
+        name,
+    })
+}
+
+#[cfg(target_os = "linux")]
+pub(crate) fn regions() -> io::Result<Vec<Region>> {
+    let maps = ::std::fs::read_to_string("/proc/self/maps")?;
+    maps.lines()
+        .map(|line| {
+            parse(line).ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidData, "malformed /proc/self/maps")
+            })
+        })
+        .collect()
+}
+
+#[cfg(target_os = "freebsd")]
+pub(crate) fn regions() -> io::Result<Vec<Region>> {
+    use std::{mem, slice};
+
+    unsafe {
+        let mut count = 0;
+        let entries = libc::kinfo_getvmmap(libc::getpid(), &mut count);
+        if entries.is_null() {
+            return Err(io::Error::last_os_error());
+        }
+
+        let regions = slice::from_raw_parts(entries, count as usize)
+            .iter()
+            .map(|entry| {
+                let prot = entry.kve_protection;
+                // Declared as an array of arrays in `libc`.
+                let kve_path = slice::from_raw_parts(
+                    entry.kve_path.as_ptr() as *const libc::c_char,
+                    mem::size_of_val(&entry.kve_path),
+                );
+                let device = entry.kve_type == libc::KVME_TYPE_DEVICE
+                    || entry.kve_type == libc::KVME_TYPE_MGTDEVICE
+                    || entry.kve_type == libc::KVME_TYPE_PHYS;
+                Region {
+                    start: entry.kve_start as usize,
+                    end: entry.kve_end as usize,
+                    perms: perms(
+                        prot & libc::KVME_PROT_READ != 0,
+                        prot & libc::KVME_PROT_WRITE != 0,
+                        prot & libc::KVME_PROT_EXEC != 0,
+                        entry.kve_flags & libc::KVME_FLAG_COW == 0,
+                    ),
+                    name: path(kve_path, if device { "[device]" } else { "" }),
+                }
+            })
+            .collect();
+        libc::free(entries as *mut libc::c_void);
+        Ok(regions)
+    }
+}
+
+#[cfg(target_os = "netbsd")]
+pub(crate) fn regions() -> io::Result<Vec<Region>> {
+    use std::slice;
+
+    /// `KVME_TYPE_DEVICE` of `<sys/sysctl.h>`.
+    const KVME_TYPE_DEVICE: u32 = 4;
+
+    unsafe {
+        let mut count = 0;
+        let entries = libc::kinfo_getvmmap(libc::getpid(), &mut count);
+        if entries.is_null() {
+            return Err(io::Error::last_os_error());
+        }
+
+        let regions = slice::from_raw_parts(entries, count)
+            .iter()
+            .map(|entry| {
+                let prot = entry.kve_protection as libc::c_int;
+                let device = entry.kve_type == KVME_TYPE_DEVICE;
+                Region {
+                    start: entry.kve_start as usize,
+                    end: entry.kve_end as usize,
+                    perms: perms(
+                        prot & libc::KVME_PROT_READ != 0,
+                        prot & libc::KVME_PROT_WRITE != 0,
+                        prot & libc::KVME_PROT_EXEC != 0,
+                        entry.kve_flags as libc::c_int & libc::KVME_FLAG_COW == 0,
+                    ),
+                    name: path(&entry.kve_path, if device { "[device]" } else { "" }),
+                }
+            })
+            .collect();
+        libc::free(entries as *mut libc::c_void);
+        Ok(regions)
+    }
+}
+
+#[cfg(target_os = "openbsd")]
+pub(crate) fn regions() -> io::Result<Vec<Region>> {
+    use std::mem;
+
+    let mut regions = Vec::new();
+    let mut entries = vec![unsafe { mem::zeroed::<libc::kinfo_vmentry>() }; 64];
+    let mut mib = [libc::CTL_KERN, libc::KERN_PROC_VMMAP, unsafe { libc::getpid() }];
+    let mut next = 0;
+    loop {
+        // The kernel reports the entries from the start address of the first one.
+        entries[0].kve_start = next;
+        let mut len = entries.len() * mem::size_of::<libc::kinfo_vmentry>();
+        let result = unsafe {
+            libc::sysctl(
+                mib.as_mut_ptr(),
+                mib.len() as libc::c_uint,
+                entries.as_mut_ptr() as *mut libc::c_void,
+                &mut len,
+                ::std::ptr::null_mut(),
+                0,
+            )
+        };
+        if result != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let count = len / mem::size_of::<libc::kinfo_vmentry>();
+        if count == 0 {
+            return Ok(regions);
+        }
+        for entry in &entries[..count] {
+            let prot = entry.kve_protection;
+            let stack = entry.kve_etype & libc::KVE_ET_STACK != 0;
+            regions.push(Region {
+                start: entry.kve_start as usize,
+                end: entry.kve_end as usize,
+                perms: perms(
+                    prot & libc::KVE_PROT_READ != 0,
+                    prot & libc::KVE_PROT_WRITE != 0,
+                    prot & libc::KVE_PROT_EXEC != 0,
+                    entry.kve_etype & libc::KVE_ET_COPYONWRITE == 0,
+                ),
+                name: if stack { "[stack]" } else { "" }.to_string(),
+            });
+        }
+        next = entries[count - 1].kve_end;
+    }
+}
+
+#[cfg(target_os = "illumos")]
+pub(crate) fn regions() -> io::Result<Vec<Region>> {
+    use std::{fs, mem, ptr};
+
+    /// `prmap_t` of `<sys/procfs.h>`.
+    #[repr(C)]
+    #[derive(Clone, Copy)]
+    struct PrMap {
+        pr_vaddr: usize,
+        pr_size: usize,
+        pr_mapname: [libc::c_char; 64],
+        pr_offset: i64,
+        pr_mflags: libc::c_int,
+        pr_pagesize: libc::c_int,
+        pr_shmid: libc::c_int,
+        pr_filler: [libc::c_int; 1],
+    }
+
+    const MA_EXEC: libc::c_int = 0x01;
+    const MA_WRITE: libc::c_int = 0x02;
+    const MA_READ: libc::c_int = 0x04;
+    const MA_SHARED: libc::c_int = 0x08;
+    const MA_BREAK: libc::c_int = 0x10;
+    const MA_STACK: libc::c_int = 0x20;
+
+    let map = fs::read("/proc/self/map")?;
+    if map.len() % mem::size_of::<PrMap>() != 0 {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "malformed /proc/self/map"));
+    }
+
+    Ok(map
+        .chunks(mem::size_of::<PrMap>())
+        .map(|chunk| {
+            let entry = unsafe { ptr::read_unaligned(chunk.as_ptr() as *const PrMap) };
+            let flags = entry.pr_mflags;
+            let name = if flags & MA_BREAK != 0 {
+                "[heap]".to_string()
+            } else if flags & MA_STACK != 0 {
+                "[stack]".to_string()
+            } else {
+                let name = entry.pr_mapname.iter().take_while(|&&c| c != 0);
+                name.map(|&c| c as u8 as char).collect()
+            };
+            Region {
+                start: entry.pr_vaddr,
+                end: entry.pr_vaddr + entry.pr_size,
+                perms: perms(
+                    flags & MA_READ != 0,
+                    flags & MA_WRITE != 0,
+                    flags & MA_EXEC != 0,
+                    flags & MA_SHARED != 0,
+                ),
+                name,
+            }
+        })
+        .collect())
+}
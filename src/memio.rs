@@ -0,0 +1,246 @@
+//! [`Read`](https://doc.rust-lang.org/stable/std/io/trait.Read.html) and
+//! [`Write`](https://doc.rust-lang.org/stable/std/io/trait.Write.html) over possibly-invalid
+//! memory.
+//!
+//! [`MemReader`](struct.MemReader.html) and [`MemWriter`](struct.MemWriter.html) let existing
+//! parsers and encoders (e.g. of `byteorder` or image formats) run directly over a range of memory
+//! that may contain holes. An access stops short before the first page that faults, and the next
+//! one reports the fault as an `io::Error` whose source is the [`Fault`](../fault/enum.Fault.html):
+//! of kind `UnexpectedEof` for bus errors (e.g. past the end of a truncated mapped file), and of
+//! kind `InvalidData` otherwise.
+//!
+//! # Examples
+//!
+//! ```
+//! use std::io::{ErrorKind, Read};
+//!
+//! use bulletproof::memio::MemReader;
+//! use bulletproof::Bulletproof;
+//!
+//! let header = *b"BPRF\x01\x00";
+//!
+//! unsafe {
+//!     let bulletproof = Bulletproof::new();
+//!
+//!     let mut magic = [0u8; 4];
+//!     let mut reader = MemReader::new(bulletproof, header.as_ptr(), header.len());
+//!     reader.read_exact(&mut magic).unwrap();
+//!     assert_eq!(&magic, b"BPRF");
+//!     assert_eq!(reader.position(), 4);
+//!
+//! #   #[cfg(not(bulletproof_fallback))]
+//! #   {
+//!     let mut reader = MemReader::new(bulletproof, std::ptr::null(), 16);
+//!     let error = reader.read_exact(&mut magic).unwrap_err();
+//!     assert_eq!(error.kind(), ErrorKind::InvalidData);
+//! #   }
+//! }
+//! ```
+
+use std::cmp;
+use std::io::{self, Read, Write};
+
+use super::{load_bytes, probe, store_bytes, Bulletproof, Fault};
+use sys::c_void;
+
+/// Converts a fault into an `io::Error`.
+fn error(fault: Fault) -> io::Error {
+    let kind = match fault {
+        Fault::Bus { .. } => io::ErrorKind::UnexpectedEof,
+        Fault::Segv { .. } | Fault::ExecViolation { .. } => io::ErrorKind::InvalidData,
+    };
+    io::Error::new(kind, fault)
+}
+
+/// Accesses the leading pages of a range of `len` bytes at `start` with `access(offset, len)`, up
+/// to the first page that faults. Returns the number of bytes accessed, or the fault if the first
+/// page faults.
+fn prefix<F>(start: usize, len: usize, mut access: F) -> Result<usize, Fault>
+where
+    F: FnMut(usize, usize) -> Result<(), Fault>,
+{
+    // The whole range is usually valid, so try it first.
+    if access(0, len).is_ok() {
+        return Ok(len);
+    }
+
+    let page_size = probe::page_size();
+    let mut offset = 0;
+    while offset < len {
+        let chunk = cmp::min(page_size - (start + offset) % page_size, len - offset);
+        if let Err(fault) = access(offset, chunk) {
+            return if offset == 0 { Err(fault) } else { Ok(offset) };
+        }
+        offset += chunk;
+    }
+    // The faulting page became valid in the meantime.
+    Ok(len)
+}
+
+/// A reader of a range of possibly-invalid memory.
+#[derive(Debug, Clone)]
+pub struct MemReader {
+    /// Witnesses that the handler is installed, and provides the retry policy.
+    bulletproof: Bulletproof,
+    ptr: *const u8,
+    len: usize,
+    pos: usize,
+}
+
+impl MemReader {
+    /// Creates a new reader of the `len` bytes at `ptr`.
+    ///
+    /// Bus errors are retried as configured by the manager's
+    /// [`Config`](../config/struct.Config.html). Its
+    /// [`Recovery`](../config/enum.Recovery.html) is ignored: the faults are reported as errors.
+    ///
+    /// # Safety
+    ///
+    /// Reading the range should have no side effects, except that it can be invalid.
+    #[inline]
+    pub unsafe fn new(bulletproof: Bulletproof, ptr: *const u8, len: usize) -> Self {
+        Self {
+            bulletproof,
+            ptr,
+            len,
+            pos: 0,
+        }
+    }
+
+    /// Returns the number of bytes read so far.
+    #[inline]
+    pub fn position(&self) -> usize {
+        self.pos
+    }
+}
+
+impl Read for MemReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = cmp::min(buf.len(), self.len - self.pos);
+        let start = self.ptr.wrapping_add(self.pos);
+        let config = self.bulletproof.config();
+        let read = prefix(start as usize, len, |offset, len| unsafe {
+            config.retry(|| {
+                load_bytes(
+                    self.bulletproof.backend(),
+                    start.wrapping_add(offset) as *const c_void,
+                    buf[offset..].as_mut_ptr() as *mut c_void,
+                    len,
+                )
+            })
+        })
+        .map_err(error)?;
+        self.pos += read;
+        Ok(read)
+    }
+}
+
+/// A writer to a range of possibly-invalid memory.
+#[derive(Debug)]
+pub struct MemWriter {
+    /// Witnesses that the handler is installed, and provides the retry policy.
+    bulletproof: Bulletproof,
+    ptr: *mut u8,
+    len: usize,
+    pos: usize,
+}
+
+impl MemWriter {
+    /// Creates a new writer to the `len` bytes at `ptr`.
+    ///
+    /// Bus errors are retried as configured by the manager's
+    /// [`Config`](../config/struct.Config.html). Writing past the end of the range writes
+    /// nothing, so that `write_all()` fails with `ErrorKind::WriteZero`.
+    ///
+    /// # Safety
+    ///
+    /// Writing the range should have no side effects other than modifying it, except that it can
+    /// be invalid.
+    #[inline]
+    pub unsafe fn new(bulletproof: Bulletproof, ptr: *mut u8, len: usize) -> Self {
+        Self {
+            bulletproof,
+            ptr,
+            len,
+            pos: 0,
+        }
+    }
+
+    /// Returns the number of bytes written so far.
+    #[inline]
+    pub fn position(&self) -> usize {
+        self.pos
+    }
+}
+
+impl Write for MemWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = cmp::min(buf.len(), self.len - self.pos);
+        let start = self.ptr.wrapping_add(self.pos);
+        let config = self.bulletproof.config();
+        let written = prefix(start as usize, len, |offset, len| unsafe {
+            config.retry(|| {
+                store_bytes(
+                    self.bulletproof.backend(),
+                    start.wrapping_add(offset) as *mut c_void,
+                    buf[offset..].as_ptr() as *const c_void,
+                    len,
+                )
+            })
+        })
+        .map_err(error)?;
+        self.pos += written;
+        Ok(written)
+    }
+
+    #[inline]
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ptr;
+
+    use super::*;
+
+    #[test]
+    #[cfg_attr(any(miri, bulletproof_fallback), ignore)]
+    fn hole() {
+        unsafe {
+            let bulletproof = Bulletproof::new();
+
+            // A readable and writable page followed by an inaccessible one.
+            let page_size = probe::page_size();
+            let prot = libc::PROT_READ | libc::PROT_WRITE;
+            let flags = libc::MAP_PRIVATE | libc::MAP_ANONYMOUS;
+            let map = libc::mmap(ptr::null_mut(), 2 * page_size, prot, flags, -1, 0);
+            assert_ne!(map, libc::MAP_FAILED);
+            let map = map as *mut u8;
+            libc::mprotect(map.add(page_size) as *mut c_void, page_size, libc::PROT_NONE);
+
+            let src = vec![7u8; 2 * page_size];
+            let mut writer = MemWriter::new(bulletproof, map.add(16), 2 * page_size);
+            assert_eq!(writer.write(&src).unwrap(), page_size - 16);
+            let error = writer.write(&src).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+            assert!(error.get_ref().unwrap().is::<Fault>());
+
+            let mut dst = vec![0u8; 2 * page_size];
+            let mut reader = MemReader::new(bulletproof, map.add(16), 2 * page_size);
+            assert_eq!(reader.read(&mut dst).unwrap(), page_size - 16);
+            assert_eq!(dst[..page_size - 16], src[..page_size - 16]);
+            assert_eq!(reader.position(), page_size - 16);
+            assert!(reader.read(&mut dst).is_err());
+
+            let mut reader = MemReader::new(bulletproof, map, 8);
+            assert_eq!(reader.read_to_end(&mut Vec::new()).unwrap(), 8);
+            let mut writer = MemWriter::new(bulletproof, map, 8);
+            assert_eq!(writer.write(&src).unwrap(), 8);
+            assert_eq!(writer.write_all(&src).unwrap_err().kind(), io::ErrorKind::WriteZero);
+
+            libc::munmap(map as *mut c_void, 2 * page_size);
+        }
+    }
+}
@@ -0,0 +1,154 @@
+//! Fault-tolerant reads of memory-mapped files (`memmap2` feature).
+//!
+//! If the file behind a [`memmap2::Mmap`](https://docs.rs/memmap2/0.9/memmap2/struct.Mmap.html) is
+//! truncated (e.g. by another process), accessing the pages past the new end of the file raises
+//! `SIGBUS`, which kills the process. [`MmapReader`](struct.MmapReader.html) reports it as
+//! [`MmapError::FileTruncated`](enum.MmapError.html#variant.FileTruncated) instead.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! # extern crate bulletproof;
+//! # extern crate memmap2;
+//! use bulletproof::Bulletproof;
+//! use bulletproof::mmap::{MmapError, MmapReader};
+//! use memmap2::Mmap;
+//! use std::fs::File;
+//!
+//! # fn main() {
+//! let file = File::open("data.bin").unwrap();
+//! let mmap = unsafe { Mmap::map(&file).unwrap() };
+//! let reader = MmapReader::new(unsafe { Bulletproof::new() }, &mmap);
+//!
+//! let mut header = [0u8; 16];
+//! match reader.read(0, &mut header) {
+//!     Ok(()) => println!("header: {:?}", header),
+//!     Err(MmapError::FileTruncated) => println!("truncated under our feet"),
+//!     Err(e) => println!("error: {}", e),
+//! }
+//! # }
+//! ```
+
+use std::error::Error;
+use std::fmt;
+
+use libc::c_void;
+use memmap2::Mmap;
+
+use super::{load_bytes, Bulletproof, Fault};
+
+/// An error of reading a memory-mapped file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MmapError {
+    /// The requested range is not inside the mapping.
+    OutOfBounds,
+    /// The file was truncated, and the requested range is past its end (`SIGBUS`).
+    FileTruncated,
+    /// The mapping is no longer accessible, e.g. it was unmapped or protected (`SIGSEGV`).
+    Unmapped,
+}
+
+impl fmt::Display for MmapError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            MmapError::OutOfBounds => "range out of bounds of the mapping",
+            MmapError::FileTruncated => "mapped file truncated",
+            MmapError::Unmapped => "mapping no longer accessible",
+        })
+    }
+}
+
+impl Error for MmapError {}
+
+/// A fault-tolerant reader of a memory-mapped file.
+#[derive(Debug, Clone, Copy)]
+pub struct MmapReader<'a> {
+    /// Witnesses that the handler is installed, and provides the retry policy.
+    bulletproof: Bulletproof,
+    mmap: &'a Mmap,
+}
+
+impl<'a> MmapReader<'a> {
+    /// Creates a new reader of `mmap`.
+    #[inline]
+    pub fn new(bulletproof: Bulletproof, mmap: &'a Mmap) -> Self {
+        Self {
+            bulletproof,
+            mmap,
+        }
+    }
+
+    /// Returns the length of the mapping.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.mmap.len()
+    }
+
+    /// Returns `true` if the mapping is empty.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.mmap.is_empty()
+    }
+
+    /// Reads `buf.len()` bytes at `offset` of the mapping into `buf`.
+    ///
+    /// Bus errors are retried as configured by the manager's
+    /// [`Config`](../config/struct.Config.html). On error, the contents of `buf` are unspecified.
+    pub fn read(&self, offset: usize, buf: &mut [u8]) -> Result<(), MmapError> {
+        match offset.checked_add(buf.len()) {
+            Some(end) if end <= self.mmap.len() => {}
+            _ => return Err(MmapError::OutOfBounds),
+        }
+
+        let result = self.bulletproof.config().retry(|| unsafe {
+            load_bytes(
+                self.bulletproof.backend(),
+                self.mmap.as_ptr().add(offset) as *const c_void,
+                buf.as_mut_ptr() as *mut c_void,
+                buf.len(),
+            )
+        });
+
+        result.map_err(|fault| match fault {
+            Fault::Bus { .. } => MmapError::FileTruncated,
+            Fault::Segv { .. } | Fault::ExecViolation { .. } => MmapError::Unmapped,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs::{self, OpenOptions};
+    use std::io::Write;
+    use std::process;
+
+    use super::*;
+
+    #[test]
+    #[cfg_attr(any(miri, bulletproof_fallback), ignore)]
+    fn truncated() {
+        let path = env::temp_dir().join(format!("bulletproof-mmap-{}", process::id()));
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)
+            .unwrap();
+        file.write_all(&[42; 65536]).unwrap();
+
+        let mmap = unsafe { Mmap::map(&file).unwrap() };
+        let reader = MmapReader::new(unsafe { Bulletproof::new() }, &mmap);
+
+        let mut buf = [0u8; 16];
+        assert_eq!(reader.read(0, &mut buf), Ok(()));
+        assert_eq!(buf, [42; 16]);
+        assert_eq!(reader.read(65536, &mut buf), Err(MmapError::OutOfBounds));
+
+        file.set_len(0).unwrap();
+        assert_eq!(reader.read(0, &mut buf), Err(MmapError::FileTruncated));
+
+        fs::remove_file(&path).unwrap();
+    }
+}
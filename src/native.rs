@@ -0,0 +1,406 @@
+//! The native backend, recovering from faults with the signal handler in `impl.c`.
+
+use std::io;
+use std::mem;
+use std::ops::Range;
+use std::ptr;
+use std::sync::Mutex;
+use std::time::Instant;
+#[cfg(feature = "tracing")]
+use std::time::Duration;
+
+use libc;
+
+use config::Config;
+use error::{HandlerConflict, RegisterError, RegisterStep};
+use fault::{Fault, Registers};
+use hook::HookFn;
+use sys::{c_int, c_void, siginfo_t, size_t};
+
+extern "C" {
+    #[cfg(not(feature = "signal-hook"))]
+    fn bulletproof_impl_register(catch: c_int, failed_sig: *mut c_int) -> c_int;
+    #[cfg(not(feature = "signal-hook"))]
+    fn bulletproof_impl_unregister();
+    fn bulletproof_impl_configure(chain: c_int, save_mask: c_int);
+    fn bulletproof_impl_register_thread(failed_step: *mut c_int) -> c_int;
+    fn bulletproof_impl_set_altstack_size(size: size_t);
+    fn bulletproof_impl_reinit_after_fork() -> size_t;
+    fn bulletproof_impl_handle_signal(sig: c_int, info: *mut siginfo_t, ctx: *mut c_void) -> c_int;
+    fn bulletproof_impl_load(loc: *const size_t, dst: *mut size_t) -> size_t;
+    fn bulletproof_impl_store(loc: *const size_t, val: size_t) -> size_t;
+    fn bulletproof_impl_load_u64(loc: *const u64, dst: *mut u64) -> size_t;
+    fn bulletproof_impl_store_u64(loc: *mut u64, val: u64) -> size_t;
+    fn bulletproof_impl_load_bytes(loc: *const c_void, dst: *mut c_void, size: size_t) -> size_t;
+    fn bulletproof_impl_load_bytes_zero_fill(
+        loc: *const u8,
+        dst: *mut u8,
+        size: size_t,
+        page_size: size_t,
+    ) -> size_t;
+    fn bulletproof_impl_store_bytes(loc: *mut c_void, src: *const c_void, size: size_t) -> size_t;
+    fn bulletproof_impl_store_bytes_prefix(
+        loc: *mut u8,
+        src: *const u8,
+        size: size_t,
+        page_size: size_t,
+    ) -> size_t;
+    fn bulletproof_impl_move_bytes(src: *const c_void, dst: *mut c_void, size: size_t) -> size_t;
+    fn bulletproof_impl_exchange_bytes(
+        loc: *mut c_void,
+        src: *const c_void,
+        old: *mut c_void,
+        size: size_t,
+    ) -> size_t;
+    fn bulletproof_impl_scan(
+        loc: *const size_t,
+        words: size_t,
+        heap_start: size_t,
+        heap_end: size_t,
+        out: *mut size_t,
+        found: *mut size_t,
+    ) -> size_t;
+    fn bulletproof_impl_protect(f: unsafe extern "C" fn(*mut c_void), data: *mut c_void) -> size_t;
+    fn bulletproof_impl_probe_range(
+        start: *const u8,
+        page_size: size_t,
+        pages: size_t,
+        readable: *mut u8,
+        writable: *mut u8,
+    );
+    fn bulletproof_impl_add_hook(
+        start: size_t,
+        end: size_t,
+        f: HookFn,
+        data: *mut c_void,
+    ) -> c_int;
+    fn bulletproof_impl_remove_hook(slot: c_int);
+    fn bulletproof_impl_last_fault(
+        sig: *mut c_int,
+        code: *mut c_int,
+        addr: *mut *mut c_void,
+        exec: *mut c_int,
+    );
+    fn bulletproof_impl_flush_icache(start: *mut c_void, len: size_t);
+    fn bulletproof_impl_last_registers(
+        pc: *mut *mut c_void,
+        sp: *mut *mut c_void,
+        fp: *mut *mut c_void,
+    ) -> c_int;
+    fn bulletproof_impl_context_registers(
+        ctx: *mut c_void,
+        pc: *mut *mut c_void,
+        sp: *mut *mut c_void,
+        fp: *mut *mut c_void,
+    ) -> c_int;
+    #[cfg(feature = "tracing")]
+    fn bulletproof_impl_last_fault_time(time: *mut libc::timespec);
+}
+
+/// Installs the signal handlers selected by `config`, and applies its process-wide settings.
+#[cfg(not(feature = "signal-hook"))]
+#[inline]
+pub unsafe fn register(config: &Config) -> Result<(), RegisterError> {
+    /// `CATCH_SEGV` and `CATCH_BUS` in `impl.c`.
+    const CATCH_SEGV: c_int = 1;
+    const CATCH_BUS: c_int = 2;
+
+    configure(config);
+    let mut catch = 0;
+    if config.get_catch_segv() {
+        catch |= CATCH_SEGV;
+    }
+    if config.get_catch_bus() {
+        catch |= CATCH_BUS;
+    }
+
+    let mut failed_sig = 0;
+    match bulletproof_impl_register(catch, &mut failed_sig) {
+        0 => Ok(()),
+        errno => Err(RegisterError::new(RegisterStep::Sigaction(failed_sig), errno)),
+    }
+}
+
+#[cfg(feature = "signal-hook")]
+pub use registry::register;
+
+/// Uninstalls the signal handlers, restoring the dispositions before them.
+#[cfg(not(feature = "signal-hook"))]
+#[inline]
+pub unsafe fn unregister() {
+    bulletproof_impl_unregister()
+}
+
+#[cfg(feature = "signal-hook")]
+pub use registry::unregister;
+
+/// A handler recorded by [`record_handlers()`](fn.record_handlers.html), and when it was last seen
+/// installed.
+struct Installed {
+    sig: c_int,
+    handler: usize,
+    last_seen: Instant,
+}
+
+/// The recorded handlers, to detect their replacement.
+static INSTALLED: Mutex<Vec<Installed>> = Mutex::new(Vec::new());
+
+/// Returns the current handler of `sig`, or `None` if it cannot be queried.
+unsafe fn current_handler(sig: c_int) -> Option<usize> {
+    let mut action: libc::sigaction = mem::zeroed();
+    if libc::sigaction(sig, ptr::null(), &mut action) != 0 {
+        return None;
+    }
+    Some(action.sa_sigaction)
+}
+
+/// Records the handlers of the signals selected by `config`, just installed by `register()`.
+pub unsafe fn record_handlers(config: &Config) {
+    let now = Instant::now();
+    let mut installed = INSTALLED.lock().unwrap();
+    let sigs = [(libc::SIGSEGV, config.get_catch_segv()), (libc::SIGBUS, config.get_catch_bus())];
+    for &(sig, catch) in &sigs {
+        if let (true, Some(handler)) = (catch, current_handler(sig)) {
+            installed.retain(|installed| installed.sig != sig);
+            installed.push(Installed { sig, handler, last_seen: now });
+        }
+    }
+}
+
+/// Forgets the recorded handlers, just uninstalled by `unregister()`.
+pub fn forget_handlers() {
+    INSTALLED.lock().unwrap().clear();
+}
+
+/// Checks that the recorded handlers are still installed.
+pub fn verify_handlers() -> Result<(), HandlerConflict> {
+    let now = Instant::now();
+    let mut installed = INSTALLED.lock().unwrap();
+    for installed in installed.iter_mut() {
+        match unsafe { current_handler(installed.sig) } {
+            Some(handler) if handler != installed.handler => {
+                let (sig, last_seen) = (installed.sig, installed.last_seen);
+                return Err(HandlerConflict::new(sig, handler, last_seen, now));
+            }
+            _ => installed.last_seen = now,
+        }
+    }
+    Ok(())
+}
+
+/// Applies the process-wide settings of `config` other than the signals to catch.
+#[inline]
+pub unsafe fn configure(config: &Config) {
+    bulletproof_impl_configure(config.get_chain() as c_int, config.get_save_sigmask() as c_int);
+}
+
+/// Registers the current thread.
+#[inline]
+pub unsafe fn register_thread() -> Result<(), RegisterError> {
+    /// `STEP_SIGALTSTACK` in `impl.c`.
+    const STEP_SIGALTSTACK: c_int = 2;
+
+    let mut failed_step = 0;
+    match bulletproof_impl_register_thread(&mut failed_step) {
+        0 => Ok(()),
+        errno if failed_step == STEP_SIGALTSTACK => {
+            Err(RegisterError::new(RegisterStep::Sigaltstack, errno))
+        }
+        errno => Err(RegisterError::new(RegisterStep::SignalMask, errno)),
+    }
+}
+
+/// Sets the size of the alternate signal stacks of threads registered afterwards.
+#[inline]
+pub fn set_altstack_size(size: usize) {
+    unsafe { bulletproof_impl_set_altstack_size(size) }
+}
+
+/// Reinstalls the handlers in a forked child. Returns 0 on success.
+#[inline]
+pub unsafe fn reinit_after_fork() -> size_t {
+    bulletproof_impl_reinit_after_fork()
+}
+
+/// Handles a fault signal. Returns nonzero if handled, and does not return if the fault was raised
+/// by a bulletproof memory access.
+#[inline]
+pub unsafe fn handle_signal(sig: c_int, info: *mut siginfo_t, ctx: *mut c_void) -> c_int {
+    bulletproof_impl_handle_signal(sig, info, ctx)
+}
+
+/// Loads a word from `loc` to `dst`. Returns the signal number on fault, and 0 otherwise.
+#[inline]
+pub unsafe fn load_word(loc: *const usize, dst: *mut usize) -> c_int {
+    bulletproof_impl_load(loc, dst) as c_int
+}
+
+/// Stores a word `val` to `loc`. Returns the signal number on fault, and 0 otherwise.
+#[inline]
+pub unsafe fn store_word(loc: *mut usize, val: usize) -> c_int {
+    bulletproof_impl_store(loc, val) as c_int
+}
+
+/// Loads a `u64` from `loc` to `dst`. Returns the signal number on fault, and 0 otherwise.
+#[inline]
+pub unsafe fn load_u64(loc: *const u64, dst: *mut u64) -> c_int {
+    bulletproof_impl_load_u64(loc, dst) as c_int
+}
+
+/// Stores a `u64` `val` to `loc`. Returns the signal number on fault, and 0 otherwise.
+#[inline]
+pub unsafe fn store_u64(loc: *mut u64, val: u64) -> c_int {
+    bulletproof_impl_store_u64(loc, val) as c_int
+}
+
+/// Loads `size` bytes from `loc` to `dst`. Returns the signal number on fault, and 0 otherwise.
+#[inline]
+pub unsafe fn load_bytes(loc: *const c_void, dst: *mut c_void, size: usize) -> c_int {
+    bulletproof_impl_load_bytes(loc, dst, size) as c_int
+}
+
+/// Loads `size` bytes from `loc` to `dst`, zero-filling the bytes of unreadable pages. Returns the
+/// number of zero-filled bytes.
+#[inline]
+pub unsafe fn load_bytes_zero_fill(
+    loc: *const u8,
+    dst: *mut u8,
+    size: usize,
+    page_size: usize,
+) -> usize {
+    bulletproof_impl_load_bytes_zero_fill(loc, dst, size, page_size)
+}
+
+/// Stores `size` bytes from `src` to `loc`. Returns the signal number on fault, and 0 otherwise.
+#[inline]
+pub unsafe fn store_bytes(loc: *mut c_void, src: *const c_void, size: usize) -> c_int {
+    bulletproof_impl_store_bytes(loc, src, size) as c_int
+}
+
+/// Stores the leading bytes of `size` bytes from `src` to `loc`, up to the first page that cannot be
+/// written. Returns the number of bytes written.
+#[inline]
+pub unsafe fn store_bytes_prefix(
+    loc: *mut u8,
+    src: *const u8,
+    size: usize,
+    page_size: usize,
+) -> usize {
+    bulletproof_impl_store_bytes_prefix(loc, src, size, page_size)
+}
+
+/// Loads `size` bytes from `loc` to `old`, and then stores `size` bytes from `src` to `loc`. Returns
+/// the signal number on fault, and 0 otherwise.
+#[inline]
+pub unsafe fn exchange_bytes(
+    loc: *mut c_void,
+    src: *const c_void,
+    old: *mut c_void,
+    size: usize,
+) -> c_int {
+    bulletproof_impl_exchange_bytes(loc, src, old, size) as c_int
+}
+
+/// Copies `size` bytes from `src` to `dst`, which may overlap. Returns the signal number on fault,
+/// and 0 otherwise.
+#[inline]
+pub unsafe fn move_bytes(src: *const c_void, dst: *mut c_void, size: usize) -> c_int {
+    bulletproof_impl_move_bytes(src, dst, size) as c_int
+}
+
+/// Stores the indexes of the `words` words from `loc` that are in `heap` to `out`, and their number
+/// to `found`. Returns the signal number on fault, and 0 otherwise.
+#[inline]
+pub unsafe fn scan(
+    loc: *const usize,
+    words: usize,
+    heap: &Range<usize>,
+    out: *mut usize,
+    found: &mut usize,
+) -> c_int {
+    bulletproof_impl_scan(loc, words, heap.start, heap.end, out, found) as c_int
+}
+
+/// Calls `f(data)` in a protected section. Returns the signal number on fault, and 0 otherwise.
+#[inline]
+pub unsafe fn protect(f: unsafe extern "C" fn(*mut c_void), data: *mut c_void) -> c_int {
+    bulletproof_impl_protect(f, data) as c_int
+}
+
+/// Sets the bits of the readable (and writable, if not null) pages of `pages` pages from `start`.
+#[inline]
+pub unsafe fn probe_range(
+    start: *const u8,
+    page_size: usize,
+    pages: usize,
+    readable: *mut u8,
+    writable: *mut u8,
+) {
+    bulletproof_impl_probe_range(start, page_size, pages, readable, writable)
+}
+
+/// Adds a fault hook `f(data, sig, info)` for faults in `range`. Returns its slot.
+#[inline]
+pub unsafe fn add_hook(range: &Range<usize>, f: HookFn, data: *mut c_void) -> io::Result<c_int> {
+    match bulletproof_impl_add_hook(range.start, range.end, f, data) {
+        -1 => Err(io::Error::other("too many fault hooks")),
+        slot => Ok(slot),
+    }
+}
+
+/// Removes the fault hook in `slot`, waiting for the handlers calling it to return.
+#[inline]
+pub unsafe fn remove_hook(slot: c_int) {
+    bulletproof_impl_remove_hook(slot)
+}
+
+/// Flushes the instruction cache for `[start, start + len)`.
+#[inline]
+pub unsafe fn flush_icache(start: *mut c_void, len: usize) {
+    bulletproof_impl_flush_icache(start, len)
+}
+
+/// Returns the last fault recovered by the current thread.
+#[inline]
+pub fn last_fault() -> Fault {
+    let (mut sig, mut code, mut addr, mut exec) = (0, 0, ptr::null_mut(), 0);
+    unsafe {
+        bulletproof_impl_last_fault(&mut sig, &mut code, &mut addr, &mut exec);
+    }
+    if exec != 0 && sig == libc::SIGSEGV {
+        return Fault::ExecViolation { addr: addr as usize, code };
+    }
+    Fault::from_signal(sig, code, addr as usize)
+}
+
+/// Returns the registers at the last fault signal handled by the current thread, if known.
+pub fn last_registers() -> Option<Registers> {
+    let (mut pc, mut sp, mut fp) = (ptr::null_mut(), ptr::null_mut(), ptr::null_mut());
+    if unsafe { bulletproof_impl_last_registers(&mut pc, &mut sp, &mut fp) } == 0 {
+        return None;
+    }
+    Some(Registers { pc: pc as usize, sp: sp as usize, fp: fp as usize })
+}
+
+/// Returns the registers of the code interrupted by a signal from the handler's context, if known.
+///
+/// It is async-signal-safe.
+pub unsafe fn context_registers(ctx: *mut c_void) -> Option<Registers> {
+    let (mut pc, mut sp, mut fp) = (ptr::null_mut(), ptr::null_mut(), ptr::null_mut());
+    if bulletproof_impl_context_registers(ctx, &mut pc, &mut sp, &mut fp) == 0 {
+        return None;
+    }
+    Some(Registers { pc: pc as usize, sp: sp as usize, fp: fp as usize })
+}
+
+/// Returns the time elapsed since the last fault recovered by the current thread was raised.
+#[cfg(feature = "tracing")]
+pub fn last_fault_elapsed() -> Duration {
+    unsafe {
+        let (mut then, mut now) = (mem::zeroed(), mem::zeroed());
+        bulletproof_impl_last_fault_time(&mut then);
+        libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut now);
+        let nanos = |t: libc::timespec| t.tv_sec as u128 * 1_000_000_000 + t.tv_nsec as u128;
+        Duration::from_nanos(nanos(now).saturating_sub(nanos(then)) as u64)
+    }
+}
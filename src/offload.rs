@@ -0,0 +1,189 @@
+//! Copies offloaded to a worker thread, for async code (`async` feature).
+//!
+//! A large protected copy blocks its thread for as long as it takes, and the thread should be
+//! registered (see the [`thread`](../thread/index.html) module). Neither suits the worker threads
+//! of an async executor, e.g. in a debugging agent embedded in a server.
+//! [`Bulletproof::read_bytes_async()`](../struct.Bulletproof.html#method.read_bytes_async) sends
+//! the copy to a dedicated registered thread instead, and returns a future of its result, which
+//! does not depend on any executor.
+//!
+//! The worker thread is spawned at the first offloaded copy, and runs the copies one at a time
+//! for the rest of the process.
+//!
+//! # Examples
+//!
+//! ```
+//! use std::future::Future;
+//! use std::pin::pin;
+//! use std::sync::Arc;
+//! use std::task::{Context, Poll, Wake, Waker};
+//! use std::thread::{self, Thread};
+//!
+//! use bulletproof::Bulletproof;
+//!
+//! // A minimal executor, for the example.
+//! struct Unpark(Thread);
+//!
+//! impl Wake for Unpark {
+//!     fn wake(self: Arc<Self>) {
+//!         self.0.unpark();
+//!     }
+//! }
+//!
+//! fn block_on<F: Future>(future: F) -> F::Output {
+//!     let waker = Waker::from(Arc::new(Unpark(thread::current())));
+//!     let mut future = pin!(future);
+//!     loop {
+//!         match future.as_mut().poll(&mut Context::from_waker(&waker)) {
+//!             Poll::Ready(output) => return output,
+//!             Poll::Pending => thread::park(),
+//!         }
+//!     }
+//! }
+//!
+//! let region = vec![42u8; 1 << 20];
+//!
+//! unsafe {
+//!     let bulletproof = Bulletproof::new();
+//!
+//!     let copy = block_on(bulletproof.read_bytes_async(region.as_ptr() as usize, region.len()));
+//!     assert_eq!(copy, Ok(region));
+//!     assert!(block_on(bulletproof.read_bytes_async(0, 16)).is_err());
+//! }
+//! ```
+
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::task::{Context, Poll, Waker};
+
+use super::{thread, Bulletproof, Fault};
+
+/// The result of a copy, and the waker of its future.
+#[derive(Debug, Default)]
+struct State {
+    result: Option<Result<Vec<u8>, Fault>>,
+    waker: Option<Waker>,
+}
+
+/// A copy sent to the worker thread.
+struct Job {
+    bulletproof: Bulletproof,
+    location: usize,
+    len: usize,
+    state: Arc<Mutex<State>>,
+}
+
+impl Job {
+    fn run(self) {
+        let mut bytes = vec![0u8; self.len];
+        let result = unsafe { self.bulletproof.read(self.location as *const u8, &mut bytes) };
+
+        let mut state = self.state.lock().unwrap();
+        state.result = Some(result.map(|_| bytes));
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+/// Returns the sender of jobs to the worker thread, spawning it first if needed.
+fn worker() -> &'static Mutex<Sender<Job>> {
+    static WORKER: OnceLock<Mutex<Sender<Job>>> = OnceLock::new();
+
+    WORKER.get_or_init(|| {
+        let (sender, receiver) = mpsc::channel::<Job>();
+        thread::spawn(move || {
+            for job in receiver {
+                job.run();
+            }
+        });
+        Mutex::new(sender)
+    })
+}
+
+/// The future of an offloaded copy, returned by
+/// [`Bulletproof::read_bytes_async()`](../struct.Bulletproof.html#method.read_bytes_async).
+///
+/// It resolves to the copied bytes, or to the fault of the copy. Dropping it does not cancel the
+/// copy.
+#[derive(Debug)]
+#[must_use = "futures do nothing unless polled"]
+pub struct ReadBytes {
+    state: Arc<Mutex<State>>,
+}
+
+impl Future for ReadBytes {
+    type Output = Result<Vec<u8>, Fault>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let mut state = self.state.lock().unwrap();
+        match state.result.take() {
+            Some(result) => Poll::Ready(result),
+            None => {
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+/// Sends the copy of `len` bytes at `location` to the worker thread.
+pub(crate) fn read_bytes(bulletproof: Bulletproof, location: usize, len: usize) -> ReadBytes {
+    let state = Arc::new(Mutex::new(State::default()));
+    let job = Job { bulletproof, location, len, state: state.clone() };
+    worker().lock().unwrap().send(job).expect("the worker thread exited");
+    ReadBytes { state }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc::{self, Receiver};
+    use std::task::Wake;
+    use std::time::Duration;
+
+    use super::*;
+
+    /// Reports the wakeups.
+    struct Notify(Mutex<Sender<()>>);
+
+    impl Wake for Notify {
+        fn wake(self: Arc<Self>) {
+            let _ = self.0.lock().unwrap().send(());
+        }
+    }
+
+    fn poll(
+        future: &mut ReadBytes,
+        wakeups: &Receiver<()>,
+        waker: &Waker,
+    ) -> Result<Vec<u8>, Fault> {
+        loop {
+            match Pin::new(&mut *future).poll(&mut Context::from_waker(waker)) {
+                Poll::Ready(result) => return result,
+                Poll::Pending => {
+                    wakeups.recv_timeout(Duration::from_secs(10)).unwrap();
+                }
+            }
+        }
+    }
+
+    #[test]
+    #[cfg_attr(any(miri, bulletproof_fallback), ignore)]
+    fn read_bytes() {
+        let (sender, wakeups) = mpsc::channel();
+        let waker = Waker::from(Arc::new(Notify(Mutex::new(sender))));
+        let region = (0..=255u8).collect::<Vec<_>>();
+
+        unsafe {
+            let bulletproof = Bulletproof::new();
+
+            let mut future = bulletproof.read_bytes_async(region.as_ptr() as usize, region.len());
+            assert_eq!(poll(&mut future, &wakeups, &waker), Ok(region));
+
+            let mut future = bulletproof.read_bytes_async(16, 16);
+            assert_eq!(poll(&mut future, &wakeups, &waker).unwrap_err().addr(), 16);
+        }
+    }
+}
@@ -0,0 +1,259 @@
+//! Page-permission oracles that pre-validate accesses.
+//!
+//! A [`PermissionOracle`](trait.PermissionOracle.html) set with
+//! [`Config::oracle()`](../config/struct.Config.html#method.oracle) is asked about each access of
+//! a value before it is performed. If it declares the range valid, the access is performed
+//! directly, skipping the protected path; if it declares it invalid, the access fails with a
+//! `SEGV_MAPERR` fault at the start of the range without touching memory; otherwise, it is
+//! performed as usual. E.g., a virtual machine that knows the layout of its own arenas can answer
+//! for the addresses inside them, and leave the others unknown.
+//!
+//! The oracle applies to [`load_usize()`], [`load()`] (and the loads built on it),
+//! [`load_vec()`], [`read()`] with `Recovery::Abort`, [`store_usize()`], [`store()`], and
+//! [`exchange()`]. The other accesses always take the protected path.
+//!
+//! # Examples
+//!
+//! ```
+//! use std::ops::Range;
+//!
+//! use bulletproof::oracle::{Permission, PermissionOracle};
+//! use bulletproof::{Bulletproof, Config};
+//!
+//! /// Knows that its arena is valid.
+//! #[derive(Debug)]
+//! struct Arena(Range<usize>);
+//!
+//! impl PermissionOracle for Arena {
+//!     fn check(&self, addr: usize, len: usize, _write: bool) -> Permission {
+//!         match addr.checked_add(len) {
+//!             Some(end) if self.0.start <= addr && end <= self.0.end => Permission::Valid,
+//!             _ => Permission::Unknown,
+//!         }
+//!     }
+//! }
+//!
+//! let arena = Box::leak(vec![42usize; 64].into_boxed_slice());
+//! let start = arena.as_ptr() as usize;
+//! let oracle = Box::leak(Box::new(Arena(start..start + 64 * std::mem::size_of::<usize>())));
+//!
+//! unsafe {
+//!     let bulletproof = Bulletproof::with_config(Config::new().oracle(oracle));
+//!
+//!     assert_eq!(bulletproof.load_usize(&arena[7]), Ok(42));
+//!     assert!(bulletproof.load_usize(std::ptr::null()).is_err());
+//! }
+//! ```
+//!
+//! [`load_usize()`]: ../struct.Bulletproof.html#method.load_usize
+//! [`load()`]: ../struct.Bulletproof.html#method.load
+//! [`load_vec()`]: ../struct.Bulletproof.html#method.load_vec
+//! [`read()`]: ../struct.Bulletproof.html#method.read
+//! [`store_usize()`]: ../struct.Bulletproof.html#method.store_usize
+//! [`store()`]: ../struct.Bulletproof.html#method.store
+//! [`exchange()`]: ../struct.Bulletproof.html#method.exchange
+
+use std::fmt::Debug;
+use std::panic::RefUnwindSafe;
+#[cfg(any(
+    target_os = "linux",
+    target_os = "freebsd",
+    target_os = "openbsd",
+    target_os = "netbsd",
+    target_os = "illumos"
+))]
+use std::io;
+
+use super::{runtime, Backend};
+#[cfg(any(
+    target_os = "linux",
+    target_os = "freebsd",
+    target_os = "openbsd",
+    target_os = "netbsd",
+    target_os = "illumos"
+))]
+use snapshot::{self, Region};
+
+/// The answer of an oracle about a range.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Permission {
+    /// The range can be accessed without faulting.
+    Valid,
+    /// Accessing the range would fault.
+    Invalid,
+    /// The oracle does not know.
+    Unknown,
+}
+
+/// An oracle of the permissions of address ranges.
+///
+/// Answering [`Permission::Valid`](enum.Permission.html#variant.Valid) wrongly crashes the process
+/// (or worse), as the access is then performed without protection. When in doubt, answer
+/// [`Permission::Unknown`](enum.Permission.html#variant.Unknown).
+pub trait PermissionOracle: Debug + Sync + RefUnwindSafe {
+    /// Returns whether `len` bytes at `addr` can be read, and written as well if `write`.
+    fn check(&self, addr: usize, len: usize, write: bool) -> Permission;
+}
+
+/// An oracle that never knows, as if there were none.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct AlwaysUnknown;
+
+impl PermissionOracle for AlwaysUnknown {
+    #[inline]
+    fn check(&self, _addr: usize, _len: usize, _write: bool) -> Permission {
+        Permission::Unknown
+    }
+}
+
+/// An oracle that declares ranges with unmapped pages invalid, with
+/// [`mincore(2)`](http://man7.org/linux/man-pages/man2/mincore.2.html).
+///
+/// It does not know the permissions of mapped pages, so it never declares a range valid. Where
+/// `mincore()` is not available, it never knows.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Mincore;
+
+impl PermissionOracle for Mincore {
+    fn check(&self, addr: usize, len: usize, _write: bool) -> Permission {
+        if !Backend::Mincore.is_available() || unsafe { runtime::mapped(addr, len) } {
+            return Permission::Unknown;
+        }
+        Permission::Invalid
+    }
+}
+
+/// An oracle that answers from a snapshot of the memory map of the process, e.g. of
+/// `/proc/self/maps` on Linux (see [`snapshot::regions()`](../snapshot/fn.regions.html)).
+///
+/// A range is valid if it is covered by regions with the permissions, and invalid otherwise. The
+/// snapshot is not updated: it should be reloaded after the memory map changes (e.g. after a
+/// region is unmapped or protected), as an access to a range unmapped since is not protected.
+#[cfg(any(
+    target_os = "linux",
+    target_os = "freebsd",
+    target_os = "openbsd",
+    target_os = "netbsd",
+    target_os = "illumos"
+))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProcMaps {
+    regions: Vec<Region>,
+}
+
+#[cfg(any(
+    target_os = "linux",
+    target_os = "freebsd",
+    target_os = "openbsd",
+    target_os = "netbsd",
+    target_os = "illumos"
+))]
+impl ProcMaps {
+    /// Takes a snapshot of the memory map of the process.
+    #[inline]
+    pub fn load() -> io::Result<Self> {
+        snapshot::regions().map(|regions| Self { regions })
+    }
+
+    /// Returns the regions of the snapshot, sorted by address.
+    #[inline]
+    pub fn regions(&self) -> &[Region] {
+        &self.regions
+    }
+}
+
+#[cfg(any(
+    target_os = "linux",
+    target_os = "freebsd",
+    target_os = "openbsd",
+    target_os = "netbsd",
+    target_os = "illumos"
+))]
+impl PermissionOracle for ProcMaps {
+    fn check(&self, addr: usize, len: usize, write: bool) -> Permission {
+        let end = match addr.checked_add(len) {
+            Some(end) => end,
+            None => return Permission::Invalid,
+        };
+
+        let mut next = addr;
+        for region in &self.regions {
+            if next >= end {
+                break;
+            }
+            if region.end <= next {
+                continue;
+            }
+            if region.start > next || !region.is_readable() || (write && region.perms[1] != b'w') {
+                return Permission::Invalid;
+            }
+            next = region.end;
+        }
+        if next >= end {
+            Permission::Valid
+        } else {
+            Permission::Invalid
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ptr;
+
+    use super::*;
+    use {Bulletproof, Config, Fault};
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn oracles() {
+        let mut x = 42usize;
+        let addr = &x as *const usize as usize;
+        let size = std::mem::size_of::<usize>();
+
+        assert_eq!(AlwaysUnknown.check(addr, size, false), Permission::Unknown);
+        assert_eq!(Mincore.check(addr, size, true), Permission::Unknown);
+        if Backend::Mincore.is_available() {
+            assert_eq!(Mincore.check(0, size, false), Permission::Invalid);
+        }
+
+        #[cfg(any(
+            target_os = "linux",
+            target_os = "freebsd",
+            target_os = "openbsd",
+            target_os = "netbsd",
+            target_os = "illumos"
+        ))]
+        {
+            let maps = ProcMaps::load().unwrap();
+            assert_eq!(maps.check(addr, size, true), Permission::Valid);
+            assert_eq!(maps.check(0, size, false), Permission::Invalid);
+            let code = oracles as *const () as usize;
+            assert_eq!(maps.check(code, 1, true), Permission::Invalid);
+        }
+
+        // An oracle declaring everything invalid is trusted without touching memory.
+        #[derive(Debug)]
+        struct Nothing;
+        impl PermissionOracle for Nothing {
+            fn check(&self, _addr: usize, _len: usize, _write: bool) -> Permission {
+                Permission::Invalid
+            }
+        }
+        static NOTHING: Nothing = Nothing;
+
+        unsafe {
+            let bulletproof = Bulletproof::with_config(Config::new().oracle(&NOTHING));
+            let fault = Fault::Segv { addr, code: ::fault::SEGV_MAPERR };
+            assert_eq!(bulletproof.load_usize(&x), Err(fault));
+            assert_eq!(bulletproof.store_usize(&mut x, 37), Err(fault));
+            assert_eq!(ptr::read(&x), 42);
+
+            let mut y = 42u64;
+            let fault = Fault::Segv { addr: &y as *const u64 as usize, code: ::fault::SEGV_MAPERR };
+            assert_eq!(bulletproof.load_u64(&y), Err(fault));
+            assert_eq!(bulletproof.store_u64(&mut y, 37), Err(fault));
+            assert_eq!(ptr::read(&y), 42);
+        }
+    }
+}
@@ -0,0 +1,104 @@
+//! Pinning of regions during long reads.
+//!
+//! A large copy of a region (e.g. of a snapshot) that is paged out or not yet populated stalls on
+//! each page, which widens the window in which another thread can unmap the rest of the region
+//! halfway through. [`pin()`](fn.pin.html) locks the pages of a range in memory with
+//! [`mlock(2)`](http://man7.org/linux/man-pages/man2/mlock.2.html) for the lifetime of the returned
+//! [`PinGuard`](struct.PinGuard.html), so that they are resident when the copy starts.
+//!
+//! It is best-effort: a locked page can still be unmapped by other threads, and the copy then
+//! faults as usual. Locking is subject to `RLIMIT_MEMLOCK`, and is not counted: unlocking a range
+//! on drop also unlocks the pages locked by other code.
+//!
+//! # Examples
+//!
+//! ```
+//! use bulletproof::{pin, Bulletproof};
+//!
+//! let region = vec![42u8; 4096];
+//! let mut copy = vec![0u8; region.len()];
+//!
+//! unsafe {
+//!     let bulletproof = Bulletproof::new();
+//!
+//!     // Copies unpinned if the pages cannot be locked, e.g. over the limit.
+//!     let _guard = pin::pin(region.as_ptr(), region.len()).ok();
+//!     assert_eq!(bulletproof.read(region.as_ptr(), &mut copy), Ok(0));
+//! }
+//! assert_eq!(copy, region);
+//! ```
+
+use std::io;
+use std::ops::Range;
+
+use libc::{self, c_void};
+
+use probe;
+
+/// Locked pages, unlocked on drop.
+#[derive(Debug)]
+pub struct PinGuard {
+    start: usize,
+    len: usize,
+}
+
+impl PinGuard {
+    /// Returns the range of the locked pages, which covers the pinned range.
+    #[inline]
+    pub fn range(&self) -> Range<usize> {
+        self.start..self.start + self.len
+    }
+}
+
+impl Drop for PinGuard {
+    fn drop(&mut self) {
+        unsafe {
+            libc::munlock(self.start as *const c_void, self.len);
+        }
+    }
+}
+
+/// Locks the pages overlapping `[location, location + len)` in memory until the guard is dropped.
+///
+/// # Errors
+///
+/// Returns the error of `mlock()`, e.g. `ENOMEM` if a part of the range is unmapped, or `EAGAIN`
+/// or `EPERM` if locking it would exceed `RLIMIT_MEMLOCK`.
+pub fn pin(location: *const u8, len: usize) -> io::Result<PinGuard> {
+    let page_size = probe::page_size();
+    let start = location as usize & !(page_size - 1);
+    let end = (location as usize)
+        .checked_add(len)
+        .and_then(|end| end.checked_add(page_size - 1))
+        .map(|end| end & !(page_size - 1))
+        .ok_or_else(|| io::Error::from_raw_os_error(libc::ENOMEM))?;
+
+    if unsafe { libc::mlock(start as *const c_void, end - start) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(PinGuard { start, len: end - start })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ptr;
+
+    use super::*;
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn pin() {
+        let page_size = probe::page_size();
+        let region = [0u8; 16];
+        let start = region.as_ptr() as usize;
+
+        let guard = super::pin(region.as_ptr(), region.len()).unwrap();
+        assert_eq!(guard.range().start, start & !(page_size - 1));
+        assert!(guard.range().end >= start + region.len());
+        assert_eq!(guard.range().end % page_size, 0);
+        drop(guard);
+
+        let error = super::pin(ptr::null(), page_size).unwrap_err();
+        assert_eq!(error.raw_os_error(), Some(libc::ENOMEM));
+    }
+}
@@ -0,0 +1,322 @@
+//! Page-validity bitmaps of address ranges.
+//!
+//! Heap dumpers and conservative scanners often need to know which pages of a large range are
+//! accessible before deciding what to copy. [`Bulletproof::probe_range()`] touches each page
+//! inside one protected loop, which is much cheaper than one protected access per page, and
+//! returns a [`PageBitmap`](struct.PageBitmap.html).
+//!
+//! # Examples
+//!
+//! ```
+//! use bulletproof::Bulletproof;
+//!
+//! let buf = vec![0u8; 4 * 4096];
+//!
+//! unsafe {
+//!     let bulletproof = Bulletproof::new();
+//!
+//!     let bitmap = bulletproof.probe_range(buf.as_ptr(), buf.len());
+//!     assert_eq!(bitmap.iter_readable().count(), bitmap.len());
+//! }
+//! ```
+//!
+//! [`Bulletproof::probe_range()`]: ../struct.Bulletproof.html#method.probe_range
+
+use std::cmp;
+use std::iter::FusedIterator;
+use std::marker::PhantomData;
+use std::ptr;
+
+#[cfg(unix)]
+use libc;
+
+use super::{backend, poisoned, runtime, Backend, Bulletproof, Fault};
+
+/// Returns the page size.
+#[cfg(unix)]
+pub(crate) fn page_size() -> usize {
+    unsafe { libc::sysconf(libc::_SC_PAGESIZE) as usize }
+}
+
+/// Returns the usual page size, as there are no pages without Unix memory protection.
+#[cfg(not(unix))]
+pub(crate) fn page_size() -> usize {
+    4096
+}
+
+/// Which pages of a range are readable (and writable, if probed).
+///
+/// The bitmap covers every page overlapping the probed range, starting from the page containing
+/// its first byte.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PageBitmap {
+    start: usize,
+    page_size: usize,
+    pages: usize,
+    readable: Vec<u8>,
+    writable: Option<Vec<u8>>,
+}
+
+impl PageBitmap {
+    /// Returns the address of the first page.
+    #[inline]
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    /// Returns the page size.
+    #[inline]
+    pub fn page_size(&self) -> usize {
+        self.page_size
+    }
+
+    /// Returns the number of pages.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.pages
+    }
+
+    /// Returns `true` if the bitmap covers no pages.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.pages == 0
+    }
+
+    /// Returns the address of the `index`-th page.
+    #[inline]
+    pub fn page_addr(&self, index: usize) -> usize {
+        self.start + index * self.page_size
+    }
+
+    /// Returns `true` if the `index`-th page is readable.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds.
+    #[inline]
+    pub fn is_readable(&self, index: usize) -> bool {
+        assert!(index < self.pages, "page index out of bounds");
+        get(&self.readable, index)
+    }
+
+    /// Returns `Some(true)` if the `index`-th page is writable, and `None` if writability was not
+    /// probed, as with backends other than
+    /// [`Backend::Signal`](../enum.Backend.html#variant.Signal).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds.
+    #[inline]
+    pub fn is_writable(&self, index: usize) -> Option<bool> {
+        assert!(index < self.pages, "page index out of bounds");
+        self.writable.as_ref().map(|writable| get(writable, index))
+    }
+
+    /// Returns an iterator over the indexes of the readable pages.
+    #[inline]
+    pub fn iter_readable<'a>(&'a self) -> impl Iterator<Item = usize> + 'a {
+        (0..self.pages).filter(move |&index| get(&self.readable, index))
+    }
+
+    /// Clears the bits of the `index`-th page.
+    fn clear(&mut self, index: usize) {
+        self.readable[index / 8] &= !(1 << (index % 8));
+        if let Some(ref mut writable) = self.writable {
+            writable[index / 8] &= !(1 << (index % 8));
+        }
+    }
+}
+
+#[inline]
+fn get(bits: &[u8], index: usize) -> bool {
+    bits[index / 8] & (1 << (index % 8)) != 0
+}
+
+/// An iterator over the pages of a range, which borrows each page after probing it, returned by
+/// [`Bulletproof::pages()`](../struct.Bulletproof.html#method.pages).
+#[derive(Debug, Clone)]
+pub struct Pages<'a> {
+    bulletproof: Bulletproof,
+    next: usize,
+    end: usize,
+    page_size: usize,
+    _marker: PhantomData<&'a [u8]>,
+}
+
+impl<'a> Pages<'a> {
+    /// Creates an iterator over the pages overlapping `[start, start + len)`.
+    pub(crate) fn new(bulletproof: Bulletproof, start: usize, len: usize) -> Self {
+        Self {
+            bulletproof,
+            next: start,
+            end: start.saturating_add(len),
+            page_size: page_size(),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<'a> Iterator for Pages<'a> {
+    type Item = Result<&'a [u8], Fault>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next >= self.end {
+            return None;
+        }
+        let start = self.next;
+        let page_end = (start & !(self.page_size - 1)).saturating_add(self.page_size);
+        let end = cmp::min(page_end, self.end);
+        self.next = end;
+        // The range is valid as promised to `Bulletproof::pages()`.
+        Some(unsafe { self.bulletproof.try_slice(start as *const u8, end - start) })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        if self.next >= self.end {
+            return (0, Some(0));
+        }
+        let first = self.next & !(self.page_size - 1);
+        let pages = (self.end - 1 - first) / self.page_size + 1;
+        (pages, Some(pages))
+    }
+}
+
+impl<'a> ExactSizeIterator for Pages<'a> {}
+
+impl<'a> FusedIterator for Pages<'a> {}
+
+/// Probes the pages overlapping `[start, start + len)` with `via`.
+pub(crate) unsafe fn probe(via: Backend, start: usize, len: usize, writable: bool) -> PageBitmap {
+    let page_size = page_size();
+    let first = start & !(page_size - 1);
+    let end = start.saturating_add(len);
+    let pages = if len == 0 {
+        0
+    } else {
+        (end - first).div_ceil(page_size)
+    };
+
+    let mut bitmap = PageBitmap {
+        start: first,
+        page_size,
+        pages,
+        readable: vec![0; pages.div_ceil(8)],
+        writable: if writable && via == Backend::Signal {
+            Some(vec![0; pages.div_ceil(8)])
+        } else {
+            None
+        },
+    };
+
+    if via == Backend::Signal {
+        let writable_ptr = match bitmap.writable {
+            Some(ref mut writable) => writable.as_mut_ptr(),
+            None => ptr::null_mut(),
+        };
+        backend::probe_range(
+            first as *const u8,
+            page_size,
+            pages,
+            bitmap.readable.as_mut_ptr(),
+            writable_ptr,
+        );
+    } else {
+        // Other backends read the first byte of each page.
+        for index in 0..pages {
+            let mut byte = 0u8;
+            if runtime::load(via, bitmap.page_addr(index), &mut byte, 1).is_ok() {
+                bitmap.readable[index / 8] |= 1 << (index % 8);
+            }
+        }
+    }
+
+    for index in 0..pages {
+        if poisoned(bitmap.page_addr(index), page_size).is_some() {
+            bitmap.clear(index);
+        }
+    }
+    bitmap
+}
+
+/// Prefetches the pages overlapping `[start, start + len)`: advises the kernel that they will be
+/// needed, and then probes their readability, which faults them in.
+pub(crate) unsafe fn prefetch(via: Backend, start: usize, len: usize) -> PageBitmap {
+    #[cfg(all(unix, not(miri)))]
+    {
+        let page_size = page_size();
+        let first = start & !(page_size - 1);
+        let end = start.saturating_add(len);
+        // Only a hint, which fails if the range is not fully mapped. The probe tells which pages
+        // are mapped.
+        libc::madvise(first as *mut libc::c_void, end - first, libc::MADV_WILLNEED);
+    }
+    probe(via, start, len, false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::Bulletproof;
+
+    #[test]
+    #[cfg_attr(any(miri, bulletproof_fallback), ignore)]
+    fn probe_range() {
+        unsafe {
+            let bulletproof = Bulletproof::new();
+            let page_size = page_size();
+
+            // Map 4 pages: read-write, read-only, inaccessible, and read-write.
+            let map = libc::mmap(
+                ptr::null_mut(),
+                4 * page_size,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                -1,
+                0,
+            );
+            assert_ne!(map, libc::MAP_FAILED);
+            let start = map as usize;
+            libc::mprotect((start + page_size) as *mut _, page_size, libc::PROT_READ);
+            libc::mprotect((start + 2 * page_size) as *mut _, page_size, libc::PROT_NONE);
+
+            let bitmap = bulletproof.probe_range_mut((start + 1) as *mut u8, 3 * page_size);
+            assert_eq!(bitmap.start(), start);
+            assert_eq!(bitmap.len(), 4);
+            assert_eq!(bitmap.iter_readable().collect::<Vec<_>>(), vec![0, 1, 3]);
+            let writable = (0..4).map(|i| bitmap.is_writable(i)).collect::<Vec<_>>();
+            assert_eq!(writable, vec![Some(true), Some(false), Some(false), Some(true)]);
+
+            let bitmap = bulletproof.probe_range(map as *const u8, page_size);
+            assert_eq!(bitmap.len(), 1);
+            assert_eq!(bitmap.is_writable(0), None);
+
+            libc::munmap(map, 4 * page_size);
+        }
+    }
+
+    #[test]
+    #[cfg_attr(any(miri, bulletproof_fallback), ignore)]
+    fn prefetch() {
+        unsafe {
+            let bulletproof = Bulletproof::new();
+            let page_size = page_size();
+
+            // Map 3 pages, and unmap the middle one.
+            let prot = libc::PROT_READ | libc::PROT_WRITE;
+            let flags = libc::MAP_PRIVATE | libc::MAP_ANONYMOUS;
+            let map = libc::mmap(ptr::null_mut(), 3 * page_size, prot, flags, -1, 0);
+            assert_ne!(map, libc::MAP_FAILED);
+            let start = map as usize;
+            libc::munmap((start + page_size) as *mut _, page_size);
+
+            let bitmap = bulletproof.prefetch(start as *const u8, 3 * page_size);
+            assert_eq!(bitmap.iter_readable().collect::<Vec<_>>(), vec![0, 2]);
+
+            let handle = bulletproof.prefetch_in_background(start as *const u8, 3 * page_size);
+            assert_eq!(handle.join().unwrap(), bitmap);
+
+            libc::munmap(map, page_size);
+            libc::munmap((start + 2 * page_size) as *mut _, page_size);
+        }
+    }
+}
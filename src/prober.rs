@@ -0,0 +1,208 @@
+//! Bulletproof accesses confined to a dedicated prober thread.
+//!
+//! Registering a thread for bulletproof memory access changes its signal mask and its alternate
+//! signal stack, which some applications do not accept on their own threads. A
+//! [`Prober`](struct.Prober.html) registers a single dedicated thread instead, and other threads
+//! send it their reads and probes over a channel, so that they need not be registered.
+//!
+//! Only the prober thread is registered, but the signal handlers are still installed for the whole
+//! process, as signal dispositions are process-wide. The signal masks of the other threads are not
+//! changed either: a thread cannot change the mask of another, and a thread that blocks `SIGSEGV`
+//! or `SIGBUS` is killed by a fault on it rather than handled. So faults on the other threads still
+//! reach the handlers, which pass them to the disposition before registration as if the handlers
+//! were not installed, as they are never in a protected section (see
+//! [`Config::chain()`](../config/struct.Config.html#method.chain)).
+//!
+//! # Examples
+//!
+//! ```
+//! use bulletproof::prober::Prober;
+//! use bulletproof::Config;
+//!
+//! let region = vec![42u8; 4096];
+//!
+//! unsafe {
+//!     let prober = Prober::start(Config::new()).unwrap();
+//!
+//!     assert_eq!(prober.read(region.as_ptr() as usize, 16), Ok(vec![42; 16]));
+//!     # #[cfg(not(bulletproof_fallback))]
+//!     assert!(prober.read(0, 16).is_err());
+//!     let bitmap = prober.probe_range(region.as_ptr() as usize, region.len());
+//!     assert_eq!(bitmap.iter_readable().count(), bitmap.len());
+//! }
+//! ```
+
+use std::sync::mpsc::{self, Sender};
+use std::thread::{self, JoinHandle};
+
+use config::Config;
+use error::RegisterError;
+use probe::PageBitmap;
+use {Bulletproof, Fault};
+
+/// A request to the prober thread, with the sender of its reply.
+enum Request {
+    Read {
+        location: usize,
+        len: usize,
+        reply: Sender<Result<Vec<u8>, Fault>>,
+    },
+    Probe {
+        location: usize,
+        len: usize,
+        reply: Sender<PageBitmap>,
+    },
+}
+
+impl Request {
+    unsafe fn serve(self, bulletproof: Bulletproof) {
+        // The requester may have panicked in the meantime.
+        match self {
+            Request::Read { location, len, reply } => {
+                let mut bytes = vec![0u8; len];
+                let result = bulletproof.read(location as *const u8, &mut bytes);
+                let _ = reply.send(result.map(|_| bytes));
+            }
+            Request::Probe { location, len, reply } => {
+                let _ = reply.send(bulletproof.probe_range(location as *const u8, len));
+            }
+        }
+    }
+}
+
+/// The handle of a prober thread, which performs the accesses requested through it. The thread
+/// exits when the handle is dropped.
+#[derive(Debug)]
+pub struct Prober {
+    requests: Option<Sender<Request>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Prober {
+    /// Spawns a prober thread, which installs the signal handlers with `config` and registers
+    /// itself, as [`Bulletproof::try_with_config()`]. The current thread is not registered.
+    ///
+    /// # Safety
+    ///
+    /// The same as [`Bulletproof::new()`](../struct.Bulletproof.html#method.new).
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of the registration on the prober thread, which then exits.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the thread cannot be spawned.
+    ///
+    /// [`Bulletproof::try_with_config()`]: ../struct.Bulletproof.html#method.try_with_config
+    pub unsafe fn start(config: Config) -> Result<Self, RegisterError> {
+        let (requests, receiver) = mpsc::channel::<Request>();
+        let (started, start) = mpsc::channel();
+        let thread = thread::Builder::new()
+            .name("bulletproof-prober".into())
+            .spawn(move || {
+                let bulletproof = match Bulletproof::try_with_config(config) {
+                    Ok(bulletproof) => bulletproof,
+                    Err(error) => return started.send(Err(error)).unwrap(),
+                };
+                started.send(Ok(())).unwrap();
+                for request in receiver {
+                    request.serve(bulletproof);
+                }
+            })
+            .expect("failed to spawn the prober thread");
+
+        if let Err(error) = start.recv().unwrap() {
+            thread.join().unwrap();
+            return Err(error);
+        }
+        Ok(Self { requests: Some(requests), thread: Some(thread) })
+    }
+
+    /// Sends `request` to the prober thread.
+    fn send(&self, request: Request) {
+        let requests = self.requests.as_ref().unwrap();
+        requests.send(request).expect("the prober thread exited");
+    }
+
+    /// Reads `len` bytes at `location` on the prober thread, as
+    /// [`Bulletproof::read()`](../struct.Bulletproof.html#method.read) with the configuration of
+    /// the prober.
+    ///
+    /// Returns `Ok(bytes)` if the location is readable (or the unreadable bytes are zero-filled),
+    /// and `Err(fault)` otherwise.
+    ///
+    /// # Safety
+    ///
+    /// Reading the location should have no side effects, except that it can be invalid.
+    pub unsafe fn read(&self, location: usize, len: usize) -> Result<Vec<u8>, Fault> {
+        let (reply, result) = mpsc::channel();
+        self.send(Request::Read { location, len, reply });
+        result.recv().expect("the prober thread exited")
+    }
+
+    /// Probes which pages overlapping `[location, location + len)` are readable on the prober
+    /// thread, as [`Bulletproof::probe_range()`](../struct.Bulletproof.html#method.probe_range).
+    ///
+    /// # Safety
+    ///
+    /// The same as [`Bulletproof::probe_range()`](../struct.Bulletproof.html#method.probe_range).
+    pub unsafe fn probe_range(&self, location: usize, len: usize) -> PageBitmap {
+        let (reply, result) = mpsc::channel();
+        self.send(Request::Probe { location, len, reply });
+        result.recv().expect("the prober thread exited")
+    }
+}
+
+impl Drop for Prober {
+    fn drop(&mut self) {
+        // The thread exits once the channel is closed.
+        drop(self.requests.take());
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::mem;
+    use std::ptr;
+
+    use libc;
+
+    use super::*;
+    use mapping::TestPages;
+
+    #[test]
+    #[cfg_attr(any(miri, bulletproof_fallback), ignore)]
+    fn prober() {
+        unsafe {
+            // Accesses from a thread that blocks `SIGSEGV`, which registration would unblock.
+            let mut set: libc::sigset_t = mem::zeroed();
+            let mut old: libc::sigset_t = mem::zeroed();
+            libc::sigemptyset(&mut set);
+            libc::sigaddset(&mut set, libc::SIGSEGV);
+            libc::pthread_sigmask(libc::SIG_BLOCK, &set, &mut old);
+
+            let prober = Prober::start(Config::new()).unwrap();
+            let pages = TestPages::new(2);
+            let (page_size, map) = (pages.page_size(), pages.page(0));
+            pages.protect(1, libc::PROT_NONE);
+
+            assert_eq!(prober.read(map as usize, 8), Ok(vec![0; 8]));
+            let fault = prober.read(map as usize + page_size - 4, 8).unwrap_err();
+            assert_eq!(fault.addr(), map as usize + page_size);
+            let bitmap = prober.probe_range(map as usize, 2 * page_size);
+            assert_eq!(bitmap.iter_readable().count(), 1);
+            drop(prober);
+
+            // The mask of this thread is untouched.
+            let mut current: libc::sigset_t = mem::zeroed();
+            libc::pthread_sigmask(libc::SIG_BLOCK, ptr::null(), &mut current);
+            assert_eq!(libc::sigismember(&current, libc::SIGSEGV), 1);
+
+            libc::pthread_sigmask(libc::SIG_SETMASK, &old, ptr::null_mut());
+        }
+    }
+}
@@ -0,0 +1,149 @@
+//! Reference-counted registration, for hosts that unload the code using bulletproof memory access.
+//!
+//! [`Bulletproof::new()`](../struct.Bulletproof.html#method.new) and its variants install the
+//! signal handlers for the rest of the process. Plugin-style hosts, which load and unload a module
+//! (e.g. a virtual machine), need to get back to the dispositions before it instead. A
+//! [`Registration`](struct.Registration.html) installs the handlers as well, but counts the live
+//! registrations, and restores the previous dispositions when the last one is dropped.
+//!
+//! The handlers are never uninstalled once installed permanently by
+//! [`Bulletproof::new()`](../struct.Bulletproof.html#method.new) or its variants, including
+//! [`Bulletproof::global()`](../struct.Bulletproof.html#method.global). A disposition replaced by
+//! another handler since (e.g. by a crash reporter) is left alone. With the `signal-hook` feature,
+//! the recovery actions are unregistered from the registry, whose own handler stays installed.
+//!
+//! # Examples
+//!
+//! ```
+//! use bulletproof::Registration;
+//!
+//! unsafe {
+//!     let registration = Registration::new().unwrap();
+//!     let bulletproof = registration.bulletproof();
+//!     assert!(bulletproof.load_usize(std::ptr::null()).is_err());
+//!
+//!     // Restores the previous dispositions if it is the last registration.
+//!     drop(registration);
+//! }
+//! ```
+
+use std::sync::Mutex;
+
+use super::{backend, Bulletproof};
+use config::Config;
+use error::RegisterError;
+#[cfg(feature = "tracing")]
+use trace;
+
+/// The registrations of the signal handlers.
+struct Registrations {
+    /// The live `Registration`s.
+    live: usize,
+    /// Whether the handlers were installed by `Bulletproof::new()` or its variants.
+    permanent: bool,
+}
+
+static REGISTRATIONS: Mutex<Registrations> = Mutex::new(Registrations {
+    live: 0,
+    permanent: false,
+});
+
+/// Installs the handlers of `config` for the rest of the process.
+pub(crate) unsafe fn register_permanently(config: Config) -> Result<Bulletproof, RegisterError> {
+    let mut registrations = REGISTRATIONS.lock().unwrap();
+    let bulletproof = Bulletproof::install(config)?;
+    registrations.permanent = true;
+    Ok(bulletproof)
+}
+
+/// A registration of the signal handlers, which are uninstalled when the last one is dropped.
+#[derive(Debug)]
+pub struct Registration {
+    bulletproof: Bulletproof,
+}
+
+impl Registration {
+    /// Installs the signal handlers, and registers the current thread, as
+    /// [`Bulletproof::try_new()`](../struct.Bulletproof.html#method.try_new).
+    ///
+    /// # Safety
+    ///
+    /// The same as [`Bulletproof::new()`](../struct.Bulletproof.html#method.new). In addition,
+    /// the manager returned by [`bulletproof()`](#method.bulletproof) should not be used after the
+    /// registration is dropped, unless the handlers are installed otherwise.
+    #[inline]
+    pub unsafe fn new() -> Result<Self, RegisterError> {
+        Self::with_config(Config::new())
+    }
+
+    /// Installs the signal handlers, and registers the current thread, as
+    /// [`Bulletproof::try_with_config()`](../struct.Bulletproof.html#method.try_with_config).
+    ///
+    /// # Safety
+    ///
+    /// The same as [`new()`](#method.new).
+    pub unsafe fn with_config(config: Config) -> Result<Self, RegisterError> {
+        let mut registrations = REGISTRATIONS.lock().unwrap();
+        let bulletproof = Bulletproof::install(config)?;
+        registrations.live += 1;
+        Ok(Self { bulletproof })
+    }
+
+    /// Returns the bulletproof memory access manager.
+    #[inline]
+    pub fn bulletproof(&self) -> Bulletproof {
+        self.bulletproof
+    }
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        let mut registrations = REGISTRATIONS.lock().unwrap();
+        registrations.live -= 1;
+        if registrations.live == 0 && !registrations.permanent {
+            unsafe { backend::unregister() };
+            backend::forget_handlers();
+            #[cfg(feature = "tracing")]
+            trace::handlers_uninstalled();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{mem, ptr};
+
+    use libc;
+
+    use super::*;
+    use tests;
+
+    #[test]
+    #[cfg_attr(any(miri, bulletproof_fallback, feature = "signal-hook"), ignore)]
+    fn restores_dispositions() {
+        unsafe fn segv_action() -> libc::sighandler_t {
+            let mut action: libc::sigaction = mem::zeroed();
+            libc::sigaction(libc::SIGSEGV, ptr::null(), &mut action);
+            action.sa_sigaction
+        }
+
+        // In a new process, as other tests install the handlers permanently.
+        if !tests::in_subprocess("registration::tests::restores_dispositions") {
+            return;
+        }
+
+        unsafe {
+            let mut ignore: libc::sigaction = mem::zeroed();
+            ignore.sa_sigaction = libc::SIG_IGN;
+            libc::sigaction(libc::SIGSEGV, &ignore, ptr::null_mut());
+
+            let first = Registration::new().unwrap();
+            let second = Registration::new().unwrap();
+            assert_ne!(segv_action(), libc::SIG_IGN);
+            drop(first);
+            assert!(second.bulletproof().load_usize(ptr::null()).is_err());
+            drop(second);
+            assert_eq!(segv_action(), libc::SIG_IGN);
+        }
+    }
+}
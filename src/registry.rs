@@ -0,0 +1,146 @@
+//! Registration through `signal-hook-registry` (`signal-hook` feature).
+//!
+//! The registry calls its actions while holding internal locks, so the actions should not be
+//! escaped with `siglongjmp()`. Instead, the action defers the recovery to a dedicated escape signal
+//! (`SIGRTMAX - 1`, or `SIGUSR2` where real-time signals are unavailable), whose handler runs after
+//! the registry's handler returned. See `bulletproof_impl_defer_signal()` in `impl.c`.
+
+use std::io;
+use std::mem;
+use std::ptr;
+use std::sync::Mutex;
+
+use libc::{self, c_int, siginfo_t};
+use signal_hook_registry::{self, SigId};
+
+use config::Config;
+use error::{RegisterError, RegisterStep};
+use native;
+
+extern "C" {
+    fn bulletproof_impl_register_escape(failed_sig: *mut c_int) -> c_int;
+    fn bulletproof_impl_defer_signal(sig: c_int, si: *const siginfo_t) -> c_int;
+    fn bulletproof_impl_save_prev_action(sig: c_int, act: *const libc::sigaction);
+}
+
+/// What has been registered.
+struct Registered {
+    escape: bool,
+    segv: Option<SigId>,
+    bus: Option<SigId>,
+}
+
+static REGISTERED: Mutex<Registered> = Mutex::new(Registered {
+    escape: false,
+    segv: None,
+    bus: None,
+});
+
+/// Registers the escape signal's handler and the recovery actions for the signals selected by
+/// `config`, each at most once, because the registry would call every registered copy.
+pub unsafe fn register(config: &Config) -> Result<(), RegisterError> {
+    native::configure(config);
+
+    let mut registered = REGISTERED.lock().unwrap();
+    if !registered.escape {
+        let mut failed_sig = 0;
+        match bulletproof_impl_register_escape(&mut failed_sig) {
+            0 => registered.escape = true,
+            errno => return Err(RegisterError::new(RegisterStep::Sigaction(failed_sig), errno)),
+        }
+    }
+    if config.get_catch_segv() && registered.segv.is_none() {
+        registered.segv = Some(register_action(libc::SIGSEGV)?);
+    }
+    if config.get_catch_bus() && registered.bus.is_none() {
+        registered.bus = Some(register_action(libc::SIGBUS)?);
+    }
+    Ok(())
+}
+
+/// Unregisters the recovery actions. The registry's handler and the escape signal's handler stay
+/// installed: the former passes foreign faults to the disposition before it, and the latter is
+/// only raised by the actions.
+pub unsafe fn unregister() {
+    let mut registered = REGISTERED.lock().unwrap();
+    for id in registered.segv.take().into_iter().chain(registered.bus.take()) {
+        signal_hook_registry::unregister(id);
+    }
+}
+
+/// Converts an error of the registry.
+fn registry_error(error: io::Error) -> RegisterError {
+    RegisterError::new(RegisterStep::Registry, error.raw_os_error().unwrap_or(0))
+}
+
+/// Registers the recovery action for `sig`.
+///
+/// The registry chains to the handler installed before its first registration for `sig`. That is
+/// typically the standard library's stack overflow handler, which resets the disposition to the
+/// default on a fault it does not own, breaking all later recoveries. So it resets the disposition
+/// before registration. If the registry already owns `sig`, it does not reinstall its handler, and
+/// the original disposition is restored.
+///
+/// On a fault outside bulletproof memory access, the action restores the disposition before
+/// registration and re-raises the signal, because the registry would otherwise retry the faulting
+/// instruction forever.
+///
+/// The registry does not install its handler with `SA_ONSTACK`, so it is added afterwards, for
+/// recovering from faults raised when the regular stack is exhausted.
+unsafe fn register_action(sig: c_int) -> Result<SigId, RegisterError> {
+    let mut old: libc::sigaction = mem::zeroed();
+    let mut default: libc::sigaction = mem::zeroed();
+    default.sa_sigaction = libc::SIG_DFL;
+    if libc::sigaction(sig, &default, &mut old) != 0 {
+        let errno = io::Error::last_os_error().raw_os_error().unwrap_or(0);
+        return Err(RegisterError::new(RegisterStep::Sigaction(sig), errno));
+    }
+
+    let registered = signal_hook_registry::register_unchecked(sig, move |info: &siginfo_t| {
+        bulletproof_impl_defer_signal(sig, info);
+    });
+
+    let mut now: libc::sigaction = mem::zeroed();
+    libc::sigaction(sig, ptr::null(), &mut now);
+    if now.sa_sigaction == libc::SIG_DFL {
+        libc::sigaction(sig, &old, ptr::null_mut());
+        now = old;
+    } else {
+        // Restored on foreign faults. If the registry already owned `sig`, the disposition before
+        // it is unknown, and the default is restored instead.
+        bulletproof_impl_save_prev_action(sig, &old);
+    }
+    if now.sa_sigaction != libc::SIG_DFL && now.sa_sigaction != libc::SIG_IGN {
+        now.sa_flags |= libc::SA_ONSTACK;
+        libc::sigaction(sig, &now, ptr::null_mut());
+    }
+
+    registered.map_err(registry_error)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+    use super::super::Bulletproof;
+
+    #[test]
+    fn composes_with_other_actions() {
+        static CALLED: AtomicUsize = AtomicUsize::new(0);
+
+        unsafe {
+            let bulletproof = Bulletproof::new();
+            let id = signal_hook_registry::register_unchecked(libc::SIGSEGV, |_: &siginfo_t| {
+                CALLED.fetch_add(1, Ordering::Relaxed);
+            }).unwrap();
+
+            assert!(bulletproof.load_usize(ptr::null()).is_err());
+            assert!(CALLED.load(Ordering::Relaxed) > 0);
+
+            // It would hang if the registry's locks were leaked.
+            assert!(signal_hook_registry::unregister(id));
+            assert_eq!(bulletproof.load_usize(ptr::null()).map_err(|f| f.addr()), Err(0));
+        }
+    }
+}
@@ -0,0 +1,224 @@
+//! Human-readable reports of faults, with addresses resolved against the loaded modules.
+//!
+//! A raw fault is a pair of numbers: the address of the faulting instruction, and the faulting
+//! address. [`FaultReport`](struct.FaultReport.html) resolves them against the memory map (see
+//! [`snapshot::regions()`](../snapshot/fn.regions.html)) into offsets in the loaded modules, e.g.
+//! `libfoo.so+0x1234`, and the instruction against the dynamic symbols with `dladdr()`, so that
+//! embedders can log where a fault happened and what it accessed.
+//!
+//! Symbols that are not exported (e.g. of static functions) are not resolved. A
+//! [`ModuleOffset`](struct.ModuleOffset.html) has the path and the offset expected by DWARF-based
+//! symbolizers, e.g. `addr2line -e libfoo.so 0x1234`, for source locations.
+//!
+//! # Examples
+//!
+//! ```
+//! # #[cfg(not(bulletproof_fallback))]
+//! # {
+//! use bulletproof::report::FaultReport;
+//! use bulletproof::Bulletproof;
+//!
+//! unsafe {
+//!     let fault = Bulletproof::new().load_usize(0xdead0000 as *const usize).unwrap_err();
+//!     let report = FaultReport::last(fault).to_string();
+//!     assert!(report.ends_with("accessing 0xdead0000"));
+//!     // E.g. "segmentation fault at libfoo.so+0x1234 accessing 0xdead0000".
+//!     println!("{}", report);
+//! }
+//! # }
+//! ```
+
+use std::ffi::CStr;
+use std::fmt;
+use std::mem;
+use std::path::Path;
+
+use libc::{self, c_void};
+
+use fault::{self, Fault, Registers};
+use snapshot::{self, Region};
+
+/// An address resolved to an offset in a loaded module.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ModuleOffset {
+    /// The path of the module, e.g. `/usr/lib/libfoo.so`.
+    pub path: String,
+    /// The lowest address the module is mapped at, i.e. its load address.
+    pub base: usize,
+    /// The offset of the address from `base`.
+    pub offset: usize,
+}
+
+impl ModuleOffset {
+    /// Resolves `addr` against the memory map of the current process.
+    ///
+    /// Returns `None` if `addr` is not in a file-backed mapping, or if the memory map cannot be
+    /// read.
+    pub fn resolve(addr: usize) -> Option<Self> {
+        Self::resolve_in(&snapshot::regions().ok()?, addr)
+    }
+
+    /// Resolves `addr` against `regions`.
+    fn resolve_in(regions: &[Region], addr: usize) -> Option<Self> {
+        let region = regions.iter().find(|region| region.start <= addr && addr < region.end)?;
+        if !region.name.starts_with('/') {
+            return None;
+        }
+        let base = regions
+            .iter()
+            .filter(|other| other.name == region.name)
+            .map(|other| other.start)
+            .min()?;
+        Some(Self { path: region.name.clone(), base, offset: addr - base })
+    }
+}
+
+impl fmt::Display for ModuleOffset {
+    /// Formats the file name of the module and the offset, e.g. `libfoo.so+0x1234`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = Path::new(&self.path).file_name().unwrap_or_default().to_string_lossy();
+        write!(f, "{}+{:#x}", name, self.offset)
+    }
+}
+
+/// Returns the dynamic symbol containing `addr` and the offset in it, with `dladdr()`.
+fn symbol(addr: usize) -> Option<(String, usize)> {
+    unsafe {
+        let mut info: libc::Dl_info = mem::zeroed();
+        if libc::dladdr(addr as *const c_void, &mut info) == 0 || info.dli_sname.is_null() {
+            return None;
+        }
+        let name = CStr::from_ptr(info.dli_sname).to_string_lossy().into_owned();
+        Some((name, addr.wrapping_sub(info.dli_saddr as usize)))
+    }
+}
+
+/// A fault with its addresses resolved, whose `Display` is a one-line report.
+///
+/// The report is e.g. `segmentation fault at libfoo.so+0x1234 (foo_get+0x14) accessing
+/// 0xdead0000`, where the location of the instruction is omitted if the registers are unknown, and
+/// each address is printed as is if it is not in a module.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FaultReport {
+    fault: Fault,
+    pc: Option<usize>,
+    pc_module: Option<ModuleOffset>,
+    pc_symbol: Option<(String, usize)>,
+    addr_module: Option<ModuleOffset>,
+}
+
+impl FaultReport {
+    /// Resolves `fault` and the program counter in `registers`, reading the memory map once.
+    pub fn new(fault: Fault, registers: Option<Registers>) -> Self {
+        let regions = snapshot::regions().unwrap_or_default();
+        let pc = match fault {
+            // The instruction is at the faulting address.
+            Fault::ExecViolation { .. } => None,
+            _ => registers.map(|registers| registers.pc).filter(|&pc| pc != 0),
+        };
+        Self {
+            fault,
+            pc,
+            pc_module: pc.and_then(|pc| ModuleOffset::resolve_in(&regions, pc)),
+            pc_symbol: pc.and_then(symbol),
+            addr_module: ModuleOffset::resolve_in(&regions, fault.addr()),
+        }
+    }
+
+    /// Resolves `fault` with the registers at the last fault of the current thread (see
+    /// [`fault::last_registers()`](../fault/fn.last_registers.html)), which it should be.
+    pub fn last(fault: Fault) -> Self {
+        Self::new(fault, fault::last_registers())
+    }
+
+    /// Returns the fault.
+    #[inline]
+    pub fn fault(&self) -> Fault {
+        self.fault
+    }
+
+    /// Returns the module and the offset of the faulting instruction, if known.
+    #[inline]
+    pub fn pc_module(&self) -> Option<&ModuleOffset> {
+        self.pc_module.as_ref()
+    }
+
+    /// Returns the dynamic symbol containing the faulting instruction and the offset in it, if
+    /// known.
+    #[inline]
+    pub fn pc_symbol(&self) -> Option<(&str, usize)> {
+        self.pc_symbol.as_ref().map(|(name, offset)| (name.as_str(), *offset))
+    }
+
+    /// Returns the module and the offset of the faulting address, if it is in a module.
+    #[inline]
+    pub fn addr_module(&self) -> Option<&ModuleOffset> {
+        self.addr_module.as_ref()
+    }
+}
+
+impl fmt::Display for FaultReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let addr = self.fault.addr();
+        let addr_module = |f: &mut fmt::Formatter| match self.addr_module {
+            Some(ref module) => write!(f, " ({})", module),
+            None => Ok(()),
+        };
+
+        match self.fault {
+            Fault::ExecViolation { .. } => {
+                write!(f, "segmentation fault executing {:#x}", addr)?;
+                return addr_module(f);
+            }
+            Fault::Segv { .. } => f.write_str("segmentation fault")?,
+            Fault::Bus { .. } => f.write_str("bus error")?,
+        }
+
+        if let Some(pc) = self.pc {
+            match self.pc_module {
+                Some(ref module) => write!(f, " at {}", module)?,
+                None => write!(f, " at {:#x}", pc)?,
+            }
+            if let Some((ref name, offset)) = self.pc_symbol {
+                write!(f, " ({}+{:#x})", name, offset)?;
+            }
+        }
+        write!(f, " accessing {:#x}", addr)?;
+        addr_module(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, ptr};
+
+    use super::*;
+    use Bulletproof;
+
+    #[test]
+    #[cfg_attr(any(miri, bulletproof_fallback), ignore)]
+    fn report() {
+        // This function is in the test executable.
+        let here = report as fn() as usize;
+        let module = ModuleOffset::resolve(here).unwrap();
+        assert_eq!(module.base + module.offset, here);
+        let exe = env::current_exe().unwrap();
+        assert_eq!(Path::new(&module.path).file_name(), exe.file_name());
+
+        unsafe {
+            let fault = Bulletproof::new().load_usize(ptr::null()).unwrap_err();
+            let report = FaultReport::last(fault).to_string();
+            assert!(report.ends_with(" accessing 0x0"), "{}", report);
+            if fault::last_registers().is_some() {
+                // The load is in the shim, which is linked into the executable.
+                let name = exe.file_name().unwrap().to_string_lossy();
+                let prefix = format!("segmentation fault at {}+", name);
+                assert!(report.starts_with(&prefix), "{}", report);
+            }
+        }
+
+        let report = FaultReport::new(Fault::Bus { addr: here, code: 0 }, None);
+        assert_eq!(report.addr_module(), Some(&module));
+        assert_eq!(report.to_string(), format!("bus error accessing {:#x} ({})", here, module));
+    }
+}
@@ -0,0 +1,215 @@
+//! Readers of single-producer single-consumer ring buffers in shared memory.
+//!
+//! A lock-free IPC queue is typically a ring of bytes in a region shared with the producer, with
+//! two free-running indices: the producer advances the head after writing, and the consumer
+//! advances the tail after reading. If the producer dies, its region may be truncated or unmapped
+//! under the consumer. [`RingReader`](struct.RingReader.html) consumes such a ring through a
+//! [`SharedRegion`](../shared/struct.SharedRegion.html), and reports the loss of the region as
+//! [`RingError::Disconnected`](enum.RingError.html#variant.Disconnected).
+//!
+//! # Examples
+//!
+//! ```
+//! use bulletproof::ring::{RingLayout, RingReader};
+//! use bulletproof::shared::SharedRegion;
+//! use bulletproof::Bulletproof;
+//!
+//! // The head, the tail, and a ring of 16 bytes.
+//! let mut memory = [0u64; 4];
+//! let layout = RingLayout { head: 0, tail: 8, data: 16, capacity: 16 };
+//!
+//! unsafe {
+//!     let bulletproof = Bulletproof::new();
+//!     let region = SharedRegion::open(bulletproof, memory.as_mut_ptr() as *mut u8, 32);
+//!
+//!     // The producer writes three bytes.
+//!     region.write_bytes(16, b"abc").unwrap();
+//!     region.write(0, 3u64).unwrap();
+//!
+//!     let mut reader = RingReader::new(region, layout);
+//!     let mut buf = [0u8; 8];
+//!     assert_eq!(reader.read(&mut buf), Ok(3));
+//!     assert_eq!(&buf[..3], b"abc");
+//!     assert_eq!(reader.available(), Ok(0));
+//! }
+//! ```
+
+use std::error::Error;
+use std::sync::atomic::{self, Ordering};
+use std::{cmp, fmt, mem};
+
+use shared::{SharedError, SharedRegion};
+
+/// An error of reading a ring.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RingError {
+    /// The region of the ring was revoked or unmapped, e.g. because the producer died.
+    Disconnected,
+    /// The head is behind the tail, or ahead of it by more than the capacity.
+    Corrupted,
+}
+
+impl fmt::Display for RingError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            RingError::Disconnected => "ring buffer disconnected",
+            RingError::Corrupted => "ring buffer indices corrupted",
+        })
+    }
+}
+
+impl Error for RingError {}
+
+impl From<SharedError> for RingError {
+    #[inline]
+    fn from(error: SharedError) -> Self {
+        match error {
+            SharedError::Revoked | SharedError::Unmapped => RingError::Disconnected,
+            // The layout is checked by `RingReader::new()`.
+            SharedError::OutOfBounds | SharedError::Misaligned => unreachable!("{}", error),
+        }
+    }
+}
+
+/// The layout of a ring in its region, as offsets in bytes.
+///
+/// The head and the tail are `u64` counts of the bytes written and read since the ring was
+/// created, which wrap around. The byte at index `i` is at `data + i % capacity`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RingLayout {
+    /// The offset of the head, written by the producer.
+    pub head: usize,
+    /// The offset of the tail, written by the consumer.
+    pub tail: usize,
+    /// The offset of the ring of bytes.
+    pub data: usize,
+    /// The capacity of the ring in bytes, which is a power of two.
+    pub capacity: usize,
+}
+
+/// The consumer of a ring buffer in a shared region.
+///
+/// The indices and the bytes are accessed with volatile loads and stores in protected sections.
+/// The head is loaded with acquire ordering and the tail stored with release ordering, with
+/// fences, so that the producer may use atomics on its side.
+#[derive(Debug)]
+pub struct RingReader {
+    region: SharedRegion,
+    layout: RingLayout,
+}
+
+impl RingReader {
+    /// Creates the consumer of the ring with `layout` in `region`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the capacity is not a power of two, or if the indices or the ring are out of
+    /// bounds of the region, or if the indices are misaligned.
+    pub fn new(region: SharedRegion, layout: RingLayout) -> Self {
+        assert!(layout.capacity.is_power_of_two(), "capacity not a power of two");
+        for &index in &[layout.head, layout.tail] {
+            let end = index.checked_add(mem::size_of::<u64>());
+            assert!(end.is_some_and(|end| end <= region.len()), "index out of bounds");
+            let addr = region.as_ptr() as usize + index;
+            assert!(addr & (mem::align_of::<u64>() - 1) == 0, "misaligned index");
+        }
+        let end = layout.data.checked_add(layout.capacity);
+        assert!(end.is_some_and(|end| end <= region.len()), "ring out of bounds");
+        Self { region, layout }
+    }
+
+    /// Returns the head and the tail.
+    fn indices(&self) -> Result<(u64, u64), RingError> {
+        let head = unsafe { self.region.read::<u64>(self.layout.head)? };
+        atomic::fence(Ordering::Acquire);
+        let tail = unsafe { self.region.read::<u64>(self.layout.tail)? };
+        if head.wrapping_sub(tail) > self.layout.capacity as u64 {
+            return Err(RingError::Corrupted);
+        }
+        Ok((head, tail))
+    }
+
+    /// Returns the number of bytes available to read.
+    pub fn available(&self) -> Result<usize, RingError> {
+        let (head, tail) = self.indices()?;
+        Ok(head.wrapping_sub(tail) as usize)
+    }
+
+    /// Reads up to `buf.len()` available bytes into `buf`, and returns their number. It does not
+    /// wait for bytes to become available.
+    ///
+    /// On error, the tail is not advanced, and the contents of `buf` are unspecified.
+    pub fn read(&mut self, buf: &mut [u8]) -> Result<usize, RingError> {
+        let (head, tail) = self.indices()?;
+        let len = cmp::min(head.wrapping_sub(tail) as usize, buf.len());
+
+        // The bytes up to the end of the ring, and those wrapped around.
+        let start = tail as usize & (self.layout.capacity - 1);
+        let first = cmp::min(len, self.layout.capacity - start);
+        self.region.read_bytes(self.layout.data + start, &mut buf[..first])?;
+        self.region.read_bytes(self.layout.data, &mut buf[first..len])?;
+
+        atomic::fence(Ordering::Release);
+        self.region.write(self.layout.tail, tail.wrapping_add(len as u64))?;
+        Ok(len)
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::env;
+    use std::fs::{self, OpenOptions};
+    use std::os::unix::io::AsRawFd;
+    use std::{process, ptr};
+
+    use super::*;
+    use Bulletproof;
+
+    #[test]
+    #[cfg_attr(any(miri, bulletproof_fallback), ignore)]
+    fn read() {
+        let path = env::temp_dir().join(format!("bulletproof-ring-{}", process::id()));
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)
+            .unwrap();
+        file.set_len(4096).unwrap();
+
+        unsafe {
+            // A shared mapping of the file, as the producer would map a shared memory object.
+            let prot = libc::PROT_READ | libc::PROT_WRITE;
+            let fd = file.as_raw_fd();
+            let map = libc::mmap(ptr::null_mut(), 4096, prot, libc::MAP_SHARED, fd, 0);
+            assert_ne!(map, libc::MAP_FAILED);
+            let region = SharedRegion::open(Bulletproof::new(), map as *mut u8, 4096);
+            let layout = RingLayout { head: 0, tail: 64, data: 128, capacity: 8 };
+            let mut reader = RingReader::new(region, layout);
+            let mut buf = [0u8; 8];
+
+            // Wraps around the end of the ring.
+            region.write(64, 6u64).unwrap();
+            region.write_bytes(128, b"cdef....").unwrap();
+            region.write_bytes(134, b"ab").unwrap();
+            region.write(0, 12u64).unwrap();
+            assert_eq!(reader.available(), Ok(6));
+            assert_eq!(reader.read(&mut buf[..4]), Ok(4));
+            assert_eq!(&buf[..4], b"abcd");
+            assert_eq!(reader.read(&mut buf), Ok(2));
+            assert_eq!(&buf[..2], b"ef");
+            assert_eq!(region.read::<u64>(64), Ok(12));
+
+            region.write(0, 100u64).unwrap();
+            assert_eq!(reader.read(&mut buf), Err(RingError::Corrupted));
+
+            // The producer dies, and its region is revoked.
+            file.set_len(0).unwrap();
+            assert_eq!(reader.read(&mut buf), Err(RingError::Disconnected));
+
+            libc::munmap(map, 4096);
+        }
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,282 @@
+//! Backends selected at runtime.
+//!
+//! By default, faults are recovered by signal handlers ([`Backend::Signal`]), which is by far the
+//! cheapest mechanism. It is not usable everywhere, e.g. if another runtime in the process owns
+//! `SIGSEGV` and does not chain to other handlers, or if the platform does not deliver signals at
+//! all. The other backends check the accesses with system calls instead, and never raise a fault.
+//!
+//! Only the accesses of [`Bulletproof`](struct.Bulletproof.html) (loads, stores, reads, scans, and
+//! probes) go through its backend. Protected sections and the modules built on fault handling
+//! (e.g. `cow`, `tracking`, and `guarded`) need the signal handlers.
+//!
+//! A [`userfaultfd(2)`](http://man7.org/linux/man-pages/man2/userfaultfd.2.html)-based manager is
+//! provided separately as `uffd::Userfaultfd` (`userfaultfd` feature), as it only covers the
+//! ranges registered with it.
+//!
+//! [`Backend::Signal`]: enum.Backend.html#variant.Signal
+
+use std::fmt;
+#[cfg(all(any(target_os = "linux", target_os = "android"), not(miri)))]
+use std::sync::OnceLock;
+use std::{cmp, ptr};
+
+use super::probe;
+use fault::{Fault, SEGV_ACCERR, SEGV_MAPERR};
+
+/// A mechanism of bulletproof memory access.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Backend {
+    /// Accesses memory directly, and recovers from faults with `SIGSEGV` and `SIGBUS` handlers.
+    Signal,
+    /// Copies memory with
+    /// [`process_vm_readv(2)`](http://man7.org/linux/man-pages/man2/process_vm_readv.2.html) and
+    /// `process_vm_writev(2)` on the current process, which fail instead of faulting (Linux and
+    /// Android only).
+    ///
+    /// An access costs a system call. Faults are reported as `SIGSEGV` faults at the first
+    /// inaccessible page: of code `SEGV_ACCERR` if it is mapped, and `SEGV_MAPERR` otherwise. Bus
+    /// errors (e.g. past the end of a truncated file) are reported as `SEGV_ACCERR` faults.
+    ProcessVmReadv,
+    /// Checks with [`mincore(2)`](http://man7.org/linux/man-pages/man2/mincore.2.html) that the
+    /// pages are mapped, and then accesses memory directly.
+    ///
+    /// It only tells unmapped pages apart: accessing a mapped page without the permission (e.g. a
+    /// guard page, or a store to read-only memory) still crashes the process, and a page can be
+    /// unmapped between the check and the access. [`detect()`](#method.detect) never picks it.
+    Mincore,
+}
+
+impl Backend {
+    /// Picks the best backend available in this process.
+    ///
+    /// It is [`Signal`](#variant.Signal) if the target supports signal handlers, as it is the
+    /// cheapest, and otherwise [`ProcessVmReadv`](#variant.ProcessVmReadv) if the system calls are
+    /// permitted (e.g. not filtered by seccomp). If none is available, it is `Signal`, for which
+    /// [`Bulletproof::with_backend()`](struct.Bulletproof.html#method.with_backend) reports the
+    /// error.
+    pub fn detect() -> Self {
+        [Backend::Signal, Backend::ProcessVmReadv]
+            .iter()
+            .cloned()
+            .find(|backend| backend.is_available())
+            .unwrap_or(Backend::Signal)
+    }
+
+    /// Returns `true` if the backend is available in this process.
+    ///
+    /// [`ProcessVmReadv`](#variant.ProcessVmReadv) is tried on a local variable once, and the
+    /// result is cached. [`Mincore`](#variant.Mincore) is tried on a local variable.
+    pub fn is_available(self) -> bool {
+        match self {
+            Backend::Signal => cfg!(not(all(
+                bulletproof_unsupported,
+                not(any(miri, bulletproof_fallback))
+            ))),
+            Backend::ProcessVmReadv => process_vm_available(),
+            Backend::Mincore => {
+                let local = 0u8;
+                unsafe { mapped(&local as *const u8 as usize, 1) }
+            }
+        }
+    }
+
+    /// Returns the name of the backend, e.g. `"process_vm_readv"`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Backend::Signal => "signal",
+            Backend::ProcessVmReadv => "process_vm_readv",
+            Backend::Mincore => "mincore",
+        }
+    }
+}
+
+impl fmt::Display for Backend {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Copies `len` bytes from `addr` to `buf` with `process_vm_readv()`, or from `buf` to `addr` with
+/// `process_vm_writev()` if `write`. Returns `true` if all bytes are copied.
+#[cfg(all(any(target_os = "linux", target_os = "android"), not(miri)))]
+unsafe fn process_vm(write: bool, addr: usize, buf: *mut u8, len: usize) -> bool {
+    let local = libc::iovec { iov_base: buf as *mut libc::c_void, iov_len: len };
+    let remote = libc::iovec { iov_base: addr as *mut libc::c_void, iov_len: len };
+    let copied = if write {
+        libc::process_vm_writev(libc::getpid(), &local, 1, &remote, 1, 0)
+    } else {
+        libc::process_vm_readv(libc::getpid(), &local, 1, &remote, 1, 0)
+    };
+    copied == len as isize
+}
+
+#[cfg(not(all(any(target_os = "linux", target_os = "android"), not(miri))))]
+unsafe fn process_vm(_write: bool, _addr: usize, _buf: *mut u8, _len: usize) -> bool {
+    false
+}
+
+#[cfg(all(any(target_os = "linux", target_os = "android"), not(miri)))]
+fn process_vm_available() -> bool {
+    static AVAILABLE: OnceLock<bool> = OnceLock::new();
+
+    *AVAILABLE.get_or_init(|| {
+        let src = 42usize;
+        let mut dst = 0usize;
+        unsafe {
+            let ok = process_vm(
+                false,
+                &src as *const usize as usize,
+                &mut dst as *mut usize as *mut u8,
+                std::mem::size_of::<usize>(),
+            );
+            ok && dst == src
+        }
+    })
+}
+
+#[cfg(not(all(any(target_os = "linux", target_os = "android"), not(miri))))]
+fn process_vm_available() -> bool {
+    false
+}
+
+/// Returns `true` if all pages overlapping `[addr, addr + len)` are mapped.
+#[cfg(all(
+    any(
+        target_os = "linux",
+        target_os = "android",
+        target_os = "macos",
+        target_os = "ios",
+        target_os = "freebsd",
+        target_os = "dragonfly",
+        target_os = "openbsd",
+        target_os = "netbsd",
+        target_os = "illumos",
+        target_os = "solaris"
+    ),
+    not(miri)
+))]
+pub(crate) unsafe fn mapped(addr: usize, len: usize) -> bool {
+    if len == 0 {
+        return true;
+    }
+    let page_size = probe::page_size();
+    let first = addr & !(page_size - 1);
+    let end = match addr.checked_add(len) {
+        Some(end) => end,
+        None => return false,
+    };
+    let mut vec = vec![0u8; (end - first).div_ceil(page_size)];
+    libc::mincore(first as _, end - first, vec.as_mut_ptr() as *mut _) == 0
+}
+
+#[cfg(not(all(
+    any(
+        target_os = "linux",
+        target_os = "android",
+        target_os = "macos",
+        target_os = "ios",
+        target_os = "freebsd",
+        target_os = "dragonfly",
+        target_os = "openbsd",
+        target_os = "netbsd",
+        target_os = "illumos",
+        target_os = "solaris"
+    ),
+    not(miri)
+)))]
+pub(crate) unsafe fn mapped(_addr: usize, _len: usize) -> bool {
+    false
+}
+
+/// Copies `len` bytes as [`process_vm()`](fn.process_vm.html), after checking that the pages of
+/// `addr` are mapped.
+unsafe fn mincore(write: bool, addr: usize, buf: *mut u8, len: usize) -> bool {
+    if !mapped(addr, len) {
+        return false;
+    }
+    if write {
+        ptr::copy_nonoverlapping(buf, addr as *mut u8, len);
+    } else {
+        ptr::copy_nonoverlapping(addr as *const u8, buf, len);
+    }
+    true
+}
+
+/// Copies `len` bytes between `addr` and `buf` with `via`, and returns the fault at the first
+/// page that cannot be accessed. On a fault, a prefix of the bytes may have been copied.
+unsafe fn transfer(via: Backend, write: bool, addr: usize, buf: *mut u8, len: usize)
+    -> Result<(), Fault>
+{
+    let copy = |addr: usize, buf: *mut u8, len: usize| match via {
+        Backend::ProcessVmReadv => process_vm(write, addr, buf, len),
+        Backend::Mincore => mincore(write, addr, buf, len),
+        Backend::Signal => unreachable!("signal accesses are performed by the backend"),
+    };
+
+    // The whole range is usually valid, so try it first.
+    if copy(addr, buf, len) {
+        return Ok(());
+    }
+
+    let page_size = probe::page_size();
+    let mut offset = 0;
+    while offset < len {
+        let start = addr.wrapping_add(offset);
+        let chunk = cmp::min(page_size - start % page_size, len - offset);
+        if !copy(start, buf.add(offset), chunk) {
+            let code = if mapped(start, 1) { SEGV_ACCERR } else { SEGV_MAPERR };
+            return Err(Fault::Segv { addr: start, code });
+        }
+        offset += chunk;
+    }
+    // The inaccessible page became accessible in the meantime.
+    Ok(())
+}
+
+/// Loads `len` bytes from `loc` to `dst` with `via`, which is not `Backend::Signal`.
+#[inline]
+pub(crate) unsafe fn load(via: Backend, loc: usize, dst: *mut u8, len: usize) -> Result<(), Fault> {
+    transfer(via, false, loc, dst, len)
+}
+
+/// Stores `len` bytes from `src` to `loc` with `via`, which is not `Backend::Signal`.
+#[inline]
+pub(crate) unsafe fn store(
+    via: Backend,
+    loc: usize,
+    src: *const u8,
+    len: usize,
+) -> Result<(), Fault> {
+    transfer(via, true, loc, src as *mut u8, len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn load_store() {
+        let mut x = 42usize;
+        let size = std::mem::size_of::<usize>();
+
+        for &via in &[Backend::ProcessVmReadv, Backend::Mincore] {
+            if !via.is_available() {
+                continue;
+            }
+            unsafe {
+                let mut y = 0usize;
+                let loc = &mut x as *mut usize as usize;
+                load(via, loc, &mut y as *mut usize as *mut u8, size).unwrap();
+                assert_eq!(y, 42);
+                store(via, loc, &37usize as *const usize as *const u8, size).unwrap();
+                assert_eq!(x, 37);
+                x = 42;
+
+                let fault = Fault::Segv { addr: 0, code: SEGV_MAPERR };
+                assert_eq!(load(via, 0, &mut y as *mut usize as *mut u8, size), Err(fault));
+                assert_eq!(store(via, 0, &y as *const usize as *const u8, size), Err(fault));
+            }
+        }
+    }
+}
@@ -0,0 +1,212 @@
+//! Safepoints with a polling page.
+//!
+//! As in HotSpot, mutator threads poll a [`Safepoint`](struct.Safepoint.html) at regular points
+//! (e.g. at each loop back-edge) with a single load from its polling page. While disarmed, the load
+//! succeeds and costs next to nothing. [`arm()`](struct.Safepoint.html#method.arm) makes the page
+//! inaccessible, so that the next poll of every thread faults, and the poll recovers from the fault
+//! and calls the callback, e.g. to park the thread until a garbage collection is done.
+//! [`disarm()`](struct.Safepoint.html#method.disarm) lets the polls succeed again.
+//!
+//! With the fallback backend (under Miri, or with `--cfg bulletproof_fallback`), the polls read a
+//! flag instead of the page.
+//!
+//! # Examples
+//!
+//! ```
+//! use bulletproof::{safepoint::Safepoint, Bulletproof};
+//! use std::sync::atomic::{AtomicUsize, Ordering};
+//! use std::sync::Arc;
+//!
+//! unsafe {
+//!     let bulletproof = Bulletproof::new();
+//!
+//!     let stops = Arc::new(AtomicUsize::new(0));
+//!     let counter = stops.clone();
+//!     let safepoint = Safepoint::new(bulletproof, move || {
+//!         counter.fetch_add(1, Ordering::SeqCst);
+//!     })
+//!     .unwrap();
+//!
+//!     assert!(!safepoint.poll());
+//!     safepoint.arm().unwrap();
+//!     assert!(safepoint.poll());
+//!     safepoint.disarm().unwrap();
+//!     assert!(!safepoint.poll());
+//!     assert_eq!(stops.load(Ordering::SeqCst), 1);
+//! }
+//! ```
+
+use std::fmt;
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use libc::{self, c_void};
+
+use super::mapping::Mapping;
+use super::{probe, Bulletproof};
+
+/// A safepoint, which threads poll to be stopped while it is armed.
+pub struct Safepoint {
+    bulletproof: Bulletproof,
+    page: Mapping,
+    page_size: usize,
+    armed: AtomicBool,
+    callback: Box<dyn Fn() + Send + Sync>,
+}
+
+impl fmt::Debug for Safepoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Safepoint")
+            .field("page", &self.page)
+            .field("armed", &self.armed)
+            .finish()
+    }
+}
+
+impl Safepoint {
+    /// Creates a disarmed safepoint that calls `callback` in the threads polling it while armed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the polling page cannot be mapped.
+    pub fn new<F>(bulletproof: Bulletproof, callback: F) -> io::Result<Self>
+    where
+        F: Fn() + Send + Sync + 'static,
+    {
+        let page_size = probe::page_size();
+        Ok(Self {
+            bulletproof,
+            page: Mapping::new(page_size)?,
+            page_size,
+            armed: AtomicBool::new(false),
+            callback: Box::new(callback),
+        })
+    }
+
+    /// Returns the address of the polling page.
+    ///
+    /// Code generated by a JIT compiler may poll by loading from it, in a protected section.
+    #[inline]
+    pub fn page(&self) -> *const usize {
+        self.page.as_ptr() as *const usize
+    }
+
+    /// Returns `true` if the safepoint is armed.
+    #[inline]
+    pub fn is_armed(&self) -> bool {
+        self.armed.load(Ordering::SeqCst)
+    }
+
+    /// Polls the safepoint, calling the callback if it is armed. Returns `true` if the callback was
+    /// called.
+    #[inline]
+    pub fn poll(&self) -> bool {
+        if !self.load() {
+            return false;
+        }
+        (self.callback)();
+        true
+    }
+
+    /// Arms the safepoint, so that the polls call the callback until it is disarmed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the polling page cannot be protected.
+    pub fn arm(&self) -> io::Result<()> {
+        self.armed.store(true, Ordering::SeqCst);
+        self.protect(libc::PROT_NONE)
+    }
+
+    /// Disarms the safepoint.
+    ///
+    /// Threads already in the callback stay there until it returns.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the polling page cannot be unprotected.
+    pub fn disarm(&self) -> io::Result<()> {
+        self.armed.store(false, Ordering::SeqCst);
+        self.protect(libc::PROT_READ)
+    }
+
+    fn protect(&self, prot: libc::c_int) -> io::Result<()> {
+        let page = self.page.as_ptr() as *mut c_void;
+        if unsafe { libc::mprotect(page, self.page_size, prot) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// Loads from the polling page. Returns `true` if the load faulted, i.e. the page is armed.
+    #[cfg(not(any(miri, bulletproof_fallback)))]
+    #[inline]
+    fn load(&self) -> bool {
+        unsafe { self.bulletproof.load_usize(self.page()).is_err() }
+    }
+
+    /// Reads the flag, as the fallback backend cannot recover from the fault.
+    #[cfg(any(miri, bulletproof_fallback))]
+    #[inline]
+    fn load(&self) -> bool {
+        let _ = self.bulletproof;
+        self.is_armed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::{Arc, Barrier};
+
+    use super::*;
+    use thread;
+
+    #[test]
+    fn arm() {
+        unsafe {
+            let bulletproof = Bulletproof::new();
+            let threads = 4;
+
+            // The mutators and the controller meet at the barrier while the safepoint is armed.
+            let barrier = Arc::new(Barrier::new(threads + 1));
+            let stopped = barrier.clone();
+            let safepoint = Arc::new(
+                Safepoint::new(bulletproof, move || {
+                    stopped.wait();
+                    stopped.wait();
+                })
+                .unwrap(),
+            );
+            let done = Arc::new(AtomicBool::new(false));
+            let stops = Arc::new(AtomicUsize::new(0));
+
+            let handles = (0..threads)
+                .map(|_| {
+                    let safepoint = safepoint.clone();
+                    let done = done.clone();
+                    let stops = stops.clone();
+                    thread::spawn(move || {
+                        while !done.load(Ordering::SeqCst) {
+                            if safepoint.poll() {
+                                stops.fetch_add(1, Ordering::SeqCst);
+                            }
+                        }
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            safepoint.arm().unwrap();
+            assert!(safepoint.is_armed());
+            barrier.wait();
+            safepoint.disarm().unwrap();
+            done.store(true, Ordering::SeqCst);
+            barrier.wait();
+
+            for handle in handles {
+                handle.join().unwrap();
+            }
+            assert_eq!(stops.load(Ordering::SeqCst), threads);
+        }
+    }
+}
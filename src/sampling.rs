@@ -0,0 +1,357 @@
+//! Sampling the registers and the stack of another thread.
+//!
+//! A sampling profiler stops a thread at an arbitrary point, and reads its registers and the top
+//! of its stack. The stack may be torn (e.g. in the middle of a function prologue) or end early,
+//! so [`Sample::capture()`](struct.Sample.html#method.capture) reads it with bulletproof loads, and
+//! walks its frame pointer chain with [`backtrace::walk()`](../backtrace/fn.walk.html).
+//!
+//! The thread can be suspended by the caller (e.g. with `thread_suspend()` and
+//! `thread_get_state()` on macOS), or by a [`Sampler`](struct.Sampler.html), which sends it a
+//! signal whose handler records the interrupted registers and waits until the sample is taken.
+//!
+//! A suspended thread may hold any lock, including that of the allocator. A
+//! [`Sample`](struct.Sample.html) is allocated with its capacity beforehand, and capturing it does
+//! not allocate or take locks.
+//!
+//! # Examples
+//!
+//! ```
+//! # #[cfg(not(bulletproof_fallback))]
+//! # {
+//! use bulletproof::sampling::Sampler;
+//! use bulletproof::Bulletproof;
+//! use std::os::unix::thread::JoinHandleExt;
+//! use std::sync::atomic::{AtomicBool, Ordering};
+//! use std::sync::Arc;
+//!
+//! let done = Arc::new(AtomicBool::new(false));
+//! let spinning = done.clone();
+//! let worker = std::thread::spawn(move || {
+//!     while !spinning.load(Ordering::SeqCst) {}
+//! });
+//!
+//! unsafe {
+//!     let sampler = Sampler::new(Bulletproof::new(), libc::SIGPROF).unwrap();
+//!     let sample = sampler.sample(worker.as_pthread_t()).unwrap();
+//!     assert!(!sample.stack().is_empty());
+//! }
+//!
+//! done.store(true, Ordering::SeqCst);
+//! worker.join().unwrap();
+//! # }
+//! ```
+
+use std::io::{self, Read};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use std::{mem, ptr};
+
+use libc::{self, c_int, c_void, siginfo_t};
+
+use super::backtrace::{self, Frame};
+use super::fault::Registers;
+use super::memio::MemReader;
+use super::{backend, Bulletproof};
+
+/// The registers and the top of the stack of a thread.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sample {
+    registers: Registers,
+    stack: Vec<u8>,
+    stack_len: usize,
+    frames: Vec<Frame>,
+    frames_len: usize,
+}
+
+impl Sample {
+    /// Creates an empty sample that captures up to `stack_len` bytes of the stack and `frames_len`
+    /// frames.
+    pub fn with_capacity(stack_len: usize, frames_len: usize) -> Self {
+        Self {
+            registers: Registers { pc: 0, sp: 0, fp: 0 },
+            stack: Vec::with_capacity(stack_len),
+            stack_len,
+            frames: Vec::with_capacity(frames_len),
+            frames_len,
+        }
+    }
+
+    /// Captures the stack of a suspended thread whose registers are `registers`.
+    ///
+    /// The stack is read from the stack pointer upwards, up to the capacity or the first
+    /// unreadable page. The frame pointer chain is walked within the read part. It does not
+    /// allocate.
+    ///
+    /// # Safety
+    ///
+    /// The thread should be suspended, and reading its stack should have no side effects.
+    pub unsafe fn capture(&mut self, bulletproof: Bulletproof, registers: Registers) {
+        self.registers = registers;
+
+        self.stack.clear();
+        self.stack.resize(self.stack_len, 0);
+        let mut reader = MemReader::new(bulletproof, registers.sp as *const u8, self.stack_len);
+        let mut len = 0;
+        while len < self.stack_len {
+            match reader.read(&mut self.stack[len..]) {
+                Ok(0) | Err(_) => break,
+                Ok(n) => len += n,
+            }
+        }
+        self.stack.truncate(len);
+
+        self.frames.clear();
+        let stack = registers.sp..registers.sp.saturating_add(len);
+        for frame in backtrace::walk(bulletproof, registers.fp, stack).take(self.frames_len) {
+            self.frames.push(frame);
+        }
+    }
+
+    /// Returns the registers of the thread.
+    #[inline]
+    pub fn registers(&self) -> Registers {
+        self.registers
+    }
+
+    /// Returns the top of the stack, starting from the stack pointer.
+    #[inline]
+    pub fn stack(&self) -> &[u8] {
+        &self.stack
+    }
+
+    /// Returns the frames of the frame pointer chain, from the innermost one.
+    #[inline]
+    pub fn frames(&self) -> &[Frame] {
+        &self.frames
+    }
+}
+
+/// No sample is being taken.
+const IDLE: usize = 0;
+/// The target thread is signaled.
+const REQUESTED: usize = 1;
+/// The target thread is recording its registers.
+const RECORDING: usize = 2;
+/// The target thread waits in the handler.
+const SUSPENDED: usize = 3;
+/// The target thread may return from the handler.
+const RESUMED: usize = 4;
+
+/// The state of the sample being taken, shared with the handler.
+static STATE: AtomicUsize = AtomicUsize::new(IDLE);
+/// The target thread, as `pthread_self()` returns.
+static TARGET: AtomicUsize = AtomicUsize::new(0);
+/// The recorded registers, or a zero program counter if they are unknown.
+static PC: AtomicUsize = AtomicUsize::new(0);
+static SP: AtomicUsize = AtomicUsize::new(0);
+static FP: AtomicUsize = AtomicUsize::new(0);
+
+/// Serializes the samples, as there is a single set of the above.
+static SAMPLING: Mutex<()> = Mutex::new(());
+
+/// Returns the identifier of `thread` to store in `TARGET`.
+#[allow(clippy::unnecessary_cast)]
+#[inline]
+fn id(thread: libc::pthread_t) -> usize {
+    // `pthread_t` is an integer or a pointer depending on the platform.
+    thread as usize
+}
+
+/// Records the registers of the interrupted code, and waits until the sample is taken.
+extern "C" fn handler(_sig: c_int, _info: *mut siginfo_t, ctx: *mut c_void) {
+    // A signal sent for a timed-out sample may arrive later, for another target.
+    if TARGET.load(Ordering::SeqCst) != id(unsafe { libc::pthread_self() }) {
+        return;
+    }
+    if STATE.compare_exchange(REQUESTED, RECORDING, Ordering::SeqCst, Ordering::SeqCst).is_err() {
+        return;
+    }
+
+    let registers = unsafe { backend::context_registers(ctx) };
+    let registers = registers.unwrap_or(Registers { pc: 0, sp: 0, fp: 0 });
+    PC.store(registers.pc, Ordering::SeqCst);
+    SP.store(registers.sp, Ordering::SeqCst);
+    FP.store(registers.fp, Ordering::SeqCst);
+    STATE.store(SUSPENDED, Ordering::SeqCst);
+
+    while STATE.load(Ordering::SeqCst) != RESUMED {
+        unsafe {
+            libc::sched_yield();
+        }
+    }
+    STATE.store(IDLE, Ordering::SeqCst);
+}
+
+/// Takes samples of other threads by suspending them with a signal.
+#[derive(Debug, Clone, Copy)]
+pub struct Sampler {
+    bulletproof: Bulletproof,
+    signal: c_int,
+    stack_len: usize,
+    frames_len: usize,
+    timeout: Duration,
+}
+
+impl Sampler {
+    /// Creates a new sampler that suspends threads with `signal`, and installs its handler.
+    ///
+    /// The signal should be reserved for sampling (e.g. `SIGPROF` or a real-time signal), as its
+    /// previous handler is replaced. By default, a sample captures up to 64 KiB of the stack and
+    /// 256 frames, and a thread that does not handle the signal within a second times out.
+    ///
+    /// # Safety
+    ///
+    /// No other handler for `signal` should be installed while the sampler is used.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of `sigaction()` if the handler cannot be installed.
+    pub unsafe fn new(bulletproof: Bulletproof, signal: c_int) -> io::Result<Self> {
+        let mut action: libc::sigaction = mem::zeroed();
+        action.sa_sigaction = handler as *const () as usize;
+        action.sa_flags = libc::SA_SIGINFO | libc::SA_RESTART | libc::SA_ONSTACK;
+        libc::sigemptyset(&mut action.sa_mask);
+        if libc::sigaction(signal, &action, ptr::null_mut()) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(Self {
+            bulletproof,
+            signal,
+            stack_len: 64 << 10,
+            frames_len: 256,
+            timeout: Duration::from_secs(1),
+        })
+    }
+
+    /// Sets the number of bytes of the stack to capture.
+    #[inline]
+    pub fn stack_len(self, stack_len: usize) -> Self {
+        Self { stack_len, ..self }
+    }
+
+    /// Sets the maximum number of frames to walk.
+    #[inline]
+    pub fn frames_len(self, frames_len: usize) -> Self {
+        Self { frames_len, ..self }
+    }
+
+    /// Sets how long to wait for a thread to handle the signal.
+    #[inline]
+    pub fn timeout(self, timeout: Duration) -> Self {
+        Self { timeout, ..self }
+    }
+
+    /// Suspends `thread`, takes a sample of it, and resumes it.
+    ///
+    /// The sample is allocated before the thread is suspended, and the thread is suspended only
+    /// while it is captured.
+    ///
+    /// # Safety
+    ///
+    /// `thread` should be a live thread of the current process, and reading its stack should have
+    /// no side effects.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind `InvalidInput` if `thread` is the current thread, of kind
+    /// `TimedOut` if it does not handle the signal in time (e.g. because it blocks the signal), of
+    /// kind `Unsupported` if the registers cannot be read from the signal context on this platform,
+    /// and the error of `pthread_kill()` if the signal cannot be sent.
+    pub unsafe fn sample(&self, thread: libc::pthread_t) -> io::Result<Sample> {
+        if libc::pthread_equal(thread, libc::pthread_self()) != 0 {
+            let message = "cannot sample the current thread";
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, message));
+        }
+        let mut sample = Sample::with_capacity(self.stack_len, self.frames_len);
+
+        let _sampling = SAMPLING.lock().unwrap_or_else(|e| e.into_inner());
+        TARGET.store(id(thread), Ordering::SeqCst);
+        STATE.store(REQUESTED, Ordering::SeqCst);
+        let errno = libc::pthread_kill(thread, self.signal);
+        if errno != 0 {
+            STATE.store(IDLE, Ordering::SeqCst);
+            return Err(io::Error::from_raw_os_error(errno));
+        }
+
+        let deadline = Instant::now() + self.timeout;
+        while STATE.load(Ordering::SeqCst) != SUSPENDED {
+            if Instant::now() >= deadline
+                && STATE
+                    .compare_exchange(REQUESTED, IDLE, Ordering::SeqCst, Ordering::SeqCst)
+                    .is_ok()
+            {
+                let message = "thread did not handle the signal";
+                return Err(io::Error::new(io::ErrorKind::TimedOut, message));
+            }
+            libc::sched_yield();
+        }
+
+        let registers = Registers {
+            pc: PC.load(Ordering::SeqCst),
+            sp: SP.load(Ordering::SeqCst),
+            fp: FP.load(Ordering::SeqCst),
+        };
+        if registers.pc != 0 {
+            sample.capture(self.bulletproof, registers);
+        }
+
+        // Waits until the thread leaves the handler, so that the next sample finds it idle.
+        STATE.store(RESUMED, Ordering::SeqCst);
+        while STATE.load(Ordering::SeqCst) != IDLE {
+            libc::sched_yield();
+        }
+
+        if registers.pc == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "registers of signal contexts are not supported",
+            ));
+        }
+        Ok(sample)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::thread::JoinHandleExt;
+    use std::sync::atomic::AtomicBool;
+    use std::sync::{mpsc, Arc};
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    #[cfg_attr(any(miri, bulletproof_fallback), ignore)]
+    fn sample() {
+        let done = Arc::new(AtomicBool::new(false));
+        let spinning = done.clone();
+        let (sender, receiver) = mpsc::channel();
+        let worker = thread::spawn(move || {
+            let local = 0usize;
+            sender.send(&local as *const usize as usize).unwrap();
+            while !spinning.load(Ordering::SeqCst) {}
+        });
+        let local = receiver.recv().unwrap();
+
+        unsafe {
+            let sampler = Sampler::new(Bulletproof::new(), libc::SIGPROF).unwrap();
+            assert_eq!(
+                sampler.sample(libc::pthread_self()).unwrap_err().kind(),
+                io::ErrorKind::InvalidInput
+            );
+
+            let sample = match sampler.sample(worker.as_pthread_t()) {
+                Err(ref e) if e.kind() == io::ErrorKind::Unsupported => return,
+                sample => sample.unwrap(),
+            };
+            let registers = sample.registers();
+            assert_ne!(registers.pc, 0);
+            assert!(registers.sp <= local && local < registers.sp + sample.stack().len());
+        }
+
+        done.store(true, Ordering::SeqCst);
+        worker.join().unwrap();
+    }
+}
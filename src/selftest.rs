@@ -0,0 +1,119 @@
+//! The runtime self test of [`Bulletproof::self_test()`](../struct.Bulletproof.html#method.self_test).
+
+use std::ptr;
+
+use super::error::SelfTestError;
+use super::fault::SEGV_MAPERR;
+use super::{Bulletproof, Fault};
+
+/// Checks that `result` is `Err(expected)`.
+fn expect_fault<T>(
+    check: &'static str,
+    result: Result<T, Fault>,
+    expected: Fault,
+) -> Result<(), SelfTestError> {
+    match result {
+        Err(fault) if fault == expected => Ok(()),
+        Err(fault) => Err(SelfTestError::new(check, Some(fault))),
+        Ok(_) => Err(SelfTestError::new(check, None)),
+    }
+}
+
+/// Runs the checks, stopping at the first failure.
+pub(crate) unsafe fn run(bulletproof: Bulletproof) -> Result<(), SelfTestError> {
+    let word = 42usize;
+    match bulletproof.load_usize(&word) {
+        Ok(42) => {}
+        result => return Err(SelfTestError::new("load from a valid location", result.err())),
+    }
+
+    expect_fault(
+        "load from the null page",
+        bulletproof.load_usize(ptr::null()),
+        Fault::Segv { addr: 0, code: SEGV_MAPERR },
+    )?;
+    expect_fault(
+        "store to the null page",
+        bulletproof.store_usize(ptr::null_mut(), 42),
+        Fault::Segv { addr: 0, code: SEGV_MAPERR },
+    )?;
+
+    #[cfg(not(any(miri, bulletproof_fallback, bulletproof_unsupported)))]
+    if bulletproof.backend() == super::Backend::Signal {
+        pages(bulletproof)?;
+    }
+    #[cfg(not(miri))]
+    platform(&word)?;
+    Ok(())
+}
+
+/// Checks the platform queries: the current stack contains `local`, and so does a readable region
+/// of the memory map.
+#[cfg(not(miri))]
+fn platform(local: &usize) -> Result<(), SelfTestError> {
+    use std::io::ErrorKind;
+
+    use super::backtrace;
+
+    let addr = local as *const usize as usize;
+    match backtrace::stack_bounds() {
+        Ok(stack) if stack.start <= addr && addr < stack.end => {}
+        Err(ref error) if error.kind() == ErrorKind::Unsupported => {}
+        _ => return Err(SelfTestError::new("stack bounds of the current thread", None)),
+    }
+
+    #[cfg(any(
+        target_os = "linux",
+        target_os = "freebsd",
+        target_os = "openbsd",
+        target_os = "netbsd",
+        target_os = "illumos"
+    ))]
+    {
+        let regions = super::snapshot::regions().unwrap_or_default();
+        let found = regions.iter().any(|region| {
+            region.start <= addr && addr < region.end && region.is_readable()
+        });
+        if !found {
+            return Err(SelfTestError::new("memory map of the process", None));
+        }
+    }
+    Ok(())
+}
+
+/// Checks faults on mapped pages, which the fallback backend cannot recover from.
+#[cfg(not(any(miri, bulletproof_fallback, bulletproof_unsupported)))]
+unsafe fn pages(bulletproof: Bulletproof) -> Result<(), SelfTestError> {
+    use libc::{self, c_void};
+
+    use super::fault::SEGV_ACCERR;
+    use super::mapping::Mapping;
+    use super::probe;
+
+    let page_size = probe::page_size();
+    let mapping = match Mapping::new(page_size) {
+        Ok(mapping) => mapping,
+        // Not a failure of the machinery under test.
+        Err(_) => return Ok(()),
+    };
+    let page = mapping.as_ptr() as *mut usize;
+    let accerr = Fault::Segv { addr: page as usize, code: SEGV_ACCERR };
+
+    if libc::mprotect(page as *mut c_void, page_size, libc::PROT_READ) == 0 {
+        expect_fault("store to a read-only page", bulletproof.store_usize(page, 42), accerr)?;
+        match bulletproof.load_usize(page) {
+            Ok(0) => {}
+            result => return Err(SelfTestError::new("load from a read-only page", result.err())),
+        }
+    }
+
+    if libc::mprotect(page as *mut c_void, page_size, libc::PROT_NONE) == 0 {
+        expect_fault("load from an inaccessible page", bulletproof.load_usize(page), accerr)?;
+        expect_fault(
+            "fault in a protected section",
+            bulletproof.protect(|| ptr::read_volatile(page)),
+            accerr,
+        )?;
+    }
+    Ok(())
+}
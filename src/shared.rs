@@ -0,0 +1,230 @@
+//! Shared-memory and device mappings that a peer may revoke.
+//!
+//! A region shared with another process (e.g. a POSIX shared memory object of an IPC channel) or
+//! mapped from a device can go away under our feet: if the peer truncates the object or the device
+//! is removed, accessing the mapping raises `SIGBUS`. [`SharedRegion`](struct.SharedRegion.html)
+//! accesses it with volatile loads and stores in protected sections, and reports the revocation as
+//! [`SharedError::Revoked`](enum.SharedError.html#variant.Revoked).
+//!
+//! # Examples
+//!
+//! ```
+//! use bulletproof::shared::{SharedError, SharedRegion};
+//! use bulletproof::Bulletproof;
+//!
+//! let mut memory = [0u32; 4];
+//!
+//! unsafe {
+//!     let bulletproof = Bulletproof::new();
+//!
+//!     let region = SharedRegion::open(bulletproof, memory.as_mut_ptr() as *mut u8, 16);
+//!     region.write(4, 42u32).unwrap();
+//!     assert_eq!(region.read::<u32>(4), Ok(42));
+//!     assert_eq!(region.read::<u32>(16), Err(SharedError::OutOfBounds));
+//!     assert_eq!(region.read::<u32>(2), Err(SharedError::Misaligned));
+//! }
+//! ```
+
+use std::error::Error;
+use std::{fmt, mem, ptr};
+
+use super::{Bulletproof, Fault};
+
+/// An error of accessing a shared region.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SharedError {
+    /// The requested range is not inside the region.
+    OutOfBounds,
+    /// The requested offset is not aligned for the accessed type.
+    Misaligned,
+    /// The backing object was revoked, e.g. truncated by the peer or removed (`SIGBUS`).
+    Revoked,
+    /// The region is no longer accessible, e.g. it was unmapped or protected (`SIGSEGV`).
+    Unmapped,
+}
+
+impl fmt::Display for SharedError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            SharedError::OutOfBounds => "range out of bounds of the shared region",
+            SharedError::Misaligned => "misaligned access to the shared region",
+            SharedError::Revoked => "shared region revoked",
+            SharedError::Unmapped => "shared region no longer accessible",
+        })
+    }
+}
+
+impl Error for SharedError {}
+
+impl From<Fault> for SharedError {
+    #[inline]
+    fn from(fault: Fault) -> Self {
+        match fault {
+            Fault::Bus { .. } => SharedError::Revoked,
+            Fault::Segv { .. } | Fault::ExecViolation { .. } => SharedError::Unmapped,
+        }
+    }
+}
+
+/// A shared-memory or device mapping that may be revoked by a peer.
+///
+/// Its accesses are volatile, so that they are neither elided nor merged, and are performed in
+/// protected sections. Unlike the accesses of [`Bulletproof`](../struct.Bulletproof.html), bus
+/// errors are not retried, as a revocation is permanent.
+#[derive(Debug, Clone, Copy)]
+pub struct SharedRegion {
+    /// Witnesses that the handler is installed.
+    bulletproof: Bulletproof,
+    ptr: *mut u8,
+    len: usize,
+}
+
+impl SharedRegion {
+    /// Opens the region of `len` bytes at `ptr`.
+    ///
+    /// # Safety
+    ///
+    /// Accessing the region should have no side effects other than those of the device (if any),
+    /// except that it can be invalid. Other accesses to it should be volatile or atomic.
+    #[inline]
+    pub unsafe fn open(bulletproof: Bulletproof, ptr: *mut u8, len: usize) -> Self {
+        Self {
+            bulletproof,
+            ptr,
+            len,
+        }
+    }
+
+    /// Returns the address of the region.
+    #[inline]
+    pub fn as_ptr(&self) -> *mut u8 {
+        self.ptr
+    }
+
+    /// Returns the length of the region.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if the region is empty.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the address of `size` bytes at `offset`, if they are inside the region.
+    fn range(&self, offset: usize, size: usize) -> Result<*mut u8, SharedError> {
+        match offset.checked_add(size) {
+            Some(end) if end <= self.len => Ok(self.ptr.wrapping_add(offset)),
+            _ => Err(SharedError::OutOfBounds),
+        }
+    }
+
+    /// Returns the address of a `T` at `offset`, if it is inside the region and aligned.
+    fn field<T>(&self, offset: usize) -> Result<*mut T, SharedError> {
+        let loc = self.range(offset, mem::size_of::<T>())?;
+        if loc as usize & (mem::align_of::<T>() - 1) != 0 {
+            return Err(SharedError::Misaligned);
+        }
+        Ok(loc as *mut T)
+    }
+
+    /// Reads a `T` at `offset` of the region with a single volatile load.
+    ///
+    /// # Safety
+    ///
+    /// Any bit pattern at `offset` should be a valid `T`.
+    #[inline]
+    pub unsafe fn read<T: Copy>(&self, offset: usize) -> Result<T, SharedError> {
+        let loc = self.field::<T>(offset)?;
+        Ok(self.bulletproof.protect(|| ptr::read_volatile(loc))?)
+    }
+
+    /// Writes a `T` at `offset` of the region with a single volatile store.
+    #[inline]
+    pub fn write<T: Copy>(&self, offset: usize, val: T) -> Result<(), SharedError> {
+        let loc = self.field::<T>(offset)?;
+        unsafe { Ok(self.bulletproof.protect(|| ptr::write_volatile(loc, val))?) }
+    }
+
+    /// Reads `buf.len()` bytes at `offset` of the region into `buf`, with a volatile load of each
+    /// byte.
+    ///
+    /// On error, the contents of `buf` are unspecified.
+    pub fn read_bytes(&self, offset: usize, buf: &mut [u8]) -> Result<(), SharedError> {
+        let loc = self.range(offset, buf.len())?;
+        let dst = buf.as_mut_ptr();
+        let len = buf.len();
+        unsafe {
+            self.bulletproof.protect(|| {
+                for i in 0..len {
+                    *dst.add(i) = ptr::read_volatile(loc.add(i));
+                }
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Writes `buf` at `offset` of the region, with a volatile store of each byte.
+    ///
+    /// On error, a prefix of `buf` may have been written.
+    pub fn write_bytes(&self, offset: usize, buf: &[u8]) -> Result<(), SharedError> {
+        let loc = self.range(offset, buf.len())?;
+        unsafe {
+            self.bulletproof.protect(|| {
+                for (i, &byte) in buf.iter().enumerate() {
+                    ptr::write_volatile(loc.add(i), byte);
+                }
+            })?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::env;
+    use std::fs::{self, OpenOptions};
+    use std::os::unix::io::AsRawFd;
+    use std::process;
+
+    use super::*;
+
+    #[test]
+    #[cfg_attr(any(miri, bulletproof_fallback), ignore)]
+    fn revoked() {
+        let path = env::temp_dir().join(format!("bulletproof-shared-{}", process::id()));
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)
+            .unwrap();
+        file.set_len(4096).unwrap();
+
+        unsafe {
+            // A shared mapping of the file, as a peer would map a shared memory object.
+            let prot = libc::PROT_READ | libc::PROT_WRITE;
+            let fd = file.as_raw_fd();
+            let map = libc::mmap(ptr::null_mut(), 4096, prot, libc::MAP_SHARED, fd, 0);
+            assert_ne!(map, libc::MAP_FAILED);
+            let region = SharedRegion::open(Bulletproof::new(), map as *mut u8, 4096);
+
+            region.write_bytes(8, b"hello").unwrap();
+            let mut buf = [0u8; 5];
+            region.read_bytes(8, &mut buf).unwrap();
+            assert_eq!(&buf, b"hello");
+            assert_eq!(region.read_bytes(4092, &mut buf), Err(SharedError::OutOfBounds));
+
+            // The peer revokes the region.
+            file.set_len(0).unwrap();
+            assert_eq!(region.read::<u64>(0), Err(SharedError::Revoked));
+            assert_eq!(region.write(0, 1u64), Err(SharedError::Revoked));
+
+            libc::munmap(map, 4096);
+        }
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,259 @@
+//! Self-snapshots of the process memory (Linux, FreeBSD, OpenBSD, NetBSD, and illumos).
+//!
+//! [`write()`](fn.write.html) walks the memory map of the current process (see
+//! [`regions()`](fn.regions.html)), reads every readable region with bulletproof memory access, and
+//! writes them to a sink. Pages that turn out to be unreadable (e.g. because the region was
+//! unmapped concurrently, or a mapped file was truncated) are recorded as missing instead of
+//! aborting the snapshot.
+//!
+//! # Format
+//!
+//! All integers are little-endian.
+//!
+//! ```text
+//! snapshot := magic:[u8; 8] = "BPSNAP01"  page_size:u64  region*  end:u64 = u64::MAX
+//! region   := start:u64  end:u64  perms:[u8; 4]  name_len:u32  name:[u8; name_len]  page*
+//! page     := 0:u8                              (unreadable)
+//!           | 1:u8  contents:[u8; page_size]    (readable)
+//! ```
+//!
+//! `perms` is the permission field of `/proc/self/maps`, e.g. `rw-p`. `name` is the path name field,
+//! e.g. `[heap]`, and may be empty. On other platforms, they are translated to the same form:
+//! the last permission is `p` for copy-on-write mappings and `s` otherwise, device mappings without
+//! a path are named `[device]`, and names are empty on OpenBSD except for `[stack]`.
+//!
+//! There is one `page` for each page of `[start, end)`.
+//!
+//! # Examples
+//!
+//! ```
+//! use bulletproof::{snapshot, Bulletproof};
+//!
+//! # #[cfg(not(bulletproof_fallback))]
+//! unsafe {
+//!     let mut sink = Vec::new();
+//!     snapshot::write(Bulletproof::new(), &mut sink).unwrap();
+//!     assert_eq!(&sink[..8], snapshot::MAGIC);
+//! }
+//! ```
+
+use std::cmp;
+use std::io::{self, Write};
+use std::ops::Range;
+use std::slice;
+
+use libc::{self, c_void};
+
+use super::mapping::Mapping;
+use super::maps;
+use super::{load_bytes, Bulletproof};
+
+/// The magic number at the beginning of a snapshot.
+pub const MAGIC: &[u8; 8] = b"BPSNAP01";
+
+/// A region of the memory map.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Region {
+    /// The start address.
+    pub start: usize,
+    /// The end address (exclusive).
+    pub end: usize,
+    /// The permissions, e.g. `rw-p`.
+    pub perms: [u8; 4],
+    /// The path name, e.g. `[heap]`, or empty for anonymous mappings.
+    pub name: String,
+}
+
+impl Region {
+    /// Returns `true` if the region is readable.
+    #[inline]
+    pub fn is_readable(&self) -> bool {
+        self.perms[0] == b'r'
+    }
+}
+
+/// Returns the memory map of the current process.
+///
+/// The regions are sorted by address. On Linux, they are read from `/proc/self/maps`; on FreeBSD
+/// and NetBSD, from `kinfo_getvmmap()`; on OpenBSD, from the `KERN_PROC_VMMAP` sysctl; and on
+/// illumos, from `/proc/self/map`.
+#[inline]
+pub fn regions() -> io::Result<Vec<Region>> {
+    maps::regions()
+}
+
+/// Intersects `[location, location + len)` with the regions of the memory map that are readable,
+/// and also writable if `write` is `true`.
+///
+/// Returns the sorted, disjoint ranges of the intersection, where adjacent regions are merged, so
+/// that a bulk operation can be split into accesses that do not fault unless the memory map
+/// changes in the meantime.
+///
+/// # Examples
+///
+/// ```
+/// use bulletproof::snapshot;
+///
+/// let buffer = vec![0u8; 100];
+/// let start = buffer.as_ptr() as usize;
+///
+/// let ranges = snapshot::clamp_to_mapped(buffer.as_ptr(), buffer.len(), true).unwrap();
+/// assert_eq!(ranges, vec![start..start + 100]);
+/// assert_eq!(snapshot::clamp_to_mapped(std::ptr::null(), 100, false).unwrap(), vec![]);
+/// ```
+///
+/// # Errors
+///
+/// Returns the error of reading the memory map.
+pub fn clamp_to_mapped(
+    location: *const u8,
+    len: usize,
+    write: bool,
+) -> io::Result<Vec<Range<usize>>> {
+    let start = location as usize;
+    let end = start.saturating_add(len);
+    let mut ranges: Vec<Range<usize>> = Vec::new();
+    for region in regions()? {
+        if !region.is_readable() || (write && region.perms[1] != b'w') {
+            continue;
+        }
+        let (start, end) = (cmp::max(start, region.start), cmp::min(end, region.end));
+        if start >= end {
+            continue;
+        }
+        match ranges.last_mut() {
+            Some(last) if last.end == start => last.end = end,
+            _ => ranges.push(start..end),
+        }
+    }
+    Ok(ranges)
+}
+
+/// Writes a snapshot of the readable regions of the current process to `sink`.
+///
+/// The memory map is read once at the beginning; regions mapped afterwards (e.g. by the sink
+/// itself) are not included. See the [module documentation](index.html) for the format.
+///
+/// With the fallback backend (under Miri, or with `--cfg bulletproof_fallback`), unreadable pages
+/// are not recovered, so it should not be used.
+///
+/// # Safety
+///
+/// Reading the readable regions should have no side effects other than populating pages. In
+/// particular, the process should not map device memory.
+pub unsafe fn write<W: Write>(bulletproof: Bulletproof, sink: &mut W) -> io::Result<()> {
+    let page_size = libc::sysconf(libc::_SC_PAGESIZE) as usize;
+    let regions = regions()?;
+
+    sink.write_all(MAGIC)?;
+    sink.write_all(&(page_size as u64).to_le_bytes())?;
+
+    // In its own mapping, so that it does not share a page with other data.
+    let page = Mapping::new(page_size)?;
+    let buffer = page.as_ptr() as usize;
+    for region in regions.iter().filter(|region| region.is_readable()) {
+        sink.write_all(&(region.start as u64).to_le_bytes())?;
+        sink.write_all(&(region.end as u64).to_le_bytes())?;
+        sink.write_all(&region.perms)?;
+        sink.write_all(&(region.name.len() as u32).to_le_bytes())?;
+        sink.write_all(region.name.as_bytes())?;
+
+        for addr in (region.start..region.end).step_by(page_size) {
+            // Skip the buffer, which would be copied onto itself.
+            if addr == buffer {
+                sink.write_all(&[0])?;
+                continue;
+            }
+
+            let result = bulletproof.config().retry(|| {
+                let dst = page.as_ptr() as *mut c_void;
+                load_bytes(bulletproof.backend(), addr as *const c_void, dst, page_size)
+            });
+            match result {
+                Ok(()) => {
+                    sink.write_all(&[1])?;
+                    sink.write_all(slice::from_raw_parts(page.as_ptr(), page_size))?;
+                }
+                Err(_) => sink.write_all(&[0])?,
+            }
+        }
+    }
+
+    sink.write_all(&u64::MAX.to_le_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryInto;
+    use std::ptr;
+
+    use super::*;
+
+    fn u64_at(buf: &[u8], pos: &mut usize) -> u64 {
+        let value = u64::from_le_bytes(buf[*pos..*pos + 8].try_into().unwrap());
+        *pos += 8;
+        value
+    }
+
+    #[test]
+    #[cfg_attr(any(miri, bulletproof_fallback), ignore)]
+    fn snapshot() {
+        let marker = Box::new(*b"bulletproof snapshot marker");
+        let addr = marker.as_ptr() as usize;
+
+        let mut sink = Vec::new();
+        unsafe {
+            write(Bulletproof::new(), &mut sink).unwrap();
+        }
+
+        // Find the page containing the marker.
+        assert_eq!(&sink[..8], MAGIC);
+        let mut pos = 8;
+        let page_size = u64_at(&sink, &mut pos) as usize;
+        loop {
+            let start = u64_at(&sink, &mut pos) as usize;
+            assert_ne!(start, u64::MAX as usize, "marker not found");
+            let end = u64_at(&sink, &mut pos) as usize;
+            pos += 4;
+            let name_len = u32::from_le_bytes(sink[pos..pos + 4].try_into().unwrap()) as usize;
+            pos += 4 + name_len;
+
+            for page in (start..end).step_by(page_size) {
+                let readable = sink[pos] == 1;
+                pos += 1;
+                if !readable {
+                    continue;
+                }
+                if page <= addr && addr < page + page_size {
+                    let offset = pos + addr - page;
+                    assert_eq!(&sink[offset..offset + marker.len()], &marker[..]);
+                    return;
+                }
+                pos += page_size;
+            }
+        }
+    }
+
+    #[test]
+    fn clamp_to_mapped() {
+        unsafe {
+            let page_size = libc::sysconf(libc::_SC_PAGESIZE) as usize;
+            let prot = libc::PROT_READ | libc::PROT_WRITE;
+            let flags = libc::MAP_PRIVATE | libc::MAP_ANONYMOUS;
+            let map = libc::mmap(ptr::null_mut(), 3 * page_size, prot, flags, -1, 0);
+            assert_ne!(map, libc::MAP_FAILED);
+            let start = map as usize;
+            libc::mprotect((start + page_size) as *mut c_void, page_size, libc::PROT_NONE);
+            libc::mprotect((start + 2 * page_size) as *mut c_void, page_size, libc::PROT_READ);
+
+            let (location, len) = ((start + 8) as *const u8, 3 * page_size - 16);
+            let readable = super::clamp_to_mapped(location, len, false).unwrap();
+            let tail = start + 2 * page_size..start + len + 8;
+            assert_eq!(readable, vec![start + 8..start + page_size, tail]);
+            let writable = super::clamp_to_mapped(location, len, true).unwrap();
+            assert_eq!(writable, vec![start + 8..start + page_size]);
+
+            libc::munmap(map, 3 * page_size);
+        }
+    }
+}
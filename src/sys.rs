@@ -0,0 +1,40 @@
+//! The C types and signal numbers of the crate's interface, also on targets without Unix signals,
+//! where `libc` does not define them.
+
+#[cfg(unix)]
+pub use libc::{c_int, c_void, siginfo_t, size_t, SIGBUS, SIGSEGV};
+
+#[cfg(not(unix))]
+pub use std::os::raw::{c_int, c_void};
+
+/// `size_t`.
+#[cfg(not(unix))]
+#[allow(non_camel_case_types)]
+pub type size_t = usize;
+
+/// `siginfo_t`, with the fields the crate reads. It is never passed by the system on these targets.
+#[cfg(not(unix))]
+#[allow(non_camel_case_types, missing_debug_implementations)]
+#[repr(C)]
+pub struct siginfo_t {
+    /// The signal code.
+    pub si_code: c_int,
+    si_addr: *mut c_void,
+}
+
+#[cfg(not(unix))]
+impl siginfo_t {
+    /// Returns the faulting address.
+    #[inline]
+    pub unsafe fn si_addr(&self) -> *mut c_void {
+        self.si_addr
+    }
+}
+
+/// `SIGSEGV`, as numbered on Linux.
+#[cfg(not(unix))]
+pub const SIGSEGV: c_int = 11;
+
+/// `SIGBUS`, as numbered on Linux.
+#[cfg(not(unix))]
+pub const SIGBUS: c_int = 7;
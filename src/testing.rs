@@ -0,0 +1,123 @@
+//! Deterministic fault injection for testing (`testing` feature).
+//!
+//! Downstream crates need to test their fault-handling paths, but provoking real faults on demand
+//! is fiddly and platform-dependent. With this module, address ranges can be marked "poisoned":
+//! bulletproof memory accesses overlapping them fail as if they faulted, without any real fault.
+//!
+//! Poisoning is process-wide, and lasts until the returned [`Poison`](struct.Poison.html) guard is
+//! dropped.
+//!
+//! # Examples
+//!
+//! ```
+//! use bulletproof::{testing, Bulletproof};
+//!
+//! let x = 42usize;
+//! let addr = &x as *const usize as usize;
+//!
+//! unsafe {
+//!     let bulletproof = Bulletproof::new();
+//!
+//!     let poison = testing::poison(addr..addr + 1);
+//!     assert_eq!(bulletproof.load_usize(&x).map_err(|f| f.addr()), Err(addr));
+//!
+//!     drop(poison);
+//!     assert_eq!(bulletproof.load_usize(&x), Ok(42));
+//! }
+//! ```
+
+use std::ops::Range;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use fault::{Fault, BUS_ADRERR, SEGV_MAPERR};
+use sys::{self, c_int};
+
+/// The poisoned ranges with their IDs and signals.
+static POISONED: Mutex<Vec<(usize, Range<usize>, c_int)>> = Mutex::new(Vec::new());
+
+/// The number of poisoned ranges, for the fast path.
+static COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// The ID of the next poisoned range.
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// A guard for a poisoned range. The range is unpoisoned when it is dropped.
+#[derive(Debug)]
+#[must_use = "the range is unpoisoned when the guard is dropped"]
+pub struct Poison {
+    id: usize,
+}
+
+impl Drop for Poison {
+    fn drop(&mut self) {
+        let mut poisoned = POISONED.lock().unwrap();
+        poisoned.retain(|&(id, _, _)| id != self.id);
+        COUNT.store(poisoned.len(), Ordering::Relaxed);
+    }
+}
+
+/// Poisons `range`, so that bulletproof memory accesses overlapping it fail as if they raised
+/// `SIGSEGV`.
+#[inline]
+pub fn poison(range: Range<usize>) -> Poison {
+    poison_with_signal(range, sys::SIGSEGV)
+}
+
+/// Poisons `range`, so that bulletproof memory accesses overlapping it fail as if they raised
+/// `signal`, which should be `SIGSEGV` or `SIGBUS`.
+pub fn poison_with_signal(range: Range<usize>, signal: c_int) -> Poison {
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    let mut poisoned = POISONED.lock().unwrap();
+    poisoned.push((id, range, signal));
+    COUNT.store(poisoned.len(), Ordering::Relaxed);
+    Poison { id }
+}
+
+/// Returns the fault for a poisoned range overlapping `[start, start + len)`, if any.
+pub(crate) fn poisoned(start: usize, len: usize) -> Option<Fault> {
+    if COUNT.load(Ordering::Relaxed) == 0 {
+        return None;
+    }
+
+    let end = start.saturating_add(len);
+    POISONED
+        .lock()
+        .unwrap()
+        .iter()
+        .find(|(_, range, _)| start < range.end && range.start < end)
+        .map(|&(_, ref range, signal)| {
+            let code = if signal == sys::SIGBUS { BUS_ADRERR } else { SEGV_MAPERR };
+            Fault::from_signal(signal, code, start.max(range.start))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::Bulletproof;
+
+    #[test]
+    fn poison_and_unpoison() {
+        let mut x = [0usize; 4];
+        let addr = &x[2] as *const usize as usize;
+
+        unsafe {
+            let bulletproof = Bulletproof::new();
+
+            let poison = poison_with_signal(addr..addr + 1, sys::SIGBUS);
+            let fault = Fault::Bus { addr, code: BUS_ADRERR };
+            assert_eq!(poisoned(addr, 1), Some(fault));
+            assert_eq!(bulletproof.load_usize(&x[2]), Err(fault));
+            assert_eq!(bulletproof.store_usize(&mut x[2], 42), Err(fault));
+            assert_eq!(bulletproof.load::<[usize; 4]>(&x), Err(fault));
+            assert_eq!(bulletproof.load_usize(&x[1]), Ok(0));
+            assert_eq!(bulletproof.store_usize(&mut x[3], 42), Ok(()));
+
+            drop(poison);
+            assert_eq!(poisoned(addr, 1), None);
+            assert_eq!(bulletproof.store_usize(&mut x[2], 37), Ok(()));
+            assert_eq!(bulletproof.load::<[usize; 4]>(&x), Ok([0, 0, 37, 42]));
+        }
+    }
+}
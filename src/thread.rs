@@ -0,0 +1,86 @@
+//! Thread registration.
+//!
+//! Bulletproof memory access relies on per-thread state: the jump buffer used for recovery is
+//! thread-local, and the signal mask, which decides whether `SIGSEGV` and `SIGBUS` reach the
+//! handler at all, is per-thread. A thread that inherited a mask blocking them is killed by the
+//! first invalid access instead of getting `Err`.
+//!
+//! Threads spawned with [`spawn()`](fn.spawn.html) are registered before running user code. Threads
+//! created by other means (e.g. by a foreign thread pool) should call
+//! [`register_current_thread()`](fn.register_current_thread.html) first.
+
+use std::thread::{self, JoinHandle};
+
+use super::backend;
+use error::RegisterError;
+
+/// Registers the current thread for bulletproof memory access.
+///
+/// It is idempotent, so calling it on an already registered thread is harmless.
+///
+/// # Panics
+///
+/// Panics if the thread's per-thread state cannot be set up. See
+/// [`try_register_current_thread()`](fn.try_register_current_thread.html) for a non-panicking
+/// variant.
+#[inline]
+pub fn register_current_thread() {
+    if let Err(error) = try_register_current_thread() {
+        panic!("bulletproof_register_thread() failed: {}", error);
+    }
+}
+
+/// Registers the current thread for bulletproof memory access, returning the error if the thread's
+/// per-thread state cannot be set up.
+#[inline]
+pub fn try_register_current_thread() -> Result<(), RegisterError> {
+    unsafe { backend::register_thread() }
+}
+
+/// Spawns a new thread registered for bulletproof memory access, returning a
+/// [`JoinHandle`](https://doc.rust-lang.org/stable/std/thread/struct.JoinHandle.html) for it.
+///
+/// It is the same as
+/// [`std::thread::spawn()`](https://doc.rust-lang.org/stable/std/thread/fn.spawn.html), except that
+/// the new thread calls [`register_current_thread()`](fn.register_current_thread.html) before
+/// running `f`.
+pub fn spawn<F, T>(f: F) -> JoinHandle<T>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    thread::spawn(move || {
+        register_current_thread();
+        f()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{mem, ptr};
+
+    use libc;
+
+    use super::*;
+    use super::super::Bulletproof;
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn spawn_unblocks_sigsegv() {
+        unsafe {
+            let bulletproof = Bulletproof::new();
+
+            // Spawned threads inherit the signal mask, so block `SIGSEGV` here.
+            let mut set: libc::sigset_t = mem::zeroed();
+            let mut old: libc::sigset_t = mem::zeroed();
+            libc::sigemptyset(&mut set);
+            libc::sigaddset(&mut set, libc::SIGSEGV);
+            libc::pthread_sigmask(libc::SIG_BLOCK, &set, &mut old);
+
+            let handle = spawn(move || bulletproof.load_usize(ptr::null()));
+
+            libc::pthread_sigmask(libc::SIG_SETMASK, &old, ptr::null_mut());
+            assert!(handle.join().unwrap().is_err());
+        }
+    }
+}
@@ -0,0 +1,185 @@
+//! Thread-local storage slots of other threads (Linux, glibc and musl).
+//!
+//! With glibc and musl, the static TLS blocks of a thread (those of the executable and of the
+//! libraries loaded at startup, which include Rust's `thread_local!` variables in an executable)
+//! are at fixed offsets from its thread descriptor, the `pthread_t`. A
+//! [`TlsSlot`](struct.TlsSlot.html) records the offset of a variable in the current thread, so
+//! that the same variable can be read in another thread given its `pthread_t`.
+//!
+//! The other thread may exit in the middle of the read, and its descriptor be freed or reused, so
+//! the slots are read with bulletproof loads: a fault is reported for the slot instead of crashing
+//! the introspection tool. A value read from a thread that exited may be garbage, hence the
+//! [`FromBytes`](../bytes/trait.FromBytes.html) bound.
+//!
+//! The TLS of libraries loaded with `dlopen()` is allocated lazily per thread, not at fixed
+//! offsets, and cannot be read this way.
+//!
+//! # Examples
+//!
+//! ```
+//! use std::cell::Cell;
+//! use std::sync::mpsc;
+//! use std::thread;
+//!
+//! use bulletproof::tls::TlsSlot;
+//! use bulletproof::Bulletproof;
+//!
+//! thread_local! {
+//!     static COUNTER: Cell<u64> = const { Cell::new(0) };
+//! }
+//!
+//! let slot = COUNTER.with(|counter| TlsSlot::<u64>::of(counter.as_ptr()));
+//! let (sender, receiver) = mpsc::channel();
+//! let (done, wait) = mpsc::channel::<()>();
+//! let worker = thread::spawn(move || {
+//!     COUNTER.with(|counter| counter.set(42));
+//!     sender.send(unsafe { libc::pthread_self() } as usize).unwrap();
+//!     wait.recv().unwrap();
+//! });
+//!
+//! let thread = receiver.recv().unwrap() as libc::pthread_t;
+//! unsafe {
+//!     let bulletproof = Bulletproof::new();
+//!     assert_eq!(slot.read(bulletproof, thread), Ok(42));
+//! }
+//! done.send(()).unwrap();
+//! worker.join().unwrap();
+//! ```
+
+use std::marker::PhantomData;
+
+use libc::{self, pthread_t};
+
+use super::{Bulletproof, Fault, FromBytes};
+
+/// Returns the address of the thread descriptor of `thread`.
+#[inline]
+#[allow(clippy::unnecessary_cast)]
+fn descriptor(thread: pthread_t) -> usize {
+    thread as usize
+}
+
+/// The slot of a thread-local variable of type `T`: its offset from the thread descriptor.
+#[derive(Debug)]
+pub struct TlsSlot<T> {
+    offset: isize,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T> Clone for TlsSlot<T> {
+    #[inline]
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for TlsSlot<T> {}
+
+impl<T> TlsSlot<T> {
+    /// Returns the slot of the thread-local variable at `local` in the current thread, e.g. from
+    /// `KEY.with(|v| v as *const _)`.
+    ///
+    /// The variable should be in a static TLS block, i.e. not of a library loaded with
+    /// `dlopen()`.
+    #[inline]
+    pub fn of(local: *const T) -> Self {
+        let thread = descriptor(unsafe { libc::pthread_self() });
+        Self::from_offset((local as usize).wrapping_sub(thread) as isize)
+    }
+
+    /// Returns the slot at `offset` bytes from the thread descriptor.
+    #[inline]
+    pub fn from_offset(offset: isize) -> Self {
+        Self {
+            offset,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Returns the offset of the slot from the thread descriptor.
+    #[inline]
+    pub fn offset(&self) -> isize {
+        self.offset
+    }
+
+    /// Returns the address of the slot in `thread`.
+    #[inline]
+    pub fn address(&self, thread: pthread_t) -> usize {
+        descriptor(thread).wrapping_add(self.offset as usize)
+    }
+
+    /// Reads the slot in `thread`.
+    ///
+    /// Returns `Err(fault)` if it cannot be read, e.g. because the thread exited and its stack was
+    /// unmapped. The value is racy if the thread modifies it concurrently.
+    ///
+    /// # Safety
+    ///
+    /// `thread` should have been a thread of this process, and reading the slot should have no
+    /// side effects.
+    #[inline]
+    pub unsafe fn read(&self, bulletproof: Bulletproof, thread: pthread_t) -> Result<T, Fault>
+    where
+        T: FromBytes,
+    {
+        bulletproof.load_unchecked(self.address(thread) as *const T)
+    }
+}
+
+/// Reads each of `slots` in `thread`, reporting a fault per slot.
+///
+/// # Safety
+///
+/// The same as [`TlsSlot::read()`](struct.TlsSlot.html#method.read).
+pub unsafe fn read_slots<T: FromBytes>(
+    bulletproof: Bulletproof,
+    thread: pthread_t,
+    slots: &[TlsSlot<T>],
+) -> Vec<Result<T, Fault>> {
+    slots.iter().map(|slot| slot.read(bulletproof, thread)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::sync::mpsc;
+    use std::thread;
+
+    use super::*;
+
+    thread_local! {
+        static FIRST: Cell<usize> = const { Cell::new(1) };
+        static SECOND: Cell<u32> = const { Cell::new(2) };
+    }
+
+    #[test]
+    #[cfg_attr(any(miri, bulletproof_fallback), ignore)]
+    fn read_slots() {
+        let first = FIRST.with(|first| TlsSlot::of(first.as_ptr()));
+        let second = SECOND.with(|second| TlsSlot::of(second.as_ptr()));
+        let (sender, receiver) = mpsc::channel();
+        let (done, wait) = mpsc::channel::<()>();
+        let worker = thread::spawn(move || {
+            FIRST.with(|first| first.set(0x1234));
+            SECOND.with(|second| second.set(7));
+            sender.send(unsafe { libc::pthread_self() } as usize).unwrap();
+            wait.recv().unwrap();
+        });
+
+        let thread = receiver.recv().unwrap() as pthread_t;
+        unsafe {
+            let bulletproof = Bulletproof::new();
+
+            assert_eq!(super::read_slots(bulletproof, thread, &[first]), vec![Ok(0x1234)]);
+            assert_eq!(second.read(bulletproof, thread), Ok(7));
+            // Not the slots of the current thread.
+            assert_eq!(first.read(bulletproof, libc::pthread_self()), Ok(1));
+
+            // A slot outside of any mapping.
+            let slot = TlsSlot::<usize>::from_offset(-(descriptor(thread) as isize));
+            assert_eq!(slot.read(bulletproof, thread).map_err(|fault| fault.addr()), Err(0));
+        }
+        done.send(()).unwrap();
+        worker.join().unwrap();
+    }
+}
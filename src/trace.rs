@@ -0,0 +1,155 @@
+//! `tracing` events (`tracing` feature).
+//!
+//! The events have the target `bulletproof`:
+//!
+//! - Caught faults are `DEBUG` events with the address, the signal and its code, and the time
+//!   spent in recovery, from the fault to the return of the access.
+//! - Installing or uninstalling the handlers and adding or removing fault hooks are `DEBUG` events,
+//!   and failing to install the handlers is a `WARN` event.
+//! - Exceeding a fault limit is a `WARN` event.
+
+use std::ops::Range;
+
+use super::backend;
+use config::Config;
+use error::RegisterError;
+use fault::Fault;
+use sys::c_int;
+
+/// A fault was recovered by the backend.
+#[inline]
+pub(crate) fn fault_caught(fault: &Fault) {
+    tracing::debug!(
+        target: "bulletproof",
+        addr = fault.addr(),
+        signal = fault.signal(),
+        code = fault.code(),
+        recovery_ns = backend::last_fault_elapsed().as_nanos() as u64,
+        "caught {}",
+        fault,
+    );
+}
+
+/// The handlers selected by `config` were installed, or `error` occurred.
+pub(crate) fn handlers_installed(config: &Config, result: Result<(), &RegisterError>) {
+    match result {
+        Ok(()) => tracing::debug!(
+            target: "bulletproof",
+            segv = config.get_catch_segv(),
+            bus = config.get_catch_bus(),
+            chain = config.get_chain(),
+            save_sigmask = config.get_save_sigmask(),
+            "installed signal handlers",
+        ),
+        Err(error) => tracing::warn!(
+            target: "bulletproof",
+            errno = error.errno(),
+            "cannot install signal handlers: {}",
+            error,
+        ),
+    }
+}
+
+/// The handlers were reinstalled in a forked child.
+pub(crate) fn handlers_reinstalled() {
+    tracing::debug!(target: "bulletproof", "reinstalled signal handlers after fork");
+}
+
+/// The handlers were uninstalled as the last registration was dropped.
+pub(crate) fn handlers_uninstalled() {
+    tracing::debug!(target: "bulletproof", "uninstalled signal handlers");
+}
+
+/// A fault hook for `range` was added to `slot`.
+pub(crate) fn hook_added(range: &Range<usize>, slot: c_int) {
+    tracing::debug!(
+        target: "bulletproof",
+        start = range.start,
+        end = range.end,
+        slot,
+        "added fault hook",
+    );
+}
+
+/// The fault hook in `slot` was removed.
+pub(crate) fn hook_removed(slot: c_int) {
+    tracing::debug!(target: "bulletproof", slot, "removed fault hook");
+}
+
+/// A fault limit of the current thread was exceeded with `fault`.
+pub(crate) fn limit_exceeded(fault: &Fault) {
+    tracing::warn!(
+        target: "bulletproof",
+        addr = fault.addr(),
+        signal = fault.signal(),
+        code = fault.code(),
+        "fault limit exceeded with {}",
+        fault,
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ptr;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use tracing::field::{Field, Visit};
+    use tracing::span::{Attributes, Id, Record};
+    use tracing::{Event, Metadata, Subscriber};
+
+    use Bulletproof;
+
+    /// Counts the caught faults at address 0.
+    struct Faults(Arc<AtomicUsize>);
+
+    /// Checks that the address of an event is 0.
+    struct NullAddr(bool);
+
+    impl Visit for NullAddr {
+        fn record_u64(&mut self, field: &Field, value: u64) {
+            if field.name() == "addr" {
+                self.0 = value == 0;
+            }
+        }
+
+        fn record_debug(&mut self, _field: &Field, _value: &dyn std::fmt::Debug) {}
+    }
+
+    impl Subscriber for Faults {
+        fn enabled(&self, metadata: &Metadata) -> bool {
+            metadata.target() == "bulletproof"
+        }
+
+        fn new_span(&self, _span: &Attributes) -> Id {
+            Id::from_u64(1)
+        }
+
+        fn record(&self, _span: &Id, _values: &Record) {}
+
+        fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+
+        fn event(&self, event: &Event) {
+            let mut null = NullAddr(false);
+            event.record(&mut null);
+            if null.0 {
+                self.0.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+
+        fn enter(&self, _span: &Id) {}
+
+        fn exit(&self, _span: &Id) {}
+    }
+
+    #[test]
+    fn fault_caught() {
+        let faults = Arc::new(AtomicUsize::new(0));
+        tracing::subscriber::with_default(Faults(faults.clone()), || unsafe {
+            let bulletproof = Bulletproof::new();
+            assert!(bulletproof.load_usize(ptr::null()).is_err());
+            assert!(bulletproof.store_usize(ptr::null_mut(), 0).is_err());
+        });
+        assert_eq!(faults.load(Ordering::SeqCst), 2);
+    }
+}
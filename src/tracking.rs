@@ -0,0 +1,235 @@
+//! Dirty-page tracking, a software write barrier.
+//!
+//! [`track()`](fn.track.html) write-protects the pages of a region. The first write to each page
+//! faults, and the handler records the page as dirty, restores write access, and retries the
+//! write. [`Tracked::take_dirty()`](struct.Tracked.html#method.take_dirty) returns the pages
+//! dirtied since the last call, and write-protects them again. Incremental and concurrent garbage
+//! collectors can use it to find the pages mutated while they were marking.
+//!
+//! With the fallback backend (under Miri, or with `--cfg bulletproof_fallback`), there is no
+//! handler to resolve the faults, so [`track()`](fn.track.html) returns an error.
+//!
+//! # Examples
+//!
+//! ```
+//! use bulletproof::{tracking, Bulletproof};
+//! use std::alloc::{self, Layout};
+//!
+//! # #[cfg(not(bulletproof_fallback))]
+//! unsafe {
+//!     let bulletproof = Bulletproof::new();
+//!
+//!     // A heap of 16 pages, which do not share pages with other data.
+//!     let layout = Layout::from_size_align(16 * 4096, 4096).unwrap();
+//!     let heap = alloc::alloc_zeroed(layout);
+//!     let tracked = tracking::track(bulletproof, heap, layout.size()).unwrap();
+//!
+//!     *heap.add(3 * 4096) = 1;
+//!     assert_eq!(tracked.take_dirty(), vec![heap as usize + 3 * 4096]);
+//!     assert!(tracked.take_dirty().is_empty());
+//!
+//!     drop(tracked);
+//!     alloc::dealloc(heap, layout);
+//! }
+//! ```
+
+use std::{io, slice};
+use std::sync::atomic::{AtomicU8, Ordering};
+
+use libc::{self, c_int, c_void, siginfo_t};
+
+use super::hook::Hook;
+use super::mapping::Mapping;
+use super::{probe, Bulletproof};
+
+/// The state shared with the fault hook, which only reads it except for `dirty`.
+#[derive(Debug)]
+struct Pages {
+    start: usize,
+    page_size: usize,
+    count: usize,
+    /// The bitmap of the dirty pages.
+    dirty: Mapping,
+}
+
+impl Pages {
+    fn dirty(&self) -> &[AtomicU8] {
+        let bits = self.dirty.as_ptr() as *const AtomicU8;
+        unsafe { slice::from_raw_parts(bits, self.count.div_ceil(8)) }
+    }
+}
+
+/// A region whose writes are tracked, created by [`track()`](fn.track.html).
+///
+/// Dropping it stops tracking, and makes the pages writable again.
+#[derive(Debug)]
+pub struct Tracked {
+    // Dropped before `pages`, which the hook refers to.
+    _hook: Hook,
+    pages: Box<Pages>,
+}
+
+impl Tracked {
+    /// Returns the address of the first tracked page.
+    #[inline]
+    pub fn start(&self) -> usize {
+        self.pages.start
+    }
+
+    /// Returns the page size.
+    #[inline]
+    pub fn page_size(&self) -> usize {
+        self.pages.page_size
+    }
+
+    /// Returns the number of tracked pages.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.pages.count
+    }
+
+    /// Returns `true` if no pages are tracked.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.pages.count == 0
+    }
+
+    /// Returns the addresses of the pages written since the last call (or since tracking began),
+    /// in ascending order, and write-protects them again.
+    ///
+    /// Writes that race with this call are reported either by this call or by the next one.
+    pub fn take_dirty(&self) -> Vec<usize> {
+        let pages = &*self.pages;
+        let mut dirty = Vec::new();
+        for (byte, bits) in pages.dirty().iter().enumerate() {
+            // Cleared before write-protecting, so that a write in between is reported by this call.
+            let bits = bits.swap(0, Ordering::SeqCst);
+            for bit in (0..8).filter(|bit| bits & (1 << bit) != 0) {
+                let page = pages.start + (byte * 8 + bit) * pages.page_size;
+                unsafe {
+                    libc::mprotect(page as *mut c_void, pages.page_size, libc::PROT_READ);
+                }
+                dirty.push(page);
+            }
+        }
+        dirty
+    }
+}
+
+impl Drop for Tracked {
+    fn drop(&mut self) {
+        let pages = &*self.pages;
+        unsafe {
+            libc::mprotect(
+                pages.start as *mut c_void,
+                pages.count * pages.page_size,
+                libc::PROT_READ | libc::PROT_WRITE,
+            );
+        }
+    }
+}
+
+/// The fault hook: records the faulting page as dirty, and makes it writable.
+unsafe extern "C" fn on_fault(data: *mut c_void, sig: c_int, info: *const siginfo_t) -> c_int {
+    if sig != libc::SIGSEGV {
+        return 0;
+    }
+
+    let pages = &*(data as *const Pages);
+    let index = ((*info).si_addr() as usize - pages.start) / pages.page_size;
+    let page = pages.start + index * pages.page_size;
+
+    // Made writable before being marked, so that a concurrent `take_dirty()` cannot leave a
+    // writable page unmarked. If the page is not mapped, the fault is not ours.
+    let prot = libc::PROT_READ | libc::PROT_WRITE;
+    if libc::mprotect(page as *mut c_void, pages.page_size, prot) != 0 {
+        return 0;
+    }
+    pages.dirty()[index / 8].fetch_or(1 << (index % 8), Ordering::SeqCst);
+    1
+}
+
+/// Tracks the writes to the pages overlapping `[start, start + len)`.
+///
+/// The pages are write-protected until the returned [`Tracked`](struct.Tracked.html) is dropped,
+/// and the first write to each page after tracking begins (or after
+/// [`take_dirty()`](struct.Tracked.html#method.take_dirty)) costs a fault.
+///
+/// # Safety
+///
+/// The pages should be mapped readable and writable, and their protection should not be changed
+/// by others while tracked. They are made readable and writable again when tracking stops. The
+/// pages may be shared with other data, whose writes are tracked as well.
+///
+/// # Errors
+///
+/// Returns an error if the pages cannot be write-protected, if too many regions are tracked (or
+/// otherwise hooked) at once, or with the fallback backend.
+pub unsafe fn track(_bulletproof: Bulletproof, start: *mut u8, len: usize) -> io::Result<Tracked> {
+    let page_size = probe::page_size();
+    let first = start as usize & !(page_size - 1);
+    let end = (start as usize + len).div_ceil(page_size) * page_size;
+    let count = (end - first) / page_size;
+
+    let pages = Box::new(Pages {
+        start: first,
+        page_size,
+        count,
+        dirty: Mapping::new(count.div_ceil(8))?,
+    });
+    let data = &*pages as *const Pages as *mut c_void;
+    let hook = Hook::add(first..end, on_fault, data)?;
+
+    if libc::mprotect(first as *mut c_void, end - first, libc::PROT_READ) != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(Tracked { _hook: hook, pages })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ptr;
+
+    use super::*;
+
+    #[test]
+    #[cfg_attr(any(miri, bulletproof_fallback), ignore)]
+    fn take_dirty() {
+        unsafe {
+            let bulletproof = Bulletproof::new();
+            let page_size = probe::page_size();
+
+            let map = libc::mmap(
+                ptr::null_mut(),
+                4 * page_size,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                -1,
+                0,
+            );
+            assert_ne!(map, libc::MAP_FAILED);
+            let start = map as *mut u8;
+
+            let tracked = track(bulletproof, start, 4 * page_size).unwrap();
+            assert_eq!(tracked.len(), 4);
+            assert!(tracked.take_dirty().is_empty());
+
+            // Plain writes, and bulletproof stores.
+            *start.add(page_size) = 1;
+            *start.add(3 * page_size + 1) = 1;
+            let word = start.add(page_size) as *mut usize;
+            assert_eq!(bulletproof.store_usize(word, 42), Ok(()));
+            let expected = vec![start as usize + page_size, start as usize + 3 * page_size];
+            assert_eq!(tracked.take_dirty(), expected);
+
+            // Write-protected again.
+            *start.add(page_size + 8) = 2;
+            assert_eq!(tracked.take_dirty(), vec![start as usize + page_size]);
+            assert_eq!(*word, 42);
+
+            drop(tracked);
+            *start = 3;
+            libc::munmap(map, 4 * page_size);
+        }
+    }
+}
@@ -0,0 +1,108 @@
+#include <errno.h>
+#include <fcntl.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <string.h>
+#include <sys/ioctl.h>
+#include <sys/syscall.h>
+#include <unistd.h>
+#include <linux/userfaultfd.h>
+
+// Opens a userfaultfd, and performs the API handshake requesting thread IDs in fault messages.
+//
+// # Returns
+//
+// The file descriptor, or -1 with `errno` set.
+int bulletproof_uffd_open() {
+  int fd = syscall(SYS_userfaultfd, O_CLOEXEC | O_NONBLOCK);
+  if (fd < 0) {
+    return -1;
+  }
+
+  struct uffdio_api api;
+  memset(&api, 0, sizeof(api));
+  api.api = UFFD_API;
+  api.features = UFFD_FEATURE_THREAD_ID;
+  if (ioctl(fd, UFFDIO_API, &api) != 0) {
+    int saved = errno;
+    close(fd);
+    errno = saved;
+    return -1;
+  }
+
+  return fd;
+}
+
+// Registers `[start, start + len)` for missing-page faults.
+//
+// # Returns
+//
+// If it fails, return -1 with `errno` set. Otherwise, return 0.
+int bulletproof_uffd_register(int fd, void *start, size_t len) {
+  struct uffdio_register reg;
+  memset(&reg, 0, sizeof(reg));
+  reg.range.start = (uintptr_t) start;
+  reg.range.len = len;
+  reg.mode = UFFDIO_REGISTER_MODE_MISSING;
+
+  return ioctl(fd, UFFDIO_REGISTER, &reg);
+}
+
+// Unregisters `[start, start + len)`.
+//
+// # Returns
+//
+// If it fails, return -1 with `errno` set. Otherwise, return 0.
+int bulletproof_uffd_unregister(int fd, void *start, size_t len) {
+  struct uffdio_range range;
+  range.start = (uintptr_t) start;
+  range.len = len;
+
+  return ioctl(fd, UFFDIO_UNREGISTER, &range);
+}
+
+// Reads a pending page fault, storing the faulting address, whether it is a write, and the
+// faulting thread's ID.
+//
+// # Returns
+//
+// If a page fault is read, return 1. If there is none pending, return 0. If it fails, return -1
+// with `errno` set. Non-fault events are skipped.
+int bulletproof_uffd_read_fault(int fd, uint64_t *addr, int *write, uint32_t *tid) {
+  struct uffd_msg msg;
+
+  for (;;) {
+    ssize_t n = read(fd, &msg, sizeof(msg));
+    if (n < 0) {
+      return errno == EAGAIN ? 0 : -1;
+    }
+    if (n != sizeof(msg) || msg.event != UFFD_EVENT_PAGEFAULT) {
+      continue;
+    }
+
+    *addr = msg.arg.pagefault.address;
+    *write = (msg.arg.pagefault.flags & UFFD_PAGEFAULT_FLAG_WRITE) != 0;
+    *tid = msg.arg.pagefault.feat.ptid;
+    return 1;
+  }
+}
+
+// Resolves a missing-page fault by mapping zero pages at `[start, start + len)`, waking up the
+// faulting threads.
+//
+// # Returns
+//
+// If the pages were mapped, return 0. If they were already mapped, return 1. If it fails, return
+// -1 with `errno` set.
+int bulletproof_uffd_zeropage(int fd, void *start, size_t len) {
+  struct uffdio_zeropage zero;
+  memset(&zero, 0, sizeof(zero));
+  zero.range.start = (uintptr_t) start;
+  zero.range.len = len;
+
+  if (ioctl(fd, UFFDIO_ZEROPAGE, &zero) != 0) {
+    return errno == EEXIST ? 1 : -1;
+  }
+
+  return 0;
+}
@@ -0,0 +1,292 @@
+//! `userfaultfd`-based backend (Linux only).
+//!
+//! Instead of catching `SIGSEGV`, this backend registers the target ranges with
+//! [`userfaultfd(2)`](http://man7.org/linux/man-pages/man2/userfaultfd.2.html). Accesses to
+//! unpopulated pages of the registered ranges are reported to a dedicated handler thread, which
+//! resolves them. No signal handler is installed, so it does not conflict with other libraries
+//! owning `SIGSEGV`.
+//!
+//! A load fails if it touches an unpopulated page of the registered ranges ("probe this arena"), or
+//! if it is not entirely inside the registered ranges. The unpopulated pages are left unpopulated
+//! after the load. A store populates the pages it touches.
+//!
+//! # Examples
+//!
+//! ```
+//! use bulletproof::uffd::Userfaultfd;
+//! use std::ptr;
+//!
+//! # extern crate libc;
+//! # extern crate bulletproof;
+//! # fn main() {
+//! unsafe {
+//!     let uffd = match Userfaultfd::new() {
+//!         Ok(uffd) => uffd,
+//!         // `userfaultfd(2)` may be disabled for unprivileged users.
+//!         Err(_) => return,
+//!     };
+//!
+//!     let page_size = libc::sysconf(libc::_SC_PAGESIZE) as usize;
+//!     let arena = libc::mmap(
+//!         ptr::null_mut(),
+//!         2 * page_size,
+//!         libc::PROT_READ | libc::PROT_WRITE,
+//!         libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+//!         -1,
+//!         0,
+//!     ) as *mut usize;
+//!     uffd.register(arena as *mut u8, 2 * page_size).unwrap();
+//!
+//!     let second = (arena as usize + page_size) as *mut usize;
+//!     assert_eq!(uffd.store(arena, &42), Ok(()));
+//!     assert_eq!(uffd.load(arena), Ok(42));
+//!     assert_eq!(uffd.load(second).map_err(|f| f.addr()), Err(second as usize));
+//!     assert!(uffd.load(ptr::null::<usize>()).is_err());
+//! }
+//! # }
+//! ```
+
+use std::io;
+use std::mem::{self, MaybeUninit};
+use std::ptr;
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+use libc::{self, c_int, c_void, size_t};
+
+use fault::{Fault, SEGV_MAPERR};
+
+extern "C" {
+    fn bulletproof_uffd_open() -> c_int;
+    fn bulletproof_uffd_register(fd: c_int, start: *mut c_void, len: size_t) -> c_int;
+    fn bulletproof_uffd_unregister(fd: c_int, start: *mut c_void, len: size_t) -> c_int;
+    fn bulletproof_uffd_read_fault(fd: c_int, addr: *mut u64, write: *mut c_int, tid: *mut u32)
+        -> c_int;
+    fn bulletproof_uffd_zeropage(fd: c_int, start: *mut c_void, len: size_t) -> c_int;
+}
+
+/// The state shared with the handler thread.
+#[derive(Debug, Default)]
+struct State {
+    /// The thread currently performing a load, if any.
+    prober: Option<u32>,
+    /// The pages the handler zero-filled for the prober's reads.
+    missing: Vec<usize>,
+}
+
+/// A `userfaultfd`-backed bulletproof memory access manager.
+///
+/// Accesses are serialized. The handler thread is stopped and the ranges are unregistered when it
+/// is dropped.
+#[derive(Debug)]
+pub struct Userfaultfd {
+    fd: c_int,
+    /// The write end of the pipe that stops the handler thread.
+    stop: c_int,
+    page_size: usize,
+    handler: Option<JoinHandle<()>>,
+    state: Arc<Mutex<State>>,
+    ranges: Mutex<Vec<(usize, usize)>>,
+    access: Mutex<()>,
+}
+
+impl Userfaultfd {
+    /// Opens a userfaultfd and spawns its handler thread.
+    ///
+    /// It fails if the kernel does not support `userfaultfd(2)` or the process is not allowed to
+    /// use it (see `vm.unprivileged_userfaultfd`).
+    pub fn new() -> io::Result<Self> {
+        let fd = unsafe { bulletproof_uffd_open() };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let mut pipe = [0 as c_int; 2];
+        if unsafe { libc::pipe2(pipe.as_mut_ptr(), libc::O_CLOEXEC) } != 0 {
+            let err = io::Error::last_os_error();
+            unsafe { libc::close(fd) };
+            return Err(err);
+        }
+
+        let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize;
+        let state = Arc::new(Mutex::new(State::default()));
+        let handler = {
+            let state = state.clone();
+            let stop = pipe[0];
+            thread::spawn(move || handle(fd, stop, page_size, &state))
+        };
+
+        Ok(Self {
+            fd,
+            stop: pipe[1],
+            page_size,
+            handler: Some(handler),
+            state,
+            ranges: Mutex::new(Vec::new()),
+            access: Mutex::new(()),
+        })
+    }
+
+    /// Registers the range `[start, start + len)`.
+    ///
+    /// # Safety
+    ///
+    /// The range should be page-aligned, and consist of private anonymous mappings that are not
+    /// accessed by means other than this manager while registered.
+    pub unsafe fn register(&self, start: *mut u8, len: usize) -> io::Result<()> {
+        if bulletproof_uffd_register(self.fd, start as *mut c_void, len) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        self.ranges.lock().unwrap().push((start as usize, len));
+        Ok(())
+    }
+
+    /// Unregisters the range `[start, start + len)`, previously registered with
+    /// [`register()`](#method.register).
+    ///
+    /// # Safety
+    ///
+    /// The same as [`register()`](#method.register).
+    pub unsafe fn unregister(&self, start: *mut u8, len: usize) -> io::Result<()> {
+        if bulletproof_uffd_unregister(self.fd, start as *mut c_void, len) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        self.ranges
+            .lock()
+            .unwrap()
+            .retain(|&range| range != (start as usize, len));
+        Ok(())
+    }
+
+    /// Loads a value of type `T` from the location.
+    ///
+    /// Returns `Ok(v)` if `location` contains `v`, and `Err(fault)` if the location is not
+    /// entirely inside the registered ranges or touches an unpopulated page.
+    ///
+    /// # Safety
+    ///
+    /// The location should satisfy the safety guarantee of
+    /// [`std::ptr::read()`](https://doc.rust-lang.org/stable/std/ptr/fn.read.html), except that it
+    /// can be an invalid pointer.
+    pub unsafe fn load<T>(&self, location: *const T) -> Result<T, Fault> {
+        let start = location as usize;
+        let size = mem::size_of::<T>();
+        if !self.contains(start, size) {
+            return Err(Fault::Segv { addr: start, code: SEGV_MAPERR });
+        }
+
+        let _access = self.access.lock().unwrap();
+        let tid = libc::syscall(libc::SYS_gettid) as u32;
+        self.state.lock().unwrap().prober = Some(tid);
+
+        let mut result = MaybeUninit::<T>::uninit();
+        ptr::copy_nonoverlapping(location as *const u8, result.as_mut_ptr() as *mut u8, size);
+
+        let missing = {
+            let mut state = self.state.lock().unwrap();
+            state.prober = None;
+            mem::take(&mut state.missing)
+        };
+        if missing.is_empty() {
+            return Ok(result.assume_init());
+        }
+
+        // Drop the zero pages, so that they remain unpopulated.
+        let addr = missing.iter().map(|&page| page.max(start)).min().unwrap();
+        for page in missing {
+            libc::madvise(page as *mut c_void, self.page_size, libc::MADV_DONTNEED);
+        }
+        Err(Fault::Segv { addr, code: SEGV_MAPERR })
+    }
+
+    /// Stores a value of type `T` to the location.
+    ///
+    /// Returns `Ok(())` if the location is entirely inside the registered ranges, and
+    /// `Err(fault)` otherwise.
+    ///
+    /// # Safety
+    ///
+    /// The location should satisfy the safety guarantee of
+    /// [`std::ptr::write()`](https://doc.rust-lang.org/stable/std/ptr/fn.write.html), except that
+    /// it can be an invalid pointer.
+    pub unsafe fn store<T>(&self, location: *mut T, src: &T) -> Result<(), Fault> {
+        if !self.contains(location as usize, mem::size_of::<T>()) {
+            return Err(Fault::Segv { addr: location as usize, code: SEGV_MAPERR });
+        }
+
+        let _access = self.access.lock().unwrap();
+        ptr::copy_nonoverlapping(
+            src as *const T as *const u8,
+            location as *mut u8,
+            mem::size_of::<T>(),
+        );
+        Ok(())
+    }
+
+    /// Checks if `[start, start + size)` is inside a registered range.
+    fn contains(&self, start: usize, size: usize) -> bool {
+        let end = match start.checked_add(size) {
+            Some(end) => end,
+            None => return false,
+        };
+
+        self.ranges
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|&(s, len)| s <= start && end <= s + len)
+    }
+}
+
+impl Drop for Userfaultfd {
+    fn drop(&mut self) {
+        unsafe {
+            libc::close(self.stop);
+        }
+        if let Some(handler) = self.handler.take() {
+            let _ = handler.join();
+        }
+        unsafe {
+            libc::close(self.fd);
+        }
+    }
+}
+
+/// The handler thread's loop. Resolves faults until the write end of `stop` is closed.
+fn handle(fd: c_int, stop: c_int, page_size: usize, state: &Mutex<State>) {
+    let mut fds = [
+        libc::pollfd { fd, events: libc::POLLIN, revents: 0 },
+        libc::pollfd { fd: stop, events: libc::POLLIN, revents: 0 },
+    ];
+
+    loop {
+        if unsafe { libc::poll(fds.as_mut_ptr(), 2, -1) } < 0 {
+            if io::Error::last_os_error().kind() == io::ErrorKind::Interrupted {
+                continue;
+            }
+            break;
+        }
+        if fds[1].revents != 0 {
+            break;
+        }
+
+        let (mut addr, mut write, mut tid) = (0u64, 0 as c_int, 0u32);
+        while unsafe { bulletproof_uffd_read_fault(fd, &mut addr, &mut write, &mut tid) } == 1 {
+            let page = addr as usize & !(page_size - 1);
+
+            // Hold the lock while resolving, so that the prober sees the record when it wakes up.
+            let mut state = state.lock().unwrap();
+            let resolved =
+                unsafe { bulletproof_uffd_zeropage(fd, page as *mut c_void, page_size) } == 0;
+            if resolved && write == 0 && state.prober == Some(tid) {
+                state.missing.push(page);
+            }
+        }
+    }
+
+    unsafe {
+        libc::close(stop);
+    }
+}
@@ -0,0 +1,235 @@
+//! The stub backend, for targets without Unix signals (e.g. WebAssembly), where faults cannot be
+//! recovered.
+//!
+//! Registration fails with [`RegisterStep::Unsupported`](../error/enum.RegisterStep.html), so
+//! [`Bulletproof::try_new()`](../struct.Bulletproof.html#method.try_new) returns an error instead
+//! of failing to build. Accesses through a manager created otherwise are all rejected as faults
+//! without touching memory. The fallback backend, performing plain accesses, can be selected
+//! instead with `--cfg bulletproof_fallback`.
+
+use std::cell::Cell;
+use std::io;
+use std::ops::Range;
+use std::ptr;
+#[cfg(feature = "tracing")]
+use std::time::Duration;
+
+use config::Config;
+use error::{HandlerConflict, RegisterError, RegisterStep};
+use fault::{Fault, Registers, SEGV_MAPERR};
+use hook::HookFn;
+use sys::{c_int, c_void, siginfo_t, size_t, SIGSEGV};
+
+thread_local! {
+    /// The last rejected access of the current thread.
+    static LAST_FAULT: Cell<Fault> = const {
+        Cell::new(Fault::Segv { addr: 0, code: SEGV_MAPERR })
+    };
+}
+
+/// Rejects an access to `loc`. Returns `SIGSEGV`.
+#[inline]
+fn reject<T>(loc: *const T) -> c_int {
+    let fault = Fault::Segv { addr: loc as usize, code: SEGV_MAPERR };
+    let _ = LAST_FAULT.try_with(|last| last.set(fault));
+    SIGSEGV
+}
+
+/// Returns an error, as faults cannot be recovered.
+#[inline]
+pub unsafe fn register(_config: &Config) -> Result<(), RegisterError> {
+    Err(RegisterError::new(RegisterStep::Unsupported, 0))
+}
+
+/// Returns an error, as faults cannot be recovered.
+#[inline]
+pub unsafe fn register_thread() -> Result<(), RegisterError> {
+    Err(RegisterError::new(RegisterStep::Unsupported, 0))
+}
+
+/// Does nothing, as no handlers are installed.
+#[inline]
+pub unsafe fn unregister() {}
+
+/// Does nothing, as no handlers are installed.
+#[inline]
+pub unsafe fn record_handlers(_config: &Config) {}
+
+/// Does nothing, as no handlers are installed.
+#[inline]
+pub fn forget_handlers() {}
+
+/// Returns `Ok(())`, as no handlers are installed.
+#[inline]
+pub fn verify_handlers() -> Result<(), HandlerConflict> {
+    Ok(())
+}
+
+/// Does nothing, as there are no signal stacks.
+#[inline]
+pub fn set_altstack_size(_size: usize) {}
+
+/// Does nothing, as there are no handlers. Returns 0.
+#[inline]
+pub unsafe fn reinit_after_fork() -> size_t {
+    0
+}
+
+/// Returns 0, as there are no signals.
+#[inline]
+pub unsafe fn handle_signal(_sig: c_int, _info: *mut siginfo_t, _ctx: *mut c_void) -> c_int {
+    0
+}
+
+/// Rejects loading a word from `loc`. Returns `SIGSEGV`.
+#[inline]
+pub unsafe fn load_word(loc: *const usize, _dst: *mut usize) -> c_int {
+    reject(loc)
+}
+
+/// Rejects storing a word to `loc`. Returns `SIGSEGV`.
+#[inline]
+pub unsafe fn store_word(loc: *mut usize, _val: usize) -> c_int {
+    reject(loc)
+}
+
+/// Rejects loading a `u64` from `loc`. Returns `SIGSEGV`.
+#[inline]
+pub unsafe fn load_u64(loc: *const u64, _dst: *mut u64) -> c_int {
+    reject(loc)
+}
+
+/// Rejects storing a `u64` to `loc`. Returns `SIGSEGV`.
+#[inline]
+pub unsafe fn store_u64(loc: *mut u64, _val: u64) -> c_int {
+    reject(loc)
+}
+
+/// Rejects loading `size` bytes from `loc`. Returns `SIGSEGV`, or 0 if `size` is 0.
+#[inline]
+pub unsafe fn load_bytes(loc: *const c_void, _dst: *mut c_void, size: usize) -> c_int {
+    if size == 0 {
+        return 0;
+    }
+    reject(loc)
+}
+
+/// Zero-fills `size` bytes of `dst`, as no page can be read. Returns `size`.
+#[inline]
+pub unsafe fn load_bytes_zero_fill(
+    _loc: *const u8,
+    dst: *mut u8,
+    size: usize,
+    _page_size: usize,
+) -> usize {
+    ptr::write_bytes(dst, 0, size);
+    size
+}
+
+/// Rejects storing `size` bytes to `loc`. Returns `SIGSEGV`, or 0 if `size` is 0.
+#[inline]
+pub unsafe fn store_bytes(loc: *mut c_void, _src: *const c_void, size: usize) -> c_int {
+    if size == 0 {
+        return 0;
+    }
+    reject(loc)
+}
+
+/// Stores nothing, as no page can be written. Returns 0.
+#[inline]
+pub unsafe fn store_bytes_prefix(
+    _loc: *mut u8,
+    _src: *const u8,
+    _size: usize,
+    _page_size: usize,
+) -> usize {
+    0
+}
+
+/// Rejects exchanging `size` bytes at `loc`. Returns `SIGSEGV`, or 0 if `size` is 0.
+#[inline]
+pub unsafe fn exchange_bytes(
+    loc: *mut c_void,
+    _src: *const c_void,
+    _old: *mut c_void,
+    size: usize,
+) -> c_int {
+    if size == 0 {
+        return 0;
+    }
+    reject(loc)
+}
+
+/// Rejects copying `size` bytes from `src`. Returns `SIGSEGV`, or 0 if `size` is 0.
+#[inline]
+pub unsafe fn move_bytes(src: *const c_void, _dst: *mut c_void, size: usize) -> c_int {
+    if size == 0 {
+        return 0;
+    }
+    reject(src)
+}
+
+/// Rejects scanning the `words` words from `loc`. Returns `SIGSEGV`, or 0 if `words` is 0.
+#[inline]
+pub unsafe fn scan(
+    loc: *const usize,
+    words: usize,
+    _heap: &Range<usize>,
+    _out: *mut usize,
+    found: &mut usize,
+) -> c_int {
+    *found = 0;
+    if words == 0 {
+        return 0;
+    }
+    reject(loc)
+}
+
+/// Rejects calling `f`, as its faults could not be recovered. Returns `SIGSEGV`.
+#[inline]
+pub unsafe fn protect(_f: unsafe extern "C" fn(*mut c_void), _data: *mut c_void) -> c_int {
+    reject(ptr::null::<u8>())
+}
+
+/// Sets no bits, as no page can be probed.
+#[inline]
+pub unsafe fn probe_range(
+    _start: *const u8,
+    _page_size: usize,
+    _pages: usize,
+    _readable: *mut u8,
+    _writable: *mut u8,
+) {
+}
+
+/// Returns an error, as there are no faults to hook.
+#[inline]
+pub unsafe fn add_hook(_range: &Range<usize>, _f: HookFn, _data: *mut c_void) -> io::Result<c_int> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "fault hooks are not supported on this platform",
+    ))
+}
+
+/// Does nothing, as no hooks are added.
+#[inline]
+pub unsafe fn remove_hook(_slot: c_int) {}
+
+/// Returns the last access rejected on the current thread.
+#[inline]
+pub fn last_fault() -> Fault {
+    LAST_FAULT.with(|last| last.get())
+}
+
+/// Returns `None`, as no fault signal is handled.
+#[inline]
+pub fn last_registers() -> Option<Registers> {
+    None
+}
+
+/// Returns zero, as rejected accesses are not recovered from a fault.
+#[cfg(feature = "tracing")]
+#[inline]
+pub fn last_fault_elapsed() -> Duration {
+    Duration::from_secs(0)
+}
@@ -0,0 +1,76 @@
+// Valgrind client requests for the `valgrind` feature.
+//
+// Under Valgrind, the intentional faults of bulletproof memory access are reported as errors, and
+// Memcheck also knows of invalid memory that does not fault, e.g. freed heap blocks.  With
+// `BULLETPROOF_VALGRIND` defined, the accessors ask Memcheck first whether the location is
+// addressable, and fail without touching it if it is not.
+//
+// The client request sequences are taken from Valgrind's `valgrind.h`, so that Valgrind's headers
+// are not required to build.  They are no-ops when not running under Valgrind.
+
+#ifndef BULLETPROOF_VALGRIND_H
+#define BULLETPROOF_VALGRIND_H
+
+#include <stddef.h>
+
+#if defined(BULLETPROOF_VALGRIND) && (defined(__x86_64__) || defined(__aarch64__))
+
+// `VG_USERREQ__RUNNING_ON_VALGRIND`.
+#define BULLETPROOF_VG_RUNNING_ON_VALGRIND 0x1001
+// `VG_USERREQ__CHANGE_ERR_DISABLEMENT`.
+#define BULLETPROOF_VG_CHANGE_ERR_DISABLEMENT 0x1801
+// `VG_USERREQ__CHECK_MEM_IS_ADDRESSABLE` of Memcheck.
+#define BULLETPROOF_VG_CHECK_MEM_IS_ADDRESSABLE ((('M' & 0xff) << 24 | ('C' & 0xff) << 16) + 4)
+
+// Performs a client request, returning `dflt` if not running under Valgrind.
+static inline unsigned long bulletproof_vg_request(unsigned long dflt, unsigned long request,
+                                                   unsigned long arg1, unsigned long arg2) {
+  volatile unsigned long args[6] = {request, arg1, arg2, 0, 0, 0};
+  volatile unsigned long result;
+
+#if defined(__x86_64__)
+  __asm__ volatile("rolq $3,  %%rdi ; rolq $13, %%rdi\n\t"
+                   "rolq $61, %%rdi ; rolq $51, %%rdi\n\t"
+                   "xchgq %%rbx,%%rbx"
+                   : "=d" (result)
+                   : "a" (&args[0]), "0" (dflt)
+                   : "cc", "memory");
+#else
+  __asm__ volatile("mov x3, %1\n\t"
+                   "mov x4, %2\n\t"
+                   "ror x12, x12, #3  ;  ror x12, x12, #13 \n\t"
+                   "ror x12, x12, #51 ;  ror x12, x12, #61 \n\t"
+                   "orr x10, x10, x10\n\t"
+                   "mov %0, x3"
+                   : "=r" (result)
+                   : "r" (dflt), "r" (&args[0])
+                   : "cc", "memory", "x3", "x4");
+#endif
+
+  return result;
+}
+
+// Checks if `[loc, loc + size)` is addressable according to Memcheck, without reporting an error.
+static inline int bulletproof_addressable(const void *loc, size_t size) {
+  if (size == 0 || !bulletproof_vg_request(0, BULLETPROOF_VG_RUNNING_ON_VALGRIND, 0, 0)) {
+    return 1;
+  }
+
+  bulletproof_vg_request(0, BULLETPROOF_VG_CHANGE_ERR_DISABLEMENT, 1, 0);
+  unsigned long bad = bulletproof_vg_request(0, BULLETPROOF_VG_CHECK_MEM_IS_ADDRESSABLE,
+                                             (unsigned long) loc, size);
+  bulletproof_vg_request(0, BULLETPROOF_VG_CHANGE_ERR_DISABLEMENT, (unsigned long) -1, 0);
+
+  return bad == 0;
+}
+
+#else
+
+static inline int bulletproof_addressable(const void *loc __attribute__((unused)),
+                                          size_t size __attribute__((unused))) {
+  return 1;
+}
+
+#endif
+
+#endif
@@ -0,0 +1,311 @@
+//! Watching the memory map for changes (Linux, FreeBSD, OpenBSD, NetBSD, and illumos).
+//!
+//! Callers that cache which pages are valid (e.g. the pages that passed
+//! [`Bulletproof::probe_range()`](../struct.Bulletproof.html#method.probe_range)) learn that a
+//! page was unmapped only by faulting on it. A [`MapsWatcher`](struct.MapsWatcher.html) keeps the
+//! last memory map it read (see [`snapshot::regions()`](../snapshot/fn.regions.html)), and reports
+//! the ranges that were mapped, unmapped, or changed since, so that the caches can be invalidated
+//! proactively. The memory map is read on demand with
+//! [`poll()`](struct.MapsWatcher.html#method.poll), or on an interval with
+//! [`spawn()`](struct.MapsWatcher.html#method.spawn).
+//!
+//! Changes are only seen when the memory map is read: a range unmapped and mapped again in between
+//! is not reported.
+//!
+//! # Examples
+//!
+//! ```
+//! use bulletproof::watch::{MapsEvent, MapsWatcher};
+//!
+//! let mut watcher = MapsWatcher::new().unwrap();
+//!
+//! unsafe {
+//!     let prot = libc::PROT_READ | libc::PROT_WRITE;
+//!     let flags = libc::MAP_PRIVATE | libc::MAP_ANONYMOUS;
+//!     let map = libc::mmap(std::ptr::null_mut(), 4096, prot, flags, -1, 0);
+//!     assert_ne!(map, libc::MAP_FAILED);
+//!     watcher.poll().unwrap();
+//!
+//!     libc::munmap(map, 4096);
+//!     let events = watcher.poll().unwrap();
+//!     assert!(events.iter().any(|event| match event {
+//!         MapsEvent::Unmapped(region) => region.start <= map as usize && region.end > map as usize,
+//!         _ => false,
+//!     }));
+//! }
+//! ```
+
+use std::io;
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use snapshot::{self, Region};
+
+/// A change of the memory map.
+///
+/// Each event is of a range of addresses, with the regions as of before and after the change
+/// clipped to the range. Adjacent ranges with the same change are reported as one event.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MapsEvent {
+    /// The range was mapped.
+    Mapped(Region),
+    /// The range was unmapped.
+    Unmapped(Region),
+    /// The permissions or the path name of the range changed, e.g. with `mprotect()`.
+    Changed {
+        /// The range before the change.
+        old: Region,
+        /// The range after the change.
+        new: Region,
+    },
+}
+
+/// Returns the region of `regions` (sorted by address) that contains `addr`.
+fn find(regions: &[Region], addr: usize) -> Option<&Region> {
+    let index = regions.partition_point(|region| region.end <= addr);
+    regions.get(index).filter(|region| region.start <= addr)
+}
+
+/// Returns `region` clipped to `[start, end)`.
+fn clip(region: &Region, start: usize, end: usize) -> Region {
+    Region {
+        start,
+        end,
+        perms: region.perms,
+        name: region.name.clone(),
+    }
+}
+
+/// Extends `last` to `next` if they are adjacent and of the same change.
+fn extend(last: &mut Region, next: &Region) -> bool {
+    if last.end != next.start || last.perms != next.perms || last.name != next.name {
+        return false;
+    }
+    last.end = next.end;
+    true
+}
+
+/// Returns the changes from `old` to `new`, which are sorted by address.
+fn diff(old: &[Region], new: &[Region]) -> Vec<MapsEvent> {
+    // The boundaries of both maps split the address space into ranges whose regions do not change.
+    let mut bounds = old
+        .iter()
+        .chain(new)
+        .flat_map(|region| vec![region.start, region.end])
+        .collect::<Vec<_>>();
+    bounds.sort_unstable();
+    bounds.dedup();
+
+    let mut events: Vec<MapsEvent> = Vec::new();
+    for range in bounds.windows(2) {
+        let (start, end) = (range[0], range[1]);
+        let event = match (find(old, start), find(new, start)) {
+            (None, None) => continue,
+            (None, Some(new)) => MapsEvent::Mapped(clip(new, start, end)),
+            (Some(old), None) => MapsEvent::Unmapped(clip(old, start, end)),
+            (Some(old), Some(new)) => {
+                if old.perms == new.perms && old.name == new.name {
+                    continue;
+                }
+                MapsEvent::Changed { old: clip(old, start, end), new: clip(new, start, end) }
+            }
+        };
+
+        let extended = match (events.last_mut(), &event) {
+            (Some(MapsEvent::Mapped(last)), MapsEvent::Mapped(next))
+            | (Some(MapsEvent::Unmapped(last)), MapsEvent::Unmapped(next)) => extend(last, next),
+            (
+                Some(MapsEvent::Changed { old: last_old, new: last_new }),
+                MapsEvent::Changed { old: next_old, new: next_new },
+            ) => {
+                let same = last_old.perms == next_old.perms && last_old.name == next_old.name;
+                same && extend(last_new, next_new) && extend(last_old, next_old)
+            }
+            _ => false,
+        };
+        if !extended {
+            events.push(event);
+        }
+    }
+    events
+}
+
+/// A watcher of the memory map, which reports its changes since the last read.
+#[derive(Debug, Clone)]
+pub struct MapsWatcher {
+    regions: Vec<Region>,
+}
+
+impl MapsWatcher {
+    /// Creates a watcher, reading the memory map.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of reading the memory map.
+    pub fn new() -> io::Result<Self> {
+        Ok(Self { regions: snapshot::regions()? })
+    }
+
+    /// Returns the memory map as of the last read.
+    #[inline]
+    pub fn regions(&self) -> &[Region] {
+        &self.regions
+    }
+
+    /// Reads the memory map, and returns its changes since the last read, sorted by address.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of reading the memory map, in which case the last read is kept.
+    pub fn poll(&mut self) -> io::Result<Vec<MapsEvent>> {
+        let regions = snapshot::regions()?;
+        let events = diff(&self.regions, &regions);
+        self.regions = regions;
+        Ok(events)
+    }
+
+    /// Polls the watcher every `interval` in a new thread, calling `f` with the changes whenever
+    /// there are some, or with the error of reading the memory map.
+    ///
+    /// The thread stops when the returned [`Watching`](struct.Watching.html) is dropped.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the thread cannot be spawned.
+    pub fn spawn<F>(mut self, interval: Duration, mut f: F) -> Watching
+    where
+        F: FnMut(io::Result<Vec<MapsEvent>>) + Send + 'static,
+    {
+        let (stop, stopped) = mpsc::channel();
+        let thread = thread::Builder::new()
+            .name("bulletproof-maps-watcher".into())
+            .spawn(move || {
+                while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+                    match self.poll() {
+                        Ok(ref events) if events.is_empty() => {}
+                        result => f(result),
+                    }
+                }
+            })
+            .expect("failed to spawn the maps watcher thread");
+        Watching { stop: Some(stop), thread: Some(thread) }
+    }
+}
+
+/// The handle of a watcher thread, returned by
+/// [`MapsWatcher::spawn()`](struct.MapsWatcher.html#method.spawn). The thread stops when the
+/// handle is dropped.
+#[derive(Debug)]
+pub struct Watching {
+    stop: Option<Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Drop for Watching {
+    fn drop(&mut self) {
+        // The thread stops once the channel is closed, after its current poll.
+        drop(self.stop.take());
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+    use std::time::Instant;
+
+    use libc;
+
+    use super::*;
+    use mapping::TestPages;
+
+    fn region(start: usize, end: usize, perms: &[u8; 4], name: &str) -> Region {
+        Region { start, end, perms: *perms, name: name.to_string() }
+    }
+
+    #[test]
+    fn diff() {
+        let old = [
+            region(0x1000, 0x4000, b"rw-p", ""),
+            region(0x4000, 0x5000, b"r--p", "/lib/a.so"),
+            region(0x8000, 0x9000, b"rw-p", ""),
+        ];
+        let new = [
+            // The middle of the first region is protected, and its end and the next are unmapped.
+            region(0x1000, 0x2000, b"rw-p", ""),
+            region(0x2000, 0x3000, b"---p", ""),
+            // A region is mapped, adjacent to an unchanged one.
+            region(0x6000, 0x8000, b"rw-p", ""),
+            region(0x8000, 0x9000, b"rw-p", ""),
+        ];
+        assert_eq!(
+            super::diff(&old, &new),
+            vec![
+                MapsEvent::Changed {
+                    old: region(0x2000, 0x3000, b"rw-p", ""),
+                    new: region(0x2000, 0x3000, b"---p", ""),
+                },
+                MapsEvent::Unmapped(region(0x3000, 0x4000, b"rw-p", "")),
+                MapsEvent::Unmapped(region(0x4000, 0x5000, b"r--p", "/lib/a.so")),
+                MapsEvent::Mapped(region(0x6000, 0x8000, b"rw-p", "")),
+            ]
+        );
+        assert_eq!(super::diff(&new, &new), vec![]);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn poll() {
+        let mut watcher = MapsWatcher::new().unwrap();
+
+        // The range is kept mapped, so that no other test can reuse it in the meantime.
+        let pages = TestPages::new(3);
+        let (page_size, start) = (pages.page_size(), pages.page(0) as usize);
+        watcher.poll().unwrap();
+        let (sender, receiver) = mpsc::channel();
+        let watching = watcher.clone().spawn(Duration::from_millis(1), move |events| {
+            let _ = sender.send(events.unwrap());
+        });
+
+        pages.protect(1, libc::PROT_READ);
+        let events = watcher.poll().unwrap();
+        let protected = |event: &MapsEvent| match *event {
+            MapsEvent::Changed { ref old, ref new } => {
+                (new.start, new.end, old.perms[1], new.perms[1])
+                    == (start + page_size, start + 2 * page_size, b'w', b'-')
+            }
+            _ => false,
+        };
+        assert!(events.iter().any(protected), "{:?}", events);
+
+        for index in 0..3 {
+            pages.protect(index, libc::PROT_NONE);
+        }
+        let events = watcher.poll().unwrap();
+        let inaccessible = events.iter().any(|event| match *event {
+            MapsEvent::Changed { ref new, .. } => {
+                new.start <= start && start < new.end && &new.perms[..3] == b"---"
+            }
+            _ => false,
+        });
+        assert!(inaccessible, "{:?}", events);
+
+        // The watcher thread reports the protection, possibly merged with the later one.
+        let middle = |event: &MapsEvent| match *event {
+            MapsEvent::Changed { ref new, .. } => {
+                new.start <= start + page_size && start + 2 * page_size <= new.end
+            }
+            _ => false,
+        };
+        let deadline = Instant::now() + Duration::from_secs(10);
+        let mut reported = Vec::new();
+        while !reported.iter().any(middle) {
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            reported.extend(receiver.recv_timeout(timeout).expect("no change reported"));
+        }
+        drop(watching);
+    }
+}
@@ -0,0 +1,264 @@
+//! Bounding the wall-clock time of accesses with a watchdog thread.
+//!
+//! An access to a page backed by `userfaultfd` (whose handler is gone or stuck) or by a
+//! network file system can block in the kernel indefinitely, instead of faulting.
+//! [`Watchdog::bound()`](struct.Watchdog.html#method.bound) runs accesses with a deadline: a
+//! watchdog thread sends the accessing thread a signal once the deadline passes, and the handler
+//! of the signal escapes from the protected section as from a fault.
+//!
+//! The signal only interrupts waits that the kernel lets signals interrupt: e.g. a `userfaultfd`
+//! fault is interrupted, but a fault on a FUSE mapping may only be interrupted by fatal signals.
+//! Only accesses with the [`Backend::Signal`](../enum.Backend.html#variant.Signal) backend are
+//! escaped.
+//!
+//! # Examples
+//!
+//! ```
+//! use bulletproof::watchdog::Watchdog;
+//! use bulletproof::{Bulletproof, TimeoutError};
+//! use std::hint;
+//! use std::sync::atomic::{AtomicBool, Ordering};
+//! use std::time::Duration;
+//!
+//! let region = [42u8; 16];
+//! let mut dst = [0u8; 16];
+//! let stuck = AtomicBool::new(true);
+//!
+//! unsafe {
+//!     let bulletproof = Bulletproof::new();
+//!     let watchdog = Watchdog::start(libc::SIGALRM).unwrap();
+//!     let timeout = Duration::from_millis(100);
+//!
+//!     let copy = watchdog.bound(timeout, || bulletproof.read(region.as_ptr(), &mut dst));
+//!     assert_eq!(copy, Ok(0));
+//!
+//!     // An access that does not complete, e.g. on a page whose `userfaultfd` handler is stuck.
+//!     let spin = || {
+//!         bulletproof.protect(|| {
+//!             while stuck.load(Ordering::SeqCst) {
+//!                 hint::spin_loop();
+//!             }
+//!         })
+//!     };
+//!     assert_eq!(watchdog.bound(timeout, spin), Err(TimeoutError::TimedOut));
+//! }
+//! ```
+
+use std::io;
+use std::mem;
+use std::ptr;
+use std::sync::{Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use libc::{self, c_int, c_void, pthread_t, siginfo_t};
+
+use error::TimeoutError;
+use fault::Fault;
+
+/// `sig_atomic_t`, which is `int` on the supported platforms.
+#[allow(non_camel_case_types)]
+type sig_atomic_t = c_int;
+
+extern "C" {
+    fn bulletproof_impl_arm_timer() -> *mut sig_atomic_t;
+    fn bulletproof_impl_disarm_timer();
+    fn bulletproof_impl_timeout_handler(sig: c_int, si: *mut siginfo_t, ctx: *mut c_void);
+}
+
+/// How often the signal is sent again to a thread whose timer expired, until the bounded access
+/// returns, e.g. to escape from each page of a zero-filling read.
+const RESEND_INTERVAL: Duration = Duration::from_millis(10);
+
+/// The armed timer of a thread.
+struct Timer {
+    id: u64,
+    thread: pthread_t,
+    signal: c_int,
+    expired: *mut sig_atomic_t,
+    deadline: Instant,
+}
+
+// The timer is removed before the thread exits, so `expired` stays valid while it is sent.
+unsafe impl Send for Timer {}
+
+/// The armed timers, and the id of the next timer.
+static TIMERS: Mutex<(Vec<Timer>, u64)> = Mutex::new((Vec::new(), 0));
+
+/// Notified when a timer is armed.
+static ARMED: Condvar = Condvar::new();
+
+/// Expires the timers past their deadlines, forever.
+fn run() {
+    let mut timers = TIMERS.lock().unwrap();
+    loop {
+        let now = Instant::now();
+        for timer in timers.0.iter_mut().filter(|timer| timer.deadline <= now) {
+            unsafe {
+                ptr::write_volatile(timer.expired, 1);
+                libc::pthread_kill(timer.thread, timer.signal);
+            }
+            timer.deadline = now + RESEND_INTERVAL;
+        }
+
+        timers = match timers.0.iter().map(|timer| timer.deadline).min() {
+            Some(deadline) => {
+                let timeout = deadline.saturating_duration_since(Instant::now());
+                ARMED.wait_timeout(timers, timeout).unwrap().0
+            }
+            None => ARMED.wait(timers).unwrap(),
+        };
+    }
+}
+
+/// Disarms the timer of the current thread on drop, even if the bounded access panics.
+struct Armed {
+    id: u64,
+}
+
+impl Drop for Armed {
+    fn drop(&mut self) {
+        // No signal is sent after the timer is removed, and those sent before are ignored once it
+        // is disarmed.
+        TIMERS.lock().unwrap().0.retain(|timer| timer.id != self.id);
+        unsafe {
+            bulletproof_impl_disarm_timer();
+        }
+    }
+}
+
+/// The watchdog that bounds the time of accesses, sending a signal to interrupt them.
+#[derive(Debug, Clone, Copy)]
+pub struct Watchdog {
+    signal: c_int,
+}
+
+impl Watchdog {
+    /// Installs the handler of `signal`, and starts the watchdog thread if it is not running yet.
+    ///
+    /// The signal should be reserved for the watchdog (e.g. `SIGALRM` or a real-time signal), as
+    /// its previous handler is replaced. A signal that arrives after the bounded access returned
+    /// is ignored, and interrupted system calls are restarted.
+    ///
+    /// # Safety
+    ///
+    /// No other handler for `signal` should be installed while the watchdog is used.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of `sigaction()` if the handler cannot be installed, and the error of
+    /// spawning the thread if it cannot be started, in which case the next call starts it again.
+    pub unsafe fn start(signal: c_int) -> io::Result<Self> {
+        let mut action: libc::sigaction = mem::zeroed();
+        action.sa_sigaction = bulletproof_impl_timeout_handler as *const () as usize;
+        action.sa_flags = libc::SA_SIGINFO | libc::SA_RESTART | libc::SA_ONSTACK;
+        libc::sigemptyset(&mut action.sa_mask);
+        if libc::sigaction(signal, &action, ptr::null_mut()) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        // Whether the thread is running, so that it is spawned again if spawning it failed.
+        static STARTED: Mutex<bool> = Mutex::new(false);
+
+        let mut started = STARTED.lock().unwrap();
+        if !*started {
+            thread::Builder::new().name("bulletproof-watchdog".into()).spawn(run)?;
+            *started = true;
+        }
+        Ok(Self { signal })
+    }
+
+    /// Returns the signal that interrupts the bounded accesses.
+    #[inline]
+    pub fn signal(&self) -> c_int {
+        self.signal
+    }
+
+    /// Calls `f`, which performs bulletproof accesses, escaping from them once `timeout` passes.
+    ///
+    /// Returns `Err(TimedOut)` if the timeout passed before `f` returned, even if it then returned
+    /// `Ok`, e.g. after zero-filling the pages whose accesses were escaped. Otherwise, returns the
+    /// result of `f`.
+    ///
+    /// # Safety
+    ///
+    /// An escaped protected section is abandoned as on a fault: see
+    /// [`Bulletproof::protect()`](../struct.Bulletproof.html#method.protect).
+    ///
+    /// # Panics
+    ///
+    /// Panics if it is nested in another call of `bound()` on the same thread.
+    pub unsafe fn bound<T, F>(&self, timeout: Duration, f: F) -> Result<T, TimeoutError>
+    where
+        F: FnOnce() -> Result<T, Fault>,
+    {
+        let deadline = match Instant::now().checked_add(timeout) {
+            Some(deadline) => deadline,
+            None => return f().map_err(TimeoutError::Fault),
+        };
+
+        // The signal may be blocked, e.g. if the thread was created while it was blocked.
+        let mut set = mem::zeroed();
+        libc::sigemptyset(&mut set);
+        libc::sigaddset(&mut set, self.signal);
+        libc::pthread_sigmask(libc::SIG_UNBLOCK, &set, ptr::null_mut());
+
+        let expired = bulletproof_impl_arm_timer();
+        assert!(!expired.is_null(), "nested bounded accesses");
+        let armed = {
+            let mut timers = TIMERS.lock().unwrap();
+            let id = timers.1;
+            timers.1 += 1;
+            let thread = libc::pthread_self();
+            let signal = self.signal;
+            timers.0.push(Timer { id, thread, signal, expired, deadline });
+            ARMED.notify_one();
+            Armed { id }
+        };
+
+        let result = f();
+        drop(armed);
+        if ptr::read_volatile(expired) != 0 {
+            return Err(TimeoutError::TimedOut);
+        }
+        result.map_err(TimeoutError::Fault)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::hint;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    use super::*;
+    use Bulletproof;
+
+    #[test]
+    fn bound() {
+        let stuck = AtomicBool::new(true);
+        let value = 42usize;
+
+        unsafe {
+            let bulletproof = Bulletproof::new();
+            let watchdog = Watchdog::start(libc::SIGALRM).unwrap();
+            let timeout = Duration::from_millis(50);
+
+            let start = Instant::now();
+            let spin = || {
+                bulletproof.protect(|| {
+                    while stuck.load(Ordering::SeqCst) {
+                        hint::spin_loop();
+                    }
+                })
+            };
+            assert_eq!(watchdog.bound(timeout, spin), Err(TimeoutError::TimedOut));
+            assert!(start.elapsed() >= timeout);
+
+            // The timer is disarmed, and can be armed again.
+            assert_eq!(watchdog.bound(timeout, || bulletproof.load(&value)), Ok(42));
+            let fault = watchdog.bound(timeout, || bulletproof.load(ptr::null::<usize>()));
+            assert!(matches!(fault, Err(TimeoutError::Fault(_))));
+            libc::raise(libc::SIGALRM);
+        }
+    }
+}